@@ -0,0 +1,156 @@
+use crate::hashing::Accounts;
+use crate::{ClientId, TransactionId};
+
+#[derive(Debug, PartialEq)]
+pub enum AccountMergeError {
+    ClientNotFound(ClientId),
+    SameClient(ClientId),
+    ConflictingTransactionIds(Vec<TransactionId>),
+}
+
+impl std::error::Error for AccountMergeError {}
+
+impl std::fmt::Display for AccountMergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccountMergeError::ClientNotFound(c) => write!(f, "ClientNotFound: {}", c),
+            AccountMergeError::SameClient(c) => write!(f, "SameClient: {}", c),
+            AccountMergeError::ConflictingTransactionIds(t) => {
+                write!(f, "ConflictingTransactionIds: {:?}", t)
+            }
+        }
+    }
+}
+
+/// Merges the `source` account into `target` (e.g. after a customer dedup), moving its balances
+/// and transaction history. Nothing changes if both accounts hold a transaction with the same id.
+pub fn merge_accounts(
+    accounts: &mut Accounts,
+    source: ClientId,
+    target: ClientId,
+    debug_logger: &mut dyn std::io::Write,
+) -> Result<(), AccountMergeError> {
+    if source == target {
+        return Err(AccountMergeError::SameClient(source));
+    }
+    let (source_account, target_account) = match (accounts.get(&source), accounts.get(&target)) {
+        (None, _) => return Err(AccountMergeError::ClientNotFound(source)),
+        (_, None) => return Err(AccountMergeError::ClientNotFound(target)),
+        (Some(source_account), Some(target_account)) => (source_account, target_account),
+    };
+
+    let conflicting_transaction_ids = target_account.conflicting_transaction_ids(source_account);
+    if !conflicting_transaction_ids.is_empty() {
+        return Err(AccountMergeError::ConflictingTransactionIds(
+            conflicting_transaction_ids,
+        ));
+    }
+
+    let source_account = accounts.remove(&source).expect("source account exists");
+    accounts
+        .get_mut(&target)
+        .expect("target account exists")
+        .merge_from(source_account);
+    writeln!(debug_logger, "merged account {} into {}", source, target)
+        .expect("error writing to debug stream");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_account::client_account_transaction::ClientAccountTransaction;
+    use crate::processing_options::ProcessingOptions;
+    use crate::{ClientAccount, TransactionType};
+
+    fn account_with_deposits(
+        client_id: ClientId,
+        deposits: &[(TransactionId, f64)],
+    ) -> ClientAccount {
+        let mut account = ClientAccount::new(client_id);
+        for (transaction_id, amount) in deposits {
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Deposit,
+                        transaction_id: *transaction_id,
+                        amount: Some(*amount),
+                    },
+                    &ProcessingOptions::default(),
+                    &mut std::io::sink(),
+                )
+                .unwrap();
+        }
+        account
+    }
+
+    #[test]
+    fn it_merges_balances_and_history() {
+        let mut accounts = Accounts::default();
+        accounts.insert(1, account_with_deposits(1, &[(1, 100.0)]));
+        let mut source = account_with_deposits(2, &[(2, 10.0)]);
+        source.locked = true;
+        accounts.insert(2, source);
+
+        let mut debug_logger = Vec::<u8>::new();
+        merge_accounts(&mut accounts, 2, 1, &mut debug_logger).unwrap();
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[&1].balance.available, 110.0);
+        assert!(accounts[&1].locked);
+        assert_eq!(
+            std::str::from_utf8(&debug_logger).unwrap(),
+            "merged account 2 into 1\n"
+        );
+
+        // the merged transaction history can be disputed on the target account
+        accounts
+            .get_mut(&1)
+            .unwrap()
+            .process_client_transaction(
+                ClientAccountTransaction {
+                    transaction_type: TransactionType::Dispute,
+                    transaction_id: 2,
+                    amount: None,
+                },
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
+        assert_eq!(accounts[&1].balance.held, 10.0);
+    }
+
+    #[test]
+    fn it_leaves_both_accounts_unchanged_on_conflicting_transaction_ids() {
+        let mut accounts = Accounts::default();
+        accounts.insert(1, account_with_deposits(1, &[(1, 100.0), (3, 1.0)]));
+        accounts.insert(2, account_with_deposits(2, &[(2, 10.0), (3, 5.0)]));
+
+        assert_eq!(
+            merge_accounts(&mut accounts, 2, 1, &mut std::io::sink()),
+            Err(AccountMergeError::ConflictingTransactionIds(vec![3]))
+        );
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[&1].balance.available, 101.0);
+        assert_eq!(accounts[&2].balance.available, 15.0);
+    }
+
+    #[test]
+    fn it_returns_error_for_unknown_or_identical_clients() {
+        let mut accounts = Accounts::default();
+        accounts.insert(1, account_with_deposits(1, &[(1, 100.0)]));
+
+        assert_eq!(
+            merge_accounts(&mut accounts, 2, 1, &mut std::io::sink()),
+            Err(AccountMergeError::ClientNotFound(2))
+        );
+        assert_eq!(
+            merge_accounts(&mut accounts, 1, 2, &mut std::io::sink()),
+            Err(AccountMergeError::ClientNotFound(2))
+        );
+        assert_eq!(
+            merge_accounts(&mut accounts, 1, 1, &mut std::io::sink()),
+            Err(AccountMergeError::SameClient(1))
+        );
+    }
+}
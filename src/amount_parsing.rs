@@ -0,0 +1,356 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::TransactionType;
+
+/// What to do with an amount sent as an empty string, `NULL` or `NaN`.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NullAmountPolicy {
+    /// Treat the amount as missing, so a deposit or withdrawal is rejected as having no amount
+    #[default]
+    Missing,
+    /// Reject the transaction with an `InvalidAmount` error, whatever its type
+    Reject,
+}
+
+impl std::str::FromStr for NullAmountPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "missing" => Ok(NullAmountPolicy::Missing),
+            "reject" => Ok(NullAmountPolicy::Reject),
+            _ => Err(format!("unknown null amount policy: {}", s)),
+        }
+    }
+}
+
+/// How amounts are written in the input.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AmountFormat {
+    /// Decimals with a `.` decimal separator, or in scientific notation (e.g. `1.5e3`)
+    #[default]
+    Standard,
+    /// Only plain decimals with a `.` decimal separator; anything else is rejected
+    Strict,
+    /// Decimals with a `,` decimal separator, optionally grouped by `.` or space thousands
+    /// separators (e.g. `1.234,56`), or in scientific notation; anything else is rejected
+    CommaDecimal,
+    /// Decimals as exported by spreadsheets, with currency symbols, `,` thousands separators and
+    /// accounting-style negatives (e.g. `$1,234.50`, `(€12.00)`) stripped; anything else is
+    /// rejected
+    Lenient,
+}
+
+impl std::str::FromStr for AmountFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(AmountFormat::Standard),
+            "strict" => Ok(AmountFormat::Strict),
+            "comma-decimal" => Ok(AmountFormat::CommaDecimal),
+            "lenient" => Ok(AmountFormat::Lenient),
+            _ => Err(format!("unknown amount format: {}", s)),
+        }
+    }
+}
+
+/// How an amount was changed from the input before the transaction was parsed.
+#[derive(Debug, PartialEq, Clone)]
+pub enum AmountNormalization {
+    /// Taken as written
+    Unchanged,
+    /// An empty string, `NULL` or `NaN` taken as no amount
+    NullAsMissing(String),
+    /// An amount rewritten as a plain decimal
+    Reformatted(String),
+    /// An amount which was rejected, leaving the transaction with no amount
+    Rejected(String),
+}
+
+/// Counts of the amounts normalized, for the summary.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+pub struct AmountNormalizationCounts {
+    pub null_as_missing: usize,
+    pub reformatted: usize,
+    pub rejected: usize,
+}
+
+impl AmountNormalizationCounts {
+    pub fn record(&mut self, normalization: &AmountNormalization) {
+        match normalization {
+            AmountNormalization::Unchanged => {}
+            AmountNormalization::NullAsMissing(_) => self.null_as_missing += 1,
+            AmountNormalization::Reformatted(_) => self.reformatted += 1,
+            AmountNormalization::Rejected(_) => self.rejected += 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn is_null(amount: &str) -> bool {
+    amount.is_empty() || amount.eq_ignore_ascii_case("null") || amount.eq_ignore_ascii_case("nan")
+}
+
+// An optional `-`, then digits with an optional `.` and fractional digits
+fn is_plain_decimal(amount: &str) -> bool {
+    let digits = amount.strip_prefix('-').unwrap_or(amount);
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, "0"));
+    !whole.is_empty()
+        && !fraction.is_empty()
+        && whole.bytes().all(|byte| byte.is_ascii_digit())
+        && fraction.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+fn is_scientific(amount: &str) -> bool {
+    amount.contains(['e', 'E']) && amount.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+// Rewrites e.g. `-1.234,56` or `1 234,5` as a plain decimal, if the thousands separators separate
+// groups of 3 digits
+fn comma_decimal_to_plain(amount: &str) -> Option<String> {
+    let (sign, digits) = match amount.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", amount),
+    };
+    let (whole, fraction) = match digits.split_once(',') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (digits, None),
+    };
+    let mut groups = whole.split(['.', ' ']);
+    let first_group = groups.next()?;
+    let grouped = whole.contains(['.', ' ']);
+    let valid_groups =
+        (!grouped || (1..=3).contains(&first_group.len())) && groups.all(|group| group.len() == 3);
+    let plain_whole: String = whole.split(['.', ' ']).collect();
+    let plain = match fraction {
+        Some(fraction) => format!("{}{}.{}", sign, plain_whole, fraction),
+        None => format!("{}{}", sign, plain_whole),
+    };
+    (valid_groups && is_plain_decimal(&plain)).then_some(plain)
+}
+
+const CURRENCY_SYMBOLS: [char; 5] = ['$', '€', '£', '¥', '₹'];
+
+// Rewrites e.g. `$1,234.50`, `-$5` or `(1,000)` as a plain decimal, if the thousands separators
+// separate groups of 3 digits
+fn lenient_to_plain(amount: &str) -> Option<String> {
+    let (negative, amount) = match amount
+        .strip_prefix('(')
+        .and_then(|amount| amount.strip_suffix(')'))
+    {
+        Some(amount) => (true, amount.trim()),
+        None => (false, amount),
+    };
+    let (negative, amount) = match amount.strip_prefix('-') {
+        Some(amount) if !negative => (true, amount.trim_start()),
+        _ => (negative, amount),
+    };
+    let digits = amount
+        .trim_start_matches(CURRENCY_SYMBOLS)
+        .trim_end_matches(CURRENCY_SYMBOLS)
+        .trim();
+
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (digits, None),
+    };
+    let mut groups = whole.split(',');
+    let first_group = groups.next()?;
+    let valid_groups = (!whole.contains(',') || (1..=3).contains(&first_group.len()))
+        && groups.all(|group| group.len() == 3);
+    let plain_whole: String = whole.split(',').collect();
+    let plain = format!(
+        "{}{}{}",
+        if negative { "-" } else { "" },
+        plain_whole,
+        fraction
+            .map(|fraction| format!(".{}", fraction))
+            .unwrap_or_default()
+    );
+    (valid_groups && is_plain_decimal(&plain)).then_some(plain)
+}
+
+/// Normalizes an amount as it appears in the input, returning the amount to parse (empty for no
+/// amount) and how it was normalized. An empty amount is expected for disputes, resolves and
+/// chargebacks, so is only a normalization for other transaction types.
+pub fn normalize_amount(
+    amount: &str,
+    transaction_type: Option<TransactionType>,
+    null_amounts: NullAmountPolicy,
+    amount_format: AmountFormat,
+) -> (String, AmountNormalization) {
+    let trimmed = amount.trim();
+    if !is_null(trimmed) {
+        return normalize_format(amount, trimmed, amount_format);
+    }
+
+    let amount_expected =
+        !transaction_type.is_some_and(|transaction_type| transaction_type.is_dispute_op());
+    if trimmed.is_empty() && !amount_expected {
+        return (String::new(), AmountNormalization::Unchanged);
+    }
+    let normalization = match null_amounts {
+        NullAmountPolicy::Missing => AmountNormalization::NullAsMissing(amount.to_string()),
+        NullAmountPolicy::Reject => AmountNormalization::Rejected(amount.to_string()),
+    };
+    (String::new(), normalization)
+}
+
+fn normalize_format(
+    amount: &str,
+    trimmed: &str,
+    amount_format: AmountFormat,
+) -> (String, AmountNormalization) {
+    let rejected = || {
+        (
+            String::new(),
+            AmountNormalization::Rejected(amount.to_string()),
+        )
+    };
+    match amount_format {
+        AmountFormat::Standard => (amount.to_string(), AmountNormalization::Unchanged),
+        AmountFormat::Strict if is_plain_decimal(trimmed) => {
+            (amount.to_string(), AmountNormalization::Unchanged)
+        }
+        AmountFormat::Strict => rejected(),
+        AmountFormat::CommaDecimal if is_scientific(trimmed) => {
+            (amount.to_string(), AmountNormalization::Unchanged)
+        }
+        AmountFormat::CommaDecimal => match comma_decimal_to_plain(trimmed) {
+            Some(plain) if plain == trimmed => (amount.to_string(), AmountNormalization::Unchanged),
+            Some(plain) => (plain, AmountNormalization::Reformatted(amount.to_string())),
+            None => rejected(),
+        },
+        AmountFormat::Lenient => match lenient_to_plain(trimmed) {
+            Some(plain) if plain == trimmed => (amount.to_string(), AmountNormalization::Unchanged),
+            Some(plain) => (plain, AmountNormalization::Reformatted(amount.to_string())),
+            None => rejected(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_null_amounts() {
+        let deposit = Some(TransactionType::Deposit);
+        let dispute = Some(TransactionType::Dispute);
+
+        assert_eq!(
+            normalize_amount(
+                "1.5",
+                deposit,
+                NullAmountPolicy::Reject,
+                AmountFormat::Standard
+            ),
+            ("1.5".to_string(), AmountNormalization::Unchanged)
+        );
+        assert_eq!(
+            normalize_amount(
+                "NULL",
+                deposit,
+                NullAmountPolicy::Missing,
+                AmountFormat::Standard
+            ),
+            (
+                String::new(),
+                AmountNormalization::NullAsMissing("NULL".to_string())
+            )
+        );
+        assert_eq!(
+            normalize_amount(
+                "NaN",
+                dispute,
+                NullAmountPolicy::Reject,
+                AmountFormat::Standard
+            ),
+            (
+                String::new(),
+                AmountNormalization::Rejected("NaN".to_string())
+            )
+        );
+        assert_eq!(
+            normalize_amount(
+                "",
+                dispute,
+                NullAmountPolicy::Reject,
+                AmountFormat::Standard
+            ),
+            (String::new(), AmountNormalization::Unchanged)
+        );
+        assert_eq!(
+            normalize_amount(
+                "",
+                deposit,
+                NullAmountPolicy::Reject,
+                AmountFormat::Standard
+            ),
+            (String::new(), AmountNormalization::Rejected(String::new()))
+        );
+    }
+
+    #[test]
+    fn test_normalize_amount_formats() {
+        let normalize = |amount, amount_format| {
+            normalize_amount(
+                amount,
+                Some(TransactionType::Deposit),
+                NullAmountPolicy::Missing,
+                amount_format,
+            )
+        };
+        let unchanged = |amount: &str| (amount.to_string(), AmountNormalization::Unchanged);
+        let reformatted = |plain: &str, amount: &str| {
+            (
+                plain.to_string(),
+                AmountNormalization::Reformatted(amount.to_string()),
+            )
+        };
+        let rejected = |amount: &str| {
+            (
+                String::new(),
+                AmountNormalization::Rejected(amount.to_string()),
+            )
+        };
+
+        assert_eq!(
+            normalize("1.5e3", AmountFormat::Standard),
+            unchanged("1.5e3")
+        );
+        assert_eq!(normalize("-2.25", AmountFormat::Strict), unchanged("-2.25"));
+        assert_eq!(normalize("1.5e3", AmountFormat::Strict), rejected("1.5e3"));
+        assert_eq!(normalize("1,5", AmountFormat::Strict), rejected("1,5"));
+        assert_eq!(
+            normalize("1.234,56", AmountFormat::CommaDecimal),
+            reformatted("1234.56", "1.234,56")
+        );
+        assert_eq!(
+            normalize("-12 345,5", AmountFormat::CommaDecimal),
+            reformatted("-12345.5", "-12 345,5")
+        );
+        assert_eq!(
+            normalize("1234", AmountFormat::CommaDecimal),
+            unchanged("1234")
+        );
+        assert_eq!(
+            normalize("2E2", AmountFormat::CommaDecimal),
+            unchanged("2E2")
+        );
+        assert_eq!(
+            normalize("1.23,4", AmountFormat::CommaDecimal),
+            rejected("1.23,4")
+        );
+        assert_eq!(
+            normalize("1,2,3", AmountFormat::CommaDecimal),
+            rejected("1,2,3")
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::dispute_cases::{CaseOutcome, DisputeCase};
+use crate::serializable_form;
+use crate::TransactionType;
+
+#[derive(Debug, Deserialize)]
+struct ArbitrationOutcomeRow {
+    case_id: String,
+    outcome: CaseOutcome,
+}
+
+/// Reads externally decided arbitration outcomes (CSV with case_id,outcome columns) of open cases
+/// imported from an earlier run, and turns each into the resolve or chargeback of its case's
+/// transaction, to be applied after the run's transactions.
+pub fn read_arbitration_transactions(
+    reader: impl std::io::Read,
+    imported_cases: &[DisputeCase],
+) -> anyhow::Result<Vec<serializable_form::Transaction>> {
+    let cases: HashMap<&str, &DisputeCase> = imported_cases
+        .iter()
+        .map(|case| (case.case_id.as_str(), case))
+        .collect();
+
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut transactions = Vec::new();
+    for row in csv_reader.deserialize() {
+        let row: ArbitrationOutcomeRow = row?;
+        let case = match cases.get(row.case_id.as_str()) {
+            Some(case) if case.outcome == CaseOutcome::Open => case,
+            Some(_) => anyhow::bail!("dispute case {} is not open", row.case_id),
+            None => anyhow::bail!("unknown dispute case: {}", row.case_id),
+        };
+        let transaction_type = match row.outcome {
+            CaseOutcome::Resolved => TransactionType::Resolve,
+            CaseOutcome::ChargedBack => TransactionType::Chargeback,
+            _ => anyhow::bail!(
+                "the arbitration outcome of dispute case {} must be resolved or charged-back",
+                row.case_id
+            ),
+        };
+        transactions.push(serializable_form::Transaction {
+            transaction_type,
+            client_id: case.client_id,
+            transaction_id: case.transaction_id,
+            amount: None,
+            counterparty: None,
+        });
+    }
+    Ok(transactions)
+}
+
+pub fn read_arbitration_transactions_file(
+    path: &Path,
+    imported_cases: &[DisputeCase],
+) -> anyhow::Result<Vec<serializable_form::Transaction>> {
+    read_arbitration_transactions(std::fs::File::open(path)?, imported_cases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(case_id: &str, transaction_id: u32, outcome: CaseOutcome) -> DisputeCase {
+        DisputeCase {
+            case_id: case_id.to_string(),
+            client_id: 1,
+            transaction_id,
+            amount: 10.0,
+            outcome,
+        }
+    }
+
+    #[test]
+    fn it_turns_outcomes_into_resolves_and_chargebacks() {
+        let cases = [
+            case("1-1", 1, CaseOutcome::Open),
+            case("1-2", 2, CaseOutcome::Open),
+            case("1-3", 3, CaseOutcome::Resolved),
+        ];
+        let transactions = read_arbitration_transactions(
+            "case_id,outcome\n1-2,charged-back\n1-1,resolved\n".as_bytes(),
+            &cases,
+        )
+        .unwrap();
+
+        let transactions: Vec<(TransactionType, u32)> = transactions
+            .iter()
+            .map(|transaction| (transaction.transaction_type, transaction.transaction_id))
+            .collect();
+        assert_eq!(
+            transactions,
+            vec![
+                (TransactionType::Chargeback, 2),
+                (TransactionType::Resolve, 1)
+            ]
+        );
+
+        for (outcomes, error) in [
+            (
+                "case_id,outcome\n1-3,resolved\n",
+                "dispute case 1-3 is not open",
+            ),
+            (
+                "case_id,outcome\n1-4,resolved\n",
+                "unknown dispute case: 1-4",
+            ),
+            (
+                "case_id,outcome\n1-1,open\n",
+                "the arbitration outcome of dispute case 1-1 must be resolved or charged-back",
+            ),
+        ] {
+            assert_eq!(
+                read_arbitration_transactions(outcomes.as_bytes(), &cases)
+                    .unwrap_err()
+                    .to_string(),
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use arrow::array::{ArrayRef, BooleanArray, Decimal128Array, UInt16Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use rust_decimal::prelude::*;
+use std::sync::Arc;
+
+use crate::ClientAccount;
+
+// Amounts are exact 4dp decimals, matching the rounding of the CSV output
+const AMOUNT_PRECISION: u8 = 38;
+const AMOUNT_SCALE: i8 = 4;
+
+pub fn record_batch_schema() -> Schema {
+    let amount_type = DataType::Decimal128(AMOUNT_PRECISION, AMOUNT_SCALE);
+    Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("available", amount_type.clone(), false),
+        Field::new("held", amount_type.clone(), false),
+        Field::new("total", amount_type, false),
+        Field::new("locked", DataType::Boolean, false),
+    ])
+}
+
+fn amount_4dp(x: f64) -> anyhow::Result<i128> {
+    let mut d = Decimal::from_f64(x)
+        .ok_or_else(|| anyhow::anyhow!("Failed to represent f64 as Decimal: {}", x))?
+        .round_dp(AMOUNT_SCALE as u32);
+    d.rescale(AMOUNT_SCALE as u32);
+    Ok(d.mantissa())
+}
+
+fn amount_array(amounts: Vec<i128>) -> anyhow::Result<ArrayRef> {
+    Ok(Arc::new(
+        Decimal128Array::from(amounts).with_precision_and_scale(AMOUNT_PRECISION, AMOUNT_SCALE)?,
+    ))
+}
+
+/// Builds a RecordBatch of the given accounts, in client id order.
+pub fn create_record_batch_from_accounts<'a>(
+    accounts: impl IntoIterator<Item = &'a ClientAccount>,
+) -> anyhow::Result<RecordBatch> {
+    let mut accounts: Vec<&ClientAccount> = accounts.into_iter().collect();
+    accounts.sort_by_key(|client_account| client_account.client_id);
+
+    let mut available = Vec::with_capacity(accounts.len());
+    let mut held = Vec::with_capacity(accounts.len());
+    let mut total = Vec::with_capacity(accounts.len());
+    for client_account in &accounts {
+        available.push(amount_4dp(client_account.balance.available)?);
+        held.push(amount_4dp(client_account.balance.held)?);
+        total.push(amount_4dp(client_account.balance.total())?);
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt16Array::from_iter_values(
+            accounts
+                .iter()
+                .map(|client_account| client_account.client_id),
+        )),
+        amount_array(available)?,
+        amount_array(held)?,
+        amount_array(total)?,
+        Arc::new(BooleanArray::from(
+            accounts
+                .iter()
+                .map(|client_account| client_account.locked)
+                .collect::<Vec<bool>>(),
+        )),
+    ];
+    Ok(RecordBatch::try_new(
+        Arc::new(record_batch_schema()),
+        columns,
+    )?)
+}
+
+/// Writes the batch as an Arrow IPC file (Feather v2)
+pub fn write_arrow_ipc(
+    record_batch: &RecordBatch,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut writer = FileWriter::try_new(output_stream, &record_batch.schema())?;
+    writer.write(record_batch)?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::ipc::reader::FileReader;
+
+    #[test]
+    fn it_builds_a_record_batch_in_client_order() {
+        let mut client_2 = ClientAccount::new(2);
+        client_2.balance.available = 1.23456;
+        client_2.locked = true;
+        let mut client_1 = ClientAccount::new(1);
+        client_1.balance.available = -1.0;
+        client_1.balance.held = 0.5;
+
+        let record_batch = create_record_batch_from_accounts([&client_2, &client_1]).unwrap();
+        assert_eq!(record_batch.num_rows(), 2);
+
+        let clients = record_batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt16Array>()
+            .unwrap();
+        assert_eq!(clients.values(), &[1, 2]);
+
+        let available = record_batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+        assert_eq!(available.value_as_string(0), "-1.0000");
+        assert_eq!(available.value_as_string(1), "1.2346");
+
+        let total = record_batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+        assert_eq!(total.value_as_string(0), "-0.5000");
+    }
+
+    #[test]
+    fn it_round_trips_through_an_ipc_file() {
+        let client_account = ClientAccount::new(7);
+        let record_batch = create_record_batch_from_accounts([&client_account]).unwrap();
+
+        let mut ipc_file = Vec::<u8>::new();
+        write_arrow_ipc(&record_batch, &mut ipc_file).unwrap();
+
+        let reader = FileReader::try_new(std::io::Cursor::new(ipc_file), None).unwrap();
+        let read_batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+        assert_eq!(read_batches, vec![record_batch]);
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use crate::client_account::error::TransactionProcessingError;
+use crate::serializable_form;
+use crate::{ClientId, TransactionId, TransactionType};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Authorization {
+    Approved,
+    Declined,
+}
+
+/// Consulted before applying a large withdrawal, e.g. to check it against a limits service.
+/// An `Err` means the authorizer could not decide, and is handled like a timeout.
+pub trait WithdrawalAuthorizer: Send + Sync {
+    fn authorize(
+        &self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+        amount: f64,
+    ) -> Result<Authorization, String>;
+}
+
+/// What to do with a withdrawal when the authorizer fails or times out.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum AuthorizationFailurePolicy {
+    /// Apply the withdrawal, and log a warning
+    FailOpen,
+    /// Reject the withdrawal with a `WithdrawalNotAuthorized` error
+    #[default]
+    FailClosed,
+}
+
+/// Which withdrawals need authorizing, and how.
+#[derive(Clone)]
+pub struct WithdrawalAuthorization {
+    pub authorizer: Arc<dyn WithdrawalAuthorizer>,
+    /// Only withdrawals of more than this amount are authorized
+    pub threshold: f64,
+    pub timeout: Duration,
+    pub on_failure: AuthorizationFailurePolicy,
+}
+
+impl std::fmt::Debug for WithdrawalAuthorization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WithdrawalAuthorization")
+            .field("threshold", &self.threshold)
+            .field("timeout", &self.timeout)
+            .field("on_failure", &self.on_failure)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WithdrawalAuthorization {
+    /// Checks a transaction before it is applied. Anything other than a withdrawal above the
+    /// threshold passes without consulting the authorizer.
+    pub fn check(
+        &self,
+        transaction: &serializable_form::Transaction,
+        debug_logger: &mut dyn std::io::Write,
+    ) -> Result<(), TransactionProcessingError> {
+        let amount = match (transaction.transaction_type, transaction.amount) {
+            (TransactionType::Withdrawal, Some(amount)) if amount > self.threshold => amount,
+            _ => return Ok(()),
+        };
+
+        // The authorizer runs on its own thread so that it can be timed out. A timed out
+        // authorizer's thread is left to finish on its own.
+        let (sender, receiver) = mpsc::channel();
+        let authorizer = Arc::clone(&self.authorizer);
+        let (client_id, transaction_id) = (transaction.client_id, transaction.transaction_id);
+        std::thread::spawn(move || {
+            let _ = sender.send(authorizer.authorize(client_id, transaction_id, amount));
+        });
+        let failure = match receiver.recv_timeout(self.timeout) {
+            Ok(Ok(Authorization::Approved)) => return Ok(()),
+            Ok(Ok(Authorization::Declined)) => {
+                return Err(TransactionProcessingError::WithdrawalNotAuthorized {
+                    client: client_id,
+                    tx: transaction_id,
+                })
+            }
+            Ok(Err(error)) => error,
+            Err(mpsc::RecvTimeoutError::Timeout) => "timed out".to_string(),
+            Err(mpsc::RecvTimeoutError::Disconnected) => "authorizer panicked".to_string(),
+        };
+
+        match self.on_failure {
+            AuthorizationFailurePolicy::FailOpen => {
+                writeln!(
+                    debug_logger,
+                    "warning processing transaction - withdrawal {} not authorized ({}), applying it anyway",
+                    transaction_id, failure
+                )
+                .expect("error writing to debug stream");
+                Ok(())
+            }
+            AuthorizationFailurePolicy::FailClosed => {
+                Err(TransactionProcessingError::WithdrawalNotAuthorized {
+                    client: client_id,
+                    tx: transaction_id,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::withdrawal;
+
+    struct LimitsService;
+
+    impl WithdrawalAuthorizer for LimitsService {
+        fn authorize(
+            &self,
+            _client_id: ClientId,
+            transaction_id: TransactionId,
+            amount: f64,
+        ) -> Result<Authorization, String> {
+            match transaction_id {
+                1 => Ok(if amount <= 1000.0 {
+                    Authorization::Approved
+                } else {
+                    Authorization::Declined
+                }),
+                2 => Err("limits service unavailable".to_string()),
+                _ => {
+                    std::thread::sleep(Duration::from_secs(5));
+                    Ok(Authorization::Approved)
+                }
+            }
+        }
+    }
+
+    fn authorization(on_failure: AuthorizationFailurePolicy) -> WithdrawalAuthorization {
+        WithdrawalAuthorization {
+            authorizer: Arc::new(LimitsService),
+            threshold: 100.0,
+            timeout: Duration::from_millis(50),
+            on_failure,
+        }
+    }
+
+    #[test]
+    fn test_withdrawals_above_the_threshold_are_authorized() {
+        let authorization = authorization(AuthorizationFailurePolicy::FailClosed);
+        let mut debug_logger = std::io::sink();
+
+        assert_eq!(
+            authorization.check(&withdrawal(1, 9, 50.0), &mut debug_logger),
+            Ok(())
+        );
+        assert_eq!(
+            authorization.check(&withdrawal(1, 1, 500.0), &mut debug_logger),
+            Ok(())
+        );
+        assert_eq!(
+            authorization.check(&withdrawal(1, 1, 5000.0), &mut debug_logger),
+            Err(TransactionProcessingError::WithdrawalNotAuthorized { client: 1, tx: 1 })
+        );
+    }
+
+    #[test]
+    fn test_authorizer_failures_follow_the_failure_policy() {
+        let mut debug_logger = std::io::sink();
+
+        let fail_closed = authorization(AuthorizationFailurePolicy::FailClosed);
+        assert_eq!(
+            fail_closed.check(&withdrawal(1, 2, 500.0), &mut debug_logger),
+            Err(TransactionProcessingError::WithdrawalNotAuthorized { client: 1, tx: 2 })
+        );
+        assert_eq!(
+            fail_closed.check(&withdrawal(1, 3, 500.0), &mut debug_logger),
+            Err(TransactionProcessingError::WithdrawalNotAuthorized { client: 1, tx: 3 })
+        );
+
+        let fail_open = authorization(AuthorizationFailurePolicy::FailOpen);
+        let mut debug_output = Vec::<u8>::new();
+        assert_eq!(
+            fail_open.check(&withdrawal(1, 3, 500.0), &mut debug_output),
+            Ok(())
+        );
+        assert_eq!(
+            String::from_utf8(debug_output).unwrap(),
+            "warning processing transaction - withdrawal 3 not authorized (timed out), applying it anyway\n"
+        );
+    }
+}
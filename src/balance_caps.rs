@@ -0,0 +1,64 @@
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::ClientId;
+
+#[derive(Debug, Deserialize)]
+struct BalanceCapRow {
+    client: ClientId,
+    cap: f64,
+}
+
+/// Reads per-client balance caps from CSV with client,cap columns.
+pub fn read_balance_caps(reader: impl std::io::Read) -> anyhow::Result<HashMap<ClientId, f64>> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut caps = HashMap::new();
+    for row in csv_reader.deserialize() {
+        let row: BalanceCapRow = row?;
+        if row.cap < 0.0 {
+            anyhow::bail!(
+                "client {} has a negative balance cap: {}",
+                row.client,
+                row.cap
+            );
+        }
+        if let Some(cap) = caps.insert(row.client, row.cap) {
+            if cap != row.cap {
+                anyhow::bail!(
+                    "client {} has both balance cap {} and balance cap {}",
+                    row.client,
+                    cap,
+                    row.cap
+                );
+            }
+        }
+    }
+    Ok(caps)
+}
+
+pub fn read_balance_caps_file(path: &Path) -> anyhow::Result<HashMap<ClientId, f64>> {
+    read_balance_caps(std::fs::File::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_the_caps_per_client() {
+        let caps = read_balance_caps("client,cap\n1,100.0\n2, 250\n1,100\n".as_bytes()).unwrap();
+        assert_eq!(caps, HashMap::from([(1, 100.0), (2, 250.0)]));
+    }
+
+    #[test]
+    fn it_rejects_conflicting_caps() {
+        let result = read_balance_caps("client,cap\n1,100.0\n1,200.0\n".as_bytes());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "client 1 has both balance cap 100 and balance cap 200"
+        );
+    }
+}
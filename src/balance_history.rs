@@ -0,0 +1,171 @@
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::dates::Date;
+use crate::hashing::Accounts;
+use crate::processing_options::ProcessingOptions;
+use crate::serializable_form::{self, round_f64_4dp_string};
+use crate::submission_order::TIMESTAMP_COLUMN;
+use crate::timestamps::TimestampParser;
+use crate::{process_transaction_isolated, ClientAccount, ClientId};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// A client's balance at the end of a day.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct EndOfDayBalance {
+    pub available: f64,
+    pub held: f64,
+    pub locked: bool,
+}
+
+impl EndOfDayBalance {
+    fn of(client_account: &ClientAccount) -> Self {
+        Self {
+            available: client_account.balance.available,
+            held: client_account.balance.held,
+            locked: client_account.locked,
+        }
+    }
+}
+
+/// End-of-day balances by client and day (days since the Unix epoch), for the days on which
+/// each client had a row.
+pub type BalancesByDay = BTreeMap<ClientId, BTreeMap<i64, EndOfDayBalance>>;
+
+/// Replays the input, whose `timestamp` column gives each row's time (in the configured timestamp
+/// format), recording each client's balance at the end of each day. Each client's rows are applied
+/// in timestamp order; rejected rows leave the balance unchanged.
+pub fn end_of_day_balances(
+    input_transactions_file: &Path,
+    processing_options: &ProcessingOptions,
+    debug_logger: &mut dyn std::io::Write,
+) -> anyhow::Result<BalancesByDay> {
+    let mut reader = csv::Reader::from_path(input_transactions_file)?;
+    processing_options.column_mapping.apply_to(&mut reader)?;
+    let headers = reader.headers()?.clone();
+    let type_index = headers.iter().position(|header| header.trim() == "type");
+    let timestamp_index = headers
+        .iter()
+        .position(|header| header.trim() == TIMESTAMP_COLUMN)
+        .ok_or_else(|| anyhow::anyhow!("input has no {} column", TIMESTAMP_COLUMN))?;
+
+    let timestamp_parser = TimestampParser::of(processing_options);
+    let mut rows = Vec::<(i64, serializable_form::Transaction)>::new();
+    for record in reader.records() {
+        let record = processing_options.type_aliases.apply(record?, type_index);
+        let timestamp = timestamp_parser
+            .parse_seconds(record.get(timestamp_index).unwrap_or_default())
+            .map_err(|error| {
+                anyhow::anyhow!(
+                    "row {} has no valid {} ({})",
+                    record.position().map_or(0, |position| position.line()),
+                    TIMESTAMP_COLUMN,
+                    error
+                )
+            })?;
+        rows.push((timestamp, record.deserialize(Some(&headers))?));
+    }
+    // Stable, so rows with equal timestamps keep their file order
+    rows.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut accounts = Accounts::default();
+    let mut balances = BalancesByDay::new();
+    for (timestamp, transaction) in rows {
+        // Outcomes are in the debug log; a rejected row just leaves the balance as it was
+        let _ = process_transaction_isolated(
+            &mut accounts,
+            &transaction,
+            processing_options,
+            debug_logger,
+        );
+        if let Some(client_account) = accounts.get(&transaction.client_id) {
+            balances.entry(transaction.client_id).or_default().insert(
+                timestamp.div_euclid(SECONDS_PER_DAY),
+                EndOfDayBalance::of(client_account),
+            );
+        }
+    }
+    Ok(balances)
+}
+
+#[derive(Debug, Serialize)]
+struct BalanceHistoryRow {
+    client: ClientId,
+    date: String,
+    available: String,
+    held: String,
+    locked: bool,
+}
+
+/// Writes one row per client per day, from the client's first day to the last day of the batch,
+/// carrying balances forward over the days on which the client had no rows.
+pub fn write_balance_history(
+    balances: &BalancesByDay,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let last_day = balances
+        .values()
+        .filter_map(|days| days.keys().next_back())
+        .max()
+        .copied();
+
+    let mut csv_writer = csv::Writer::from_writer(output_stream);
+    for (client_id, days) in balances {
+        let (Some((&first_day, _)), Some(last_day)) = (days.iter().next(), last_day) else {
+            continue;
+        };
+        let mut balance = None;
+        for day in first_day..=last_day {
+            if let Some(end_of_day) = days.get(&day) {
+                balance = Some(*end_of_day);
+            }
+            if let Some(balance) = balance {
+                csv_writer.serialize(BalanceHistoryRow {
+                    client: *client_id,
+                    date: Date::from_days_since_epoch(day).to_string(),
+                    available: round_f64_4dp_string(balance.available)?,
+                    held: round_f64_4dp_string(balance.held)?,
+                    locked: balance.locked,
+                })?;
+            }
+        }
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_balance_history_carries_balances_forward() {
+        let balance = |available| EndOfDayBalance {
+            available,
+            held: 0.0,
+            locked: false,
+        };
+        let mut balances = BalancesByDay::new();
+        balances
+            .entry(1)
+            .or_default()
+            .extend([(19_000, balance(1.0)), (19_002, balance(3.0))]);
+        balances.entry(2).or_default().insert(19_001, balance(5.0));
+
+        let mut output = Vec::<u8>::new();
+        write_balance_history(&balances, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"client,date,available,held,locked
+1,2022-01-08,1.0000,0.0000,false
+1,2022-01-09,1.0000,0.0000,false
+1,2022-01-10,3.0000,0.0000,false
+2,2022-01-09,5.0000,0.0000,false
+2,2022-01-10,5.0000,0.0000,false
+"#
+        );
+    }
+}
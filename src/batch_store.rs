@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+use crate::output_options::BoolFormat;
+use crate::serializable_form;
+use crate::summary::Summary;
+
+const ACCOUNTS_FILE: &str = "accounts.csv";
+const SUMMARY_FILE: &str = "summary.json";
+
+/// A directory keeping each batch's output and summary, in a subdirectory named by batch id, so
+/// later runs can refer back to the balances as of a batch.
+#[derive(Debug, Clone)]
+pub struct BatchStore {
+    pub directory: PathBuf,
+}
+
+impl BatchStore {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    pub fn batch_directory(&self, batch_id: &str) -> PathBuf {
+        self.directory.join(batch_id)
+    }
+
+    /// The ids of the stored batches, numeric ids first in numeric order, then the others
+    /// in name order
+    pub fn batch_ids(&self) -> anyhow::Result<Vec<String>> {
+        if !self.directory.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut batch_ids = Vec::new();
+        for entry in std::fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                batch_ids.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        batch_ids.sort_by(|a, b| match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+            (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+            (Err(_), Err(_)) => a.cmp(b),
+        });
+        Ok(batch_ids)
+    }
+
+    /// One more than the highest numeric batch id stored, or 1 for an empty store
+    pub fn next_batch_id(&self) -> anyhow::Result<String> {
+        let highest = self
+            .batch_ids()?
+            .iter()
+            .filter_map(|batch_id| batch_id.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0);
+        Ok((highest + 1).to_string())
+    }
+
+    /// Stores a batch's output (in client id order) and summary. A stored batch is never
+    /// overwritten.
+    pub fn write_batch(
+        &self,
+        batch_id: &str,
+        output: &[serializable_form::Output],
+        summary: &Summary,
+    ) -> anyhow::Result<PathBuf> {
+        let batch_directory = self.batch_directory(batch_id);
+        if batch_directory.exists() {
+            anyhow::bail!("batch {} is already in the batch store", batch_id);
+        }
+        std::fs::create_dir_all(&batch_directory)?;
+
+        let mut rows: Vec<&serializable_form::Output> = output.iter().collect();
+        rows.sort_by_key(|output| output.client);
+        let mut csv_writer = csv::Writer::from_path(batch_directory.join(ACCOUNTS_FILE))?;
+        for output in rows {
+            csv_writer.serialize(output.row(BoolFormat::default()))?;
+        }
+        csv_writer.flush()?;
+
+        let mut summary_writer = std::fs::File::create(batch_directory.join(SUMMARY_FILE))?;
+        summary.write_json(&mut summary_writer)?;
+        Ok(batch_directory)
+    }
+
+    /// Copies the balances as of a stored batch to the output stream
+    pub fn write_balances(
+        &self,
+        batch_id: &str,
+        output_stream: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let accounts_file = self.batch_directory(batch_id).join(ACCOUNTS_FILE);
+        let mut accounts = std::fs::File::open(&accounts_file)
+            .map_err(|_| anyhow::anyhow!("batch {} is not in the batch store", batch_id))?;
+        std::io::copy(&mut accounts, output_stream)?;
+        Ok(())
+    }
+}
+
+/// Whether `batch_id` can name a batch store subdirectory
+pub fn is_valid_batch_id(batch_id: &str) -> bool {
+    !batch_id.is_empty()
+        && batch_id != "."
+        && batch_id != ".."
+        && !batch_id.contains(['/', '\\'])
+        && Path::new(batch_id).components().count() == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::Accounts;
+    use crate::ClientAccount;
+
+    #[test]
+    fn test_batch_store_numbers_and_keeps_batches() {
+        let store = BatchStore::new(std::env::temp_dir().join("rs_bpt-test_batch_store"));
+        let _ = std::fs::remove_dir_all(&store.directory);
+        assert_eq!(store.next_batch_id().unwrap(), "1");
+
+        let mut accounts = Accounts::default();
+        for client_id in [2, 1] {
+            let mut client_account = ClientAccount::new(client_id);
+            client_account.balance.available = f64::from(client_id);
+            accounts.insert(client_id, client_account);
+        }
+        let output = accounts
+            .values()
+            .map(serializable_form::Output::from_client_account)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let summary = Summary::from_accounts(&accounts).unwrap();
+
+        store.write_batch("1", &output, &summary).unwrap();
+        store.write_batch("month-end", &output, &summary).unwrap();
+        assert!(store.write_batch("1", &output, &summary).is_err());
+        assert_eq!(store.batch_ids().unwrap(), vec!["1", "month-end"]);
+        assert_eq!(store.next_batch_id().unwrap(), "2");
+
+        let mut balances = Vec::<u8>::new();
+        store.write_balances("1", &mut balances).unwrap();
+        assert_eq!(
+            String::from_utf8(balances).unwrap(),
+            "client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n2,2.0000,0.0000,2.0000,false\n"
+        );
+        assert!(store.write_balances("3", &mut Vec::<u8>::new()).is_err());
+
+        std::fs::remove_dir_all(&store.directory).unwrap();
+    }
+
+    #[test]
+    fn test_is_valid_batch_id() {
+        assert!(is_valid_batch_id("2022-03"));
+        assert!(!is_valid_batch_id(".."));
+        assert!(!is_valid_batch_id("a/b"));
+        assert!(!is_valid_batch_id(""));
+    }
+}
@@ -0,0 +1,194 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::hashing::Accounts;
+use crate::process_transactions_file;
+use crate::processing_options::ProcessingOptions;
+
+/// The name of the benchmark of the core processing loop
+pub const PROCESSING_BENCHMARK: &str = "process_transactions_file";
+
+/// A benchmark's measurement, as written by `bench` and read by `bench-compare`
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct BenchResult {
+    /// Mean time per iteration, in nanoseconds
+    pub mean_ns: f64,
+    /// Rows processed per second, where the benchmark processes rows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throughput: Option<f64>,
+}
+
+/// The results of a bench run by benchmark name, read from or written as a JSON object, e.g.
+/// `{"process_transactions_file": {"mean_ns": 1250000.0, "throughput": 800000.0}}`
+pub type BenchResults = BTreeMap<String, BenchResult>;
+
+/// Times processing the input file `iterations` times, each into fresh accounts.
+pub fn run_bench(
+    input_transactions_file: &Path,
+    iterations: usize,
+    processing_options: &ProcessingOptions,
+) -> Result<BenchResults, Box<dyn std::error::Error>> {
+    let rows = csv::Reader::from_path(input_transactions_file)?
+        .records()
+        .count();
+    let iterations = iterations.max(1);
+
+    let started_at = Instant::now();
+    for _ in 0..iterations {
+        let mut accounts = Accounts::default();
+        process_transactions_file(
+            &mut accounts,
+            input_transactions_file.to_path_buf(),
+            processing_options,
+            &mut [],
+            &mut [],
+            &mut std::io::sink(),
+        )?;
+    }
+    let elapsed = started_at.elapsed();
+
+    let mean_ns = elapsed.as_nanos() as f64 / iterations as f64;
+    let mut results = BenchResults::new();
+    results.insert(
+        PROCESSING_BENCHMARK.to_string(),
+        BenchResult {
+            mean_ns,
+            throughput: (mean_ns > 0.0).then_some(rows as f64 * 1e9 / mean_ns),
+        },
+    );
+    Ok(results)
+}
+
+pub fn read_results(file: &Path) -> anyhow::Result<BenchResults> {
+    Ok(serde_json::from_reader(std::fs::File::open(file)?)?)
+}
+
+pub fn write_results(
+    results: &BenchResults,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    serde_json::to_writer_pretty(&mut *output_stream, results)?;
+    writeln!(output_stream)?;
+    Ok(())
+}
+
+/// How a benchmark's mean time changed between the baseline and current results
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BenchComparison {
+    pub benchmark: String,
+    pub baseline_ns: Option<f64>,
+    pub current_ns: Option<f64>,
+    /// The change of the mean time as a percentage of the baseline's
+    pub change_percent: Option<String>,
+    pub regressed: bool,
+}
+
+/// Compares the mean times of the benchmarks in either results. A benchmark regressed if its
+/// mean time grew by more than `max_regression_percent` of the baseline's. Benchmarks which are
+/// only in one of the results are reported without a change.
+pub fn compare(
+    baseline: &BenchResults,
+    current: &BenchResults,
+    max_regression_percent: f64,
+) -> Vec<BenchComparison> {
+    let mut benchmarks: Vec<&String> = baseline.keys().chain(current.keys()).collect();
+    benchmarks.sort();
+    benchmarks.dedup();
+
+    benchmarks
+        .into_iter()
+        .map(|benchmark| {
+            let baseline_ns = baseline.get(benchmark).map(|result| result.mean_ns);
+            let current_ns = current.get(benchmark).map(|result| result.mean_ns);
+            let change_percent = match (baseline_ns, current_ns) {
+                (Some(baseline_ns), Some(current_ns)) if baseline_ns > 0.0 => {
+                    Some((current_ns - baseline_ns) / baseline_ns * 100.0)
+                }
+                _ => None,
+            };
+            BenchComparison {
+                benchmark: benchmark.clone(),
+                baseline_ns,
+                current_ns,
+                change_percent: change_percent
+                    .map(|change_percent| format!("{:.2}", change_percent)),
+                regressed: change_percent
+                    .is_some_and(|change_percent| change_percent > max_regression_percent),
+            }
+        })
+        .collect()
+}
+
+pub fn write_report(
+    comparisons: &[BenchComparison],
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(output_stream);
+    for comparison in comparisons {
+        writer.serialize(comparison)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(mean_ns: &[(&str, f64)]) -> BenchResults {
+        mean_ns
+            .iter()
+            .map(|(benchmark, mean_ns)| {
+                (
+                    benchmark.to_string(),
+                    BenchResult {
+                        mean_ns: *mean_ns,
+                        throughput: None,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_flags_regressions_over_the_threshold() {
+        let baseline = results(&[("a", 1000.0), ("b", 1000.0), ("c", 1000.0), ("old", 10.0)]);
+        let current = results(&[("a", 1049.0), ("b", 1051.0), ("c", 500.0), ("new", 10.0)]);
+
+        let comparisons = compare(&baseline, &current, 5.0);
+        let summary: Vec<(&str, Option<&str>, bool)> = comparisons
+            .iter()
+            .map(|comparison| {
+                (
+                    comparison.benchmark.as_str(),
+                    comparison.change_percent.as_deref(),
+                    comparison.regressed,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a", Some("4.90"), false),
+                ("b", Some("5.10"), true),
+                ("c", Some("-50.00"), false),
+                ("new", None, false),
+                ("old", None, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reads_the_results_it_writes() {
+        let file = std::env::temp_dir().join("rs_bpt-bench-it_reads_the_results_it_writes.json");
+        let mut written = results(&[("a", 1000.0)]);
+        written.get_mut("a").unwrap().throughput = Some(9.5);
+        write_results(&written, &mut std::fs::File::create(&file).unwrap()).unwrap();
+
+        let read = read_results(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(read, written);
+    }
+}
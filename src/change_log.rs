@@ -0,0 +1,52 @@
+use serde_derive::Serialize;
+
+use crate::client_account::applied_transaction::AppliedTransaction;
+use crate::journal::{JournalEntry, JournalSink};
+use crate::serializable_form::round_f64_4dp_string;
+use crate::{ClientId, TransactionId, TransactionType};
+
+#[derive(Debug, Serialize)]
+struct SerializableAccountUpdate {
+    client: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+    tx: TransactionId,
+    reason: TransactionType,
+}
+
+/// Appends a row with the client's new balances for every applied transaction, flushing each one
+/// so downstream consumers see balance changes while the batch is still running.
+pub struct ChangeLogWriter<W: std::io::Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: std::io::Write> ChangeLogWriter<W> {
+    pub fn new(output_stream: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(output_stream),
+        }
+    }
+}
+
+impl<W: std::io::Write> JournalSink for ChangeLogWriter<W> {
+    fn record(&mut self, _entry: &JournalEntry) -> anyhow::Result<()> {
+        anyhow::bail!("the change log needs the balances after the transaction")
+    }
+
+    fn record_applied(&mut self, applied_transaction: &AppliedTransaction) -> anyhow::Result<()> {
+        let balance = applied_transaction.balance;
+        self.writer.serialize(SerializableAccountUpdate {
+            client: applied_transaction.journal_entry.client_id,
+            available: round_f64_4dp_string(balance.available)?,
+            held: round_f64_4dp_string(balance.held)?,
+            total: round_f64_4dp_string(balance.total())?,
+            locked: applied_transaction.locked,
+            tx: applied_transaction.journal_entry.transaction_id,
+            reason: applied_transaction.journal_entry.transaction_type,
+        })?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
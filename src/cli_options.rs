@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+
+use crate::batch_store::BatchStore;
+use crate::compression::Compression;
+use crate::dispute_cases::DisputeCasesOptions;
+use crate::error_report::ErrorReportOptions;
+use crate::groups::GroupOptions;
+use crate::output_options::OutputOptions;
+use crate::output_parts::OutputPartsOptions;
+#[cfg(feature = "plaintext-accounting")]
+use crate::plaintext_accounting::PlaintextAccountingOptions;
+use crate::processing_options::ProcessingOptions;
+use crate::settlement::SettlementOptions;
+use crate::statement::StatementOptions;
+use crate::suspense::SuspenseOptions;
+
+/// The input path which reads the transactions from stdin.
+pub const STDIN_INPUT: &str = "-";
+
+#[derive(Debug, Default, Clone)]
+pub struct CliOptions {
+    pub processing_options: ProcessingOptions,
+
+    /// Scan the input first to pre-size the account maps
+    pub presize: bool,
+
+    /// Process the input in this many shards by client id, each on its own thread (one if 0 or 1)
+    pub threads: usize,
+
+    pub output_options: OutputOptions,
+
+    /// Write the output as numbered part files instead of to the output stream
+    pub output_parts: Option<OutputPartsOptions>,
+
+    /// Write balances aggregated per client group
+    pub groups: Option<GroupOptions>,
+
+    /// Write a JSON summary of all accounts to this file
+    pub summary_file: Option<PathBuf>,
+
+    /// Fail the run if the accounts' balances don't add up to the transactions applied
+    pub verify: bool,
+
+    /// Write a double-entry journal of every applied transaction to this file
+    pub journal_file: Option<PathBuf>,
+
+    /// Append each account's new balances to this file as transactions are applied
+    pub change_log_file: Option<PathBuf>,
+
+    /// Compression of the output stream, journal and change log
+    pub compression: Compression,
+
+    /// Write the applied transactions as Beancount or ledger-cli entries
+    #[cfg(feature = "plaintext-accounting")]
+    pub plaintext_accounting: Option<PlaintextAccountingOptions>,
+
+    /// Write the output as an Arrow IPC (Feather v2) file
+    #[cfg(feature = "arrow")]
+    pub arrow_file: Option<PathBuf>,
+
+    /// Write each client's gross deposits, withdrawals and net movement to this CSV file
+    pub netting_file: Option<PathBuf>,
+
+    /// Write a settlement instruction for each client whose net movement exceeds a threshold
+    pub settlement: Option<SettlementOptions>,
+
+    /// Write a QIF or OFX statement per client
+    pub statements: Option<StatementOptions>,
+
+    /// Report rejected deposits and withdrawals as suspense items
+    pub suspense: Option<SuspenseOptions>,
+
+    /// Write every rejected transaction, with its input line and error, to a CSV or JSON report
+    pub errors: Option<ErrorReportOptions>,
+
+    /// Write rejected transactions which may succeed in a later run to this file, in the input format
+    pub retry_file: Option<PathBuf>,
+
+    /// Write the transactions parked for review to this file, in the input format
+    pub review_file: Option<PathBuf>,
+
+    /// Write the rows dead-lettered for unknown types to this file, as read
+    pub dead_letter_file: Option<PathBuf>,
+
+    /// Import open dispute cases from an earlier run, and/or write this run's dispute cases
+    pub dispute_cases: Option<DisputeCasesOptions>,
+
+    /// Write the clients whose processing errored (and were left out of the output) to this CSV file
+    pub errored_clients_file: Option<PathBuf>,
+
+    /// If processing is aborted, write the accounts as they were then to this file
+    pub partial_snapshot_file: Option<PathBuf>,
+
+    /// Start from the account state in this snapshot, written by an earlier run's `snapshot_out_file`
+    pub snapshot_in_file: Option<PathBuf>,
+
+    /// Write the account state at the end of the run to this snapshot, for a later run to start from
+    pub snapshot_out_file: Option<PathBuf>,
+
+    /// Write only each client's balance changes and new locks against `snapshot_in_file` instead
+    /// of the output, leaving the state as it was
+    pub dry_run: bool,
+
+    /// Tag the run with this batch id (in the summary and manifest)
+    pub batch_id: Option<String>,
+
+    /// Keep the run's output and summary in this batch store, under its batch id (by default the
+    /// next number)
+    pub batch_store: Option<BatchStore>,
+
+    /// Write the output's control totals and checksum to this JSON file
+    pub control_totals_file: Option<PathBuf>,
+
+    /// Write a JSON manifest of the run (versions, config, timings, input and output digests)
+    pub manifest_file: Option<PathBuf>,
+}
+
+impl CliOptions {
+    /// Whether an exporter needs all journal entries in memory at the end of the run
+    pub fn needs_journal_entries(&self) -> bool {
+        #[cfg(feature = "plaintext-accounting")]
+        if self.plaintext_accounting.is_some() {
+            return true;
+        }
+
+        self.statements.is_some()
+    }
+
+    /// Whether the output rows are needed all at once (for a batch store, the control totals or a
+    /// template), rather than written as each account is formatted
+    pub fn needs_output_rows(&self) -> bool {
+        #[cfg(feature = "output-template")]
+        if self.output_options.template_file.is_some() {
+            return true;
+        }
+
+        self.batch_store.is_some() || self.control_totals_file.is_some()
+    }
+
+    /// The files read: the transactions file, any dispute cases imported from an earlier run with
+    /// their arbitration outcomes, any client notes and any account state snapshot
+    pub fn input_paths(&self, input_file: &Path) -> Vec<PathBuf> {
+        let mut input_paths = Vec::new();
+        // stdin can't be read again to be digested
+        if input_file != Path::new(STDIN_INPUT) {
+            input_paths.push(input_file.to_path_buf());
+        }
+        input_paths.extend(
+            self.dispute_cases
+                .as_ref()
+                .and_then(|options| options.import_file.clone()),
+        );
+        input_paths.extend(
+            self.dispute_cases
+                .as_ref()
+                .and_then(|options| options.arbitration_file.clone()),
+        );
+        input_paths.extend(self.output_options.client_notes_file.clone());
+        input_paths.extend(self.snapshot_in_file.clone());
+        input_paths
+    }
+
+    /// The files (or, for statements, the directory) written besides the output itself
+    pub fn output_paths(&self) -> Vec<PathBuf> {
+        let mut output_paths = Vec::new();
+        output_paths.extend(
+            self.groups
+                .as_ref()
+                .map(|options| options.output_file.clone()),
+        );
+        output_paths.extend(self.summary_file.clone());
+        output_paths.extend(self.journal_file.clone());
+        output_paths.extend(self.change_log_file.clone());
+        output_paths.extend(self.netting_file.clone());
+        output_paths.extend(self.settlement.as_ref().map(|options| options.file.clone()));
+        #[cfg(feature = "plaintext-accounting")]
+        output_paths.extend(
+            self.plaintext_accounting
+                .as_ref()
+                .map(|options| options.file.clone()),
+        );
+        #[cfg(feature = "arrow")]
+        output_paths.extend(self.arrow_file.clone());
+        output_paths.extend(
+            self.statements
+                .as_ref()
+                .map(|options| options.directory.clone()),
+        );
+        output_paths.extend(self.suspense.as_ref().map(|options| options.file.clone()));
+        output_paths.extend(self.errors.as_ref().map(|options| options.file.clone()));
+        output_paths.extend(self.retry_file.clone());
+        output_paths.extend(self.review_file.clone());
+        output_paths.extend(self.dead_letter_file.clone());
+        output_paths.extend(
+            self.dispute_cases
+                .as_ref()
+                .and_then(|options| options.file.clone()),
+        );
+        output_paths.extend(self.errored_clients_file.clone());
+        output_paths.extend(self.partial_snapshot_file.clone());
+        output_paths.extend(self.snapshot_out_file.clone());
+        output_paths.extend(self.control_totals_file.clone());
+        output_paths.extend(
+            self.output_parts
+                .as_ref()
+                .map(|options| options.manifest_path()),
+        );
+        output_paths
+    }
+}
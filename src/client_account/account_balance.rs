@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct AccountBalance {
     pub available: f64,
     pub held: f64,
@@ -17,4 +19,77 @@ impl AccountBalance {
     pub fn total(&self) -> f64 {
         self.available + self.held
     }
+
+    /// True if available, held and total each differ from `other` by no more than `tolerance`.
+    pub fn approx_eq(&self, other: &AccountBalance, tolerance: f64) -> bool {
+        self.diff(other).within(tolerance)
+    }
+
+    /// The change from this balance to `other` (i.e. `other - self`).
+    pub fn diff(&self, other: &AccountBalance) -> BalanceDelta {
+        BalanceDelta {
+            available: other.available - self.available,
+            held: other.held - self.held,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BalanceDelta {
+    pub available: f64,
+    pub held: f64,
+}
+
+impl BalanceDelta {
+    pub fn total(&self) -> f64 {
+        self.available + self.held
+    }
+
+    pub fn within(&self, tolerance: f64) -> bool {
+        self.available.abs() <= tolerance
+            && self.held.abs() <= tolerance
+            && self.total().abs() <= tolerance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_computes_the_delta_to_another_balance() {
+        let before = AccountBalance {
+            available: 10.0,
+            held: 5.0,
+        };
+        let after = AccountBalance {
+            available: 7.5,
+            held: 7.5,
+        };
+
+        let delta = before.diff(&after);
+        assert_eq!(delta.available, -2.5);
+        assert_eq!(delta.held, 2.5);
+        assert_eq!(delta.total(), 0.0);
+    }
+
+    #[test]
+    fn it_compares_balances_within_a_tolerance() {
+        let a = AccountBalance {
+            available: 0.1 + 0.2,
+            held: 1.0,
+        };
+        let b = AccountBalance {
+            available: 0.3,
+            held: 1.0,
+        };
+        assert!(a.approx_eq(&b, 0.00005));
+        assert!(!a.approx_eq(
+            &AccountBalance {
+                available: 0.3001,
+                held: 1.0
+            },
+            0.00005
+        ));
+    }
 }
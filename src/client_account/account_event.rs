@@ -0,0 +1,13 @@
+use crate::{TransactionId, TransactionType};
+
+/// A transaction or dispute event which was applied to an account, with the balances after it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AccountEvent {
+    pub transaction_id: TransactionId,
+    pub transaction_type: TransactionType,
+    // Follows DisputableTransaction: a deposit is positive and a withdrawal is negative
+    pub amount: f64,
+    pub available: f64,
+    pub held: f64,
+    pub locked: bool,
+}
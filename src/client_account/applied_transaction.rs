@@ -0,0 +1,19 @@
+use crate::journal::JournalEntry;
+
+use super::account_balance::AccountBalance;
+
+/// The result of successfully applying a transaction to an account: its journal entry plus the
+/// account state afterwards, so callers can report the new balance without re-querying.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AppliedTransaction {
+    pub journal_entry: JournalEntry,
+    pub balance: AccountBalance,
+    pub locked: bool,
+    // Positive when funds moved into held (a dispute), negative when released (resolve/chargeback)
+    pub held_change: f64,
+    // The part of a deposit which was not credited because of the client's balance cap
+    pub suspended_excess: Option<f64>,
+    // The other account's side of a transaction between two clients: the credit of a transfer to
+    // its counterparty, or of a closed account's residual balance to the sweep client
+    pub counterparty: Option<Box<AppliedTransaction>>,
+}
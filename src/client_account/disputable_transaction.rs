@@ -1,11 +1,36 @@
+use serde_derive::{Deserialize, Serialize};
+
 use crate::TransactionId;
 
+/// Where a charged back transaction is in the representment (second presentment) flow
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChargebackState {
+    /// Charged back, and may be represented
+    ChargedBack,
+    /// Represented: the funds are held again until the second decision
+    Represented,
+    /// Charged back again after a representment, which is final
+    FinalChargedBack,
+}
+
 // Encodes a deposit as a positive amount and a withdrawal as a negative amount.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DisputableTransaction {
     pub transaction_id: TransactionId,
     pub amount: f64,
     pub is_under_dispute: bool,
+    pub chargeback_state: Option<ChargebackState>,
+    /// How many times the transaction has been disputed in this run
+    pub disputes: usize,
+    /// The case of the current or last dispute
+    pub case_id: Option<String>,
+    /// How many runs the transaction has been carried over into from a state snapshot
+    #[serde(default)]
+    pub runs_carried_over: u32,
+    /// When the transaction was first written to a state snapshot, in seconds since the Unix epoch
+    #[serde(default)]
+    pub retained_since: Option<u64>,
 }
 
 impl DisputableTransaction {
@@ -14,6 +39,11 @@ impl DisputableTransaction {
             transaction_id,
             amount,
             is_under_dispute: false,
+            chargeback_state: None,
+            disputes: 0,
+            case_id: None,
+            runs_carried_over: 0,
+            retained_since: None,
         }
     }
 
@@ -22,6 +52,22 @@ impl DisputableTransaction {
             transaction_id,
             amount: -amount,
             is_under_dispute: false,
+            chargeback_state: None,
+            disputes: 0,
+            case_id: None,
+            runs_carried_over: 0,
+            retained_since: None,
         }
     }
+
+    /// Whether a dispute may still change the transaction's outcome: it is held, or charged back
+    /// and may still be represented
+    pub fn is_open(&self) -> bool {
+        self.is_held() || self.chargeback_state == Some(ChargebackState::ChargedBack)
+    }
+
+    /// Whether the transaction's amount is currently held: while it is under dispute or represented
+    pub fn is_held(&self) -> bool {
+        self.is_under_dispute || self.chargeback_state == Some(ChargebackState::Represented)
+    }
 }
@@ -5,11 +5,15 @@ pub enum DisputeRelatedTransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    Representment,
+    FinalChargeback,
+    RepresentmentWon,
 }
 
 #[derive(Debug)]
 pub struct DisputeRelatedTransaction {
     pub referenced_transaction_id: TransactionId,
+    #[allow(dead_code)]
     pub dispute_related_transaction_type: DisputeRelatedTransactionType,
 }
 
@@ -34,4 +38,25 @@ impl DisputeRelatedTransaction {
             dispute_related_transaction_type: DisputeRelatedTransactionType::Chargeback,
         }
     }
+
+    pub fn new_representment_transaction(referenced_transaction_id: TransactionId) -> Self {
+        Self {
+            referenced_transaction_id,
+            dispute_related_transaction_type: DisputeRelatedTransactionType::Representment,
+        }
+    }
+
+    pub fn new_final_chargeback_transaction(referenced_transaction_id: TransactionId) -> Self {
+        Self {
+            referenced_transaction_id,
+            dispute_related_transaction_type: DisputeRelatedTransactionType::FinalChargeback,
+        }
+    }
+
+    pub fn new_representment_won_transaction(referenced_transaction_id: TransactionId) -> Self {
+        Self {
+            referenced_transaction_id,
+            dispute_related_transaction_type: DisputeRelatedTransactionType::RepresentmentWon,
+        }
+    }
 }
@@ -1,38 +1,178 @@
-use crate::TransactionId;
+use crate::{ClientId, TransactionId};
 
-#[derive(Debug, PartialEq)]
+/// Why a transaction was not applied. Every error names the client and the transaction it is for:
+/// for a dispute op `tx` is the referenced transaction, and for a transfer whose counterparty
+/// can't be credited `client` is the counterparty.
+#[derive(Debug, PartialEq, Clone)]
 pub enum TransactionProcessingError {
-    ReferencedTransactionNotFound(TransactionId),
-    TransactionAlreadyHasPendingDisupte(TransactionId),
-    TransactionDoesNotHavePendingDisupte(TransactionId),
-    TransactionIDAlreadyExists(TransactionId),
-    AmountNotPresentForDeposit(TransactionId),
-    AmountNotPresentForWithdrawal(TransactionId),
+    ReferencedTransactionNotFound { client: ClientId, tx: TransactionId },
+    TransactionAlreadyHasPendingDisupte { client: ClientId, tx: TransactionId },
+    TransactionDoesNotHavePendingDisupte { client: ClientId, tx: TransactionId },
+    TransactionIDAlreadyExists { client: ClientId, tx: TransactionId },
+    AmountNotPresentForDeposit { client: ClientId, tx: TransactionId },
+    AmountNotPresentForWithdrawal { client: ClientId, tx: TransactionId },
+    AmountNotPresentForTransfer { client: ClientId, tx: TransactionId },
+    CounterpartyNotPresentForTransfer { client: ClientId, tx: TransactionId },
+    HeldBalanceUnderflow { client: ClientId, tx: TransactionId },
+    ClientNotFound { client: ClientId, tx: TransactionId },
+    AccountAlreadyOpen { client: ClientId, tx: TransactionId },
+    ClientErrored { client: ClientId, tx: TransactionId },
+    AccountClosed { client: ClientId, tx: TransactionId },
+    AccountHasPendingDisputes { client: ClientId, tx: TransactionId },
+    AccountLocked { client: ClientId, tx: TransactionId },
+    TransactionLimitExceeded { client: ClientId, tx: TransactionId },
+    ClientLimitExceeded { client: ClientId, tx: TransactionId },
+    WithdrawalNotAuthorized { client: ClientId, tx: TransactionId },
+    InvalidAmount { client: ClientId, tx: TransactionId },
+    ParkedForReview { client: ClientId, tx: TransactionId },
+    BalanceCapExceeded { client: ClientId, tx: TransactionId },
+    TransactionNotChargedBack { client: ClientId, tx: TransactionId },
+    TransactionNotRepresented { client: ClientId, tx: TransactionId },
+    ClientMismatchOnDispute { client: ClientId, tx: TransactionId },
 }
 
-impl std::error::Error for TransactionProcessingError {}
-
-impl std::fmt::Display for TransactionProcessingError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl TransactionProcessingError {
+    /// The variant's name, e.g. `ReferencedTransactionNotFound`
+    pub fn name(&self) -> &'static str {
         match self {
-            TransactionProcessingError::ReferencedTransactionNotFound(t) => {
-                write!(f, "ReferencedTransactionNotFound: {}", t)
+            TransactionProcessingError::ReferencedTransactionNotFound { .. } => {
+                "ReferencedTransactionNotFound"
+            }
+            TransactionProcessingError::TransactionAlreadyHasPendingDisupte { .. } => {
+                "TransactionAlreadyHasPendingDisupte"
+            }
+            TransactionProcessingError::TransactionDoesNotHavePendingDisupte { .. } => {
+                "TransactionDoesNotHavePendingDisupte"
+            }
+            TransactionProcessingError::TransactionIDAlreadyExists { .. } => {
+                "TransactionIDAlreadyExists"
             }
-            TransactionProcessingError::TransactionAlreadyHasPendingDisupte(t) => {
-                write!(f, "TransactionAlreadyHasPendingDisupte: {}", t)
+            TransactionProcessingError::AmountNotPresentForDeposit { .. } => {
+                "AmountNotPresentForDeposit"
             }
-            TransactionProcessingError::TransactionDoesNotHavePendingDisupte(t) => {
-                write!(f, "TransactionDoesNotHavePendingDisupte: {}", t)
+            TransactionProcessingError::AmountNotPresentForWithdrawal { .. } => {
+                "AmountNotPresentForWithdrawal"
             }
-            TransactionProcessingError::TransactionIDAlreadyExists(t) => {
-                write!(f, "TransactionIDAlreadyExists: {}", t)
+            TransactionProcessingError::AmountNotPresentForTransfer { .. } => {
+                "AmountNotPresentForTransfer"
             }
-            TransactionProcessingError::AmountNotPresentForDeposit(t) => {
-                write!(f, "AmountNotPresentForDeposit: {}", t)
+            TransactionProcessingError::CounterpartyNotPresentForTransfer { .. } => {
+                "CounterpartyNotPresentForTransfer"
             }
-            TransactionProcessingError::AmountNotPresentForWithdrawal(t) => {
-                write!(f, "AmountNotPresentForWithdrawal: {}", t)
+            TransactionProcessingError::HeldBalanceUnderflow { .. } => "HeldBalanceUnderflow",
+            TransactionProcessingError::ClientNotFound { .. } => "ClientNotFound",
+            TransactionProcessingError::AccountAlreadyOpen { .. } => "AccountAlreadyOpen",
+            TransactionProcessingError::ClientErrored { .. } => "ClientErrored",
+            TransactionProcessingError::AccountClosed { .. } => "AccountClosed",
+            TransactionProcessingError::AccountHasPendingDisputes { .. } => {
+                "AccountHasPendingDisputes"
             }
+            TransactionProcessingError::AccountLocked { .. } => "AccountLocked",
+            TransactionProcessingError::TransactionLimitExceeded { .. } => {
+                "TransactionLimitExceeded"
+            }
+            TransactionProcessingError::ClientLimitExceeded { .. } => "ClientLimitExceeded",
+            TransactionProcessingError::WithdrawalNotAuthorized { .. } => "WithdrawalNotAuthorized",
+            TransactionProcessingError::InvalidAmount { .. } => "InvalidAmount",
+            TransactionProcessingError::ParkedForReview { .. } => "ParkedForReview",
+            TransactionProcessingError::BalanceCapExceeded { .. } => "BalanceCapExceeded",
+            TransactionProcessingError::TransactionNotChargedBack { .. } => {
+                "TransactionNotChargedBack"
+            }
+            TransactionProcessingError::TransactionNotRepresented { .. } => {
+                "TransactionNotRepresented"
+            }
+            TransactionProcessingError::ClientMismatchOnDispute { .. } => "ClientMismatchOnDispute",
+        }
+    }
+
+    /// The client the error is for
+    pub fn client(&self) -> ClientId {
+        match self {
+            TransactionProcessingError::ReferencedTransactionNotFound { client, .. }
+            | TransactionProcessingError::TransactionAlreadyHasPendingDisupte { client, .. }
+            | TransactionProcessingError::TransactionDoesNotHavePendingDisupte { client, .. }
+            | TransactionProcessingError::TransactionIDAlreadyExists { client, .. }
+            | TransactionProcessingError::AmountNotPresentForDeposit { client, .. }
+            | TransactionProcessingError::AmountNotPresentForWithdrawal { client, .. }
+            | TransactionProcessingError::AmountNotPresentForTransfer { client, .. }
+            | TransactionProcessingError::CounterpartyNotPresentForTransfer { client, .. }
+            | TransactionProcessingError::HeldBalanceUnderflow { client, .. }
+            | TransactionProcessingError::ClientNotFound { client, .. }
+            | TransactionProcessingError::AccountAlreadyOpen { client, .. }
+            | TransactionProcessingError::ClientErrored { client, .. }
+            | TransactionProcessingError::AccountClosed { client, .. }
+            | TransactionProcessingError::AccountHasPendingDisputes { client, .. }
+            | TransactionProcessingError::AccountLocked { client, .. }
+            | TransactionProcessingError::TransactionLimitExceeded { client, .. }
+            | TransactionProcessingError::ClientLimitExceeded { client, .. }
+            | TransactionProcessingError::WithdrawalNotAuthorized { client, .. }
+            | TransactionProcessingError::InvalidAmount { client, .. }
+            | TransactionProcessingError::ParkedForReview { client, .. }
+            | TransactionProcessingError::BalanceCapExceeded { client, .. }
+            | TransactionProcessingError::TransactionNotChargedBack { client, .. }
+            | TransactionProcessingError::TransactionNotRepresented { client, .. }
+            | TransactionProcessingError::ClientMismatchOnDispute { client, .. } => *client,
+        }
+    }
+
+    /// The transaction the error is for
+    pub fn tx(&self) -> TransactionId {
+        match self {
+            TransactionProcessingError::ReferencedTransactionNotFound { tx, .. }
+            | TransactionProcessingError::TransactionAlreadyHasPendingDisupte { tx, .. }
+            | TransactionProcessingError::TransactionDoesNotHavePendingDisupte { tx, .. }
+            | TransactionProcessingError::TransactionIDAlreadyExists { tx, .. }
+            | TransactionProcessingError::AmountNotPresentForDeposit { tx, .. }
+            | TransactionProcessingError::AmountNotPresentForWithdrawal { tx, .. }
+            | TransactionProcessingError::AmountNotPresentForTransfer { tx, .. }
+            | TransactionProcessingError::CounterpartyNotPresentForTransfer { tx, .. }
+            | TransactionProcessingError::HeldBalanceUnderflow { tx, .. }
+            | TransactionProcessingError::ClientNotFound { tx, .. }
+            | TransactionProcessingError::AccountAlreadyOpen { tx, .. }
+            | TransactionProcessingError::ClientErrored { tx, .. }
+            | TransactionProcessingError::AccountClosed { tx, .. }
+            | TransactionProcessingError::AccountHasPendingDisputes { tx, .. }
+            | TransactionProcessingError::AccountLocked { tx, .. }
+            | TransactionProcessingError::TransactionLimitExceeded { tx, .. }
+            | TransactionProcessingError::ClientLimitExceeded { tx, .. }
+            | TransactionProcessingError::WithdrawalNotAuthorized { tx, .. }
+            | TransactionProcessingError::InvalidAmount { tx, .. }
+            | TransactionProcessingError::ParkedForReview { tx, .. }
+            | TransactionProcessingError::BalanceCapExceeded { tx, .. }
+            | TransactionProcessingError::TransactionNotChargedBack { tx, .. }
+            | TransactionProcessingError::TransactionNotRepresented { tx, .. }
+            | TransactionProcessingError::ClientMismatchOnDispute { tx, .. } => *tx,
+        }
+    }
+
+    // Whether the error is about the client's account rather than the transaction itself
+    fn is_about_client(&self) -> bool {
+        matches!(
+            self,
+            TransactionProcessingError::ClientNotFound { .. }
+                | TransactionProcessingError::AccountAlreadyOpen { .. }
+                | TransactionProcessingError::ClientErrored { .. }
+                | TransactionProcessingError::AccountClosed { .. }
+                | TransactionProcessingError::AccountHasPendingDisputes { .. }
+                | TransactionProcessingError::AccountLocked { .. }
+                | TransactionProcessingError::TransactionLimitExceeded { .. }
+                | TransactionProcessingError::ClientLimitExceeded { .. }
+        )
+    }
+}
+
+impl std::error::Error for TransactionProcessingError {}
+
+// `thiserror` isn't among the dependencies, so Display is written out. It names the one id the
+// error is about, as the error report and debug log formats expect; both are in `client()` and
+// `tx()`.
+impl std::fmt::Display for TransactionProcessingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_about_client() {
+            write!(f, "{}: {}", self.name(), self.client())
+        } else {
+            write!(f, "{}: {}", self.name(), self.tx())
         }
     }
 }
@@ -0,0 +1,101 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Typed metadata attached to an account by integrators (e.g. a KYC tier or region), holding at
+/// most one value of each type. Processing never reads or changes it.
+#[derive(Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, (&'static str, Box<dyn Any + Send + Sync>)>,
+}
+
+impl Extensions {
+    /// Inserts a value, returning the previous value of the same type
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(
+                TypeId::of::<T>(),
+                (std::any::type_name::<T>(), Box::new(value)),
+            )
+            .and_then(|(_, previous)| previous.downcast().ok().map(|previous| *previous))
+    }
+
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|(_, value)| value.downcast_ref())
+    }
+
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.values
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|(_, value)| value.downcast_mut())
+    }
+
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.values
+            .remove(&TypeId::of::<T>())
+            .and_then(|(_, value)| value.downcast().ok().map(|value| *value))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Adds the other extensions' values of types which these extensions don't have
+    pub fn merge_from(&mut self, other: Extensions) {
+        for (type_id, value) in other.values {
+            self.values.entry(type_id).or_insert(value);
+        }
+    }
+}
+
+impl std::fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut type_names: Vec<&str> = self.values.values().map(|(name, _)| *name).collect();
+        type_names.sort_unstable();
+        f.debug_set().entries(type_names).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct KycTier(u8);
+
+    #[derive(Debug, PartialEq)]
+    struct Region(&'static str);
+
+    #[test]
+    fn it_holds_one_value_per_type() {
+        let mut extensions = Extensions::default();
+        assert!(extensions.is_empty());
+
+        assert_eq!(extensions.insert(KycTier(1)), None);
+        assert_eq!(extensions.insert(Region("eu")), None);
+        assert_eq!(extensions.insert(KycTier(2)), Some(KycTier(1)));
+        assert_eq!(extensions.get::<KycTier>(), Some(&KycTier(2)));
+
+        extensions.get_mut::<Region>().unwrap().0 = "us";
+        assert_eq!(extensions.remove::<Region>(), Some(Region("us")));
+        assert_eq!(extensions.get::<Region>(), None);
+        assert_eq!(
+            format!("{:?}", extensions),
+            format!("{{{:?}}}", std::any::type_name::<KycTier>())
+        );
+    }
+
+    #[test]
+    fn merging_keeps_existing_values() {
+        let mut extensions = Extensions::default();
+        extensions.insert(KycTier(1));
+        let mut other = Extensions::default();
+        other.insert(KycTier(3));
+        other.insert(Region("eu"));
+
+        extensions.merge_from(other);
+        assert_eq!(extensions.get::<KycTier>(), Some(&KycTier(1)));
+        assert_eq!(extensions.get::<Region>(), Some(&Region("eu")));
+    }
+}
@@ -0,0 +1,12 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::TransactionId;
+
+/// Why an account was locked: the chargeback which locked it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct LockReason {
+    /// The transaction which was charged back
+    pub transaction_id: TransactionId,
+    /// The dispute case which ended in the chargeback, if known
+    pub case_id: Option<String>,
+}
@@ -1,9 +1,17 @@
-use std::collections::{hash_map, HashMap};
-
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{hash_map, BTreeSet, VecDeque};
+
+use crate::compaction::ArchivedTransaction;
+use crate::dispute_cases;
+use crate::hashing::HashMap;
+use crate::journal::JournalEntry;
+use crate::processing_options::{
+    BalanceCapPolicy, HeldBalanceUnderflowPolicy, LockedAccountPolicy, ProcessingOptions,
+};
 use crate::{ClientId, TransactionId, TransactionType};
 
 mod disputable_transaction;
-use disputable_transaction::DisputableTransaction;
+use disputable_transaction::{ChargebackState, DisputableTransaction};
 
 mod dispute_related_transaction;
 use dispute_related_transaction::DisputeRelatedTransaction;
@@ -17,191 +25,916 @@ use client_account_transaction::ClientAccountTransaction;
 pub mod account_balance;
 use account_balance::AccountBalance;
 
-#[derive(Debug)]
+pub mod account_event;
+use account_event::AccountEvent;
+
+pub mod applied_transaction;
+use applied_transaction::AppliedTransaction;
+
+pub mod extensions;
+use extensions::Extensions;
+
+pub mod lock_reason;
+use lock_reason::LockReason;
+
+// Differences in held smaller than half of the smallest output unit are rounding noise
+const HELD_BALANCE_TOLERANCE: f64 = 0.00005;
+// Likewise for how far a deposit goes past a balance cap
+const BALANCE_CAP_TOLERANCE: f64 = 0.00005;
+
+/// A client's account. Serialized (without its events and extensions) to carry it over between
+/// runs in a state snapshot.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ClientAccount {
     pub client_id: ClientId,
     disputable_transactions: HashMap<TransactionId, DisputableTransaction>,
     pub balance: AccountBalance,
     pub locked: bool,
+    /// Why the account was locked, if it is
+    pub lock_reason: Option<LockReason>,
+    /// How many of the account's transactions were charged back. A final chargeback after a
+    /// representment confirms an earlier chargeback, so isn't counted again.
+    pub chargebacks: usize,
+    /// The total amount of those chargebacks
+    pub charged_back: f64,
+    /// Closed for having more chargebacks than `ProcessingOptions::max_chargebacks`. A closed
+    /// account rejects new deposits and withdrawals, but its disputes can still run their course.
+    pub closed: bool,
+    /// Closed by a `close_account` transaction, which swept out its residual balance. Unlike an
+    /// account closed for its chargebacks, it rejects every further transaction.
+    #[serde(default)]
+    pub closed_by_request: bool,
+    /// The ids of the settled transactions forgotten past
+    /// `ProcessingOptions::dispute_retention_runs`, kept so that a backfill still knows them
+    #[serde(default)]
+    forgotten_transaction_ids: BTreeSet<TransactionId>,
+    #[serde(skip)]
+    events: VecDeque<AccountEvent>,
+    errored: Option<String>,
+    /// Metadata attached by integrators, e.g. for risk scoring
+    #[serde(skip)]
+    pub extensions: Extensions,
 }
 
 impl ClientAccount {
     pub fn new(client_id: ClientId) -> Self {
+        Self::with_capacity(client_id, 0)
+    }
+
+    /// Creates an account with room for `capacity` transactions before its transaction map rehashes
+    pub fn with_capacity(client_id: ClientId, capacity: usize) -> Self {
         Self {
             client_id,
-            disputable_transactions: HashMap::new(),
+            disputable_transactions: HashMap::with_capacity_and_hasher(
+                capacity,
+                Default::default(),
+            ),
             balance: AccountBalance::default(),
             locked: false,
+            lock_reason: None,
+            chargebacks: 0,
+            charged_back: 0.0,
+            closed: false,
+            closed_by_request: false,
+            forgotten_transaction_ids: BTreeSet::new(),
+            events: VecDeque::new(),
+            errored: None,
+            extensions: Extensions::default(),
+        }
+    }
+
+    /// The most recent events applied to this account, oldest first.
+    /// Only recorded when `ProcessingOptions::account_event_capacity` is non-zero.
+    pub fn events(&self) -> impl Iterator<Item = &AccountEvent> {
+        self.events.iter()
+    }
+
+    /// Returns the (sorted) transaction ids held by both this account and `other`.
+    pub fn conflicting_transaction_ids(&self, other: &ClientAccount) -> Vec<TransactionId> {
+        let mut transaction_ids: Vec<TransactionId> = other
+            .disputable_transactions
+            .keys()
+            .filter(|transaction_id| self.disputable_transactions.contains_key(transaction_id))
+            .copied()
+            .collect();
+        transaction_ids.sort_unstable();
+        transaction_ids
+    }
+
+    /// Moves another account's balances and transaction history into this one. Callers should check
+    /// `conflicting_transaction_ids` first; on a conflict the other account's transaction wins.
+    /// Extensions this account already has a value of the type of are kept, and so is this
+    /// account's lock reason.
+    pub fn merge_from(&mut self, other: ClientAccount) {
+        self.balance.available += other.balance.available;
+        self.balance.held += other.balance.held;
+        self.locked |= other.locked;
+        if self.lock_reason.is_none() {
+            self.lock_reason = other.lock_reason;
+        }
+        self.chargebacks += other.chargebacks;
+        self.charged_back += other.charged_back;
+        self.closed |= other.closed;
+        self.closed_by_request |= other.closed_by_request;
+        self.forgotten_transaction_ids
+            .extend(other.forgotten_transaction_ids);
+        self.disputable_transactions
+            .extend(other.disputable_transactions);
+        self.extensions.merge_from(other.extensions);
+    }
+
+    fn record_event(&mut self, journal_entry: &JournalEntry, amount: f64, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        while self.events.len() >= capacity {
+            self.events.pop_front();
         }
+        self.events.push_back(AccountEvent {
+            transaction_id: journal_entry.transaction_id,
+            transaction_type: journal_entry.transaction_type,
+            amount,
+            available: self.balance.available,
+            held: self.balance.held,
+            locked: self.locked,
+        });
+    }
+
+    /// Why processing this account failed unexpectedly (e.g. panicked), if it did.
+    /// An errored account's state can't be trusted and it rejects all further transactions.
+    pub fn errored(&self) -> Option<&str> {
+        self.errored.as_deref()
+    }
+
+    pub fn mark_errored(&mut self, reason: String) {
+        self.errored = Some(reason);
+    }
+
+    /// Credits the residual balance swept out of another client's closed account
+    pub fn credit_sweep(&mut self, amount: f64) {
+        self.balance.available += amount;
+    }
+
+    /// Takes back the client's side of a transfer whose counterparty's side was rejected, so that
+    /// the transfer is applied to both accounts or neither
+    pub fn revert_transfer(&mut self, transaction_id: TransactionId) {
+        if let Some(transfer) = self.disputable_transactions.remove(&transaction_id) {
+            self.balance.available -= transfer.amount;
+            if self
+                .events
+                .back()
+                .is_some_and(|event| event.transaction_id == transaction_id)
+            {
+                self.events.pop_back();
+            }
+        }
+    }
+
+    /// Adds a transaction which was disputed in an earlier run and is still under dispute, with
+    /// its amount held, so that it can be resolved or charged back in this run.
+    pub fn import_open_dispute(
+        &mut self,
+        transaction_id: TransactionId,
+        amount: f64,
+        case_id: &str,
+    ) -> Result<(), TransactionProcessingError> {
+        match self.disputable_transactions.entry(transaction_id) {
+            hash_map::Entry::Occupied(_) => {
+                Err(TransactionProcessingError::TransactionIDAlreadyExists {
+                    client: self.client_id,
+                    tx: transaction_id,
+                })
+            }
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(DisputableTransaction {
+                    transaction_id,
+                    amount,
+                    is_under_dispute: true,
+                    chargeback_state: None,
+                    disputes: 0,
+                    case_id: Some(case_id.to_string()),
+                    runs_carried_over: 0,
+                    retained_since: None,
+                });
+                self.balance.held += amount;
+                Ok(())
+            }
+        }
+    }
+
+    /// Carries the account over from a state snapshot into a new run. Each retained transaction
+    /// counts the run, and once one has been carried over into more than `dispute_retention_runs`
+    /// runs it is forgotten if settled, so disputes can no longer reach it and its id is no longer
+    /// checked for duplicates. Only its id is kept, for a backfill. Open disputes and chargebacks
+    /// which may be represented are kept. Returns how many transactions were forgotten.
+    pub fn carry_over(&mut self, dispute_retention_runs: Option<u32>) -> usize {
+        for disputable_transaction in self.disputable_transactions.values_mut() {
+            disputable_transaction.runs_carried_over += 1;
+        }
+        let Some(dispute_retention_runs) = dispute_retention_runs else {
+            return 0;
+        };
+        self.forget_settled(|disputable_transaction| {
+            disputable_transaction.runs_carried_over > dispute_retention_runs
+        })
+        .len()
+    }
+
+    /// Sets when the retained transactions not yet stamped were first written to a state snapshot
+    pub fn stamp_retained_since(&mut self, saved_at: u64) {
+        for disputable_transaction in self.disputable_transactions.values_mut() {
+            disputable_transaction
+                .retained_since
+                .get_or_insert(saved_at);
+        }
+    }
+
+    /// Forgets the settled transactions retained since before `retained_before` (in seconds since
+    /// the Unix epoch), as `carry_over` forgets them past the dispute retention, and returns them
+    /// for the archive in transaction id order. Unstamped transactions are kept.
+    pub fn compact(&mut self, retained_before: u64) -> Vec<ArchivedTransaction> {
+        let mut archived: Vec<ArchivedTransaction> = self
+            .forget_settled(|disputable_transaction| {
+                disputable_transaction
+                    .retained_since
+                    .is_some_and(|retained_since| retained_since < retained_before)
+            })
+            .into_iter()
+            .map(|disputable_transaction| ArchivedTransaction {
+                client_id: self.client_id,
+                transaction_id: disputable_transaction.transaction_id,
+                amount: disputable_transaction.amount,
+                charged_back: disputable_transaction.chargeback_state.is_some(),
+                retained_since: disputable_transaction.retained_since.unwrap_or_default(),
+            })
+            .collect();
+        archived.sort_unstable_by_key(|archived_transaction| archived_transaction.transaction_id);
+        archived
+    }
+
+    /// Removes the settled retained transactions which `is_expired`, keeping only their ids
+    fn forget_settled(
+        &mut self,
+        is_expired: impl Fn(&DisputableTransaction) -> bool,
+    ) -> Vec<DisputableTransaction> {
+        let expired: Vec<TransactionId> = self
+            .disputable_transactions
+            .values()
+            .filter(|disputable_transaction| {
+                !disputable_transaction.is_open() && is_expired(disputable_transaction)
+            })
+            .map(|disputable_transaction| disputable_transaction.transaction_id)
+            .collect();
+        self.forgotten_transaction_ids.extend(&expired);
+        expired
+            .iter()
+            .filter_map(|transaction_id| self.disputable_transactions.remove(transaction_id))
+            .collect()
+    }
+
+    /// The deposits and withdrawals retained for disputes
+    pub fn retained_transactions(&self) -> usize {
+        self.disputable_transactions.len()
+    }
+
+    /// Whether the account had the deposit or withdrawal with this id in an earlier run, carried
+    /// over from a state snapshot, whether it is still retained or was forgotten since
+    pub fn carries_over_transaction(&self, transaction_id: TransactionId) -> bool {
+        self.forgotten_transaction_ids.contains(&transaction_id)
+            || self
+                .disputable_transactions
+                .get(&transaction_id)
+                .is_some_and(|disputable_transaction| disputable_transaction.runs_carried_over > 0)
+    }
+
+    /// Whether the account retains the deposit or withdrawal with this id
+    pub fn has_transaction(&self, transaction_id: TransactionId) -> bool {
+        self.disputable_transactions.contains_key(&transaction_id)
     }
 
+    /// An account is untouched if no transaction has ever been applied to it
+    pub fn is_untouched(&self) -> bool {
+        self.disputable_transactions.is_empty() && self.forgotten_transaction_ids.is_empty()
+    }
+
+    // A new transaction can't be retained once the account holds `max_transactions_per_client`
+    // (or its tier's limit).
+    // A repeated id is left to be rejected as a duplicate.
+    fn check_transaction_limit(
+        &self,
+        transaction_id: TransactionId,
+        processing_options: &ProcessingOptions,
+    ) -> Result<(), TransactionProcessingError> {
+        match processing_options.max_transactions_for_client(self.client_id) {
+            Some(max_transactions_per_client)
+                if self.disputable_transactions.len() >= max_transactions_per_client
+                    && !self.disputable_transactions.contains_key(&transaction_id) =>
+            {
+                Err(TransactionProcessingError::TransactionLimitExceeded {
+                    client: self.client_id,
+                    tx: transaction_id,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Returns how much of a deposit can be credited without taking the total balance above the
+    // client's cap: all of it, or with `BalanceCapPolicy::SuspendExcess` the part up to the cap
+    fn creditable_deposit_amount(
+        &self,
+        transaction_id: TransactionId,
+        amount: f64,
+        processing_options: &ProcessingOptions,
+    ) -> Result<f64, TransactionProcessingError> {
+        let cap = match processing_options.balance_cap(self.client_id) {
+            Some(cap) => cap,
+            None => return Ok(amount),
+        };
+        let headroom = cap - self.balance.total();
+        if amount <= headroom + BALANCE_CAP_TOLERANCE {
+            return Ok(amount);
+        }
+
+        match processing_options.balance_cap_policy(self.client_id) {
+            BalanceCapPolicy::SuspendExcess if headroom > BALANCE_CAP_TOLERANCE => Ok(headroom),
+            _ => Err(TransactionProcessingError::BalanceCapExceeded {
+                client: self.client_id,
+                tx: transaction_id,
+            }),
+        }
+    }
+
+    // The process_xyz functions return the signed amount they applied to the balance
     fn process_disputable_transaction(
         &mut self,
         disputable_transaction: DisputableTransaction,
-    ) -> Result<(), TransactionProcessingError> {
+    ) -> Result<f64, TransactionProcessingError> {
         if let hash_map::Entry::Vacant(e) = self
             .disputable_transactions
             .entry(disputable_transaction.transaction_id)
         {
-            self.balance.available += disputable_transaction.amount;
+            let amount = disputable_transaction.amount;
+            self.balance.available += amount;
             e.insert(disputable_transaction);
-            Ok(())
+            Ok(amount)
         } else {
-            Err(TransactionProcessingError::TransactionIDAlreadyExists(
-                disputable_transaction.transaction_id,
-            ))
+            Err(TransactionProcessingError::TransactionIDAlreadyExists {
+                client: self.client_id,
+                tx: disputable_transaction.transaction_id,
+            })
         }
     }
 
     fn process_dispute(
         &mut self,
         transaction: DisputeRelatedTransaction,
-    ) -> Result<(), TransactionProcessingError> {
+    ) -> Result<f64, TransactionProcessingError> {
         let maybe_referenced_transaction = self
             .disputable_transactions
             .get_mut(&transaction.referenced_transaction_id);
 
-        if let Some(mut referenced_transaction) = maybe_referenced_transaction {
-            if referenced_transaction.is_under_dispute {
+        if let Some(referenced_transaction) = maybe_referenced_transaction {
+            // a represented transaction is still in its (second) dispute
+            if referenced_transaction.is_held() {
                 Err(
-                    TransactionProcessingError::TransactionAlreadyHasPendingDisupte(
-                        transaction.referenced_transaction_id,
-                    ),
+                    TransactionProcessingError::TransactionAlreadyHasPendingDisupte {
+                        client: self.client_id,
+                        tx: transaction.referenced_transaction_id,
+                    },
                 )
             } else {
                 let amount = referenced_transaction.amount;
                 self.balance.available -= amount;
                 self.balance.held += amount;
                 referenced_transaction.is_under_dispute = true;
-                Ok(())
+                referenced_transaction.chargeback_state = None;
+                referenced_transaction.disputes += 1;
+                referenced_transaction.case_id = Some(dispute_cases::case_id(
+                    self.client_id,
+                    transaction.referenced_transaction_id,
+                    referenced_transaction.disputes,
+                ));
+                Ok(amount)
             }
         } else {
-            Err(TransactionProcessingError::ReferencedTransactionNotFound(
-                transaction.referenced_transaction_id,
-            ))
+            Err(TransactionProcessingError::ReferencedTransactionNotFound {
+                client: self.client_id,
+                tx: transaction.referenced_transaction_id,
+            })
         }
     }
 
-    fn process_resolve(
-        &mut self,
-        transaction: DisputeRelatedTransaction,
-    ) -> Result<(), TransactionProcessingError> {
-        let maybe_referenced_transaction = self
+    // Returns the amount of a transaction which is under dispute
+    fn disputed_amount(
+        &self,
+        referenced_transaction_id: TransactionId,
+    ) -> Result<f64, TransactionProcessingError> {
+        match self.disputable_transactions.get(&referenced_transaction_id) {
+            Some(referenced_transaction) if referenced_transaction.is_under_dispute => {
+                Ok(referenced_transaction.amount)
+            }
+            Some(_) => Err(
+                TransactionProcessingError::TransactionDoesNotHavePendingDisupte {
+                    client: self.client_id,
+                    tx: referenced_transaction_id,
+                },
+            ),
+            None => Err(TransactionProcessingError::ReferencedTransactionNotFound {
+                client: self.client_id,
+                tx: referenced_transaction_id,
+            }),
+        }
+    }
+
+    // Returns how much of `amount` can be released from held for the referenced transaction.
+    // Releasing must not push held past zero unless other pending disputes account for it
+    // (e.g. a disputed withdrawal alongside a disputed deposit).
+    fn releasable_held_amount(
+        &self,
+        referenced_transaction_id: TransactionId,
+        amount: f64,
+        processing_options: &ProcessingOptions,
+        debug_logger: &mut dyn std::io::Write,
+    ) -> Result<f64, TransactionProcessingError> {
+        let remaining_held = self.balance.held - amount;
+        if remaining_held * amount.signum() >= -HELD_BALANCE_TOLERANCE {
+            return Ok(amount);
+        }
+
+        let other_disputed_amounts: f64 = self
             .disputable_transactions
-            .get_mut(&transaction.referenced_transaction_id);
+            .values()
+            .filter(|disputable_transaction| {
+                disputable_transaction.is_held()
+                    && disputable_transaction.transaction_id != referenced_transaction_id
+            })
+            .map(|disputable_transaction| disputable_transaction.amount)
+            .sum();
+        if (remaining_held - other_disputed_amounts).abs() <= HELD_BALANCE_TOLERANCE {
+            return Ok(amount);
+        }
 
-        if let Some(mut referenced_transaction) = maybe_referenced_transaction {
-            if referenced_transaction.is_under_dispute {
-                let amount = referenced_transaction.amount;
-                self.balance.available += amount;
-                self.balance.held -= amount;
-                referenced_transaction.is_under_dispute = false;
-                Ok(())
-            } else {
-                Err(
-                    TransactionProcessingError::TransactionDoesNotHavePendingDisupte(
-                        transaction.referenced_transaction_id,
-                    ),
+        match processing_options.held_balance_underflow {
+            HeldBalanceUnderflowPolicy::Clamp => {
+                let releasable = if self.balance.held * amount.signum() > 0.0 {
+                    self.balance.held
+                } else {
+                    0.0
+                };
+                writeln!(
+                    debug_logger,
+                    "warning processing transaction - HeldBalanceUnderflow: {} (released {} of {})",
+                    referenced_transaction_id, releasable, amount
                 )
+                .expect("error writing to debug stream");
+                Ok(releasable)
             }
-        } else {
-            Err(TransactionProcessingError::ReferencedTransactionNotFound(
-                transaction.referenced_transaction_id,
-            ))
+            HeldBalanceUnderflowPolicy::Reject | HeldBalanceUnderflowPolicy::Abort => {
+                Err(TransactionProcessingError::HeldBalanceUnderflow {
+                    client: self.client_id,
+                    tx: referenced_transaction_id,
+                })
+            }
+        }
+    }
+
+    fn end_dispute(&mut self, referenced_transaction_id: TransactionId) {
+        if let Some(referenced_transaction) = self
+            .disputable_transactions
+            .get_mut(&referenced_transaction_id)
+        {
+            referenced_transaction.is_under_dispute = false;
         }
     }
 
+    fn process_resolve(
+        &mut self,
+        transaction: DisputeRelatedTransaction,
+        processing_options: &ProcessingOptions,
+        debug_logger: &mut dyn std::io::Write,
+    ) -> Result<f64, TransactionProcessingError> {
+        let referenced_transaction_id = transaction.referenced_transaction_id;
+        let amount = self.disputed_amount(referenced_transaction_id)?;
+        let amount = self.releasable_held_amount(
+            referenced_transaction_id,
+            amount,
+            processing_options,
+            debug_logger,
+        )?;
+
+        self.balance.available += amount;
+        self.balance.held -= amount;
+        self.end_dispute(referenced_transaction_id);
+        Ok(amount)
+    }
+
     fn process_chargeback(
         &mut self,
         transaction: DisputeRelatedTransaction,
-    ) -> Result<(), TransactionProcessingError> {
-        let maybe_referenced_transaction = self
+        processing_options: &ProcessingOptions,
+        debug_logger: &mut dyn std::io::Write,
+    ) -> Result<f64, TransactionProcessingError> {
+        let referenced_transaction_id = transaction.referenced_transaction_id;
+        let amount = self.disputed_amount(referenced_transaction_id)?;
+        let amount = self.releasable_held_amount(
+            referenced_transaction_id,
+            amount,
+            processing_options,
+            debug_logger,
+        )?;
+
+        self.balance.held -= amount;
+        self.end_dispute(referenced_transaction_id);
+        self.set_chargeback_state(
+            referenced_transaction_id,
+            Some(ChargebackState::ChargedBack),
+        );
+        self.lock(referenced_transaction_id);
+        self.chargebacks += 1;
+        self.charged_back += amount;
+        if processing_options
+            .max_chargebacks
+            .is_some_and(|max_chargebacks| self.chargebacks > max_chargebacks)
+        {
+            self.closed = true;
+        }
+        Ok(amount)
+    }
+
+    // Locks the account for the chargeback of the referenced transaction. An account which is
+    // already locked keeps the reason it was first locked for.
+    fn lock(&mut self, referenced_transaction_id: TransactionId) {
+        self.locked = true;
+        if self.lock_reason.is_none() {
+            self.lock_reason = Some(LockReason {
+                transaction_id: referenced_transaction_id,
+                case_id: self
+                    .disputable_transactions
+                    .get(&referenced_transaction_id)
+                    .and_then(|referenced_transaction| referenced_transaction.case_id.clone()),
+            });
+        }
+    }
+
+    fn set_chargeback_state(
+        &mut self,
+        referenced_transaction_id: TransactionId,
+        chargeback_state: Option<ChargebackState>,
+    ) {
+        if let Some(referenced_transaction) = self
             .disputable_transactions
-            .get_mut(&transaction.referenced_transaction_id);
+            .get_mut(&referenced_transaction_id)
+        {
+            referenced_transaction.chargeback_state = chargeback_state;
+        }
+    }
 
-        if let Some(mut referenced_transaction) = maybe_referenced_transaction {
-            if referenced_transaction.is_under_dispute {
-                self.balance.held -= referenced_transaction.amount;
-                referenced_transaction.is_under_dispute = false;
-                self.locked = true;
-                Ok(())
-            } else {
-                Err(
-                    TransactionProcessingError::TransactionDoesNotHavePendingDisupte(
-                        transaction.referenced_transaction_id,
-                    ),
-                )
+    // Returns the amount of a referenced transaction which is in the given chargeback state
+    fn amount_in_chargeback_state(
+        &self,
+        referenced_transaction_id: TransactionId,
+        chargeback_state: ChargebackState,
+    ) -> Result<f64, TransactionProcessingError> {
+        match self.disputable_transactions.get(&referenced_transaction_id) {
+            Some(referenced_transaction)
+                if referenced_transaction.chargeback_state == Some(chargeback_state) =>
+            {
+                Ok(referenced_transaction.amount)
             }
-        } else {
-            Err(TransactionProcessingError::ReferencedTransactionNotFound(
-                transaction.referenced_transaction_id,
-            ))
+            Some(_) if chargeback_state == ChargebackState::ChargedBack => {
+                Err(TransactionProcessingError::TransactionNotChargedBack {
+                    client: self.client_id,
+                    tx: referenced_transaction_id,
+                })
+            }
+            Some(_) => Err(TransactionProcessingError::TransactionNotRepresented {
+                client: self.client_id,
+                tx: referenced_transaction_id,
+            }),
+            None => Err(TransactionProcessingError::ReferencedTransactionNotFound {
+                client: self.client_id,
+                tx: referenced_transaction_id,
+            }),
         }
     }
 
+    // A representment contests a chargeback: the charged back funds are held again until the
+    // second decision. The account stays locked.
+    fn process_representment(
+        &mut self,
+        transaction: DisputeRelatedTransaction,
+    ) -> Result<f64, TransactionProcessingError> {
+        let referenced_transaction_id = transaction.referenced_transaction_id;
+        let amount = self
+            .amount_in_chargeback_state(referenced_transaction_id, ChargebackState::ChargedBack)?;
+
+        self.balance.held += amount;
+        self.set_chargeback_state(
+            referenced_transaction_id,
+            Some(ChargebackState::Represented),
+        );
+        Ok(amount)
+    }
+
+    fn process_final_chargeback(
+        &mut self,
+        transaction: DisputeRelatedTransaction,
+        processing_options: &ProcessingOptions,
+        debug_logger: &mut dyn std::io::Write,
+    ) -> Result<f64, TransactionProcessingError> {
+        let referenced_transaction_id = transaction.referenced_transaction_id;
+        let amount = self
+            .amount_in_chargeback_state(referenced_transaction_id, ChargebackState::Represented)?;
+        let amount = self.releasable_held_amount(
+            referenced_transaction_id,
+            amount,
+            processing_options,
+            debug_logger,
+        )?;
+
+        self.balance.held -= amount;
+        self.set_chargeback_state(
+            referenced_transaction_id,
+            Some(ChargebackState::FinalChargedBack),
+        );
+        Ok(amount)
+    }
+
+    // A won representment releases the held funds to the client, like a resolve. The account
+    // stays locked: unlocking is left to the operator.
+    fn process_representment_won(
+        &mut self,
+        transaction: DisputeRelatedTransaction,
+        processing_options: &ProcessingOptions,
+        debug_logger: &mut dyn std::io::Write,
+    ) -> Result<f64, TransactionProcessingError> {
+        let referenced_transaction_id = transaction.referenced_transaction_id;
+        let amount = self
+            .amount_in_chargeback_state(referenced_transaction_id, ChargebackState::Represented)?;
+        let amount = self.releasable_held_amount(
+            referenced_transaction_id,
+            amount,
+            processing_options,
+            debug_logger,
+        )?;
+
+        self.balance.available += amount;
+        self.balance.held -= amount;
+        self.set_chargeback_state(referenced_transaction_id, None);
+        Ok(amount)
+    }
+
+    // Closing sweeps the available balance out of the account. It has to wait until the account's
+    // disputes (and representments) are decided, as these may still move funds.
+    fn process_close_account(
+        &mut self,
+        transaction_id: TransactionId,
+    ) -> Result<f64, TransactionProcessingError> {
+        if self
+            .disputable_transactions
+            .values()
+            .any(|disputable_transaction| disputable_transaction.is_held())
+        {
+            return Err(TransactionProcessingError::AccountHasPendingDisputes {
+                client: self.client_id,
+                tx: transaction_id,
+            });
+        }
+
+        let residual = self.balance.available;
+        self.balance.available = 0.0;
+        self.closed = true;
+        self.closed_by_request = true;
+        Ok(-residual)
+    }
+
     fn log_error(
         &self,
         debug_logger: &mut dyn std::io::Write,
         transaction: &ClientAccountTransaction,
-        error: TransactionProcessingError,
+        error: &TransactionProcessingError,
     ) {
-        writeln!(debug_logger, "error processing transaction - {}", error)
-            .expect("error writing to debug stream");
+        writeln!(
+            debug_logger,
+            "error processing transaction - {} (client {})",
+            error, self.client_id
+        )
+        .expect("error writing to debug stream");
         writeln!(debug_logger, "{:?}", transaction).expect("error writing to debug stream");
     }
 
+    /// Applies the transaction and returns its journal entry. Errors are logged before being returned.
     pub fn process_client_transaction(
         &mut self,
         transaction: ClientAccountTransaction,
+        processing_options: &ProcessingOptions,
         debug_logger: &mut dyn std::io::Write,
-    ) {
-        let res: Result<(), TransactionProcessingError> = match transaction.transaction_type {
+    ) -> Result<AppliedTransaction, TransactionProcessingError> {
+        if self.errored.is_some() {
+            let error = TransactionProcessingError::ClientErrored {
+                client: self.client_id,
+                tx: transaction.transaction_id,
+            };
+            self.log_error(debug_logger, &transaction, &error);
+            return Err(error);
+        }
+        if self.closed_by_request {
+            let error = TransactionProcessingError::AccountClosed {
+                client: self.client_id,
+                tx: transaction.transaction_id,
+            };
+            self.log_error(debug_logger, &transaction, &error);
+            return Err(error);
+        }
+        if matches!(
+            transaction.transaction_type,
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer
+        ) {
+            let error = if self.closed {
+                Some(TransactionProcessingError::AccountClosed {
+                    client: self.client_id,
+                    tx: transaction.transaction_id,
+                })
+            } else if self.locked
+                && processing_options.locked_accounts == LockedAccountPolicy::Reject
+            {
+                Some(TransactionProcessingError::AccountLocked {
+                    client: self.client_id,
+                    tx: transaction.transaction_id,
+                })
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                self.log_error(debug_logger, &transaction, &error);
+                return Err(error);
+            }
+        }
+
+        let held_before = self.balance.held;
+        let mut suspended_excess = None;
+        let res: Result<f64, TransactionProcessingError> = match transaction.transaction_type {
             TransactionType::Deposit => {
                 if let Some(amount) = transaction.amount {
-                    self.process_disputable_transaction(
-                        DisputableTransaction::new_deposit_transaction(
-                            transaction.transaction_id,
-                            amount,
-                        ),
-                    )
+                    self.check_transaction_limit(transaction.transaction_id, processing_options)
+                        .and_then(|()| {
+                            self.creditable_deposit_amount(
+                                transaction.transaction_id,
+                                amount,
+                                processing_options,
+                            )
+                        })
+                        .and_then(|creditable_amount| {
+                            if creditable_amount < amount {
+                                suspended_excess = Some(amount - creditable_amount);
+                            }
+                            self.process_disputable_transaction(
+                                DisputableTransaction::new_deposit_transaction(
+                                    transaction.transaction_id,
+                                    creditable_amount,
+                                ),
+                            )
+                        })
                 } else {
-                    Err(TransactionProcessingError::AmountNotPresentForDeposit(
-                        transaction.transaction_id,
-                    ))
+                    Err(TransactionProcessingError::AmountNotPresentForDeposit {
+                        client: self.client_id,
+                        tx: transaction.transaction_id,
+                    })
                 }
             }
             TransactionType::Withdrawal => {
                 if let Some(amount) = transaction.amount {
-                    self.process_disputable_transaction(
-                        DisputableTransaction::new_withdrawal_transaction(
-                            transaction.transaction_id,
-                            amount,
-                        ),
-                    )
+                    self.check_transaction_limit(transaction.transaction_id, processing_options)
+                        .and_then(|()| {
+                            self.process_disputable_transaction(
+                                DisputableTransaction::new_withdrawal_transaction(
+                                    transaction.transaction_id,
+                                    amount,
+                                ),
+                            )
+                        })
                 } else {
-                    Err(TransactionProcessingError::AmountNotPresentForWithdrawal(
-                        transaction.transaction_id,
-                    ))
+                    Err(TransactionProcessingError::AmountNotPresentForWithdrawal {
+                        client: self.client_id,
+                        tx: transaction.transaction_id,
+                    })
                 }
             }
+            // the client's side of a transfer has a negative amount and is retained like a
+            // withdrawal, the counterparty's side like a deposit. A transfer can't be partly
+            // credited, so the counterparty's balance cap applies in full.
+            TransactionType::Transfer => match transaction.amount {
+                Some(amount) if amount < 0.0 => self
+                    .check_transaction_limit(transaction.transaction_id, processing_options)
+                    .and_then(|()| {
+                        self.process_disputable_transaction(
+                            DisputableTransaction::new_withdrawal_transaction(
+                                transaction.transaction_id,
+                                -amount,
+                            ),
+                        )
+                    }),
+                Some(amount) => self
+                    .check_transaction_limit(transaction.transaction_id, processing_options)
+                    .and_then(|()| {
+                        self.creditable_deposit_amount(
+                            transaction.transaction_id,
+                            amount,
+                            processing_options,
+                        )
+                    })
+                    .and_then(|creditable_amount| {
+                        if creditable_amount < amount {
+                            return Err(TransactionProcessingError::BalanceCapExceeded {
+                                client: self.client_id,
+                                tx: transaction.transaction_id,
+                            });
+                        }
+                        self.process_disputable_transaction(
+                            DisputableTransaction::new_deposit_transaction(
+                                transaction.transaction_id,
+                                amount,
+                            ),
+                        )
+                    }),
+                None => Err(TransactionProcessingError::AmountNotPresentForTransfer {
+                    client: self.client_id,
+                    tx: transaction.transaction_id,
+                }),
+            },
             TransactionType::Dispute => self.process_dispute(
                 DisputeRelatedTransaction::new_dispute_transaction(transaction.transaction_id),
             ),
             TransactionType::Resolve => self.process_resolve(
                 DisputeRelatedTransaction::new_resolve_transaction(transaction.transaction_id),
+                processing_options,
+                debug_logger,
             ),
             TransactionType::Chargeback => self.process_chargeback(
                 DisputeRelatedTransaction::new_chargeback_transaction(transaction.transaction_id),
+                processing_options,
+                debug_logger,
+            ),
+            TransactionType::Representment => self.process_representment(
+                DisputeRelatedTransaction::new_representment_transaction(
+                    transaction.transaction_id,
+                ),
             ),
+            TransactionType::FinalChargeback => self.process_final_chargeback(
+                DisputeRelatedTransaction::new_final_chargeback_transaction(
+                    transaction.transaction_id,
+                ),
+                processing_options,
+                debug_logger,
+            ),
+            TransactionType::RepresentmentWon => self.process_representment_won(
+                DisputeRelatedTransaction::new_representment_won_transaction(
+                    transaction.transaction_id,
+                ),
+                processing_options,
+                debug_logger,
+            ),
+            // this account already exists
+            TransactionType::OpenAccount => Err(TransactionProcessingError::AccountAlreadyOpen {
+                client: self.client_id,
+                tx: transaction.transaction_id,
+            }),
+            TransactionType::CloseAccount => self.process_close_account(transaction.transaction_id),
         };
 
-        if let Err(e) = res {
-            self.log_error(debug_logger, &transaction, e);
+        match res {
+            Ok(amount) => {
+                let journal_entry = JournalEntry::new(
+                    self.client_id,
+                    transaction.transaction_id,
+                    transaction.transaction_type,
+                    amount,
+                );
+                self.record_event(
+                    &journal_entry,
+                    amount,
+                    processing_options.account_event_capacity,
+                );
+                Ok(AppliedTransaction {
+                    journal_entry,
+                    balance: self.balance,
+                    locked: self.locked,
+                    held_change: self.balance.held - held_before,
+                    suspended_excess,
+                    counterparty: None,
+                })
+            }
+            Err(e) => {
+                self.log_error(debug_logger, &transaction, &e);
+                Err(e)
+            }
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -223,7 +956,7 @@ mod tests {
                 account.process_disputable_transaction(
                     DisputableTransaction::new_deposit_transaction(1, 200.0),
                 ),
-                Err(TransactionProcessingError::TransactionIDAlreadyExists(1)),
+                Err(TransactionProcessingError::TransactionIDAlreadyExists { client: 1, tx: 1 }),
             );
         }
 
@@ -270,17 +1003,25 @@ mod tests {
 
         assert_eq!(
             account.process_dispute(DisputeRelatedTransaction::new_dispute_transaction(1)),
-            Err(TransactionProcessingError::ReferencedTransactionNotFound(1))
+            Err(TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 1 })
         );
 
         assert_eq!(
-            account.process_resolve(DisputeRelatedTransaction::new_resolve_transaction(1)),
-            Err(TransactionProcessingError::ReferencedTransactionNotFound(1))
+            account.process_resolve(
+                DisputeRelatedTransaction::new_resolve_transaction(1),
+                &ProcessingOptions::default(),
+                &mut std::io::sink()
+            ),
+            Err(TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 1 })
         );
 
         assert_eq!(
-            account.process_chargeback(DisputeRelatedTransaction::new_chargeback_transaction(1)),
-            Err(TransactionProcessingError::ReferencedTransactionNotFound(1))
+            account.process_chargeback(
+                DisputeRelatedTransaction::new_chargeback_transaction(1),
+                &ProcessingOptions::default(),
+                &mut std::io::sink()
+            ),
+            Err(TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 1 })
         );
     }
 
@@ -322,7 +1063,10 @@ mod tests {
         if let Err(the_error) = res {
             assert_eq!(
                 the_error,
-                TransactionProcessingError::TransactionAlreadyHasPendingDisupte(2)
+                TransactionProcessingError::TransactionAlreadyHasPendingDisupte {
+                    client: 1,
+                    tx: 2
+                }
             );
         } else {
             panic!("Should have returned an error");
@@ -344,11 +1088,18 @@ mod tests {
         assert_eq!(account.balance.total(), 100.0);
         assert_eq!(account.locked, false);
 
-        let res = account.process_resolve(DisputeRelatedTransaction::new_resolve_transaction(1));
+        let res = account.process_resolve(
+            DisputeRelatedTransaction::new_resolve_transaction(1),
+            &ProcessingOptions::default(),
+            &mut std::io::sink(),
+        );
         if let Err(the_error) = res {
             assert_eq!(
                 the_error,
-                TransactionProcessingError::TransactionDoesNotHavePendingDisupte(1)
+                TransactionProcessingError::TransactionDoesNotHavePendingDisupte {
+                    client: 1,
+                    tx: 1
+                }
             );
         } else {
             panic!("Should have returned an error");
@@ -377,12 +1128,18 @@ mod tests {
         assert_eq!(account.balance.total(), 100.0);
         assert_eq!(account.locked, false);
 
-        let res =
-            account.process_chargeback(DisputeRelatedTransaction::new_chargeback_transaction(1));
+        let res = account.process_chargeback(
+            DisputeRelatedTransaction::new_chargeback_transaction(1),
+            &ProcessingOptions::default(),
+            &mut std::io::sink(),
+        );
         if let Err(the_error) = res {
             assert_eq!(
                 the_error,
-                TransactionProcessingError::TransactionDoesNotHavePendingDisupte(1)
+                TransactionProcessingError::TransactionDoesNotHavePendingDisupte {
+                    client: 1,
+                    tx: 1
+                }
             );
         } else {
             panic!("Should have returned an error");
@@ -464,7 +1221,13 @@ mod tests {
 
         // now resolve
         let resolve_transaction = DisputeRelatedTransaction::new_resolve_transaction(2);
-        account.process_resolve(resolve_transaction).unwrap();
+        account
+            .process_resolve(
+                resolve_transaction,
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
 
         assert_eq!(account.disputable_transactions.len(), 2);
         assert_eq!(account.balance.available, 110.0);
@@ -514,7 +1277,13 @@ mod tests {
 
         // now chargeback
         let chargeback_transaction = DisputeRelatedTransaction::new_chargeback_transaction(2);
-        account.process_chargeback(chargeback_transaction).unwrap();
+        account
+            .process_chargeback(
+                chargeback_transaction,
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
 
         assert_eq!(account.disputable_transactions.len(), 2);
         assert_eq!(account.balance.available, 100.0);
@@ -523,6 +1292,9 @@ mod tests {
         assert_eq!(account.locked, true);
         let referenced_transaction = account.disputable_transactions.get(&2).unwrap();
         assert_eq!(referenced_transaction.is_under_dispute, false);
+        let lock_reason = account.lock_reason.as_ref().unwrap();
+        assert_eq!(lock_reason.transaction_id, 2);
+        assert_eq!(lock_reason.case_id.as_deref(), Some("1-2"));
     }
 
     #[test]
@@ -564,7 +1336,13 @@ mod tests {
 
         // now chargeback
         let chargeback_transaction = DisputeRelatedTransaction::new_chargeback_transaction(2);
-        account.process_chargeback(chargeback_transaction).unwrap();
+        account
+            .process_chargeback(
+                chargeback_transaction,
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
 
         assert_eq!(account.disputable_transactions.len(), 2);
         assert_eq!(account.balance.available, 100.0);
@@ -576,23 +1354,535 @@ mod tests {
     }
 
     #[cfg(test)]
-    mod process_client_transaction {
+    mod held_balance_underflow {
         use super::*;
 
-        #[test]
-        fn it_should_ignore_errors_generated_from_process_disputable_transaction_when_transaction_id_already_exists(
-        ) {
+        // Disputes deposit 2 and then corrupts held so it no longer covers the disputed amount
+        fn account_with_inconsistent_held() -> ClientAccount {
             let mut account = ClientAccount::new(1);
+            account
+                .process_disputable_transaction(DisputableTransaction::new_deposit_transaction(
+                    1, 100.0,
+                ))
+                .unwrap();
+            account
+                .process_disputable_transaction(DisputableTransaction::new_deposit_transaction(
+                    2, 10.0,
+                ))
+                .unwrap();
+            account
+                .process_dispute(DisputeRelatedTransaction::new_dispute_transaction(2))
+                .unwrap();
+            account.balance.held = 4.0;
+            account
+        }
+
+        fn options(policy: HeldBalanceUnderflowPolicy) -> ProcessingOptions {
+            ProcessingOptions {
+                held_balance_underflow: policy,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn it_rejects_a_resolve_which_would_underflow_held() {
+            let mut account = account_with_inconsistent_held();
+
+            assert_eq!(
+                account.process_resolve(
+                    DisputeRelatedTransaction::new_resolve_transaction(2),
+                    &options(HeldBalanceUnderflowPolicy::Reject),
+                    &mut std::io::sink(),
+                ),
+                Err(TransactionProcessingError::HeldBalanceUnderflow { client: 1, tx: 2 })
+            );
+            assert_eq!(account.balance.available, 100.0);
+            assert_eq!(account.balance.held, 4.0);
+            assert_eq!(account.disputable_transactions[&2].is_under_dispute, true);
+
+            assert_eq!(
+                account.process_chargeback(
+                    DisputeRelatedTransaction::new_chargeback_transaction(2),
+                    &options(HeldBalanceUnderflowPolicy::Abort),
+                    &mut std::io::sink(),
+                ),
+                Err(TransactionProcessingError::HeldBalanceUnderflow { client: 1, tx: 2 })
+            );
+            assert_eq!(account.balance.held, 4.0);
+            assert_eq!(account.locked, false);
+        }
+
+        #[test]
+        fn it_clamps_the_release_to_held_and_warns() {
+            let mut account = account_with_inconsistent_held();
             let mut debug_logger = Vec::<u8>::new();
 
+            let released = account
+                .process_resolve(
+                    DisputeRelatedTransaction::new_resolve_transaction(2),
+                    &options(HeldBalanceUnderflowPolicy::Clamp),
+                    &mut debug_logger,
+                )
+                .unwrap();
+
+            assert_eq!(released, 4.0);
+            assert_eq!(account.balance.available, 104.0);
+            assert_eq!(account.balance.held, 0.0);
+            assert_eq!(account.disputable_transactions[&2].is_under_dispute, false);
+            let debug_log_str = std::str::from_utf8(&debug_logger).unwrap();
+            assert!(
+                debug_log_str.contains("warning processing transaction - HeldBalanceUnderflow: 2")
+            );
+        }
+
+        #[test]
+        fn it_allows_held_to_cross_zero_when_other_disputes_account_for_it() {
+            let mut account = ClientAccount::new(1);
+            account
+                .process_disputable_transaction(DisputableTransaction::new_deposit_transaction(
+                    1, 100.0,
+                ))
+                .unwrap();
+            account
+                .process_disputable_transaction(DisputableTransaction::new_withdrawal_transaction(
+                    2, 50.0,
+                ))
+                .unwrap();
+            account
+                .process_dispute(DisputeRelatedTransaction::new_dispute_transaction(1))
+                .unwrap();
+            account
+                .process_dispute(DisputeRelatedTransaction::new_dispute_transaction(2))
+                .unwrap();
+            assert_eq!(account.balance.held, 50.0);
+
+            account
+                .process_resolve(
+                    DisputeRelatedTransaction::new_resolve_transaction(1),
+                    &ProcessingOptions::default(),
+                    &mut std::io::sink(),
+                )
+                .unwrap();
+            assert_eq!(account.balance.available, 100.0);
+            assert_eq!(account.balance.held, -50.0);
+            assert_eq!(account.balance.total(), 50.0);
+        }
+    }
+
+    #[cfg(test)]
+    mod representment {
+        use super::*;
+
+        fn apply(
+            account: &mut ClientAccount,
+            transaction_type: TransactionType,
+            transaction_id: TransactionId,
+            amount: Option<f64>,
+        ) -> Result<AppliedTransaction, TransactionProcessingError> {
             account.process_client_transaction(
                 ClientAccountTransaction {
-                    transaction_type: TransactionType::Deposit,
-                    transaction_id: 1,
-                    amount: Some(100.0),
+                    transaction_type,
+                    transaction_id,
+                    amount,
                 },
-                &mut debug_logger,
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+        }
+
+        fn charged_back_account() -> ClientAccount {
+            let mut account = ClientAccount::new(1);
+            apply(&mut account, TransactionType::Deposit, 1, Some(100.0)).unwrap();
+            apply(&mut account, TransactionType::Deposit, 2, Some(10.0)).unwrap();
+            apply(&mut account, TransactionType::Dispute, 1, None).unwrap();
+            apply(&mut account, TransactionType::Chargeback, 1, None).unwrap();
+            account
+        }
+
+        #[test]
+        fn it_holds_the_funds_again_until_the_representment_is_won() {
+            let mut account = charged_back_account();
+            apply(&mut account, TransactionType::Representment, 1, None).unwrap();
+            assert_eq!(account.balance.available, 10.0);
+            assert_eq!(account.balance.held, 100.0);
+            assert_eq!(
+                account.disputable_transactions[&1].chargeback_state,
+                Some(ChargebackState::Represented)
             );
+
+            apply(&mut account, TransactionType::RepresentmentWon, 1, None).unwrap();
+            assert_eq!(account.balance.available, 110.0);
+            assert_eq!(account.balance.held, 0.0);
+            assert_eq!(account.disputable_transactions[&1].chargeback_state, None);
+            assert_eq!(account.locked, true);
+        }
+
+        #[test]
+        fn it_releases_the_held_funds_on_a_final_chargeback() {
+            let mut account = charged_back_account();
+            apply(&mut account, TransactionType::Representment, 1, None).unwrap();
+            apply(&mut account, TransactionType::FinalChargeback, 1, None).unwrap();
+            assert_eq!(account.balance.available, 10.0);
+            assert_eq!(account.balance.held, 0.0);
+
+            // a final chargeback can't be represented again
+            assert_eq!(
+                apply(&mut account, TransactionType::Representment, 1, None),
+                Err(TransactionProcessingError::TransactionNotChargedBack { client: 1, tx: 1 })
+            );
+        }
+
+        #[test]
+        fn it_rejects_steps_out_of_order() {
+            let mut account = charged_back_account();
+            assert_eq!(
+                apply(&mut account, TransactionType::Representment, 2, None),
+                Err(TransactionProcessingError::TransactionNotChargedBack { client: 1, tx: 2 })
+            );
+            assert_eq!(
+                apply(&mut account, TransactionType::RepresentmentWon, 1, None),
+                Err(TransactionProcessingError::TransactionNotRepresented { client: 1, tx: 1 })
+            );
+            assert_eq!(
+                apply(&mut account, TransactionType::FinalChargeback, 3, None),
+                Err(TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 3 })
+            );
+
+            apply(&mut account, TransactionType::Representment, 1, None).unwrap();
+            assert_eq!(
+                apply(&mut account, TransactionType::Dispute, 1, None),
+                Err(
+                    TransactionProcessingError::TransactionAlreadyHasPendingDisupte {
+                        client: 1,
+                        tx: 1
+                    }
+                )
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod chargeback_exposure {
+        use super::*;
+
+        fn apply(
+            account: &mut ClientAccount,
+            transaction_type: TransactionType,
+            transaction_id: TransactionId,
+            amount: Option<f64>,
+            processing_options: &ProcessingOptions,
+        ) -> Result<AppliedTransaction, TransactionProcessingError> {
+            account.process_client_transaction(
+                ClientAccountTransaction {
+                    transaction_type,
+                    transaction_id,
+                    amount,
+                },
+                processing_options,
+                &mut std::io::sink(),
+            )
+        }
+
+        #[test]
+        fn it_closes_accounts_with_too_many_chargebacks() {
+            let processing_options = ProcessingOptions {
+                max_chargebacks: Some(1),
+                ..ProcessingOptions::default()
+            };
+            let mut account = ClientAccount::new(1);
+            for (transaction_id, amount) in [(1, 100.0), (2, 10.0), (3, 5.0)] {
+                apply(
+                    &mut account,
+                    TransactionType::Deposit,
+                    transaction_id,
+                    Some(amount),
+                    &processing_options,
+                )
+                .unwrap();
+            }
+            for transaction_id in [1, 2] {
+                for transaction_type in [TransactionType::Dispute, TransactionType::Chargeback] {
+                    apply(
+                        &mut account,
+                        transaction_type,
+                        transaction_id,
+                        None,
+                        &processing_options,
+                    )
+                    .unwrap();
+                }
+                assert_eq!(account.closed, transaction_id == 2);
+            }
+            assert_eq!(account.chargebacks, 2);
+            assert_eq!(account.charged_back, 110.0);
+
+            assert_eq!(
+                apply(
+                    &mut account,
+                    TransactionType::Deposit,
+                    4,
+                    Some(1.0),
+                    &processing_options
+                ),
+                Err(TransactionProcessingError::AccountClosed { client: 1, tx: 4 })
+            );
+            // the disputes of a closed account still run their course
+            for transaction_type in [TransactionType::Dispute, TransactionType::Resolve] {
+                apply(&mut account, transaction_type, 3, None, &processing_options).unwrap();
+            }
+            assert_eq!(account.balance.available, 5.0);
+        }
+
+        #[test]
+        fn it_rejects_deposits_and_withdrawals_on_locked_accounts_if_configured() {
+            let mut account = ClientAccount::new(1);
+            for (transaction_type, amount) in [
+                (TransactionType::Deposit, Some(100.0)),
+                (TransactionType::Dispute, None),
+                (TransactionType::Chargeback, None),
+            ] {
+                apply(
+                    &mut account,
+                    transaction_type,
+                    1,
+                    amount,
+                    &ProcessingOptions::default(),
+                )
+                .unwrap();
+            }
+            // allowed by default
+            apply(
+                &mut account,
+                TransactionType::Deposit,
+                2,
+                Some(10.0),
+                &ProcessingOptions::default(),
+            )
+            .unwrap();
+
+            let processing_options = ProcessingOptions {
+                locked_accounts: LockedAccountPolicy::Reject,
+                ..ProcessingOptions::default()
+            };
+            for (transaction_type, transaction_id) in [
+                (TransactionType::Deposit, 3),
+                (TransactionType::Withdrawal, 4),
+            ] {
+                assert_eq!(
+                    apply(
+                        &mut account,
+                        transaction_type,
+                        transaction_id,
+                        Some(1.0),
+                        &processing_options
+                    ),
+                    Err(TransactionProcessingError::AccountLocked {
+                        client: 1,
+                        tx: transaction_id
+                    })
+                );
+            }
+            // the disputes of a locked account still run their course
+            apply(
+                &mut account,
+                TransactionType::Dispute,
+                2,
+                None,
+                &processing_options,
+            )
+            .unwrap();
+            assert_eq!(account.balance.held, 10.0);
+        }
+
+        #[test]
+        fn it_closes_accounts_once_their_disputes_are_decided() {
+            let processing_options = ProcessingOptions::default();
+            let mut account = ClientAccount::new(1);
+            for (transaction_type, amount) in [
+                (TransactionType::Deposit, Some(100.0)),
+                (TransactionType::Dispute, None),
+            ] {
+                apply(
+                    &mut account,
+                    transaction_type,
+                    1,
+                    amount,
+                    &processing_options,
+                )
+                .unwrap();
+            }
+            assert_eq!(
+                apply(
+                    &mut account,
+                    TransactionType::CloseAccount,
+                    2,
+                    None,
+                    &processing_options
+                ),
+                Err(TransactionProcessingError::AccountHasPendingDisputes { client: 1, tx: 2 })
+            );
+
+            apply(
+                &mut account,
+                TransactionType::Resolve,
+                1,
+                None,
+                &processing_options,
+            )
+            .unwrap();
+            let applied_transaction = apply(
+                &mut account,
+                TransactionType::CloseAccount,
+                3,
+                None,
+                &processing_options,
+            )
+            .unwrap();
+            assert_eq!(applied_transaction.journal_entry.amount, 100.0);
+            assert_eq!(account.balance.available, 0.0);
+            assert!(account.closed && account.closed_by_request);
+
+            // unlike an account closed for its chargebacks, nothing runs its course
+            for (transaction_type, transaction_id) in [
+                (TransactionType::Dispute, 1),
+                (TransactionType::CloseAccount, 4),
+            ] {
+                assert_eq!(
+                    apply(
+                        &mut account,
+                        transaction_type,
+                        transaction_id,
+                        None,
+                        &processing_options
+                    ),
+                    Err(TransactionProcessingError::AccountClosed {
+                        client: 1,
+                        tx: transaction_id
+                    })
+                );
+            }
+        }
+    }
+
+    mod process_client_transaction {
+        use super::*;
+
+        #[test]
+        fn it_returns_the_resulting_account_state() {
+            let mut account = ClientAccount::new(1);
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Deposit,
+                        transaction_id: 1,
+                        amount: Some(100.0),
+                    },
+                    &ProcessingOptions::default(),
+                    &mut std::io::sink(),
+                )
+                .unwrap();
+
+            let applied_transaction = account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Dispute,
+                        transaction_id: 1,
+                        amount: None,
+                    },
+                    &ProcessingOptions::default(),
+                    &mut std::io::sink(),
+                )
+                .unwrap();
+
+            assert_eq!(
+                applied_transaction.journal_entry,
+                JournalEntry::new(1, 1, TransactionType::Dispute, 100.0)
+            );
+            assert_eq!(applied_transaction.balance.available, 0.0);
+            assert_eq!(applied_transaction.balance.held, 100.0);
+            assert_eq!(applied_transaction.locked, false);
+            assert_eq!(applied_transaction.held_change, 100.0);
+        }
+
+        #[test]
+        fn it_keeps_a_bounded_list_of_events_when_enabled() {
+            let mut account = ClientAccount::new(1);
+            let processing_options = ProcessingOptions {
+                account_event_capacity: 2,
+                ..Default::default()
+            };
+
+            for (transaction_type, transaction_id, amount) in [
+                (TransactionType::Deposit, 1, Some(100.0)),
+                (TransactionType::Deposit, 1, Some(100.0)),
+                (TransactionType::Withdrawal, 2, Some(25.0)),
+                (TransactionType::Dispute, 2, None),
+            ] {
+                let _ = account.process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type,
+                        transaction_id,
+                        amount,
+                    },
+                    &processing_options,
+                    &mut std::io::sink(),
+                );
+            }
+
+            // the duplicate deposit was rejected and the first deposit has been evicted
+            let events: Vec<&AccountEvent> = account.events().collect();
+            assert_eq!(events.len(), 2);
+            assert_eq!(
+                *events[0],
+                AccountEvent {
+                    transaction_id: 2,
+                    transaction_type: TransactionType::Withdrawal,
+                    amount: -25.0,
+                    available: 75.0,
+                    held: 0.0,
+                    locked: false,
+                }
+            );
+            assert_eq!(events[1].transaction_type, TransactionType::Dispute);
+            assert_eq!(events[1].available, 100.0);
+            assert_eq!(events[1].held, -25.0);
+        }
+
+        #[test]
+        fn it_does_not_keep_events_by_default() {
+            let mut account = ClientAccount::new(1);
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Deposit,
+                        transaction_id: 1,
+                        amount: Some(100.0),
+                    },
+                    &ProcessingOptions::default(),
+                    &mut std::io::sink(),
+                )
+                .unwrap();
+
+            assert_eq!(account.events().count(), 0);
+        }
+
+        #[test]
+        fn it_should_ignore_errors_generated_from_process_disputable_transaction_when_transaction_id_already_exists(
+        ) {
+            let mut account = ClientAccount::new(1);
+            let mut debug_logger = Vec::<u8>::new();
+
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Deposit,
+                        transaction_id: 1,
+                        amount: Some(100.0),
+                    },
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap();
             assert_eq!(account.balance.available, 100.0);
             assert_eq!(account.balance.held, 0.0);
             assert_eq!(account.balance.total(), 100.0);
@@ -601,14 +1891,17 @@ mod tests {
             assert_eq!(error_log_str, "",);
 
             // another transaction (deposit) with the same transaction id
-            account.process_client_transaction(
-                ClientAccountTransaction {
-                    transaction_type: TransactionType::Deposit,
-                    transaction_id: 1,
-                    amount: Some(200.0),
-                },
-                &mut debug_logger,
-            );
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Deposit,
+                        transaction_id: 1,
+                        amount: Some(200.0),
+                    },
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap_err();
             assert_eq!(account.balance.available, 100.0);
             assert_eq!(account.balance.held, 0.0);
             assert_eq!(account.balance.total(), 100.0);
@@ -623,14 +1916,17 @@ mod tests {
 
             // another transaction (withdrawal) with the same transaction id
 
-            account.process_client_transaction(
-                ClientAccountTransaction {
-                    transaction_type: TransactionType::Withdrawal,
-                    transaction_id: 1,
-                    amount: Some(50.0),
-                },
-                &mut debug_logger,
-            );
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Withdrawal,
+                        transaction_id: 1,
+                        amount: Some(50.0),
+                    },
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap_err();
             assert_eq!(account.balance.available, 100.0);
             assert_eq!(account.balance.held, 0.0);
             assert_eq!(account.balance.total(), 100.0);
@@ -650,14 +1946,17 @@ mod tests {
             let mut debug_logger = Vec::<u8>::new();
 
             // deposit
-            account.process_client_transaction(
-                ClientAccountTransaction {
-                    transaction_type: TransactionType::Deposit,
-                    transaction_id: 1,
-                    amount: None,
-                },
-                &mut debug_logger,
-            );
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Deposit,
+                        transaction_id: 1,
+                        amount: None,
+                    },
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap_err();
             assert_eq!(account.balance.available, 0.0);
             assert_eq!(account.balance.held, 0.0);
             assert_eq!(account.balance.total(), 0.0);
@@ -672,14 +1971,17 @@ mod tests {
             debug_logger.clear();
 
             // same for a withdrawal
-            account.process_client_transaction(
-                ClientAccountTransaction {
-                    transaction_type: TransactionType::Withdrawal,
-                    transaction_id: 1,
-                    amount: None,
-                },
-                &mut debug_logger,
-            );
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Withdrawal,
+                        transaction_id: 1,
+                        amount: None,
+                    },
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap_err();
             assert_eq!(account.balance.available, 0.0);
             assert_eq!(account.balance.held, 0.0);
             assert_eq!(account.balance.total(), 0.0);
@@ -703,14 +2005,17 @@ mod tests {
             let mut account = ClientAccount::new(1);
             let mut debug_logger = Vec::<u8>::new();
 
-            account.process_client_transaction(
-                ClientAccountTransaction {
-                    transaction_type: TransactionType::Dispute,
-                    transaction_id: 1,
-                    amount: None,
-                },
-                &mut debug_logger,
-            );
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Dispute,
+                        transaction_id: 1,
+                        amount: None,
+                    },
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap_err();
             let error_log_str = std::str::from_utf8(&debug_logger).unwrap();
             assert!(error_log_str
                 .contains("error processing transaction - ReferencedTransactionNotFound"));
@@ -718,14 +2023,17 @@ mod tests {
             assert!(error_log_str.contains("transaction_id: 1"));
             debug_logger.clear();
 
-            account.process_client_transaction(
-                ClientAccountTransaction {
-                    transaction_type: TransactionType::Resolve,
-                    transaction_id: 1,
-                    amount: None,
-                },
-                &mut debug_logger,
-            );
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Resolve,
+                        transaction_id: 1,
+                        amount: None,
+                    },
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap_err();
             let error_log_str = std::str::from_utf8(&debug_logger).unwrap();
             assert!(error_log_str
                 .contains("error processing transaction - ReferencedTransactionNotFound"));
@@ -733,14 +2041,17 @@ mod tests {
             assert!(error_log_str.contains("transaction_id: 1"));
             debug_logger.clear();
 
-            account.process_client_transaction(
-                ClientAccountTransaction {
-                    transaction_type: TransactionType::Chargeback,
-                    transaction_id: 1,
-                    amount: None,
-                },
-                &mut debug_logger,
-            );
+            account
+                .process_client_transaction(
+                    ClientAccountTransaction {
+                        transaction_type: TransactionType::Chargeback,
+                        transaction_id: 1,
+                        amount: None,
+                    },
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap_err();
             let error_log_str = std::str::from_utf8(&debug_logger).unwrap();
             assert!(error_log_str
                 .contains("error processing transaction - ReferencedTransactionNotFound"));
@@ -761,7 +2072,13 @@ mod tests {
                 transaction_id: 1,
                 amount: Some(100.0),
             };
-            account.process_client_transaction(deposit, &mut debug_logger);
+            account
+                .process_client_transaction(
+                    deposit,
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap();
             assert_eq!(account.disputable_transactions.len(), 1);
             assert_eq!(account.balance.available, 100.0);
             assert_eq!(account.balance.held, 0.0);
@@ -774,7 +2091,13 @@ mod tests {
                 transaction_id: 2,
                 amount: Some(10.0),
             };
-            account.process_client_transaction(transaction_to_dispute, &mut debug_logger);
+            account
+                .process_client_transaction(
+                    transaction_to_dispute,
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap();
             assert_eq!(account.disputable_transactions.len(), 2);
             assert_eq!(account.balance.available, 110.0);
             assert_eq!(account.balance.held, 0.0);
@@ -787,7 +2110,13 @@ mod tests {
                 transaction_id: 2,
                 amount: None,
             };
-            account.process_client_transaction(dispute, &mut debug_logger);
+            account
+                .process_client_transaction(
+                    dispute,
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap();
             assert_eq!(account.disputable_transactions.len(), 2);
             assert_eq!(account.balance.available, 100.0);
             assert_eq!(account.balance.held, 10.0);
@@ -805,7 +2134,13 @@ mod tests {
                 transaction_id: 2,
                 amount: None,
             };
-            account.process_client_transaction(resolve, &mut debug_logger);
+            account
+                .process_client_transaction(
+                    resolve,
+                    &ProcessingOptions::default(),
+                    &mut debug_logger,
+                )
+                .unwrap();
 
             assert_eq!(account.disputable_transactions.len(), 2);
             assert_eq!(account.balance.available, 110.0);
@@ -0,0 +1,100 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::ClientId;
+
+#[derive(Debug, Deserialize)]
+struct ClientNoteRow {
+    client: ClientId,
+    #[serde(default)]
+    flag: Option<String>,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+/// The operator flags (e.g. `under-investigation`) and free-text notes recorded for one client.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ClientNote {
+    pub flags: Vec<String>,
+    pub notes: Vec<String>,
+}
+
+impl ClientNote {
+    /// The flags as written in the output's `flags` column
+    pub fn flags_field(&self) -> String {
+        self.flags.join(";")
+    }
+
+    /// The notes as written in the output's `notes` column
+    pub fn notes_field(&self) -> String {
+        self.notes.join("; ")
+    }
+}
+
+/// Manual operator flags and notes per client, read from a sidecar CSV file with
+/// client,flag,note columns. A client may have several rows; either of flag and note may be empty.
+#[derive(Debug, Default, PartialEq)]
+pub struct ClientNotes {
+    notes: BTreeMap<ClientId, ClientNote>,
+}
+
+impl ClientNotes {
+    pub fn from_reader(reader: impl std::io::Read) -> anyhow::Result<Self> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(reader);
+        let mut notes = BTreeMap::<ClientId, ClientNote>::new();
+        for row in csv_reader.deserialize() {
+            let row: ClientNoteRow = row?;
+            let client_note = notes.entry(row.client).or_default();
+            if let Some(flag) = row.flag.filter(|flag| !flag.is_empty()) {
+                if !client_note.flags.contains(&flag) {
+                    client_note.flags.push(flag);
+                }
+            }
+            if let Some(note) = row.note.filter(|note| !note.is_empty()) {
+                client_note.notes.push(note);
+            }
+        }
+        Ok(Self { notes })
+    }
+
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+
+    pub fn get(&self, client_id: ClientId) -> Option<&ClientNote> {
+        self.notes.get(&client_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_collects_the_flags_and_notes_per_client() {
+        let client_notes = ClientNotes::from_reader(
+            "client,flag,note
+1,under-investigation,ticket 4411
+2,,called about a missing deposit
+1,under-investigation,
+1,vip,escalate to ops
+"
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let client_1 = client_notes.get(1).unwrap();
+        assert_eq!(client_1.flags_field(), "under-investigation;vip");
+        assert_eq!(client_1.notes_field(), "ticket 4411; escalate to ops");
+
+        let client_2 = client_notes.get(2).unwrap();
+        assert_eq!(client_2.flags_field(), "");
+        assert_eq!(client_2.notes_field(), "called about a missing deposit");
+
+        assert_eq!(client_notes.get(3), None);
+    }
+}
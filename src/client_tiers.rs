@@ -0,0 +1,132 @@
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::processing_options::BalanceCapPolicy;
+use crate::ClientId;
+
+/// The processing options a tier overrides for its clients. Read from JSON with the fields named
+/// like the command line flags, e.g. `{"balance-cap": 1000.0}`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TierPolicy {
+    pub balance_cap: Option<f64>,
+
+    pub balance_cap_exceeded: Option<BalanceCapPolicy>,
+
+    pub max_transactions_per_client: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientTierRow {
+    client: ClientId,
+    tier: String,
+}
+
+/// Client tiers (e.g. retail and merchant) with their policies, and which tier each client is in.
+/// Clients without a tier get the run's processing options.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ClientTiers {
+    tiers: HashMap<String, TierPolicy>,
+    client_tiers: HashMap<ClientId, String>,
+}
+
+impl ClientTiers {
+    /// Reads the tiers from a JSON object of tier name to policy, and the clients' tiers from CSV
+    /// with client,tier columns.
+    pub fn from_readers(
+        tiers_reader: impl std::io::Read,
+        client_tiers_reader: impl std::io::Read,
+    ) -> anyhow::Result<Self> {
+        let tiers: HashMap<String, TierPolicy> = serde_json::from_reader(tiers_reader)?;
+
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(client_tiers_reader);
+        let mut client_tiers = HashMap::new();
+        for row in csv_reader.deserialize() {
+            let row: ClientTierRow = row?;
+            if !tiers.contains_key(&row.tier) {
+                anyhow::bail!(
+                    "client {} is mapped to unknown tier {}",
+                    row.client,
+                    row.tier
+                );
+            }
+            if let Some(tier) = client_tiers.insert(row.client, row.tier.clone()) {
+                if tier != row.tier {
+                    anyhow::bail!(
+                        "client {} is mapped to both tier {} and tier {}",
+                        row.client,
+                        tier,
+                        row.tier
+                    );
+                }
+            }
+        }
+
+        Ok(Self {
+            tiers,
+            client_tiers,
+        })
+    }
+
+    pub fn from_paths(tiers_file: &Path, client_tiers_file: &Path) -> anyhow::Result<Self> {
+        Self::from_readers(
+            std::fs::File::open(tiers_file)?,
+            std::fs::File::open(client_tiers_file)?,
+        )
+    }
+
+    pub fn tier(&self, client_id: ClientId) -> Option<&str> {
+        self.client_tiers.get(&client_id).map(String::as_str)
+    }
+
+    /// The policy of the client's tier, if it is in one
+    pub fn policy(&self, client_id: ClientId) -> Option<&TierPolicy> {
+        self.tier(client_id).map(|tier| &self.tiers[tier])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIERS: &str = r#"{
+        "retail": {"balance-cap": 1000.0, "balance-cap-exceeded": "suspend-excess"},
+        "merchant": {"max-transactions-per-client": 100000}
+    }"#;
+
+    #[test]
+    fn it_looks_up_the_policy_of_a_clients_tier() {
+        let client_tiers = ClientTiers::from_readers(
+            TIERS.as_bytes(),
+            "client,tier\n1,retail\n2,merchant\n".as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(client_tiers.tier(1), Some("retail"));
+        assert_eq!(
+            client_tiers.policy(1),
+            Some(&TierPolicy {
+                balance_cap: Some(1000.0),
+                balance_cap_exceeded: Some(BalanceCapPolicy::SuspendExcess),
+                max_transactions_per_client: None,
+            })
+        );
+        assert_eq!(
+            client_tiers.policy(2).unwrap().max_transactions_per_client,
+            Some(100000)
+        );
+        assert_eq!(client_tiers.policy(3), None);
+    }
+
+    #[test]
+    fn it_rejects_clients_mapped_to_unknown_tiers() {
+        let result = ClientTiers::from_readers(TIERS.as_bytes(), "client,tier\n1,vip\n".as_bytes());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "client 1 is mapped to unknown tier vip"
+        );
+    }
+}
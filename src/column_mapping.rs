@@ -0,0 +1,126 @@
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::submission_order::{SEQUENCE_COLUMN, TIMESTAMP_COLUMN};
+
+/// The columns which an upstream column may be mapped onto
+const MAPPABLE_COLUMNS: [&str; 7] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "counterparty",
+    TIMESTAMP_COLUMN,
+    SEQUENCE_COLUMN,
+];
+
+/// Renames upstream input columns onto the columns read, e.g. `txn_kind=type,cust=client`, so
+/// files with other header names can be read without renaming them first. Read from JSON as an
+/// object of upstream name to column, e.g. `{"txn_kind": "type"}`.
+#[derive(Debug, Default, PartialEq, Clone, Deserialize)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct ColumnMapping {
+    renames: BTreeMap<String, String>,
+}
+
+impl ColumnMapping {
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty()
+    }
+
+    /// The headers with the mapped columns renamed
+    pub fn apply(&self, headers: &csv::StringRecord) -> csv::StringRecord {
+        headers
+            .iter()
+            .map(|header| {
+                self.renames
+                    .get(header.trim())
+                    .map_or(header, String::as_str)
+            })
+            .collect()
+    }
+
+    /// Renames the reader's headers, so that records are read with the mapped column names
+    pub fn apply_to<R: std::io::Read>(&self, reader: &mut csv::Reader<R>) -> csv::Result<()> {
+        if !self.is_empty() {
+            let headers = self.apply(reader.headers()?);
+            reader.set_headers(headers);
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for ColumnMapping {
+    type Error = String;
+
+    fn try_from(renames: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        for (upstream, column) in &renames {
+            if !MAPPABLE_COLUMNS.contains(&column.as_str()) {
+                return Err(format!(
+                    "cannot map column {} onto unknown column {}",
+                    upstream, column
+                ));
+            }
+        }
+        let mut columns: Vec<&String> = renames.values().collect();
+        columns.sort();
+        if let Some(pair) = columns.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(format!("more than one column is mapped onto {}", pair[0]));
+        }
+        Ok(Self { renames })
+    }
+}
+
+impl std::str::FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut renames = BTreeMap::new();
+        for rename in s.split(',').filter(|rename| !rename.trim().is_empty()) {
+            let (upstream, column) = rename
+                .split_once('=')
+                .ok_or_else(|| format!("invalid column mapping: {}", rename))?;
+            renames.insert(upstream.trim().to_string(), column.trim().to_string());
+        }
+        Self::try_from(renames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_renames_the_mapped_headers() {
+        let mapping: ColumnMapping = "txn_kind=type, cust=client,ref=tx,value=amount"
+            .parse()
+            .unwrap();
+        let headers = csv::StringRecord::from(vec!["txn_kind", " cust", "ref", "value", "memo"]);
+        assert_eq!(
+            mapping.apply(&headers),
+            csv::StringRecord::from(vec!["type", "client", "tx", "amount", "memo"])
+        );
+
+        let from_json: ColumnMapping = serde_json::from_str(
+            r#"{"txn_kind": "type", "cust": "client", "ref": "tx", "value": "amount"}"#,
+        )
+        .unwrap();
+        assert_eq!(from_json, mapping);
+    }
+
+    #[test]
+    fn it_rejects_invalid_mappings() {
+        assert_eq!(
+            "kind=category".parse::<ColumnMapping>(),
+            Err("cannot map column kind onto unknown column category".to_string())
+        );
+        assert_eq!(
+            "a=client,b=client".parse::<ColumnMapping>(),
+            Err("more than one column is mapped onto client".to_string())
+        );
+        assert_eq!(
+            "type".parse::<ColumnMapping>(),
+            Err("invalid column mapping: type".to_string())
+        );
+    }
+}
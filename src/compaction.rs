@@ -0,0 +1,235 @@
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::path::Path;
+
+use crate::serializable_form::round_f64_4dp_string;
+use crate::state_snapshot;
+use crate::{ClientAccount, ClientId, TransactionId};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// A settled transaction forgotten by a compaction
+#[derive(Debug, PartialEq)]
+pub struct ArchivedTransaction {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    /// Positive for a deposit, negative for a withdrawal
+    pub amount: f64,
+    pub charged_back: bool,
+    /// When the transaction was first written to a state snapshot, in seconds since the Unix epoch
+    pub retained_since: u64,
+}
+
+/// Forgets the settled transactions (never disputed, resolved or finally charged back) which have
+/// been retained in state snapshots for more than `retention_days` before `now` (in seconds since
+/// the Unix epoch), so disputes can no longer reach them. Open disputes and chargebacks which may
+/// be represented are kept however old they are. Transactions from a snapshot which predates
+/// their stamping are stamped with `now`, so that they age from the first compaction. Returns the
+/// forgotten transactions in client and transaction id order.
+pub fn compact<S: BuildHasher>(
+    accounts: &mut HashMap<ClientId, ClientAccount, S>,
+    retention_days: u64,
+    now: u64,
+) -> Vec<ArchivedTransaction> {
+    let retained_before = now.saturating_sub(retention_days.saturating_mul(SECONDS_PER_DAY));
+    let mut client_accounts: Vec<&mut ClientAccount> = accounts.values_mut().collect();
+    client_accounts.sort_unstable_by_key(|client_account| client_account.client_id);
+    client_accounts
+        .into_iter()
+        .flat_map(|client_account| {
+            client_account.stamp_retained_since(now);
+            client_account.compact(retained_before)
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+struct SerializableArchivedTransaction {
+    client: ClientId,
+    tx: TransactionId,
+    #[serde(rename = "type")]
+    transaction_type: &'static str,
+    amount: String,
+    charged_back: bool,
+    retained_since: u64,
+}
+
+/// Writes the summary of the forgotten transactions as CSV
+pub fn write_archive(
+    archived_transactions: &[ArchivedTransaction],
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    // the header is written explicitly so that a compaction which forgot nothing still has one
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(output_stream);
+    csv_writer.write_record([
+        "client",
+        "tx",
+        "type",
+        "amount",
+        "charged_back",
+        "retained_since",
+    ])?;
+    for archived_transaction in archived_transactions {
+        csv_writer.serialize(SerializableArchivedTransaction {
+            client: archived_transaction.client_id,
+            tx: archived_transaction.transaction_id,
+            transaction_type: if archived_transaction.amount < 0.0 {
+                "withdrawal"
+            } else {
+                "deposit"
+            },
+            amount: round_f64_4dp_string(archived_transaction.amount.abs())?,
+            charged_back: archived_transaction.charged_back,
+            retained_since: archived_transaction.retained_since,
+        })?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Compacts a state snapshot into `snapshot_out_file` (which may be the same file), archiving the
+/// forgotten transactions to `archive_file`. Returns how many were forgotten.
+pub fn compact_snapshot(
+    snapshot_in_file: &Path,
+    snapshot_out_file: &Path,
+    archive_file: &Path,
+    retention_days: u64,
+) -> anyhow::Result<usize> {
+    let mut accounts = state_snapshot::load_snapshot(snapshot_in_file)?;
+    let archived_transactions = compact(
+        &mut accounts,
+        retention_days,
+        crate::manifest::unix_timestamp(),
+    );
+    let mut archive_writer = std::io::BufWriter::new(std::fs::File::create(archive_file)?);
+    write_archive(&archived_transactions, &mut archive_writer)?;
+    std::io::Write::flush(&mut archive_writer)?;
+    state_snapshot::save_snapshot(&accounts, snapshot_out_file)?;
+    Ok(archived_transactions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing_options::ProcessingOptions;
+    use crate::testing::{accounts_from, chargeback, deposit, dispute, resolve, withdrawal};
+
+    const DAY: u64 = SECONDS_PER_DAY;
+
+    #[test]
+    fn it_forgets_the_settled_transactions_retained_past_the_window() {
+        let options = ProcessingOptions::default();
+        let mut accounts = accounts_from(
+            &[
+                deposit(1, 1, 5.0),
+                withdrawal(1, 2, 1.5),
+                deposit(1, 3, 2.0),
+                dispute(1, 3),
+                deposit(2, 4, 1.0),
+                dispute(2, 4),
+                chargeback(2, 4),
+            ],
+            &options,
+        );
+        for client_account in accounts.values_mut() {
+            client_account.stamp_retained_since(10 * DAY);
+        }
+        crate::process_transaction(
+            &mut accounts,
+            &deposit(1, 5, 4.0),
+            &options,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+
+        // the deposit not yet in a snapshot is stamped now, so it is kept
+        assert_eq!(compact(&mut accounts, 30, 20 * DAY), vec![]);
+        let archived_transactions = compact(&mut accounts, 30, 50 * DAY);
+        assert_eq!(
+            archived_transactions,
+            vec![
+                ArchivedTransaction {
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: 5.0,
+                    charged_back: false,
+                    retained_since: 10 * DAY,
+                },
+                ArchivedTransaction {
+                    client_id: 1,
+                    transaction_id: 2,
+                    amount: -1.5,
+                    charged_back: false,
+                    retained_since: 10 * DAY,
+                },
+            ]
+        );
+        // the open dispute and the chargeback which may still be represented are kept
+        assert!(accounts[&1].has_transaction(3));
+        assert!(accounts[&1].has_transaction(5));
+        assert!(accounts[&2].has_transaction(4));
+        assert!(accounts[&1].carries_over_transaction(1));
+
+        crate::process_transaction(
+            &mut accounts,
+            &resolve(1, 3),
+            &options,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        let archived_transactions = compact(&mut accounts, 30, 50 * DAY);
+        assert_eq!(
+            archived_transactions
+                .iter()
+                .map(|archived_transaction| archived_transaction.transaction_id)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+        let error = crate::process_transaction(
+            &mut accounts,
+            &dispute(1, 1),
+            &options,
+            &mut std::io::sink(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            crate::TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 1 }
+        );
+    }
+
+    #[test]
+    fn it_writes_the_archive_of_the_forgotten_transactions() {
+        let mut archive = Vec::new();
+        write_archive(
+            &[
+                ArchivedTransaction {
+                    client_id: 1,
+                    transaction_id: 2,
+                    amount: -1.5,
+                    charged_back: false,
+                    retained_since: 86_400,
+                },
+                ArchivedTransaction {
+                    client_id: 2,
+                    transaction_id: 4,
+                    amount: 1.0,
+                    charged_back: true,
+                    retained_since: 172_800,
+                },
+            ],
+            &mut archive,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(archive).unwrap(),
+            "client,tx,type,amount,charged_back,retained_since
+1,2,withdrawal,1.5000,false,86400
+2,4,deposit,1.0000,true,172800
+"
+        );
+    }
+}
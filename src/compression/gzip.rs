@@ -0,0 +1,320 @@
+use std::io::Write;
+
+use crate::control_totals::crc32_update;
+
+const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const BLOCK_SIZE: usize = 64 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 64;
+const NO_POSITION: usize = usize::MAX;
+
+const LENGTH_BASES: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Packs values into bytes least significant bit first, as deflate (RFC 1951) does.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buffer: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.bit_buffer |= u64::from(value) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting with their most significant bit
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn align(&mut self) {
+        if self.bit_count > 0 {
+            self.write_bits(0, 8 - self.bit_count);
+        }
+    }
+
+    // A literal/length symbol, with the fixed Huffman code from RFC 1951 3.2.6
+    fn write_symbol(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_match(&mut self, length: usize, distance: usize) {
+        let code = LENGTH_BASES.partition_point(|base| *base <= length) - 1;
+        self.write_symbol(257 + code as u32);
+        self.write_bits(
+            (length - LENGTH_BASES[code]) as u32,
+            LENGTH_EXTRA_BITS[code],
+        );
+
+        let code = DISTANCE_BASES.partition_point(|base| *base <= distance) - 1;
+        self.write_code(code as u32, 5);
+        self.write_bits(
+            (distance - DISTANCE_BASES[code]) as u32,
+            DISTANCE_EXTRA_BITS[code],
+        );
+    }
+}
+
+/// Writes a gzip stream, compressing with LZ77 over a 32 KiB window and deflate's fixed Huffman
+/// codes. The stream is finished by `finish`, or failing that when the writer is dropped.
+pub struct GzipWriter<W: Write> {
+    inner: Option<W>,
+    /// The last `WINDOW_SIZE` bytes already compressed, followed by the bytes still to compress
+    history: Vec<u8>,
+    /// The position in the whole stream of `history[0]`
+    history_start: usize,
+    /// The index in `history` of the first byte still to compress
+    pending: usize,
+    /// The latest stream position with each hash of 3 bytes
+    head: Vec<usize>,
+    /// The previous stream position with the same hash, for the positions in the window
+    previous: Vec<usize>,
+    bits: BitWriter,
+    crc: u32,
+    size: u32,
+    finished: bool,
+}
+
+impl<W: Write> GzipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner: Some(inner),
+            history: Vec::new(),
+            history_start: 0,
+            pending: 0,
+            head: vec![NO_POSITION; 1 << HASH_BITS],
+            previous: vec![NO_POSITION; WINDOW_SIZE],
+            bits: BitWriter {
+                bytes: GZIP_HEADER.to_vec(),
+                ..BitWriter::default()
+            },
+            crc: 0xFFFF_FFFF,
+            size: 0,
+            finished: false,
+        }
+    }
+
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.write_trailer()?;
+        Ok(self.inner.take().expect("gzip writer already finished"))
+    }
+
+    fn inner(&mut self) -> &mut W {
+        self.inner.as_mut().expect("gzip writer already finished")
+    }
+
+    fn hash(&self, index: usize) -> usize {
+        let bytes = &self.history[index..index + MIN_MATCH];
+        let value = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+        (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, index: usize) {
+        if index + MIN_MATCH > self.history.len() {
+            return;
+        }
+        let hash = self.hash(index);
+        let position = self.history_start + index;
+        self.previous[position % WINDOW_SIZE] = self.head[hash];
+        self.head[hash] = position;
+    }
+
+    // The longest earlier match in the window for the bytes at `index`, not reaching past `end`
+    fn longest_match(&self, index: usize, end: usize) -> Option<(usize, usize)> {
+        let max_length = (end - index).min(MAX_MATCH);
+        if max_length < MIN_MATCH {
+            return None;
+        }
+        let position = self.history_start + index;
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(index)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == NO_POSITION
+                || candidate >= position
+                || candidate < self.history_start
+                || position - candidate > WINDOW_SIZE
+            {
+                break;
+            }
+            let candidate_index = candidate - self.history_start;
+            let length = self.history[candidate_index..]
+                .iter()
+                .zip(&self.history[index..index + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length >= MIN_MATCH && best.is_none_or(|(best_length, _)| length > best_length) {
+                best = Some((length, position - candidate));
+                if length == max_length {
+                    break;
+                }
+            }
+            let next = self.previous[candidate % WINDOW_SIZE];
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+        best
+    }
+
+    // Compresses the pending bytes up to `end` as one block
+    fn compress_block(&mut self, end: usize, last: bool) {
+        self.bits.write_bits(u32::from(last), 1);
+        self.bits.write_bits(1, 2);
+
+        let mut index = self.pending;
+        while index < end {
+            match self.longest_match(index, end) {
+                Some((length, distance)) => {
+                    self.bits.write_match(length, distance);
+                    for matched in index..index + length {
+                        self.insert(matched);
+                    }
+                    index += length;
+                }
+                None => {
+                    self.bits.write_symbol(u32::from(self.history[index]));
+                    self.insert(index);
+                    index += 1;
+                }
+            }
+        }
+        self.bits.write_symbol(256);
+        self.pending = end;
+
+        if self.pending > WINDOW_SIZE {
+            let discarded = self.pending - WINDOW_SIZE;
+            self.history.drain(..discarded);
+            self.history_start += discarded;
+            self.pending -= discarded;
+        }
+    }
+
+    fn write_compressed_bytes(&mut self) -> std::io::Result<()> {
+        let bytes = std::mem::take(&mut self.bits.bytes);
+        self.inner().write_all(&bytes)
+    }
+
+    fn write_trailer(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.compress_block(self.history.len(), true);
+        self.bits.align();
+        let crc = !self.crc;
+        let size = self.size;
+        self.bits.bytes.extend_from_slice(&crc.to_le_bytes());
+        self.bits.bytes.extend_from_slice(&size.to_le_bytes());
+        self.write_compressed_bytes()?;
+        self.inner().flush()
+    }
+}
+
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.crc = crc32_update(self.crc, buf);
+        // The size is kept modulo 2^32, as gzip records it
+        self.size = self.size.wrapping_add(buf.len() as u32);
+        self.history.extend_from_slice(buf);
+        while self.history.len() - self.pending >= BLOCK_SIZE {
+            self.compress_block(self.pending + BLOCK_SIZE, false);
+        }
+        self.write_compressed_bytes()?;
+        Ok(buf.len())
+    }
+
+    /// Compresses the buffered bytes and ends with an empty stored block (a deflate sync flush),
+    /// so that everything written so far can be decompressed
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        if self.pending < self.history.len() {
+            self.compress_block(self.history.len(), false);
+        }
+        self.bits.write_bits(0, 3);
+        self.bits.align();
+        self.bits.bytes.extend_from_slice(&[0, 0, 0xff, 0xff]);
+        self.write_compressed_bytes()?;
+        self.inner().flush()
+    }
+}
+
+impl<W: Write> Drop for GzipWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_trailer();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gzip(input: &[u8]) -> Vec<u8> {
+        let mut gzip_writer = GzipWriter::new(Vec::new());
+        gzip_writer.write_all(input).unwrap();
+        gzip_writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_gzip_of_nothing_is_an_empty_final_block() {
+        assert_eq!(
+            gzip(b""),
+            vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_gzip_encodes_repeats_as_matches() {
+        // "a" as a literal, then "aaaa" as a match of length 4 at distance 1
+        assert_eq!(
+            gzip(b"aaaaa"),
+            vec![
+                0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff, 0x4b, 0x04, 0x01, 0x00, 0xb9, 0x93, 0xac,
+                0xee, 5, 0, 0, 0
+            ]
+        );
+
+        let input =
+            "client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n".repeat(10_000);
+        let compressed = gzip(input.as_bytes());
+        assert!(compressed.len() < input.len() / 50);
+        assert_eq!(
+            compressed[compressed.len() - 4..],
+            (input.len() as u32).to_le_bytes()
+        );
+    }
+}
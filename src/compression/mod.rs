@@ -0,0 +1,98 @@
+use std::io::Write;
+
+#[cfg(feature = "gzip")]
+mod gzip;
+
+#[cfg(feature = "gzip")]
+pub use gzip::GzipWriter;
+
+/// How output files and the output stream are compressed. gzip needs the `gzip` feature, which
+/// builds in a small deflate encoder; there is no zstd, as no zstd crate is available to the build.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Compression {
+    #[default]
+    None,
+    /// gzip (RFC 1952), written with fixed Huffman codes
+    #[cfg(feature = "gzip")]
+    Gz,
+}
+
+impl Compression {
+    pub fn writer<W: Write>(self, inner: W) -> CompressedWriter<W> {
+        match self {
+            Compression::None => CompressedWriter::Plain(inner),
+            #[cfg(feature = "gzip")]
+            Compression::Gz => CompressedWriter::Gzip(GzipWriter::new(inner)),
+        }
+    }
+
+    /// What a file written with this compression has added to its name, e.g. `.gz`
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            #[cfg(feature = "gzip")]
+            Compression::Gz => ".gz",
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            #[cfg(feature = "gzip")]
+            "gz" => Ok(Compression::Gz),
+            _ => Err(format!("unknown compression: {}", s)),
+        }
+    }
+}
+
+pub enum CompressedWriter<W: Write> {
+    Plain(W),
+    #[cfg(feature = "gzip")]
+    Gzip(GzipWriter<W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    /// Writes the end of the compressed stream, returning the inner writer
+    pub fn finish(self) -> std::io::Result<W> {
+        match self {
+            CompressedWriter::Plain(inner) => Ok(inner),
+            #[cfg(feature = "gzip")]
+            CompressedWriter::Gzip(gzip_writer) => gzip_writer.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            CompressedWriter::Plain(inner) => inner.write(buf),
+            #[cfg(feature = "gzip")]
+            CompressedWriter::Gzip(gzip_writer) => gzip_writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            CompressedWriter::Plain(inner) => inner.flush(),
+            #[cfg(feature = "gzip")]
+            CompressedWriter::Gzip(gzip_writer) => gzip_writer.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_compression() {
+        #[cfg(feature = "gzip")]
+        assert_eq!("gz".parse(), Ok(Compression::Gz));
+        assert_eq!("none".parse(), Ok(Compression::None));
+        assert!("zst".parse::<Compression>().is_err());
+    }
+}
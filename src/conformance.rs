@@ -0,0 +1,223 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::hashing::Accounts;
+use crate::output_options::BoolFormat;
+use crate::serializable_form::round_f64_4dp_string;
+use crate::ClientId;
+
+/// An account as given in a reference implementation's output.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ExpectedAccount {
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExpectedRow {
+    client: ClientId,
+    available: f64,
+    held: f64,
+    total: f64,
+    locked: String,
+}
+
+/// Reads an output file (with the locked column in any `--bool-format`).
+pub fn read_expected(
+    input_stream: impl std::io::Read,
+) -> anyhow::Result<BTreeMap<ClientId, ExpectedAccount>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input_stream);
+    let mut expected = BTreeMap::new();
+    for row in reader.deserialize() {
+        let row: ExpectedRow = row?;
+        let locked = BoolFormat::parse_any(&row.locked).ok_or_else(|| {
+            anyhow::anyhow!(
+                "invalid locked value for client {}: {}",
+                row.client,
+                row.locked
+            )
+        })?;
+        expected.insert(
+            row.client,
+            ExpectedAccount {
+                available: row.available,
+                held: row.held,
+                total: row.total,
+                locked,
+            },
+        );
+    }
+    Ok(expected)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiscrepancyKind {
+    /// The expected output has the client, but this run doesn't
+    MissingClient,
+    /// This run has a client the expected output doesn't
+    UnexpectedClient,
+    /// An amount differs by more than the tolerance
+    AmountDelta,
+    LockMismatch,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Discrepancy {
+    pub client: ClientId,
+    pub discrepancy: DiscrepancyKind,
+    /// The differing column, for amount and lock discrepancies
+    pub column: Option<&'static str>,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+fn amount_discrepancy(
+    client: ClientId,
+    column: &'static str,
+    expected: f64,
+    actual: f64,
+    tolerance: f64,
+) -> anyhow::Result<Option<Discrepancy>> {
+    if (expected - actual).abs() <= tolerance {
+        return Ok(None);
+    }
+    Ok(Some(Discrepancy {
+        client,
+        discrepancy: DiscrepancyKind::AmountDelta,
+        column: Some(column),
+        expected: Some(round_f64_4dp_string(expected)?),
+        actual: Some(round_f64_4dp_string(actual)?),
+    }))
+}
+
+/// Compares the accounts with the expected output, in client id order. Errored accounts are left
+/// out of the output, so they are left out of the comparison too.
+pub fn compare(
+    accounts: &Accounts,
+    expected: &BTreeMap<ClientId, ExpectedAccount>,
+    tolerance: f64,
+) -> anyhow::Result<Vec<Discrepancy>> {
+    let client_ids: BTreeSet<ClientId> = accounts
+        .values()
+        .filter(|client_account| client_account.errored().is_none())
+        .map(|client_account| client_account.client_id)
+        .chain(expected.keys().copied())
+        .collect();
+
+    let mut discrepancies = Vec::new();
+    for client in client_ids {
+        let actual = accounts
+            .get(&client)
+            .filter(|client_account| client_account.errored().is_none());
+        let (expected, actual) = match (expected.get(&client), actual) {
+            (Some(expected), Some(actual)) => (expected, actual),
+            (Some(_), None) => {
+                discrepancies.push(Discrepancy {
+                    client,
+                    discrepancy: DiscrepancyKind::MissingClient,
+                    column: None,
+                    expected: None,
+                    actual: None,
+                });
+                continue;
+            }
+            (None, _) => {
+                discrepancies.push(Discrepancy {
+                    client,
+                    discrepancy: DiscrepancyKind::UnexpectedClient,
+                    column: None,
+                    expected: None,
+                    actual: None,
+                });
+                continue;
+            }
+        };
+
+        for (column, expected_amount, actual_amount) in [
+            ("available", expected.available, actual.balance.available),
+            ("held", expected.held, actual.balance.held),
+            ("total", expected.total, actual.balance.total()),
+        ] {
+            discrepancies.extend(amount_discrepancy(
+                client,
+                column,
+                expected_amount,
+                actual_amount,
+                tolerance,
+            )?);
+        }
+        if expected.locked != actual.locked {
+            discrepancies.push(Discrepancy {
+                client,
+                discrepancy: DiscrepancyKind::LockMismatch,
+                column: Some("locked"),
+                expected: Some(expected.locked.to_string()),
+                actual: Some(actual.locked.to_string()),
+            });
+        }
+    }
+    Ok(discrepancies)
+}
+
+pub fn write_report(
+    discrepancies: &[Discrepancy],
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(output_stream);
+    csv_writer.write_record(["client", "discrepancy", "column", "expected", "actual"])?;
+    for discrepancy in discrepancies {
+        csv_writer.serialize(discrepancy)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClientAccount;
+
+    #[test]
+    fn test_compare_reports_each_kind_of_discrepancy() {
+        let expected = read_expected(
+            r#"client,available,held,total,locked
+1,10.0000,0.0000,10.0000,FALSE
+2,5.0000,1.0000,6.0000,1
+3,1.0000,0.0000,1.0000,0
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let mut accounts = Accounts::default();
+        let mut client_account = ClientAccount::new(1);
+        client_account.balance.available = 10.00004;
+        accounts.insert(1, client_account);
+        let mut client_account = ClientAccount::new(2);
+        client_account.balance.available = 5.5;
+        client_account.balance.held = 1.0;
+        accounts.insert(2, client_account);
+        accounts.insert(4, ClientAccount::new(4));
+
+        let mut output = Vec::<u8>::new();
+        write_report(&compare(&accounts, &expected, 0.0001).unwrap(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"client,discrepancy,column,expected,actual
+2,amount-delta,available,5.0000,5.5000
+2,amount-delta,total,6.0000,6.5000
+2,lock-mismatch,locked,true,false
+3,missing-client,,,
+4,unexpected-client,,,
+"#
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use rust_decimal::Decimal;
+use serde_derive::Serialize;
+use std::str::FromStr;
+
+use crate::serializable_form;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Continues a CRC-32 (IEEE) over more bytes; start with `0xFFFF_FFFF` and invert the result.
+pub(crate) fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for byte in bytes {
+        crc = CRC32_TABLE[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// Passes bytes through to the inner writer while computing their CRC-32 (IEEE).
+pub struct Crc32Writer<'a> {
+    inner: &'a mut dyn std::io::Write,
+    crc: u32,
+    bytes_written: u64,
+}
+
+impl<'a> Crc32Writer<'a> {
+    pub fn new(inner: &'a mut dyn std::io::Write) -> Self {
+        Self {
+            inner,
+            crc: 0xFFFF_FFFF,
+            bytes_written: 0,
+        }
+    }
+
+    pub fn crc32(&self) -> u32 {
+        !self.crc
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+impl std::io::Write for Crc32Writer<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..written]);
+        self.bytes_written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Batch-level totals which let downstream loaders verify they ingested the full output.
+/// The sums are exact sums of the rounded values as written.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ControlTotals {
+    pub client_count: usize,
+    pub sum_available: String,
+    pub sum_held: String,
+    pub sum_total: String,
+    pub checksum_algorithm: String,
+    pub checksum: String,
+}
+
+impl ControlTotals {
+    pub fn new(output: &[serializable_form::Output], crc32: u32) -> anyhow::Result<Self> {
+        let mut sum_available = Decimal::ZERO;
+        let mut sum_held = Decimal::ZERO;
+        let mut sum_total = Decimal::ZERO;
+        for output in output {
+            sum_available += Decimal::from_str(&output.available)?;
+            sum_held += Decimal::from_str(&output.held)?;
+            sum_total += Decimal::from_str(&output.total)?;
+        }
+
+        Ok(Self {
+            client_count: output.len(),
+            sum_available: format!("{:.4}", sum_available),
+            sum_held: format!("{:.4}", sum_held),
+            sum_total: format!("{:.4}", sum_total),
+            checksum_algorithm: "crc32".to_string(),
+            checksum: format!("{:08x}", crc32),
+        })
+    }
+
+    pub fn write_json(&self, output_stream: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut *output_stream, self)?;
+        writeln!(output_stream)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_crc32_writer() {
+        let mut output = Vec::<u8>::new();
+        let mut crc32_writer = Crc32Writer::new(&mut output);
+        crc32_writer.write_all(b"123456789").unwrap();
+
+        // the standard CRC-32 check value
+        assert_eq!(crc32_writer.crc32(), 0xCBF4_3926);
+        assert_eq!(output, b"123456789");
+    }
+
+    #[test]
+    fn test_control_totals() {
+        let output = vec![
+            serializable_form::Output {
+                client: 1,
+                available: "1.5000".to_string(),
+                held: "0.0000".to_string(),
+                total: "1.5000".to_string(),
+                locked: false,
+                lock_reason: None,
+                chargebacks: 0,
+                charged_back: "0.0000".to_string(),
+                closed: false,
+            },
+            serializable_form::Output {
+                client: 2,
+                available: "-1.0000".to_string(),
+                held: "0.1234".to_string(),
+                total: "-0.8766".to_string(),
+                locked: true,
+                lock_reason: None,
+                chargebacks: 0,
+                charged_back: "0.0000".to_string(),
+                closed: false,
+            },
+        ];
+
+        let control_totals = ControlTotals::new(&output, 0xCBF4_3926).unwrap();
+
+        assert_eq!(
+            control_totals,
+            ControlTotals {
+                client_count: 2,
+                sum_available: "0.5000".to_string(),
+                sum_held: "0.1234".to_string(),
+                sum_total: "0.6234".to_string(),
+                checksum_algorithm: "crc32".to_string(),
+                checksum: "cbf43926".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use chrono::{Datelike, NaiveDate, Utc};
+
+/// The days from 0001-01-01, chrono's day 1, to the Unix epoch
+const EPOCH_DAYS_FROM_CE: i64 = 719_163;
+
+/// A calendar date in UTC.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    pub fn today() -> Self {
+        Utc::now().date_naive().into()
+    }
+
+    /// The date `days` after the Unix epoch. Panics past chrono's range of about 262,000 years
+    /// either side of the epoch, which no parsed timestamp reaches.
+    pub fn from_days_since_epoch(days: i64) -> Self {
+        i32::try_from(days + EPOCH_DAYS_FROM_CE)
+            .ok()
+            .and_then(NaiveDate::from_num_days_from_ce_opt)
+            .expect("date out of range")
+            .into()
+    }
+
+    pub fn days_since_epoch(self) -> i64 {
+        i64::from(self.naive_date().num_days_from_ce()) - EPOCH_DAYS_FROM_CE
+    }
+
+    fn naive_date(self) -> NaiveDate {
+        i32::try_from(self.year)
+            .ok()
+            .and_then(|year| NaiveDate::from_ymd_opt(year, self.month, self.day))
+            .expect("date out of range")
+    }
+
+    /// YYYYMMDD
+    pub fn to_compact_string(self) -> String {
+        format!("{:04}{:02}{:02}", self.year, self.month, self.day)
+    }
+
+    /// MM/DD/YYYY
+    pub fn to_us_string(self) -> String {
+        format!("{:02}/{:02}/{:04}", self.month, self.day, self.year)
+    }
+}
+
+impl From<NaiveDate> for Date {
+    fn from(date: NaiveDate) -> Self {
+        Self {
+            year: i64::from(date.year()),
+            month: date.month(),
+            day: date.day(),
+        }
+    }
+}
+
+/// YYYY-MM-DD
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Parses YYYY-MM-DD
+impl std::str::FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid date (expected YYYY-MM-DD): {}", s);
+        // chrono takes fields of any width, so the shape is checked first
+        let is_shaped = s.len() == 10
+            && s.bytes().enumerate().all(|(index, byte)| match index {
+                4 | 7 => byte == b'-',
+                _ => byte.is_ascii_digit(),
+            });
+        if !is_shaped {
+            return Err(invalid());
+        }
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map(Date::from)
+            .map_err(|_| invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "2024-02-29".parse::<Date>(),
+            Ok(Date {
+                year: 2024,
+                month: 2,
+                day: 29
+            })
+        );
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("2000-02-29".parse::<Date>().is_ok());
+        assert!("2100-02-29".parse::<Date>().is_err());
+        assert!("2023-04-31".parse::<Date>().is_err());
+        assert!("2023-13-01".parse::<Date>().is_err());
+        assert!("2023-1-01".parse::<Date>().is_err());
+        assert!("20230101".parse::<Date>().is_err());
+    }
+
+    #[test]
+    fn test_from_days_since_epoch() {
+        assert_eq!(
+            Date::from_days_since_epoch(0),
+            Date {
+                year: 1970,
+                month: 1,
+                day: 1
+            }
+        );
+        assert_eq!(
+            Date::from_days_since_epoch(19_052),
+            Date {
+                year: 2022,
+                month: 3,
+                day: 1
+            }
+        );
+        assert_eq!(
+            Date::from_days_since_epoch(19_051).to_string(),
+            "2022-02-28"
+        );
+    }
+
+    #[test]
+    fn test_days_since_epoch() {
+        for days in [-719_468, -1, 0, 59, 19_051, 19_052, 2_932_896] {
+            assert_eq!(Date::from_days_since_epoch(days).days_since_epoch(), days);
+        }
+    }
+
+    #[test]
+    fn test_formatting() {
+        let date = Date {
+            year: 2022,
+            month: 3,
+            day: 1,
+        };
+        assert_eq!(date.to_string(), "2022-03-01");
+        assert_eq!(date.to_compact_string(), "20220301");
+        assert_eq!(date.to_us_string(), "03/01/2022");
+    }
+}
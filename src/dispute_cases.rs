@@ -0,0 +1,380 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::client_account::error::TransactionProcessingError;
+use crate::hashing::Accounts;
+use crate::journal::{JournalEntry, JournalSink, LedgerAccount};
+use crate::serializable_form::round_f64_4dp_string;
+use crate::{ClientAccount, ClientId, TransactionId, TransactionType};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DisputeCasesFormat {
+    Csv,
+    Json,
+}
+
+impl std::str::FromStr for DisputeCasesFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(DisputeCasesFormat::Csv),
+            "json" => Ok(DisputeCasesFormat::Json),
+            _ => Err(format!("unknown dispute cases format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DisputeCasesOptions {
+    /// Write the dispute cases to this file
+    pub file: Option<PathBuf>,
+    /// Import the open cases from this file (written by an earlier run) before processing
+    pub import_file: Option<PathBuf>,
+    /// Resolve or charge back imported open cases with the arbitration outcomes in this file
+    /// after processing
+    pub arbitration_file: Option<PathBuf>,
+    pub format: DisputeCasesFormat,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaseOutcome {
+    Open,
+    Resolved,
+    ChargedBack,
+    /// The chargeback was represented and awaits the second decision
+    Represented,
+    FinalChargedBack,
+    RepresentmentWon,
+}
+
+/// The lifecycle of one dispute of a transaction. The amount is the disputed transaction's
+/// amount: positive for a deposit, negative for a withdrawal.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DisputeCase {
+    pub case_id: String,
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: f64,
+    pub outcome: CaseOutcome,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SerializableDisputeCase {
+    case_id: String,
+    client: ClientId,
+    tx: TransactionId,
+    amount: String,
+    outcome: CaseOutcome,
+}
+
+/// Follows every dispute from the dispute to its resolve or chargeback, and a chargeback through
+/// the representment flow to its final outcome. A case is identified by
+/// its client and transaction ids, with a `-<n>` suffix for the n-th dispute of a transaction
+/// within a run.
+#[derive(Debug, Default)]
+pub struct DisputeCases {
+    cases: Vec<DisputeCase>,
+    open_cases: HashMap<(ClientId, TransactionId), usize>,
+    // the charged back and represented cases, which the representment flow may still update
+    charged_back_cases: HashMap<(ClientId, TransactionId), usize>,
+    disputes: HashMap<(ClientId, TransactionId), usize>,
+}
+
+/// The id of the case of the n-th dispute of a transaction within a run
+pub fn case_id(client_id: ClientId, transaction_id: TransactionId, disputes: usize) -> String {
+    if disputes == 1 {
+        format!("{}-{}", client_id, transaction_id)
+    } else {
+        format!("{}-{}-{}", client_id, transaction_id, disputes)
+    }
+}
+
+impl DisputeCases {
+    /// Carries on the open cases imported from an earlier run.
+    pub fn with_open_cases(open_cases: Vec<DisputeCase>) -> Self {
+        let mut dispute_cases = Self::default();
+        for case in open_cases {
+            dispute_cases.open(case);
+        }
+        dispute_cases
+    }
+
+    pub fn cases(&self) -> &[DisputeCase] {
+        &self.cases
+    }
+
+    fn open(&mut self, case: DisputeCase) {
+        self.open_cases
+            .insert((case.client_id, case.transaction_id), self.cases.len());
+        self.cases.push(case);
+    }
+
+    fn close(&mut self, entry: &JournalEntry, outcome: CaseOutcome) -> Option<usize> {
+        let index = self
+            .open_cases
+            .remove(&(entry.client_id, entry.transaction_id))?;
+        self.cases[index].outcome = outcome;
+        Some(index)
+    }
+
+    fn represent(&mut self, entry: &JournalEntry, outcome: CaseOutcome) {
+        let key = (entry.client_id, entry.transaction_id);
+        let index = if outcome == CaseOutcome::Represented {
+            self.charged_back_cases.get(&key).copied()
+        } else {
+            self.charged_back_cases.remove(&key)
+        };
+        if let Some(index) = index {
+            self.cases[index].outcome = outcome;
+        }
+    }
+
+    pub fn write(
+        &self,
+        format: DisputeCasesFormat,
+        output_stream: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let cases = self
+            .cases
+            .iter()
+            .map(|case| {
+                Ok(SerializableDisputeCase {
+                    case_id: case.case_id.clone(),
+                    client: case.client_id,
+                    tx: case.transaction_id,
+                    amount: round_f64_4dp_string(case.amount)?,
+                    outcome: case.outcome,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        match format {
+            DisputeCasesFormat::Csv => {
+                let mut csv_writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(output_stream);
+                csv_writer.write_record(["case_id", "client", "tx", "amount", "outcome"])?;
+                for case in &cases {
+                    csv_writer.serialize(case)?;
+                }
+                csv_writer.flush()?;
+            }
+            DisputeCasesFormat::Json => {
+                serde_json::to_writer_pretty(&mut *output_stream, &cases)?;
+                writeln!(output_stream)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl JournalSink for DisputeCases {
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        match entry.transaction_type {
+            TransactionType::Dispute => {
+                // a new dispute ends the representment flow of an earlier chargeback
+                self.charged_back_cases
+                    .remove(&(entry.client_id, entry.transaction_id));
+                let disputes = self
+                    .disputes
+                    .entry((entry.client_id, entry.transaction_id))
+                    .or_default();
+                *disputes += 1;
+                let case_id = case_id(entry.client_id, entry.transaction_id, *disputes);
+                let amount = if entry.credit == LedgerAccount::ClientHeld(entry.client_id) {
+                    entry.amount
+                } else {
+                    -entry.amount
+                };
+                self.open(DisputeCase {
+                    case_id,
+                    client_id: entry.client_id,
+                    transaction_id: entry.transaction_id,
+                    amount,
+                    outcome: CaseOutcome::Open,
+                });
+            }
+            TransactionType::Resolve => {
+                self.close(entry, CaseOutcome::Resolved);
+            }
+            TransactionType::Chargeback => {
+                if let Some(index) = self.close(entry, CaseOutcome::ChargedBack) {
+                    self.charged_back_cases
+                        .insert((entry.client_id, entry.transaction_id), index);
+                }
+            }
+            TransactionType::Representment => self.represent(entry, CaseOutcome::Represented),
+            TransactionType::FinalChargeback => {
+                self.represent(entry, CaseOutcome::FinalChargedBack)
+            }
+            TransactionType::RepresentmentWon => {
+                self.represent(entry, CaseOutcome::RepresentmentWon)
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Reads every case from a file written by `DisputeCases::write`.
+pub fn read_cases(file: &Path, format: DisputeCasesFormat) -> anyhow::Result<Vec<DisputeCase>> {
+    let input_stream = std::fs::File::open(file)?;
+    let cases: Vec<SerializableDisputeCase> = match format {
+        DisputeCasesFormat::Csv => csv::Reader::from_reader(input_stream)
+            .deserialize()
+            .collect::<Result<_, _>>()?,
+        DisputeCasesFormat::Json => serde_json::from_reader(input_stream)?,
+    };
+
+    cases
+        .into_iter()
+        .map(|case| {
+            Ok(DisputeCase {
+                case_id: case.case_id,
+                client_id: case.client,
+                transaction_id: case.tx,
+                amount: case.amount.parse()?,
+                outcome: case.outcome,
+            })
+        })
+        .collect()
+}
+
+/// Reads the open cases from a file written by `DisputeCases::write`.
+pub fn read_open_cases(
+    file: &Path,
+    format: DisputeCasesFormat,
+) -> anyhow::Result<Vec<DisputeCase>> {
+    Ok(read_cases(file, format)?
+        .into_iter()
+        .filter(|case| case.outcome == CaseOutcome::Open)
+        .collect())
+}
+
+/// Adds the open cases to the accounts (creating them as needed), so that their resolves and
+/// chargebacks can be processed in this run.
+pub fn import_open_cases(
+    accounts: &mut Accounts,
+    open_cases: &[DisputeCase],
+) -> Result<(), TransactionProcessingError> {
+    for case in open_cases {
+        accounts
+            .entry(case.client_id)
+            .or_insert_with(|| ClientAccount::new(case.client_id))
+            .import_open_dispute(case.transaction_id, case.amount, &case.case_id)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispute_cases_follow_each_dispute() {
+        let mut dispute_cases = DisputeCases::with_open_cases(vec![DisputeCase {
+            case_id: "2-9".to_string(),
+            client_id: 2,
+            transaction_id: 9,
+            amount: 3.0,
+            outcome: CaseOutcome::Open,
+        }]);
+        for entry in [
+            JournalEntry::new(1, 1, TransactionType::Deposit, 10.0),
+            JournalEntry::new(1, 1, TransactionType::Dispute, 10.0),
+            JournalEntry::new(1, 1, TransactionType::Resolve, 10.0),
+            JournalEntry::new(1, 1, TransactionType::Dispute, 10.0),
+            JournalEntry::new(1, 2, TransactionType::Dispute, -2.5),
+            JournalEntry::new(2, 9, TransactionType::Chargeback, 3.0),
+        ] {
+            dispute_cases.record(&entry).unwrap();
+        }
+
+        let mut output = Vec::<u8>::new();
+        dispute_cases
+            .write(DisputeCasesFormat::Csv, &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"case_id,client,tx,amount,outcome
+2-9,2,9,3.0000,charged-back
+1-1,1,1,10.0000,resolved
+1-1-2,1,1,10.0000,open
+1-2,1,2,-2.5000,open
+"#
+        );
+    }
+
+    #[test]
+    fn test_dispute_cases_follow_the_representment_flow() {
+        let mut dispute_cases = DisputeCases::default();
+        for entry in [
+            JournalEntry::new(1, 1, TransactionType::Dispute, 10.0),
+            JournalEntry::new(1, 1, TransactionType::Chargeback, 10.0),
+            JournalEntry::new(1, 1, TransactionType::Representment, 10.0),
+            JournalEntry::new(1, 2, TransactionType::Dispute, 5.0),
+            JournalEntry::new(1, 2, TransactionType::Chargeback, 5.0),
+            JournalEntry::new(1, 2, TransactionType::Representment, 5.0),
+            JournalEntry::new(1, 2, TransactionType::FinalChargeback, 5.0),
+            JournalEntry::new(1, 3, TransactionType::Dispute, 1.0),
+            JournalEntry::new(1, 3, TransactionType::Chargeback, 1.0),
+            JournalEntry::new(1, 3, TransactionType::Representment, 1.0),
+            JournalEntry::new(1, 3, TransactionType::RepresentmentWon, 1.0),
+        ] {
+            dispute_cases.record(&entry).unwrap();
+        }
+
+        let outcomes: Vec<CaseOutcome> = dispute_cases
+            .cases()
+            .iter()
+            .map(|case| case.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                CaseOutcome::Represented,
+                CaseOutcome::FinalChargedBack,
+                CaseOutcome::RepresentmentWon
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_cases_round_trip_into_a_later_run() {
+        let mut dispute_cases = DisputeCases::default();
+        dispute_cases
+            .record(&JournalEntry::new(1, 1, TransactionType::Dispute, 10.0))
+            .unwrap();
+        dispute_cases
+            .record(&JournalEntry::new(1, 2, TransactionType::Dispute, 1.0))
+            .unwrap();
+        dispute_cases
+            .record(&JournalEntry::new(1, 2, TransactionType::Resolve, 1.0))
+            .unwrap();
+
+        let cases_file = std::env::temp_dir().join("rs_bpt-dispute_cases-round_trip.json");
+        dispute_cases
+            .write(
+                DisputeCasesFormat::Json,
+                &mut std::fs::File::create(&cases_file).unwrap(),
+            )
+            .unwrap();
+        let open_cases = read_open_cases(&cases_file, DisputeCasesFormat::Json).unwrap();
+        std::fs::remove_file(&cases_file).unwrap();
+
+        assert_eq!(open_cases.len(), 1);
+        assert_eq!(open_cases[0].case_id, "1-1");
+
+        let mut accounts = Accounts::default();
+        import_open_cases(&mut accounts, &open_cases).unwrap();
+        assert_eq!(accounts[&1].balance.held, 10.0);
+        assert_eq!(accounts[&1].balance.available, 0.0);
+        assert_eq!(
+            import_open_cases(&mut accounts, &open_cases),
+            Err(TransactionProcessingError::TransactionIDAlreadyExists { client: 1, tx: 1 })
+        );
+    }
+}
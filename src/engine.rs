@@ -0,0 +1,250 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::path::Path;
+
+use crate::client_account::applied_transaction::AppliedTransaction;
+use crate::client_account::error::TransactionProcessingError;
+use crate::output_options::OutputOptions;
+use crate::processing_options::ProcessingOptions;
+use crate::state_snapshot;
+use crate::{
+    create_serializable_output_from_accounts, process_transaction_isolated, serializable_form,
+    ClientAccount, ClientId,
+};
+
+/// Applies transactions to the accounts it owns, for embedding the processor as a library without
+/// managing the accounts map. Built with `TransactionEngine::builder()`. The accounts map hashes
+/// with `S`, the standard library's SipHash unless the builder is given another `hasher`.
+pub struct TransactionEngine<S = RandomState> {
+    accounts: HashMap<ClientId, ClientAccount, S>,
+    processing_options: ProcessingOptions,
+    output_options: OutputOptions,
+    strict: bool,
+    debug_logger: Box<dyn std::io::Write>,
+}
+
+impl TransactionEngine {
+    pub fn builder() -> TransactionEngineBuilder {
+        TransactionEngineBuilder::default()
+    }
+}
+
+impl<S: BuildHasher + Default> TransactionEngine<S> {
+    /// Applies the transaction to its client's account. A rejected transaction leaves the accounts
+    /// as they were and is returned as the error, after the resolve without dispute policy.
+    /// Opening a new account has no journal entry, so returns `None`.
+    pub fn apply(
+        &mut self,
+        transaction: serializable_form::Transaction,
+    ) -> Result<Option<AppliedTransaction>, TransactionProcessingError> {
+        let outcome = process_transaction_isolated(
+            &mut self.accounts,
+            &transaction,
+            &self.processing_options,
+            &mut self.debug_logger,
+        );
+        self.processing_options
+            .resolve_without_dispute_outcome(&transaction, outcome)
+    }
+
+    /// Applies the transactions in order and returns how many were rejected. Rejected transactions
+    /// are skipped, unless the engine is strict or the processing options make the rejection fatal,
+    /// in which case the rejection stops processing and is returned.
+    pub fn apply_all(
+        &mut self,
+        transactions: impl IntoIterator<Item = serializable_form::Transaction>,
+    ) -> Result<usize, TransactionProcessingError> {
+        let mut rejected = 0;
+        for transaction in transactions {
+            if let Err(error) = self.apply(transaction) {
+                if self.strict || self.processing_options.is_fatal(&error) {
+                    return Err(error);
+                }
+                rejected += 1;
+            }
+        }
+        Ok(rejected)
+    }
+
+    pub fn accounts(&self) -> &HashMap<ClientId, ClientAccount, S> {
+        &self.accounts
+    }
+
+    /// Writes the state of the accounts to a snapshot file, for an engine to continue from with
+    /// `load_snapshot`
+    pub fn save_snapshot(&self, file: &Path) -> anyhow::Result<()> {
+        state_snapshot::save_snapshot(&self.accounts, file)
+    }
+
+    /// Replaces the accounts with those in a snapshot file written by `save_snapshot`, carried over
+    /// into a new run with the engine's `ProcessingOptions::dispute_retention_runs`
+    pub fn load_snapshot(&mut self, file: &Path) -> anyhow::Result<()> {
+        self.accounts = state_snapshot::load_snapshot(file)?.into_iter().collect();
+        state_snapshot::carry_over(
+            &mut self.accounts,
+            self.processing_options.dispute_retention_runs,
+        );
+        Ok(())
+    }
+
+    /// The output rows of the accounts which the output options include
+    pub fn into_output(self) -> anyhow::Result<Vec<serializable_form::Output>> {
+        create_serializable_output_from_accounts(&self.accounts, &self.output_options)
+    }
+}
+
+/// Configures a `TransactionEngine`. By default it applies the default processing and output
+/// options, skips rejected transactions, discards the debug log and hashes with SipHash.
+#[derive(Default)]
+pub struct TransactionEngineBuilder<S = RandomState> {
+    hasher: S,
+    processing_options: ProcessingOptions,
+    output_options: OutputOptions,
+    strict: bool,
+    debug_logger: Option<Box<dyn std::io::Write>>,
+}
+
+impl<S: BuildHasher + Default> TransactionEngineBuilder<S> {
+    /// The hasher of the accounts map, e.g. `hashing::BuildHasher::default()` for the one the
+    /// binary uses
+    pub fn hasher<T: BuildHasher + Default>(self, hasher: T) -> TransactionEngineBuilder<T> {
+        TransactionEngineBuilder {
+            hasher,
+            processing_options: self.processing_options,
+            output_options: self.output_options,
+            strict: self.strict,
+            debug_logger: self.debug_logger,
+        }
+    }
+
+    pub fn processing_options(mut self, processing_options: ProcessingOptions) -> Self {
+        self.processing_options = processing_options;
+        self
+    }
+
+    pub fn output_options(mut self, output_options: OutputOptions) -> Self {
+        self.output_options = output_options;
+        self
+    }
+
+    /// Stop `apply_all` at the first rejected transaction
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Where errors and rejected transactions are logged
+    pub fn debug_logger(mut self, debug_logger: impl std::io::Write + 'static) -> Self {
+        self.debug_logger = Some(Box::new(debug_logger));
+        self
+    }
+
+    pub fn build(self) -> TransactionEngine<S> {
+        TransactionEngine {
+            accounts: HashMap::with_capacity_and_hasher(
+                self.processing_options.expected_clients.unwrap_or(0),
+                self.hasher,
+            ),
+            processing_options: self.processing_options,
+            output_options: self.output_options,
+            strict: self.strict,
+            debug_logger: self
+                .debug_logger
+                .unwrap_or_else(|| Box::new(std::io::sink())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientId, TransactionType};
+
+    fn transaction(
+        transaction_type: TransactionType,
+        client_id: ClientId,
+        transaction_id: crate::TransactionId,
+        amount: Option<f64>,
+    ) -> serializable_form::Transaction {
+        serializable_form::Transaction {
+            transaction_type,
+            client_id,
+            transaction_id,
+            amount,
+            counterparty: None,
+        }
+    }
+
+    fn transactions() -> Vec<serializable_form::Transaction> {
+        vec![
+            transaction(TransactionType::Deposit, 1, 1, Some(2.0)),
+            transaction(TransactionType::Dispute, 1, 9, None),
+            transaction(TransactionType::Deposit, 2, 3, Some(1.5)),
+            transaction(TransactionType::Withdrawal, 1, 4, Some(0.5)),
+        ]
+    }
+
+    #[test]
+    fn it_skips_rejected_transactions() {
+        let mut engine = TransactionEngine::builder().build();
+        assert_eq!(engine.apply_all(transactions()), Ok(1));
+        assert_eq!(engine.accounts().len(), 2);
+        assert_eq!(engine.accounts()[&1].balance.available, 1.5);
+
+        let mut output = engine.into_output().unwrap();
+        output.sort_by_key(|output| output.client);
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].available, "1.5000");
+        assert_eq!(output[1].total, "1.5000");
+    }
+
+    #[test]
+    fn it_stops_at_the_first_rejection_when_strict() {
+        let mut engine = TransactionEngine::builder()
+            .strict(true)
+            .debug_logger(Vec::new())
+            .build();
+        assert_eq!(
+            engine.apply_all(transactions()),
+            Err(TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 9 })
+        );
+        assert_eq!(engine.accounts().len(), 1);
+        assert_eq!(engine.accounts()[&1].balance.available, 2.0);
+    }
+
+    #[test]
+    fn it_continues_from_a_saved_snapshot() {
+        let file =
+            std::env::temp_dir().join("rs_bpt-engine-it_continues_from_a_saved_snapshot.json");
+        let mut engine = TransactionEngine::builder().build();
+        engine.apply_all(transactions()).unwrap();
+        engine
+            .apply(transaction(TransactionType::Dispute, 2, 3, None))
+            .unwrap();
+        engine.save_snapshot(&file).unwrap();
+
+        let mut engine = TransactionEngine::builder().build();
+        engine.load_snapshot(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        engine
+            .apply(transaction(TransactionType::Chargeback, 2, 3, None))
+            .unwrap();
+        assert_eq!(engine.accounts()[&1].balance.available, 1.5);
+        assert!(engine.accounts()[&2].locked);
+        assert_eq!(engine.accounts()[&2].balance.total(), 0.0);
+    }
+
+    #[test]
+    fn it_hashes_the_accounts_with_the_builders_hasher() {
+        let engine = TransactionEngine::builder().build();
+        let _: &std::collections::HashMap<ClientId, crate::ClientAccount> = engine.accounts();
+
+        let mut engine = TransactionEngine::builder()
+            .hasher(crate::hashing::BuildHasher::default())
+            .build();
+        assert_eq!(engine.apply_all(transactions()), Ok(1));
+        let accounts: &crate::hashing::Accounts = engine.accounts();
+        assert_eq!(accounts[&1].balance.available, 1.5);
+    }
+}
@@ -0,0 +1,207 @@
+use serde_derive::Serialize;
+use std::path::PathBuf;
+
+use crate::client_account::error::TransactionProcessingError;
+use crate::rejection::RejectionSink;
+use crate::serializable_form;
+use crate::{ClientId, TransactionId, TransactionType};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ErrorReportFormat {
+    Csv,
+    Json,
+}
+
+impl std::str::FromStr for ErrorReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ErrorReportFormat::Csv),
+            "json" => Ok(ErrorReportFormat::Json),
+            _ => Err(format!("unknown error report format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ErrorReportOptions {
+    pub file: PathBuf,
+    pub format: ErrorReportFormat,
+}
+
+/// A rejected transaction, as reported
+#[derive(Debug, Serialize)]
+struct ErrorReportRow {
+    /// The input line, empty for parked dispute ops and arbitration outcomes
+    line: Option<u64>,
+    client: ClientId,
+    tx: TransactionId,
+    #[serde(rename = "type")]
+    transaction_type: TransactionType,
+    /// The `TransactionProcessingError` variant
+    error: &'static str,
+    message: String,
+}
+
+enum ErrorReportEncoder<W: std::io::Write> {
+    Csv(Box<csv::Writer<W>>),
+    Json { writer: W, rows: usize },
+}
+
+/// Writes every rejected transaction as it is rejected, for downstream reconciliation: as CSV, or
+/// as a JSON array once finished.
+pub struct ErrorReportWriter<W: std::io::Write> {
+    encoder: ErrorReportEncoder<W>,
+}
+
+impl<W: std::io::Write> ErrorReportWriter<W> {
+    pub fn new(output_stream: W, format: ErrorReportFormat) -> anyhow::Result<Self> {
+        let encoder = match format {
+            ErrorReportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(output_stream);
+                writer.write_record(["line", "client", "tx", "type", "error", "message"])?;
+                ErrorReportEncoder::Csv(Box::new(writer))
+            }
+            ErrorReportFormat::Json => {
+                let mut writer = output_stream;
+                write!(writer, "[")?;
+                ErrorReportEncoder::Json { writer, rows: 0 }
+            }
+        };
+        Ok(Self { encoder })
+    }
+
+    /// Closes the JSON array and flushes the report
+    pub fn finish(self) -> anyhow::Result<()> {
+        match self.encoder {
+            ErrorReportEncoder::Csv(mut writer) => writer.flush()?,
+            ErrorReportEncoder::Json { mut writer, rows } => {
+                if rows > 0 {
+                    writeln!(writer)?;
+                }
+                writeln!(writer, "]")?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: std::io::Write> RejectionSink for ErrorReportWriter<W> {
+    fn record_rejection(
+        &mut self,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        self.record_rejection_at(None, transaction, error)
+    }
+
+    fn record_rejection_at(
+        &mut self,
+        line: Option<u64>,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        let row = ErrorReportRow {
+            line,
+            client: transaction.client_id,
+            tx: transaction.transaction_id,
+            transaction_type: transaction.transaction_type,
+            error: error.name(),
+            message: error.to_string(),
+        };
+        match &mut self.encoder {
+            ErrorReportEncoder::Csv(writer) => writer.serialize(row)?,
+            ErrorReportEncoder::Json { writer, rows } => {
+                write!(writer, "{}\n  ", if *rows == 0 { "" } else { "," })?;
+                serde_json::to_writer(&mut *writer, &row)?;
+                *rows += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_report(format: ErrorReportFormat) -> String {
+        let mut output = Vec::<u8>::new();
+        let mut writer = ErrorReportWriter::new(&mut output, format).unwrap();
+        writer
+            .record_rejection_at(
+                Some(3),
+                &serializable_form::Transaction {
+                    transaction_type: TransactionType::Dispute,
+                    client_id: 1,
+                    transaction_id: 7,
+                    amount: None,
+                    counterparty: None,
+                },
+                &TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 7 },
+            )
+            .unwrap();
+        writer
+            .record_rejection(
+                &serializable_form::Transaction {
+                    transaction_type: TransactionType::Deposit,
+                    client_id: 2,
+                    transaction_id: 1,
+                    amount: Some(1.5),
+                    counterparty: None,
+                },
+                &TransactionProcessingError::TransactionIDAlreadyExists { client: 2, tx: 1 },
+            )
+            .unwrap();
+        writer.finish().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_report_rejections_as_csv() {
+        assert_eq!(
+            write_report(ErrorReportFormat::Csv),
+            "line,client,tx,type,error,message\n\
+             3,1,7,dispute,ReferencedTransactionNotFound,ReferencedTransactionNotFound: 7\n\
+             ,2,1,deposit,TransactionIDAlreadyExists,TransactionIDAlreadyExists: 1\n"
+        );
+    }
+
+    #[test]
+    fn test_report_rejections_as_json() {
+        let report: serde_json::Value =
+            serde_json::from_str(&write_report(ErrorReportFormat::Json)).unwrap();
+        assert_eq!(
+            report,
+            serde_json::json!([
+                {
+                    "line": 3,
+                    "client": 1,
+                    "tx": 7,
+                    "type": "dispute",
+                    "error": "ReferencedTransactionNotFound",
+                    "message": "ReferencedTransactionNotFound: 7"
+                },
+                {
+                    "line": null,
+                    "client": 2,
+                    "tx": 1,
+                    "type": "deposit",
+                    "error": "TransactionIDAlreadyExists",
+                    "message": "TransactionIDAlreadyExists: 1"
+                }
+            ])
+        );
+
+        let mut output = Vec::<u8>::new();
+        ErrorReportWriter::new(&mut output, ErrorReportFormat::Json)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "[]\n");
+    }
+}
@@ -0,0 +1,41 @@
+use serde_derive::Serialize;
+
+use crate::hashing::Accounts;
+use crate::ClientId;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ErroredClient<'a> {
+    pub client: ClientId,
+    pub reason: &'a str,
+}
+
+/// The accounts which errored during processing, in client id order
+pub fn errored_clients(accounts: &Accounts) -> Vec<ErroredClient<'_>> {
+    let mut errored_clients: Vec<ErroredClient> = accounts
+        .values()
+        .filter_map(|client_account| {
+            client_account.errored().map(|reason| ErroredClient {
+                client: client_account.client_id,
+                reason,
+            })
+        })
+        .collect();
+    errored_clients.sort_by_key(|errored_client| errored_client.client);
+    errored_clients
+}
+
+pub fn write_report(
+    accounts: &Accounts,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    // the header is written explicitly so that an empty report still has one
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(output_stream);
+    csv_writer.write_record(["client", "reason"])?;
+    for errored_client in errored_clients(accounts) {
+        csv_writer.serialize(errored_client)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
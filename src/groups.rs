@@ -0,0 +1,173 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::serializable_form::round_f64_4dp_string;
+use crate::{ClientAccount, ClientId};
+
+#[derive(Debug, Clone)]
+pub struct GroupOptions {
+    /// CSV file with client,group columns
+    pub mapping_file: PathBuf,
+    pub output_file: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupMappingRow {
+    client: ClientId,
+    group: String,
+}
+
+/// Maps clients (e.g. merchants) onto groups (e.g. parent companies).
+#[derive(Debug, Default, PartialEq)]
+pub struct GroupMapping {
+    groups: HashMap<ClientId, String>,
+}
+
+impl GroupMapping {
+    pub fn from_reader(reader: impl std::io::Read) -> anyhow::Result<Self> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut groups = HashMap::new();
+        for row in csv_reader.deserialize() {
+            let row: GroupMappingRow = row?;
+            if let Some(group) = groups.insert(row.client, row.group.clone()) {
+                if group != row.group {
+                    anyhow::bail!(
+                        "client {} is mapped to both group {} and group {}",
+                        row.client,
+                        group,
+                        row.group
+                    );
+                }
+            }
+        }
+        Ok(Self { groups })
+    }
+
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+
+    pub fn group(&self, client_id: ClientId) -> Option<&str> {
+        self.groups.get(&client_id).map(String::as_str)
+    }
+}
+
+/// Aggregated balances of a group's clients. The group is locked if any of its clients is.
+#[derive(Debug, Default, PartialEq)]
+pub struct GroupBalance {
+    pub client_count: usize,
+    pub locked_count: usize,
+    pub available: f64,
+    pub held: f64,
+}
+
+impl GroupBalance {
+    pub fn total(&self) -> f64 {
+        self.available + self.held
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked_count > 0
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SerializableGroupBalance<'a> {
+    group: &'a str,
+    clients: usize,
+    locked_clients: usize,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+/// Group balances by group name. Clients which aren't mapped to a group are left out.
+pub fn group_balances<'a>(
+    accounts: impl IntoIterator<Item = &'a ClientAccount>,
+    mapping: &'a GroupMapping,
+) -> BTreeMap<&'a str, GroupBalance> {
+    let mut group_balances = BTreeMap::<&str, GroupBalance>::new();
+    for client_account in accounts {
+        if let Some(group) = mapping.group(client_account.client_id) {
+            let group_balance = group_balances.entry(group).or_default();
+            group_balance.client_count += 1;
+            if client_account.locked {
+                group_balance.locked_count += 1;
+            }
+            group_balance.available += client_account.balance.available;
+            group_balance.held += client_account.balance.held;
+        }
+    }
+    group_balances
+}
+
+pub fn write_report(
+    group_balances: &BTreeMap<&str, GroupBalance>,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(output_stream);
+    csv_writer.write_record([
+        "group",
+        "clients",
+        "locked_clients",
+        "available",
+        "held",
+        "total",
+        "locked",
+    ])?;
+    for (group, group_balance) in group_balances {
+        csv_writer.serialize(SerializableGroupBalance {
+            group,
+            clients: group_balance.client_count,
+            locked_clients: group_balance.locked_count,
+            available: round_f64_4dp_string(group_balance.available)?,
+            held: round_f64_4dp_string(group_balance.held)?,
+            total: round_f64_4dp_string(group_balance.total())?,
+            locked: group_balance.locked(),
+        })?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_aggregates_balances_per_group() {
+        let mapping =
+            GroupMapping::from_reader("client,group\n1,acme\n2, acme\n3,globex\n".as_bytes())
+                .unwrap();
+        let mut client_1 = ClientAccount::new(1);
+        client_1.balance.available = 10.0;
+        let mut client_2 = ClientAccount::new(2);
+        client_2.balance.available = 5.0;
+        client_2.balance.held = 2.5;
+        client_2.locked = true;
+        let mut client_4 = ClientAccount::new(4);
+        client_4.balance.available = 100.0;
+
+        let group_balances = group_balances([&client_1, &client_2, &client_4], &mapping);
+
+        let mut report = Vec::<u8>::new();
+        write_report(&group_balances, &mut report).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&report).unwrap(),
+            "group,clients,locked_clients,available,held,total,locked\n\
+             acme,2,1,15.0000,2.5000,17.5000,true\n"
+        );
+    }
+
+    #[test]
+    fn it_rejects_a_client_mapped_to_two_groups() {
+        assert!(GroupMapping::from_reader("client,group\n1,acme\n1,globex\n".as_bytes()).is_err());
+        assert!(GroupMapping::from_reader("client,group\n1,acme\n1,acme\n".as_bytes()).is_ok());
+    }
+}
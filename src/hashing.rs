@@ -0,0 +1,21 @@
+//! The hasher of the hot maps: the accounts map and each account's transaction map. With the
+//! `fast-hash` feature (on by default) they hash with aHash, which is much cheaper than the
+//! standard library's SipHash for the small integer keys used here. The `secure-hash` feature
+//! keeps SipHash for inputs from untrusted sources, where its HashDoS resistance matters; it
+//! wins if both features are enabled.
+//!
+//! The library's entry points take the accounts map with any hasher, and `TransactionEngine`
+//! defaults to SipHash unless its builder is given one of these.
+
+use crate::{ClientAccount, ClientId};
+
+#[cfg(all(feature = "fast-hash", not(feature = "secure-hash")))]
+pub type BuildHasher = ahash::RandomState;
+
+#[cfg(not(all(feature = "fast-hash", not(feature = "secure-hash"))))]
+pub type BuildHasher = std::collections::hash_map::RandomState;
+
+pub type HashMap<K, V> = std::collections::HashMap<K, V, BuildHasher>;
+
+/// Every client's account, by client id
+pub type Accounts = HashMap<ClientId, ClientAccount>;
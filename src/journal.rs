@@ -0,0 +1,271 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::client_account::applied_transaction::AppliedTransaction;
+use crate::serializable_form::round_f64_4dp_string;
+use crate::{ClientId, TransactionId, TransactionType};
+
+/// The general ledger accounts a processed transaction is posted to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LedgerAccount {
+    ClientAvailable(ClientId),
+    ClientHeld(ClientId),
+    Suspense,
+    ChargebackExpense,
+}
+
+impl std::fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerAccount::ClientAvailable(client_id) => {
+                write!(f, "client:{}:available", client_id)
+            }
+            LedgerAccount::ClientHeld(client_id) => write!(f, "client:{}:held", client_id),
+            LedgerAccount::Suspense => write!(f, "suspense"),
+            LedgerAccount::ChargebackExpense => write!(f, "chargeback_expense"),
+        }
+    }
+}
+
+impl std::str::FromStr for LedgerAccount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let client_account = |client_id: &str, account: fn(ClientId) -> LedgerAccount| {
+            client_id
+                .parse()
+                .map(account)
+                .map_err(|_| format!("unknown ledger account: {}", s))
+        };
+        match s.split(':').collect::<Vec<&str>>().as_slice() {
+            ["suspense"] => Ok(LedgerAccount::Suspense),
+            ["chargeback_expense"] => Ok(LedgerAccount::ChargebackExpense),
+            ["client", client_id, "available"] => {
+                client_account(client_id, LedgerAccount::ClientAvailable)
+            }
+            ["client", client_id, "held"] => client_account(client_id, LedgerAccount::ClientHeld),
+            _ => Err(format!("unknown ledger account: {}", s)),
+        }
+    }
+}
+
+/// A double-entry journal line for a successfully applied transaction. The amount is always positive.
+#[derive(Debug, PartialEq, Clone)]
+pub struct JournalEntry {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub transaction_type: TransactionType,
+    pub debit: LedgerAccount,
+    pub credit: LedgerAccount,
+    pub amount: f64,
+}
+
+impl JournalEntry {
+    // `signed_amount` follows DisputableTransaction: a deposit is positive and a withdrawal is negative.
+    // For dispute related transactions it is the amount of the referenced transaction.
+    pub fn new(
+        client_id: ClientId,
+        transaction_id: TransactionId,
+        transaction_type: TransactionType,
+        signed_amount: f64,
+    ) -> Self {
+        let (debit, credit) = match transaction_type {
+            // an open account entry never moves funds, its amount is always zero. A close account
+            // entry sweeps the residual balance out through suspense, like a withdrawal, and
+            // each side of a transfer passes through suspense too.
+            TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::OpenAccount
+            | TransactionType::CloseAccount
+            | TransactionType::Transfer => (
+                LedgerAccount::Suspense,
+                LedgerAccount::ClientAvailable(client_id),
+            ),
+            TransactionType::Dispute => (
+                LedgerAccount::ClientAvailable(client_id),
+                LedgerAccount::ClientHeld(client_id),
+            ),
+            TransactionType::Resolve | TransactionType::RepresentmentWon => (
+                LedgerAccount::ClientHeld(client_id),
+                LedgerAccount::ClientAvailable(client_id),
+            ),
+            TransactionType::Chargeback | TransactionType::FinalChargeback => (
+                LedgerAccount::ClientHeld(client_id),
+                LedgerAccount::ChargebackExpense,
+            ),
+            // the charged back funds return to held until the second decision
+            TransactionType::Representment => (
+                LedgerAccount::ChargebackExpense,
+                LedgerAccount::ClientHeld(client_id),
+            ),
+        };
+
+        let (debit, credit) = if signed_amount < 0.0 {
+            (credit, debit)
+        } else {
+            (debit, credit)
+        };
+
+        Self {
+            client_id,
+            transaction_id,
+            transaction_type,
+            debit,
+            credit,
+            amount: signed_amount.abs(),
+        }
+    }
+}
+
+/// Receives a journal entry for every transaction that is applied to an account.
+pub trait JournalSink {
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()>;
+
+    /// Called with the account state after the transaction as well. Defaults to `record`.
+    fn record_applied(&mut self, applied_transaction: &AppliedTransaction) -> anyhow::Result<()> {
+        self.record(&applied_transaction.journal_entry)
+    }
+}
+
+impl JournalSink for Vec<JournalEntry> {
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        self.push(entry.clone());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SerializableJournalEntry {
+    tx: TransactionId,
+    client: ClientId,
+    #[serde(rename = "type")]
+    transaction_type: TransactionType,
+    debit: String,
+    credit: String,
+    amount: String,
+}
+
+/// Writes journal entries as CSV rows.
+pub struct CsvJournalWriter<W: std::io::Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: std::io::Write> CsvJournalWriter<W> {
+    pub fn new(output_stream: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(output_stream),
+        }
+    }
+}
+
+impl<W: std::io::Write> JournalSink for CsvJournalWriter<W> {
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        self.writer.serialize(SerializableJournalEntry {
+            tx: entry.transaction_id,
+            client: entry.client_id,
+            transaction_type: entry.transaction_type,
+            debit: entry.debit.to_string(),
+            credit: entry.credit.to_string(),
+            amount: round_f64_4dp_string(entry.amount)?,
+        })?;
+        Ok(())
+    }
+}
+
+/// Reads a journal written by `CsvJournalWriter`.
+pub fn read_journal(input_stream: impl std::io::Read) -> anyhow::Result<Vec<JournalEntry>> {
+    let mut reader = csv::Reader::from_reader(input_stream);
+    let mut journal_entries = Vec::new();
+    for row in reader.deserialize() {
+        let row: SerializableJournalEntry = row?;
+        journal_entries.push(JournalEntry {
+            client_id: row.client,
+            transaction_id: row.tx,
+            transaction_type: row.transaction_type,
+            debit: row.debit.parse().map_err(anyhow::Error::msg)?,
+            credit: row.credit.parse().map_err(anyhow::Error::msg)?,
+            amount: row.amount.parse()?,
+        });
+    }
+    Ok(journal_entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_journal_entry_for_each_transaction_type() {
+        let deposit = JournalEntry::new(1, 1, TransactionType::Deposit, 100.0);
+        assert_eq!(deposit.debit, LedgerAccount::Suspense);
+        assert_eq!(deposit.credit, LedgerAccount::ClientAvailable(1));
+        assert_eq!(deposit.amount, 100.0);
+
+        let withdrawal = JournalEntry::new(1, 2, TransactionType::Withdrawal, -25.0);
+        assert_eq!(withdrawal.debit, LedgerAccount::ClientAvailable(1));
+        assert_eq!(withdrawal.credit, LedgerAccount::Suspense);
+        assert_eq!(withdrawal.amount, 25.0);
+
+        let dispute = JournalEntry::new(1, 1, TransactionType::Dispute, 100.0);
+        assert_eq!(dispute.debit, LedgerAccount::ClientAvailable(1));
+        assert_eq!(dispute.credit, LedgerAccount::ClientHeld(1));
+
+        let resolve = JournalEntry::new(1, 1, TransactionType::Resolve, 100.0);
+        assert_eq!(resolve.debit, LedgerAccount::ClientHeld(1));
+        assert_eq!(resolve.credit, LedgerAccount::ClientAvailable(1));
+
+        let chargeback = JournalEntry::new(1, 1, TransactionType::Chargeback, 100.0);
+        assert_eq!(chargeback.debit, LedgerAccount::ClientHeld(1));
+        assert_eq!(chargeback.credit, LedgerAccount::ChargebackExpense);
+
+        // disputing a withdrawal moves funds the other way
+        let withdrawal_dispute = JournalEntry::new(1, 2, TransactionType::Dispute, -25.0);
+        assert_eq!(withdrawal_dispute.debit, LedgerAccount::ClientHeld(1));
+        assert_eq!(withdrawal_dispute.credit, LedgerAccount::ClientAvailable(1));
+        assert_eq!(withdrawal_dispute.amount, 25.0);
+    }
+
+    #[test]
+    fn test_csv_journal_writer() {
+        let mut output = Vec::<u8>::new();
+        {
+            let mut journal_writer = CsvJournalWriter::new(&mut output);
+            journal_writer
+                .record(&JournalEntry::new(1, 1, TransactionType::Deposit, 1.5))
+                .unwrap();
+            journal_writer
+                .record(&JournalEntry::new(1, 1, TransactionType::Dispute, 1.5))
+                .unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"tx,client,type,debit,credit,amount
+1,1,deposit,suspense,client:1:available,1.5000
+1,1,dispute,client:1:available,client:1:held,1.5000
+"#
+        );
+    }
+
+    #[test]
+    fn test_read_journal_round_trips_the_csv_journal() {
+        let journal_entries = vec![
+            JournalEntry::new(1, 1, TransactionType::Deposit, 1.5),
+            JournalEntry::new(1, 1, TransactionType::Dispute, 1.5),
+            JournalEntry::new(1, 1, TransactionType::Chargeback, 1.5),
+            JournalEntry::new(2, 2, TransactionType::Withdrawal, -0.25),
+        ];
+        let mut output = Vec::<u8>::new();
+        {
+            let mut journal_writer = CsvJournalWriter::new(&mut output);
+            for entry in &journal_entries {
+                journal_writer.record(entry).unwrap();
+            }
+        }
+
+        assert_eq!(read_journal(output.as_slice()).unwrap(), journal_entries);
+        assert_eq!(
+            "client:x:held".parse::<LedgerAccount>(),
+            Err("unknown ledger account: client:x:held".to_string())
+        );
+    }
+}
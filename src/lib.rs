@@ -1,18 +1,103 @@
-use serde_derive::Deserialize;
-use std::path::PathBuf;
-use std::{collections::HashMap, path::Path};
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::{hash_map, HashMap};
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
 
+pub mod account_merge;
+pub mod amount_parsing;
+pub mod arbitration;
+use amount_parsing::{AmountNormalization, AmountNormalizationCounts};
+#[cfg(feature = "arrow")]
+pub mod arrow_output;
+pub mod authorization;
+pub mod balance_caps;
+pub mod balance_history;
+pub mod batch_store;
+pub mod bench;
+pub mod change_log;
 pub mod client_account;
 use client_account::{
-    client_account_transaction::ClientAccountTransaction, error::TransactionProcessingError,
-    ClientAccount,
+    applied_transaction::AppliedTransaction, client_account_transaction::ClientAccountTransaction,
+    error::TransactionProcessingError, ClientAccount,
 };
+pub mod cli_options;
+pub mod client_notes;
+pub mod client_tiers;
+pub mod column_mapping;
+use client_notes::ClientNotes;
+use column_mapping::ColumnMapping;
+pub mod compaction;
+pub mod compression;
+pub mod conformance;
+use cli_options::CliOptions;
+pub mod control_totals;
+use control_totals::{ControlTotals, Crc32Writer};
+pub mod dates;
+pub mod dispute_cases;
+pub mod engine;
+pub mod error_report;
+pub mod errored_clients;
+pub mod groups;
+pub mod hashing;
+use hashing::Accounts;
+pub mod journal;
+pub mod logging;
+use journal::{CsvJournalWriter, JournalEntry, JournalSink};
+pub mod manifest;
+#[cfg(feature = "memprofile")]
+pub mod memprofile;
+pub mod merkle;
+use manifest::{FileDigest, RunManifest, StreamDigest};
+pub mod netting;
+use netting::Netting;
+pub mod output_options;
+pub mod output_parts;
+use output_parts::OutputParts;
+#[cfg(feature = "output-template")]
+pub mod output_template;
+use output_options::{BoolFormat, OutputOptions};
+pub mod parallel;
+pub mod partial_progress;
+use partial_progress::{PartialProgress, ProcessingAborted};
+pub mod pending_disputes;
+use pending_disputes::{PendingDisputeCounts, PendingDisputes};
+#[cfg(feature = "plaintext-accounting")]
+pub mod plaintext_accounting;
+pub mod policy_matrix;
+pub mod processing_options;
+use processing_options::{CrossClientDisputePolicy, ProcessingOptions, UnknownTypePolicy};
+pub mod rejection;
+use rejection::RejectionSink;
+pub mod retry;
+pub mod review;
+pub mod schema;
+use schema::SchemaVersion;
 pub mod serializable_form;
+pub mod settlement;
+pub mod simulation;
+pub mod snapshot_diff;
+pub mod state_diff;
+pub mod state_snapshot;
+pub mod statement;
+pub mod streaming_metrics;
+pub mod submission_order;
+pub mod summary;
+use summary::Summary;
+pub mod suspense;
+pub mod testing;
+pub mod timestamps;
+pub mod trial_balance;
+use trial_balance::TrialBalance;
+pub mod type_aliases;
+pub mod unknown_types;
+use suspense::Suspense;
+use unknown_types::{UnknownTypeCounts, UnknownTypeRow};
 
 pub type ClientId = u16;
 pub type TransactionId = u32;
 
-#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
 pub enum TransactionType {
     #[serde(rename = "deposit")]
     Deposit,
@@ -28,55 +113,1008 @@ pub enum TransactionType {
 
     #[serde(rename = "chargeback")]
     Chargeback,
+
+    #[serde(rename = "representment")]
+    Representment,
+
+    #[serde(rename = "final_chargeback")]
+    FinalChargeback,
+
+    #[serde(rename = "representment_won")]
+    RepresentmentWon,
+
+    #[serde(rename = "open_account")]
+    OpenAccount,
+
+    #[serde(rename = "close_account")]
+    CloseAccount,
+
+    #[serde(rename = "transfer")]
+    Transfer,
+}
+
+impl TransactionType {
+    /// Whether the transaction refers to an earlier deposit or withdrawal instead of moving funds
+    /// of its own: a dispute, resolve, chargeback or a step of the representment flow
+    pub fn is_dispute_op(&self) -> bool {
+        !matches!(
+            self,
+            TransactionType::Deposit
+                | TransactionType::Withdrawal
+                | TransactionType::OpenAccount
+                | TransactionType::CloseAccount
+                | TransactionType::Transfer
+        )
+    }
+}
+
+impl std::fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Representment => "representment",
+            TransactionType::FinalChargeback => "final_chargeback",
+            TransactionType::RepresentmentWon => "representment_won",
+            TransactionType::OpenAccount => "open_account",
+            TransactionType::CloseAccount => "close_account",
+            TransactionType::Transfer => "transfer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for TransactionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(TransactionType::Deposit),
+            "withdrawal" => Ok(TransactionType::Withdrawal),
+            "dispute" => Ok(TransactionType::Dispute),
+            "resolve" => Ok(TransactionType::Resolve),
+            "chargeback" => Ok(TransactionType::Chargeback),
+            "representment" => Ok(TransactionType::Representment),
+            "final_chargeback" => Ok(TransactionType::FinalChargeback),
+            "representment_won" => Ok(TransactionType::RepresentmentWon),
+            "open_account" => Ok(TransactionType::OpenAccount),
+            "close_account" => Ok(TransactionType::CloseAccount),
+            "transfer" => Ok(TransactionType::Transfer),
+            _ => Err(format!("unknown transaction type: {}", s)),
+        }
+    }
+}
+
+fn log_transaction_error(
+    debug_logger: &mut dyn std::io::Write,
+    transaction: &serializable_form::Transaction,
+    error: &TransactionProcessingError,
+) {
+    writeln!(
+        debug_logger,
+        "error processing transaction - {} (client {})",
+        error, transaction.client_id
+    )
+    .expect("error writing to debug stream");
+    writeln!(debug_logger, "{:?}", transaction).expect("error writing to debug stream");
 }
 
-fn process_transaction(
-    accounts: &mut HashMap<ClientId, ClientAccount>,
+// Why a transaction of this type can't create an account for a client which has not been seen yet
+fn implicit_account_error(
+    accounts_len: usize,
+    client_id: ClientId,
+    transaction_id: TransactionId,
+    transaction_type: TransactionType,
+    processing_options: &ProcessingOptions,
+) -> Option<TransactionProcessingError> {
+    if !processing_options
+        .implicit_accounts
+        .allows(transaction_type)
+    {
+        Some(TransactionProcessingError::ClientNotFound {
+            client: client_id,
+            tx: transaction_id,
+        })
+    } else if processing_options
+        .max_clients
+        .is_some_and(|max_clients| accounts_len >= max_clients)
+    {
+        Some(TransactionProcessingError::ClientLimitExceeded {
+            client: client_id,
+            tx: transaction_id,
+        })
+    } else {
+        None
+    }
+}
+
+// The client which has the transaction a dispute op references, if it isn't the row's own client
+// and the cross client dispute policy looks for it
+fn cross_client_owner<S: BuildHasher>(
+    accounts: &HashMap<ClientId, ClientAccount, S>,
+    transaction: &serializable_form::Transaction,
+    processing_options: &ProcessingOptions,
+) -> Option<ClientId> {
+    if processing_options.cross_client_disputes != CrossClientDisputePolicy::Locate
+        || !transaction.transaction_type.is_dispute_op()
+        || accounts
+            .get(&transaction.client_id)
+            .is_some_and(|client_account| {
+                client_account.has_transaction(transaction.transaction_id)
+            })
+    {
+        return None;
+    }
+    accounts
+        .values()
+        .find(|client_account| client_account.has_transaction(transaction.transaction_id))
+        .map(|client_account| client_account.client_id)
+}
+
+// Returns the counterparty and amount of a transfer, checked before either account is touched
+fn check_transfer<S: BuildHasher>(
+    accounts: &HashMap<ClientId, ClientAccount, S>,
+    transaction: &serializable_form::Transaction,
+    processing_options: &ProcessingOptions,
+) -> Result<(ClientId, f64), TransactionProcessingError> {
+    let amount = match transaction.amount {
+        Some(amount) if amount.is_finite() && amount > 0.0 => amount,
+        Some(_) => {
+            return Err(TransactionProcessingError::InvalidAmount {
+                client: transaction.client_id,
+                tx: transaction.transaction_id,
+            })
+        }
+        None => {
+            return Err(TransactionProcessingError::AmountNotPresentForTransfer {
+                client: transaction.client_id,
+                tx: transaction.transaction_id,
+            })
+        }
+    };
+    let counterparty = transaction.counterparty.ok_or(
+        TransactionProcessingError::CounterpartyNotPresentForTransfer {
+            client: transaction.client_id,
+            tx: transaction.transaction_id,
+        },
+    )?;
+    if !accounts.contains_key(&counterparty) {
+        if let Some(error) = implicit_account_error(
+            accounts.len(),
+            counterparty,
+            transaction.transaction_id,
+            TransactionType::Transfer,
+            processing_options,
+        ) {
+            return Err(error);
+        }
+    }
+    Ok((counterparty, amount))
+}
+
+/// Applies the transaction to its client's account (and a transfer to its counterparty's too),
+/// creating the account if the implicit accounts policy allows it. Opening a new account has no
+/// journal entry, so returns `None`.
+fn process_transaction<S: BuildHasher>(
+    accounts: &mut HashMap<ClientId, ClientAccount, S>,
     transaction: &serializable_form::Transaction,
+    processing_options: &ProcessingOptions,
     debug_logger: &mut dyn std::io::Write,
-) -> Result<(), TransactionProcessingError> {
-    let client_account = accounts
-        .entry(transaction.client_id)
-        .or_insert_with(|| ClientAccount::new(transaction.client_id));
+) -> Result<Option<AppliedTransaction>, TransactionProcessingError> {
+    let transfer = if transaction.transaction_type == TransactionType::Transfer {
+        match check_transfer(accounts, transaction, processing_options) {
+            Ok(transfer) => Some(transfer),
+            Err(error) => {
+                log_transaction_error(debug_logger, transaction, &error);
+                return Err(error);
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Some(owner) = cross_client_owner(accounts, transaction, processing_options) {
+        let error = TransactionProcessingError::ClientMismatchOnDispute {
+            client: transaction.client_id,
+            tx: transaction.transaction_id,
+        };
+        log_transaction_error(debug_logger, transaction, &error);
+        writeln!(
+            debug_logger,
+            "transaction {} belongs to client {}, not client {}",
+            transaction.transaction_id, owner, transaction.client_id
+        )
+        .expect("error writing to debug stream");
+        return Err(error);
+    }
+
+    let accounts_len = accounts.len();
+    let client_account = match accounts.entry(transaction.client_id) {
+        hash_map::Entry::Occupied(entry) => entry.into_mut(),
+        hash_map::Entry::Vacant(entry) => {
+            if let Some(error) = implicit_account_error(
+                accounts_len,
+                transaction.client_id,
+                transaction.transaction_id,
+                transaction.transaction_type,
+                processing_options,
+            ) {
+                log_transaction_error(debug_logger, transaction, &error);
+                return Err(error);
+            }
+
+            let client_account = entry.insert(ClientAccount::with_capacity(
+                transaction.client_id,
+                processing_options
+                    .expected_transactions_per_client
+                    .unwrap_or(0),
+            ));
+            if transaction.transaction_type == TransactionType::OpenAccount {
+                return Ok(None);
+            }
+            client_account
+        }
+    };
+
+    if let Some(withdrawal_authorization) = &processing_options.withdrawal_authorization {
+        if let Err(error) = withdrawal_authorization.check(transaction, debug_logger) {
+            log_transaction_error(debug_logger, transaction, &error);
+            return Err(error);
+        }
+    }
+
+    // what a close account sweeps out
+    let residual = client_account.balance.available;
+    let mut client_account_transaction = ClientAccountTransaction::from(transaction);
+    if let Some((_, amount)) = transfer {
+        // the client's side of a transfer debits its account
+        client_account_transaction.amount = Some(-amount);
+    }
+    let mut applied_transaction = client_account.process_client_transaction(
+        client_account_transaction,
+        processing_options,
+        debug_logger,
+    )?;
+
+    if let Some((counterparty, amount)) = transfer {
+        let created = !accounts.contains_key(&counterparty);
+        let counterparty_account = accounts.entry(counterparty).or_insert_with(|| {
+            ClientAccount::with_capacity(
+                counterparty,
+                processing_options
+                    .expected_transactions_per_client
+                    .unwrap_or(0),
+            )
+        });
+        let credit = counterparty_account.process_client_transaction(
+            ClientAccountTransaction {
+                transaction_type: TransactionType::Transfer,
+                transaction_id: transaction.transaction_id,
+                amount: Some(amount),
+            },
+            processing_options,
+            debug_logger,
+        );
+        match credit {
+            Ok(credit) => applied_transaction.counterparty = Some(Box::new(credit)),
+            Err(error) => {
+                if created {
+                    accounts.remove(&counterparty);
+                }
+                if let Some(client_account) = accounts.get_mut(&transaction.client_id) {
+                    client_account.revert_transfer(transaction.transaction_id);
+                }
+                return Err(error);
+            }
+        }
+    }
+
+    if transaction.transaction_type == TransactionType::CloseAccount {
+        if let Some(sweep_client) = processing_options.sweep_client {
+            let sweep_account = accounts
+                .entry(sweep_client)
+                .or_insert_with(|| ClientAccount::new(sweep_client));
+            let held_before = sweep_account.balance.held;
+            sweep_account.credit_sweep(residual);
+            applied_transaction.counterparty = Some(Box::new(AppliedTransaction {
+                journal_entry: JournalEntry::new(
+                    sweep_client,
+                    transaction.transaction_id,
+                    TransactionType::CloseAccount,
+                    residual,
+                ),
+                balance: sweep_account.balance,
+                locked: sweep_account.locked,
+                held_change: sweep_account.balance.held - held_before,
+                suspended_excess: None,
+                counterparty: None,
+            }));
+        } else {
+            writeln!(
+                debug_logger,
+                "closed account with a residual balance of {} (client {})",
+                residual, transaction.client_id
+            )
+            .expect("error writing to debug stream");
+        }
+    }
+    Ok(Some(applied_transaction))
+}
 
-    let client_account_transaction = ClientAccountTransaction::from(transaction);
-    client_account.process_client_transaction(client_account_transaction, debug_logger);
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panicked".to_string()
+    }
+}
 
+/// Like `process_transaction`, but a panic only poisons the transaction's client: the account is
+/// marked errored and the transaction rejected, and processing continues with the next transaction.
+pub(crate) fn process_transaction_isolated<S: BuildHasher>(
+    accounts: &mut HashMap<ClientId, ClientAccount, S>,
+    transaction: &serializable_form::Transaction,
+    processing_options: &ProcessingOptions,
+    debug_logger: &mut dyn std::io::Write,
+) -> Result<Option<AppliedTransaction>, TransactionProcessingError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        process_transaction(accounts, transaction, processing_options, debug_logger)
+    }))
+    .unwrap_or_else(|panic| {
+        let reason = panic_message(panic.as_ref());
+        let _ = writeln!(
+            debug_logger,
+            "error processing transaction - client {} errored: {}",
+            transaction.client_id, reason
+        );
+        if let Some(client_account) = accounts.get_mut(&transaction.client_id) {
+            client_account.mark_errored(reason);
+        }
+        Err(TransactionProcessingError::ClientErrored {
+            client: transaction.client_id,
+            tx: transaction.transaction_id,
+        })
+    })
+}
+
+/// Records the outcome of applying a transaction (read from the input line, where known) after the
+/// resolve without dispute policy with the journal or rejection sinks (both, for a deposit partly
+/// credited up to a balance cap), and returns the error if it should stop processing.
+fn record_outcome(
+    transaction: &serializable_form::Transaction,
+    line: Option<u64>,
+    outcome: Result<Option<AppliedTransaction>, TransactionProcessingError>,
+    processing_options: &ProcessingOptions,
+    journal_sinks: &mut [&mut dyn JournalSink],
+    rejection_sinks: &mut [&mut dyn RejectionSink],
+) -> Result<(), Box<dyn std::error::Error>> {
+    match processing_options.resolve_without_dispute_outcome(transaction, outcome) {
+        Ok(None) => {}
+        Ok(Some(applied_transaction)) => {
+            for journal_sink in journal_sinks.iter_mut() {
+                journal_sink.record_applied(&applied_transaction)?;
+                if let Some(counterparty) = &applied_transaction.counterparty {
+                    journal_sink.record_applied(counterparty)?;
+                }
+            }
+            // the excess over a balance cap is reported like a rejected deposit of that amount
+            if let Some(excess) = applied_transaction.suspended_excess {
+                let excess_transaction = serializable_form::Transaction {
+                    amount: Some(excess),
+                    ..transaction.clone()
+                };
+                let error = TransactionProcessingError::BalanceCapExceeded {
+                    client: transaction.client_id,
+                    tx: transaction.transaction_id,
+                };
+                for rejection_sink in rejection_sinks.iter_mut() {
+                    rejection_sink.record_rejection_at(line, &excess_transaction, &error)?;
+                }
+            }
+        }
+        Err(error) => {
+            for rejection_sink in rejection_sinks.iter_mut() {
+                rejection_sink.record_rejection_at(line, transaction, &error)?;
+            }
+            if processing_options.is_fatal(&error) {
+                return Err(error.into());
+            }
+        }
+    }
     Ok(())
 }
 
-pub fn process_transactions_file(
-    accounts: &mut HashMap<ClientId, ClientAccount>,
+/// Counts from processing a file
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct ProcessingCounts {
+    /// The dispute ops parked in the pending dispute buffer (all zero if the buffer is disabled)
+    pub pending_disputes: PendingDisputeCounts,
+    pub amount_normalizations: AmountNormalizationCounts,
+    pub unknown_types: UnknownTypeCounts,
+    /// The transactions skipped by `ProcessingOptions::backfill` as already in the loaded state
+    pub backfilled: usize,
+}
+
+/// Processes every transaction in the file, returning the counts of the dispute ops parked in
+/// the pending dispute buffer, of the amounts normalized and of the rows with unknown types. An
+/// error which stops processing is returned as a `ProcessingAborted` with the progress made.
+pub fn process_transactions_file<S: BuildHasher>(
+    accounts: &mut HashMap<ClientId, ClientAccount, S>,
     input_transactions_file: PathBuf,
+    processing_options: &ProcessingOptions,
+    journal_sinks: &mut [&mut dyn JournalSink],
+    rejection_sinks: &mut [&mut dyn RejectionSink],
     debug_logger: &mut dyn std::io::Write,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<ProcessingCounts, Box<dyn std::error::Error>> {
+    process_input(
+        accounts,
+        &mut || submission_order::read_rows(&input_transactions_file, processing_options),
+        processing_options,
+        journal_sinks,
+        rejection_sinks,
+        debug_logger,
+    )
+}
+
+/// Processes every transaction read from the reader (e.g. stdin) like `process_transactions_file`.
+/// With two-pass ordering the input is buffered in memory, since every pass reads all of it.
+pub fn process_transactions_reader<R: std::io::Read + 'static, S: BuildHasher>(
+    accounts: &mut HashMap<ClientId, ClientAccount, S>,
+    reader: R,
+    processing_options: &ProcessingOptions,
+    journal_sinks: &mut [&mut dyn JournalSink],
+    rejection_sinks: &mut [&mut dyn RejectionSink],
+    debug_logger: &mut dyn std::io::Write,
+) -> Result<ProcessingCounts, Box<dyn std::error::Error>> {
+    let passes = processing_options.ordering.passes();
+    let mut reader = Some(reader);
+    let mut buffered_input: Option<Vec<u8>> = None;
+    process_input(
+        accounts,
+        &mut || {
+            if passes == 1 {
+                let reader = reader.take().expect("a single pass reads the input once");
+                return submission_order::read_rows_from_reader(reader, processing_options);
+            }
+            if buffered_input.is_none() {
+                let mut input = Vec::new();
+                if let Some(mut reader) = reader.take() {
+                    reader.read_to_end(&mut input)?;
+                }
+                buffered_input = Some(input);
+            }
+            submission_order::read_rows_from_reader(
+                std::io::Cursor::new(buffered_input.clone().unwrap_or_default()),
+                processing_options,
+            )
+        },
+        processing_options,
+        journal_sinks,
+        rejection_sinks,
+        debug_logger,
+    )
+}
+
+fn process_input<S: BuildHasher>(
+    accounts: &mut HashMap<ClientId, ClientAccount, S>,
+    read_input: &mut dyn FnMut() -> anyhow::Result<submission_order::InputRowResults>,
+    processing_options: &ProcessingOptions,
+    journal_sinks: &mut [&mut dyn JournalSink],
+    rejection_sinks: &mut [&mut dyn RejectionSink],
+    debug_logger: &mut dyn std::io::Write,
+) -> Result<ProcessingCounts, Box<dyn std::error::Error>> {
+    let mut progress = PartialProgress::default();
+    process_rows(
+        accounts,
+        read_input,
+        processing_options,
+        journal_sinks,
+        rejection_sinks,
+        debug_logger,
+        &mut progress,
+    )
+    .map_err(|error| ProcessingAborted { progress, error }.into())
+}
+
+fn process_rows<S: BuildHasher>(
+    accounts: &mut HashMap<ClientId, ClientAccount, S>,
+    read_input: &mut dyn FnMut() -> anyhow::Result<submission_order::InputRowResults>,
+    processing_options: &ProcessingOptions,
+    journal_sinks: &mut [&mut dyn JournalSink],
+    rejection_sinks: &mut [&mut dyn RejectionSink],
+    debug_logger: &mut dyn std::io::Write,
+    progress: &mut PartialProgress,
+) -> Result<ProcessingCounts, Box<dyn std::error::Error>> {
+    if let Some(expected_clients) = processing_options.expected_clients {
+        accounts.reserve(expected_clients.saturating_sub(accounts.len()));
+    }
+
+    let mut pending_disputes = PendingDisputes::new(processing_options.pending_dispute_window);
+    let mut amount_normalizations = AmountNormalizationCounts::default();
+    let mut unknown_types = UnknownTypeCounts::default();
+    let mut backfilled = 0;
+    let ordering = processing_options.ordering;
+    for pass in 0..ordering.passes() {
+        let input_rows = read_input()?;
+        for (row, input_row) in input_rows.enumerate() {
+            let submission_order::InputRow {
+                transaction,
+                amount_normalization,
+                line,
+            } = match input_row {
+                Ok(input_row) => input_row,
+                Err(error) => {
+                    let policy = processing_options.unknown_type;
+                    let unknown_type_row = match error.downcast_ref::<UnknownTypeRow>() {
+                        Some(unknown_type_row) if policy != UnknownTypePolicy::Abort => {
+                            unknown_type_row
+                        }
+                        _ => return Err(error.into()),
+                    };
+                    // the row is skipped in every pass, but reported in the first
+                    if pass == 0 {
+                        writeln!(debug_logger, "error processing row - {}", unknown_type_row)
+                            .expect("error writing to debug stream");
+                        unknown_types.record(policy);
+                        if policy == UnknownTypePolicy::DeadLetter {
+                            for rejection_sink in rejection_sinks.iter_mut() {
+                                rejection_sink.record_dead_letter(unknown_type_row)?;
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
+            if !ordering.applies_in_pass(pass, transaction.transaction_type) {
+                continue;
+            }
+
+            for expired in pending_disputes.expire(row) {
+                let error = TransactionProcessingError::ReferencedTransactionNotFound {
+                    client: expired.client_id,
+                    tx: expired.transaction_id,
+                };
+                record_outcome(
+                    &expired,
+                    None,
+                    Err(error),
+                    processing_options,
+                    journal_sinks,
+                    rejection_sinks,
+                )?;
+            }
+
+            amount_normalizations.record(&amount_normalization);
+            match &amount_normalization {
+                AmountNormalization::Unchanged => {}
+                AmountNormalization::NullAsMissing(amount) => {
+                    writeln!(
+                        debug_logger,
+                        "amount {:?} of transaction {} (client {}) taken as missing",
+                        amount, transaction.transaction_id, transaction.client_id
+                    )
+                    .expect("error writing to debug stream");
+                }
+                AmountNormalization::Reformatted(amount) => {
+                    writeln!(
+                        debug_logger,
+                        "amount {:?} of transaction {} (client {}) read as {:?}",
+                        amount,
+                        transaction.transaction_id,
+                        transaction.client_id,
+                        transaction.amount.unwrap_or_default()
+                    )
+                    .expect("error writing to debug stream");
+                }
+                AmountNormalization::Rejected(amount) => {
+                    writeln!(
+                        debug_logger,
+                        "amount {:?} of transaction {} (client {}) rejected",
+                        amount, transaction.transaction_id, transaction.client_id
+                    )
+                    .expect("error writing to debug stream");
+                    let error = TransactionProcessingError::InvalidAmount {
+                        client: transaction.client_id,
+                        tx: transaction.transaction_id,
+                    };
+                    log_transaction_error(debug_logger, &transaction, &error);
+                    record_outcome(
+                        &transaction,
+                        Some(line),
+                        Err(error),
+                        processing_options,
+                        journal_sinks,
+                        rejection_sinks,
+                    )?;
+                    progress.record(line, transaction.client_id);
+                    continue;
+                }
+            }
+
+            if processing_options.backfill && is_carried_over(accounts, &transaction) {
+                backfilled += 1;
+                progress.record(line, transaction.client_id);
+                continue;
+            }
+
+            let outcome = process_transaction_isolated(
+                accounts,
+                &transaction,
+                processing_options,
+                debug_logger,
+            );
+            if let Err(
+                TransactionProcessingError::ReferencedTransactionNotFound { .. }
+                | TransactionProcessingError::ClientNotFound { .. },
+            ) = outcome
+            {
+                if pending_disputes.park(row, &transaction) {
+                    progress.record(line, transaction.client_id);
+                    continue;
+                }
+            }
+            let unparked = match outcome {
+                Ok(Some(_)) => pending_disputes.unpark(&transaction),
+                _ => Vec::new(),
+            };
+            record_outcome(
+                &transaction,
+                Some(line),
+                outcome,
+                processing_options,
+                journal_sinks,
+                rejection_sinks,
+            )?;
+
+            for parked in unparked {
+                let outcome = process_transaction_isolated(
+                    accounts,
+                    &parked,
+                    processing_options,
+                    debug_logger,
+                );
+                record_outcome(
+                    &parked,
+                    None,
+                    outcome,
+                    processing_options,
+                    journal_sinks,
+                    rejection_sinks,
+                )?;
+            }
+            progress.record(line, transaction.client_id);
+        }
+
+        for expired in pending_disputes.expire_all() {
+            let error = TransactionProcessingError::ReferencedTransactionNotFound {
+                client: expired.client_id,
+                tx: expired.transaction_id,
+            };
+            record_outcome(
+                &expired,
+                None,
+                Err(error),
+                processing_options,
+                journal_sinks,
+                rejection_sinks,
+            )?;
+        }
+    }
+
+    Ok(ProcessingCounts {
+        pending_disputes: pending_disputes.counts(),
+        amount_normalizations,
+        unknown_types,
+        backfilled,
+    })
+}
+
+// Whether the transaction is, or (for a dispute op) refers to, one which its client's account had
+// in an earlier run, so that a row of the earlier run's file is applied only once
+fn is_carried_over<S: BuildHasher>(
+    accounts: &HashMap<ClientId, ClientAccount, S>,
+    transaction: &serializable_form::Transaction,
+) -> bool {
+    accounts
+        .get(&transaction.client_id)
+        .is_some_and(|client_account| {
+            client_account.carries_over_transaction(transaction.transaction_id)
+        })
+}
+
+/// Processes the input and compares the accounts with a reference implementation's output,
+/// returning the discrepancies.
+pub fn check_conformance(
+    input_transactions_file: PathBuf,
+    expected_file: &Path,
+    tolerance: f64,
+    processing_options: &ProcessingOptions,
+    debug_logger: &mut dyn std::io::Write,
+) -> Result<Vec<conformance::Discrepancy>, Box<dyn std::error::Error>> {
+    let expected = conformance::read_expected(std::fs::File::open(expected_file)?)?;
+    let mut accounts = Accounts::default();
+    process_transactions_file(
+        &mut accounts,
+        input_transactions_file,
+        processing_options,
+        &mut [],
+        &mut [],
+        debug_logger,
+    )?;
+    Ok(conformance::compare(&accounts, &expected, tolerance)?)
+}
+
+/// Size hints for pre-allocating the account maps, from a quick pass over the file
+#[derive(Debug, PartialEq)]
+pub struct SizeHints {
+    pub clients: usize,
+    pub transactions_per_client: usize,
+}
+
+pub fn scan_size_hints(
+    input_transactions_file: &Path,
+    column_mapping: &ColumnMapping,
+) -> Result<SizeHints, Box<dyn std::error::Error>> {
     let mut reader = csv::Reader::from_path(input_transactions_file)?;
+    column_mapping.apply_to(&mut reader)?;
+    let client_column = reader
+        .headers()?
+        .iter()
+        .position(|header| header.trim() == "client")
+        .ok_or("input has no client column")?;
 
-    for transaction in reader.deserialize() {
-        process_transaction(accounts, &transaction?, debug_logger)?;
+    let mut clients = std::collections::HashSet::<ClientId>::new();
+    let mut rows: usize = 0;
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        rows += 1;
+        if let Some(client_id) = record
+            .get(client_column)
+            .and_then(|client| client.trim().parse().ok())
+        {
+            clients.insert(client_id);
+        }
     }
 
-    Ok(())
+    Ok(SizeHints {
+        clients: clients.len(),
+        transactions_per_client: if clients.is_empty() {
+            0
+        } else {
+            rows.div_ceil(clients.len())
+        },
+    })
 }
 
 pub fn write_output(
     output: &[serializable_form::Output],
+    bool_format: BoolFormat,
+    output_stream: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(write_csv_rows_plain(
+        output.iter().map(Ok),
+        bool_format,
+        output_stream,
+    )?)
+}
+
+/// Writes the output with each client's operator flags and notes appended as `flags` and `notes`
+/// columns (empty for clients without any), in output schema version 2.
+pub fn write_output_with_notes(
+    output: &[serializable_form::Output],
+    bool_format: BoolFormat,
+    client_notes: &ClientNotes,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let output_options = OutputOptions {
+        bool_format,
+        schema_version: SchemaVersion::V2,
+        ..OutputOptions::default()
+    };
+    write_output_with_columns(output, &output_options, Some(client_notes), output_stream)
+}
+
+/// Writes the output with the client notes' `flags` and `notes` columns appended if there are
+/// client notes, then the columns the output options ask for: `lock_tx` and `lock_case` saying
+/// why each locked account was locked, and `chargebacks`, `charged_back` and `closed` with each
+/// account's chargeback exposure. The columns are empty where they don't apply, and are only in
+/// output schema version 2.
+pub fn write_output_with_columns(
+    output: &[serializable_form::Output],
+    output_options: &OutputOptions,
+    client_notes: Option<&ClientNotes>,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    write_csv_rows_with_columns(
+        output.iter().map(Ok),
+        output_options,
+        client_notes,
+        output_stream,
+    )
+}
+
+/// Writes the output straight from the accounts which the output options include, formatting one
+/// row at a time instead of collecting the rows first, in the format of `write_csv_output`. Sorted
+/// by client, only an index of the accounts is collected.
+pub fn write_output_from_accounts<S: BuildHasher>(
+    accounts: &HashMap<ClientId, ClientAccount, S>,
+    output_options: &OutputOptions,
+    client_notes: Option<&ClientNotes>,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let included = accounts
+        .values()
+        .filter(|client_account| output_options.includes(client_account));
+    if output_options.sort_by_client {
+        let mut index: Vec<&ClientAccount> = included.collect();
+        index.sort_unstable_by_key(|client_account| client_account.client_id);
+        return write_csv_rows(
+            index
+                .into_iter()
+                .map(serializable_form::Output::from_client_account),
+            output_options,
+            client_notes,
+            output_stream,
+        );
+    }
+    write_csv_rows(
+        included.map(serializable_form::Output::from_client_account),
+        output_options,
+        client_notes,
+        output_stream,
+    )
+}
+
+fn write_csv_output(
+    output: &[serializable_form::Output],
+    output_options: &OutputOptions,
+    client_notes: Option<&ClientNotes>,
     output_stream: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut cvs_output_writer = csv::Writer::from_writer(output_stream);
+    Ok(write_csv_rows(
+        output.iter().map(Ok),
+        output_options,
+        client_notes,
+        output_stream,
+    )?)
+}
 
-    for output in output {
-        cvs_output_writer.serialize(output)?;
+/// Writes the rows as they come, with any columns for the client notes and output options.
+fn write_csv_rows<O: Borrow<serializable_form::Output>>(
+    outputs: impl Iterator<Item = anyhow::Result<O>>,
+    output_options: &OutputOptions,
+    client_notes: Option<&ClientNotes>,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    if output_options
+        .optional_columns(client_notes.is_some())
+        .is_empty()
+    {
+        return write_csv_rows_plain(outputs, output_options.bool_format, output_stream);
     }
+    write_csv_rows_with_columns(outputs, output_options, client_notes, output_stream)
+}
 
+// The header comes from the first row, so an empty output has none
+fn write_csv_rows_plain<O: Borrow<serializable_form::Output>>(
+    outputs: impl Iterator<Item = anyhow::Result<O>>,
+    bool_format: BoolFormat,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut csv_writer = csv::Writer::from_writer(output_stream);
+    for output in outputs {
+        csv_writer.serialize(output?.borrow().row(bool_format))?;
+    }
+    csv_writer.flush()?;
     Ok(())
 }
 
-pub fn create_serializable_output_from_accounts(
-    accounts: &HashMap<ClientId, ClientAccount>,
+fn write_csv_rows_with_columns<O: Borrow<serializable_form::Output>>(
+    outputs: impl Iterator<Item = anyhow::Result<O>>,
+    output_options: &OutputOptions,
+    client_notes: Option<&ClientNotes>,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    output_options.check_schema_version(client_notes.is_some())?;
+    // the header is written explicitly so that an empty output still has one
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(output_stream);
+    let mut headers = vec!["client", "available", "held", "total", "locked"];
+    headers.extend(output_options.optional_columns(client_notes.is_some()));
+    csv_writer.write_record(&headers)?;
+    for output in outputs {
+        let output = output?;
+        let output = output.borrow();
+        let row = output.row(output_options.bool_format);
+        let mut record = vec![
+            row.client.to_string(),
+            row.available.to_string(),
+            row.held.to_string(),
+            row.total.to_string(),
+            row.locked.to_string(),
+        ];
+        if let Some(client_notes) = client_notes {
+            let client_note = client_notes.get(output.client).cloned().unwrap_or_default();
+            record.extend([client_note.flags_field(), client_note.notes_field()]);
+        }
+        if output_options.lock_reasons {
+            match &output.lock_reason {
+                Some(lock_reason) => record.extend([
+                    lock_reason.transaction_id.to_string(),
+                    lock_reason.case_id.clone().unwrap_or_default(),
+                ]),
+                None => record.extend([String::new(), String::new()]),
+            }
+        }
+        if output_options.chargeback_totals {
+            record.extend([
+                output.chargebacks.to_string(),
+                output.charged_back.clone(),
+                output_options.bool_format.format(output.closed).to_string(),
+            ]);
+        }
+        csv_writer.write_record(&record)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Below this many accounts the output is formatted on the calling thread, as starting threads
+/// would cost more than it saves
+const PARALLEL_OUTPUT_MIN_ACCOUNTS: usize = 10_000;
+
+/// The output rows of the accounts which the output options include, in the accounts map's order
+/// unless sorted by client. Large account maps are formatted in chunks, one per available core.
+pub fn create_serializable_output_from_accounts<S: BuildHasher>(
+    accounts: &HashMap<ClientId, ClientAccount, S>,
+    output_options: &OutputOptions,
+) -> anyhow::Result<Vec<serializable_form::Output>> {
+    let mut included = Vec::with_capacity(accounts.len());
+    included.extend(
+        accounts
+            .values()
+            .filter(|client_account| output_options.includes(client_account)),
+    );
+
+    if output_options.sort_by_client {
+        included.sort_unstable_by_key(|client_account| client_account.client_id);
+    }
+
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    if included.len() < PARALLEL_OUTPUT_MIN_ACCOUNTS || threads == 1 {
+        return create_serializable_output_chunk(&included);
+    }
+
+    let chunk_size = included.len().div_ceil(threads);
+    let chunks = std::thread::scope(|scope| {
+        let handles: Vec<_> = included
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| create_serializable_output_chunk(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("output formatting thread panicked"))
+            .collect::<anyhow::Result<Vec<_>>>()
+    })?;
+
+    let mut output = Vec::with_capacity(included.len());
+    for chunk in chunks {
+        output.extend(chunk);
+    }
+    Ok(output)
+}
+
+fn create_serializable_output_chunk(
+    client_accounts: &[&ClientAccount],
 ) -> anyhow::Result<Vec<serializable_form::Output>> {
-    let mut output = Vec::new();
-    for client_account in accounts.values() {
+    let mut output = Vec::with_capacity(client_accounts.len());
+    for client_account in client_accounts {
         output.push(serializable_form::Output::from_client_account(
             client_account,
         )?);
@@ -86,21 +1124,1044 @@ pub fn create_serializable_output_from_accounts(
 
 pub fn cli(
     input_file: PathBuf,
+    cli_options: &CliOptions,
     output_stream: &mut dyn std::io::Write,
     debug_logger: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut accounts = HashMap::<ClientId, ClientAccount>::new();
-    process_transactions_file(&mut accounts, input_file, debug_logger)?;
+    let started_at = manifest::unix_timestamp();
+    let mut accounts = match &cli_options.snapshot_in_file {
+        Some(snapshot_in_file) => {
+            let mut accounts = state_snapshot::load_snapshot(snapshot_in_file)?;
+            let dispute_retention_runs = cli_options.processing_options.dispute_retention_runs;
+            let forgotten = state_snapshot::carry_over(&mut accounts, dispute_retention_runs);
+            if forgotten > 0 {
+                writeln!(
+                    debug_logger,
+                    "forgot {} settled transactions carried over into more than {} runs",
+                    forgotten,
+                    dispute_retention_runs.unwrap_or_default()
+                )?;
+            }
+            accounts
+        }
+        None => Accounts::default(),
+    };
+    let mut processing_options = cli_options.processing_options.clone();
+    let from_stdin = input_file == Path::new(cli_options::STDIN_INPUT);
+    cli_options
+        .output_options
+        .check_schema_version(cli_options.output_options.client_notes_file.is_some())?;
+    if cli_options.presize && from_stdin {
+        return Err("--presize can't scan the input when it is read from stdin".into());
+    }
+    if cli_options.threads > 1 && from_stdin {
+        return Err("--threads can't shard the input when it is read from stdin".into());
+    }
+    if cli_options.threads > 1 && processing_options.max_clients.is_some() {
+        return Err("--max-clients can't be enforced across the shards of --threads".into());
+    }
+    if cli_options.threads > 1 && processing_options.sweep_client.is_some() {
+        return Err("--sweep-client can't be credited across the shards of --threads".into());
+    }
+    if let Some(shards) = cli_options
+        .output_parts
+        .as_ref()
+        .and_then(|options| options.shards)
+    {
+        if shards <= 1 {
+            return Err("--shard-output-parts needs the shards of --threads".into());
+        }
+        #[cfg(feature = "output-template")]
+        if cli_options.output_options.template_file.is_some() {
+            return Err("--shard-output-parts can't tell the client of a template's rows".into());
+        }
+    }
+    if cli_options.threads > 1
+        && processing_options.cross_client_disputes == CrossClientDisputePolicy::Locate
+    {
+        return Err(
+            "--cross-client-disputes locate can't search across the shards of --threads".into(),
+        );
+    }
+    if processing_options.backfill && cli_options.snapshot_in_file.is_none() {
+        return Err("--backfill needs the state of --snapshot-in to skip the known ids of".into());
+    }
+    if cli_options.dry_run && cli_options.snapshot_in_file.is_none() {
+        return Err("--dry-run needs the state of --snapshot-in to diff against".into());
+    }
+    if cli_options.dry_run && cli_options.snapshot_out_file.is_some() {
+        return Err("--dry-run leaves the state as it was, so can't write --snapshot-out".into());
+    }
+    // the output parts, batch store, control totals, template and manifest are all of the
+    // accounts' output, which a dry run replaces
+    if cli_options.dry_run
+        && (cli_options.output_parts.is_some()
+            || cli_options.needs_output_rows()
+            || cli_options.manifest_file.is_some())
+    {
+        return Err("--dry-run writes only the changes, not the output of the accounts".into());
+    }
+    if cli_options.presize {
+        let size_hints = scan_size_hints(&input_file, &processing_options.column_mapping)?;
+        processing_options.expected_clients = Some(size_hints.clients);
+        processing_options.expected_transactions_per_client =
+            Some(size_hints.transactions_per_client);
+    }
 
-    let serializable_output = create_serializable_output_from_accounts(&accounts)?;
-    write_output(&serializable_output, output_stream)?;
+    let mut journal_writer = match &cli_options.journal_file {
+        Some(journal_file) => Some(CsvJournalWriter::new(
+            cli_options
+                .compression
+                .writer(std::fs::File::create(journal_file)?),
+        )),
+        None => None,
+    };
+    let mut change_log_writer = match &cli_options.change_log_file {
+        Some(change_log_file) => Some(change_log::ChangeLogWriter::new(
+            cli_options
+                .compression
+                .writer(std::fs::File::create(change_log_file)?),
+        )),
+        None => None,
+    };
+    let mut journal_sinks = Vec::<&mut dyn JournalSink>::new();
+    if let Some(journal_writer) = journal_writer.as_mut() {
+        journal_sinks.push(journal_writer);
+    }
+    if let Some(change_log_writer) = change_log_writer.as_mut() {
+        journal_sinks.push(change_log_writer);
+    }
+    let mut journal_entries = Vec::<JournalEntry>::new();
+    if cli_options.needs_journal_entries() {
+        journal_sinks.push(&mut journal_entries);
+    }
+    let mut netting = (cli_options.netting_file.is_some() || cli_options.settlement.is_some())
+        .then(Netting::default);
+    if let Some(netting) = netting.as_mut() {
+        journal_sinks.push(netting);
+    }
+    let mut arbitration_transactions = Vec::new();
+    let mut dispute_cases = match &cli_options.dispute_cases {
+        Some(options) => {
+            let imported_cases = match &options.import_file {
+                Some(import_file) => dispute_cases::read_cases(import_file, options.format)?,
+                None => Vec::new(),
+            };
+            if let Some(arbitration_file) = &options.arbitration_file {
+                arbitration_transactions = arbitration::read_arbitration_transactions_file(
+                    arbitration_file,
+                    &imported_cases,
+                )?;
+            }
+            processing_options.known_dispute_cases = imported_cases
+                .iter()
+                .map(|case| (case.client_id, case.transaction_id))
+                .collect();
+            let open_cases: Vec<dispute_cases::DisputeCase> = imported_cases
+                .into_iter()
+                .filter(|case| case.outcome == dispute_cases::CaseOutcome::Open)
+                .collect();
+            dispute_cases::import_open_cases(&mut accounts, &open_cases)?;
+            Some(dispute_cases::DisputeCases::with_open_cases(open_cases))
+        }
+        None => None,
+    };
+    if let Some(dispute_cases) = dispute_cases.as_mut() {
+        journal_sinks.push(dispute_cases);
+    }
+    let mut streaming_metrics = streaming_metrics::StreamingMetrics::default();
+    journal_sinks.push(&mut streaming_metrics);
+    let mut trial_balance = TrialBalance::opening(&accounts);
+    journal_sinks.push(&mut trial_balance);
+
+    let mut suspense = cli_options
+        .suspense
+        .as_ref()
+        .map(|options| Suspense::new(options.mode));
+    let mut rejection_sinks = Vec::<&mut dyn RejectionSink>::new();
+    if let Some(suspense) = suspense.as_mut() {
+        rejection_sinks.push(suspense);
+    }
+    let mut error_report_writer = match &cli_options.errors {
+        Some(options) => Some(error_report::ErrorReportWriter::new(
+            std::io::BufWriter::new(std::fs::File::create(&options.file)?),
+            options.format,
+        )?),
+        None => None,
+    };
+    if let Some(error_report_writer) = error_report_writer.as_mut() {
+        rejection_sinks.push(error_report_writer);
+    }
+    let mut retry_writer = match &cli_options.retry_file {
+        Some(retry_file) => Some(retry::RetryWriter::new(std::fs::File::create(retry_file)?)?),
+        None => None,
+    };
+    if let Some(retry_writer) = retry_writer.as_mut() {
+        rejection_sinks.push(retry_writer);
+    }
+    let mut review_writer = match &cli_options.review_file {
+        Some(review_file) => Some(review::ReviewWriter::new(std::fs::File::create(
+            review_file,
+        )?)?),
+        None => None,
+    };
+    if let Some(review_writer) = review_writer.as_mut() {
+        rejection_sinks.push(review_writer);
+    }
+    let mut dead_letter_writer = match &cli_options.dead_letter_file {
+        Some(dead_letter_file) => Some(unknown_types::DeadLetterWriter::new(
+            std::fs::File::create(dead_letter_file)?,
+        )),
+        None => None,
+    };
+    if let Some(dead_letter_writer) = dead_letter_writer.as_mut() {
+        rejection_sinks.push(dead_letter_writer);
+    }
+
+    // taken once the imported open cases are held, so that they aren't reported as changes
+    let opening_state = cli_options
+        .dry_run
+        .then(|| state_diff::OpeningState::of(&accounts));
+
+    #[cfg(feature = "memprofile")]
+    memprofile::enter(memprofile::Subsystem::Processing);
+    let processed = if from_stdin {
+        process_transactions_reader(
+            &mut accounts,
+            std::io::stdin(),
+            &processing_options,
+            &mut journal_sinks,
+            &mut rejection_sinks,
+            debug_logger,
+        )
+    } else {
+        parallel::process_transactions_parallel(
+            &mut accounts,
+            input_file.clone(),
+            cli_options.threads,
+            &processing_options,
+            &mut journal_sinks,
+            &mut rejection_sinks,
+            debug_logger,
+        )
+    };
+    let processing_counts = match processed {
+        Ok(processing_counts) => processing_counts,
+        Err(error) => {
+            if let Some(error_report_writer) = error_report_writer.take() {
+                error_report_writer.finish()?;
+            }
+            if let Some(partial_snapshot_file) = &cli_options.partial_snapshot_file {
+                write_output_from_accounts(
+                    &accounts,
+                    &cli_options.output_options,
+                    None,
+                    &mut std::fs::File::create(partial_snapshot_file)?,
+                )?;
+            }
+            return Err(error);
+        }
+    };
+    // the arbitration outcomes finalize the long-running disputes after this run's transactions
+    for transaction in &arbitration_transactions {
+        let outcome = process_transaction_isolated(
+            &mut accounts,
+            transaction,
+            &processing_options,
+            debug_logger,
+        );
+        record_outcome(
+            transaction,
+            None,
+            outcome,
+            &processing_options,
+            &mut journal_sinks,
+            &mut rejection_sinks,
+        )?;
+    }
+    drop(journal_sinks);
+    let trial_balance = trial_balance.report(&accounts)?;
+    if cli_options.verify && !trial_balance.balanced {
+        return Err(format!(
+            "trial balance mismatch: the accounts total {} (held {}), expected {} (held {})",
+            trial_balance.total,
+            trial_balance.held,
+            trial_balance.expected_total,
+            trial_balance.expected_held
+        )
+        .into());
+    }
+    if let Some(snapshot_out_file) = &cli_options.snapshot_out_file {
+        state_snapshot::save_snapshot(&accounts, snapshot_out_file)?;
+    }
+    let pending_dispute_counts = (processing_options.pending_dispute_window > 0)
+        .then_some(processing_counts.pending_disputes);
+    let amount_normalization_counts = (!processing_counts.amount_normalizations.is_empty())
+        .then_some(processing_counts.amount_normalizations);
+    let unknown_type_counts =
+        (!processing_counts.unknown_types.is_empty()).then_some(processing_counts.unknown_types);
+    let backfilled = processing_options
+        .backfill
+        .then_some(processing_counts.backfilled);
+    let metrics_summary = streaming_metrics.summary()?;
+    let batch_id = match (&cli_options.batch_id, &cli_options.batch_store) {
+        (Some(batch_id), _) => Some(batch_id.clone()),
+        (None, Some(batch_store)) => Some(batch_store.next_batch_id()?),
+        (None, None) => None,
+    };
+    let summary = || -> anyhow::Result<Summary> {
+        Ok(Summary::from_accounts(&accounts)?
+            .with_batch_id(batch_id.clone())
+            .with_pending_disputes(pending_dispute_counts)
+            .with_amount_normalizations(amount_normalization_counts)
+            .with_unknown_types(unknown_type_counts)
+            .with_backfilled(backfilled)
+            .with_trial_balance(trial_balance.clone())
+            .with_metrics(metrics_summary.clone()))
+    };
+    #[cfg(feature = "memprofile")]
+    memprofile::enter(memprofile::Subsystem::Reporting);
+    // flush the journal, change log, error report, retry, review and dead-letter files before they
+    // may be digested for the manifest
+    drop(journal_writer);
+    drop(change_log_writer);
+    if let Some(error_report_writer) = error_report_writer {
+        error_report_writer.finish()?;
+    }
+    drop(retry_writer);
+    drop(review_writer);
+    drop(dead_letter_writer);
+
+    if let (Some(options), Some(suspense)) = (&cli_options.suspense, &suspense) {
+        let mut suspense_writer = std::fs::File::create(&options.file)?;
+        suspense.write_report(&mut suspense_writer)?;
+    }
+
+    #[cfg(feature = "plaintext-accounting")]
+    if let Some(options) = &cli_options.plaintext_accounting {
+        let mut plaintext_writer = std::fs::File::create(&options.file)?;
+        plaintext_accounting::write_plaintext_accounting(
+            &journal_entries,
+            options.format,
+            &dates::Date::today().to_string(),
+            &options.commodity,
+            &mut plaintext_writer,
+        )?;
+    }
+
+    if let Some(options) = &cli_options.statements {
+        statement::write_statements(&accounts, &journal_entries, options, dates::Date::today())?;
+    }
+
+    if let (Some(netting_file), Some(netting)) = (&cli_options.netting_file, &netting) {
+        let mut netting_writer = std::fs::File::create(netting_file)?;
+        netting.write_report(&mut netting_writer)?;
+    }
+
+    if let (Some(options), Some(netting)) = (&cli_options.settlement, &netting) {
+        let mut settlement_writer = std::fs::File::create(&options.file)?;
+        settlement::write_settlement_file(
+            netting,
+            options.threshold,
+            &options.template,
+            options.value_date.unwrap_or_else(dates::Date::today),
+            &mut settlement_writer,
+        )?;
+    }
+
+    if let (Some(options), Some(dispute_cases)) = (&cli_options.dispute_cases, &dispute_cases) {
+        if let Some(file) = &options.file {
+            let mut dispute_cases_writer = std::fs::File::create(file)?;
+            dispute_cases.write(options.format, &mut dispute_cases_writer)?;
+        }
+    }
+
+    if let Some(errored_clients_file) = &cli_options.errored_clients_file {
+        let mut errored_clients_writer = std::fs::File::create(errored_clients_file)?;
+        errored_clients::write_report(&accounts, &mut errored_clients_writer)?;
+    }
+
+    if let Some(summary_file) = &cli_options.summary_file {
+        let mut summary_writer = std::fs::File::create(summary_file)?;
+        summary()?.write_json(&mut summary_writer)?;
+    }
+
+    #[cfg(feature = "arrow")]
+    if let Some(arrow_file) = &cli_options.arrow_file {
+        let mut arrow_writer = std::fs::File::create(arrow_file)?;
+        let record_batch = arrow_output::create_record_batch_from_accounts(
+            accounts
+                .values()
+                .filter(|client_account| cli_options.output_options.includes(client_account)),
+        )?;
+        arrow_output::write_arrow_ipc(&record_batch, &mut arrow_writer)?;
+    }
+
+    if let Some(opening_state) = &opening_state {
+        let mut compressed_output = cli_options.compression.writer(output_stream);
+        state_diff::write_report(
+            &state_diff::account_deltas(opening_state, &accounts),
+            &mut compressed_output,
+        )?;
+        compressed_output.finish()?;
+        return Ok(());
+    }
+
+    // the output is streamed from the accounts, unless the rows are needed all at once
+    let serializable_output = if cli_options.needs_output_rows() {
+        Some(create_serializable_output_from_accounts(
+            &accounts,
+            &cli_options.output_options,
+        )?)
+    } else {
+        None
+    };
+    let client_notes = match &cli_options.output_options.client_notes_file {
+        Some(client_notes_file) => Some(ClientNotes::from_path(client_notes_file)?),
+        None => None,
+    };
+    if let Some(options) = &cli_options.groups {
+        let mapping = groups::GroupMapping::from_path(&options.mapping_file)?;
+        let group_balances = groups::group_balances(
+            accounts
+                .values()
+                .filter(|client_account| cli_options.output_options.includes(client_account)),
+            &mapping,
+        );
+        let mut groups_writer = std::fs::File::create(&options.output_file)?;
+        groups::write_report(&group_balances, &mut groups_writer)?;
+    }
+
+    let stored_batch = match (&cli_options.batch_store, &batch_id, &serializable_output) {
+        (Some(batch_store), Some(batch_id), Some(serializable_output)) => {
+            Some(batch_store.write_batch(batch_id, serializable_output, &summary()?)?)
+        }
+        _ => None,
+    };
+
+    let mut output_parts = cli_options
+        .output_parts
+        .as_ref()
+        .map(|options| OutputParts::new(options.clone(), cli_options.compression));
+    let mut compressed_output = cli_options.compression.writer(output_stream);
+    let output_writer: &mut dyn std::io::Write = match output_parts.as_mut() {
+        Some(output_parts) => output_parts,
+        None => &mut compressed_output,
+    };
+    let mut crc32_writer = Crc32Writer::new(output_writer);
+    match &serializable_output {
+        Some(serializable_output) => {
+            #[cfg(feature = "output-template")]
+            if let Some(template_file) = &cli_options.output_options.template_file {
+                output_template::render_output_template(
+                    template_file,
+                    serializable_output,
+                    &summary()?,
+                    client_notes.as_ref(),
+                    &mut crc32_writer,
+                )?;
+            } else {
+                write_csv_output(
+                    serializable_output,
+                    &cli_options.output_options,
+                    client_notes.as_ref(),
+                    &mut crc32_writer,
+                )?;
+            }
+            #[cfg(not(feature = "output-template"))]
+            write_csv_output(
+                serializable_output,
+                &cli_options.output_options,
+                client_notes.as_ref(),
+                &mut crc32_writer,
+            )?;
+        }
+        None => write_output_from_accounts(
+            &accounts,
+            &cli_options.output_options,
+            client_notes.as_ref(),
+            &mut crc32_writer,
+        )?,
+    }
+
+    // The checksums are of the output before compression
+    let output_digest = StreamDigest {
+        bytes: crc32_writer.bytes_written(),
+        crc32: format!("{:08x}", crc32_writer.crc32()),
+    };
+    let output_crc32 = crc32_writer.crc32();
+    let written_parts = match output_parts {
+        Some(output_parts) => output_parts.finish()?,
+        None => Vec::new(),
+    };
+    compressed_output.finish()?;
+
+    if let (Some(control_totals_file), Some(serializable_output)) =
+        (&cli_options.control_totals_file, &serializable_output)
+    {
+        let mut control_totals_writer = std::fs::File::create(control_totals_file)?;
+        ControlTotals::new(serializable_output, output_crc32)?
+            .write_json(&mut control_totals_writer)?;
+    }
+
+    if let Some(manifest_file) = &cli_options.manifest_file {
+        let mut outputs = Vec::new();
+        for output_path in cli_options.output_paths() {
+            outputs.extend(FileDigest::of_path(&output_path)?);
+        }
+        for part in &written_parts {
+            outputs.push(FileDigest::of_file(&part.path)?);
+        }
+        if let Some(stored_batch) = &stored_batch {
+            outputs.extend(FileDigest::of_path(stored_batch)?);
+        }
+        let run_manifest = RunManifest {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            config: format!("{:?}", cli_options),
+            started_at,
+            finished_at: manifest::unix_timestamp(),
+            inputs: cli_options
+                .input_paths(&input_file)
+                .iter()
+                .map(|input_path| FileDigest::of_file(input_path))
+                .collect::<anyhow::Result<_>>()?,
+            outputs,
+            stdout: output_digest,
+            summary: Some(summary()?),
+            seed: None,
+        };
+        let mut manifest_writer = std::fs::File::create(manifest_file)?;
+        run_manifest.write_json(&mut manifest_writer)?;
+    }
 
     Ok(())
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
+    use amount_parsing::NullAmountPolicy;
+    use processing_options::{BalanceCapPolicy, ImplicitAccounts, LimitPolicy};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_transaction_type_display_round_trips_through_from_str() {
+        for transaction_type in [
+            TransactionType::Deposit,
+            TransactionType::Withdrawal,
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Chargeback,
+            TransactionType::Representment,
+            TransactionType::FinalChargeback,
+            TransactionType::RepresentmentWon,
+            TransactionType::OpenAccount,
+            TransactionType::CloseAccount,
+            TransactionType::Transfer,
+        ] {
+            assert_eq!(
+                transaction_type.to_string().parse::<TransactionType>(),
+                Ok(transaction_type)
+            );
+            // the same name as the input column
+            assert_eq!(
+                serde_json::to_string(&transaction_type).unwrap(),
+                format!("\"{}\"", transaction_type)
+            );
+        }
+        assert_eq!(TransactionType::Withdrawal.to_string(), "withdrawal");
+        assert!("Deposit".parse::<TransactionType>().is_err());
+    }
+
+    struct FailingWriter;
+
+    impl std::io::Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("write failed"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_a_panic_only_poisons_the_transactions_client() {
+        let transaction = |client_id, transaction_id| serializable_form::Transaction {
+            client_id,
+            transaction_id,
+            transaction_type: TransactionType::Deposit,
+            amount: Some(10.0),
+            counterparty: None,
+        };
+        let mut accounts = Accounts::default();
+        for (client_id, transaction_id) in [(1, 1), (2, 2)] {
+            process_transaction_isolated(
+                &mut accounts,
+                &transaction(client_id, transaction_id),
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
+        }
+
+        // logging the duplicate id error to a failing debug stream panics
+        assert_eq!(
+            process_transaction_isolated(
+                &mut accounts,
+                &transaction(1, 1),
+                &ProcessingOptions::default(),
+                &mut FailingWriter,
+            ),
+            Err(TransactionProcessingError::ClientErrored { client: 1, tx: 1 })
+        );
+        assert_eq!(
+            accounts[&1].errored(),
+            Some("error writing to debug stream: Custom { kind: Other, error: \"write failed\" }")
+        );
+
+        assert_eq!(
+            process_transaction_isolated(
+                &mut accounts,
+                &transaction(1, 3),
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::ClientErrored { client: 1, tx: 3 })
+        );
+        assert!(process_transaction_isolated(
+            &mut accounts,
+            &transaction(2, 4),
+            &ProcessingOptions::default(),
+            &mut std::io::sink(),
+        )
+        .is_ok());
+        assert_eq!(accounts[&2].balance.available, 20.0);
+
+        let output =
+            create_serializable_output_from_accounts(&accounts, &OutputOptions::default()).unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].client, 2);
+
+        let mut report = Vec::<u8>::new();
+        errored_clients::write_report(&accounts, &mut report).unwrap();
+        assert!(std::str::from_utf8(&report)
+            .unwrap()
+            .starts_with("client,reason\n1,\"error writing to debug stream"));
+    }
+
+    #[test]
+    fn test_process_transaction_sweeps_closed_accounts_to_the_sweep_client() {
+        let processing_options = ProcessingOptions {
+            sweep_client: Some(9),
+            ..Default::default()
+        };
+        let mut accounts = Accounts::default();
+
+        // a close account never creates the account
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &testing::transaction(TransactionType::CloseAccount, 1, 1, None),
+                &ProcessingOptions {
+                    implicit_accounts: ImplicitAccounts::Allow,
+                    ..processing_options.clone()
+                },
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::ClientNotFound { client: 1, tx: 1 })
+        );
+
+        for (client_id, transaction_id) in [(1, 2), (9, 3)] {
+            process_transaction(
+                &mut accounts,
+                &testing::deposit(client_id, transaction_id, 4.0),
+                &processing_options,
+                &mut std::io::sink(),
+            )
+            .unwrap();
+        }
+        let applied_transaction = process_transaction(
+            &mut accounts,
+            &testing::transaction(TransactionType::CloseAccount, 1, 4, None),
+            &processing_options,
+            &mut std::io::sink(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let sweep = applied_transaction.counterparty.unwrap();
+        assert_eq!(
+            sweep.journal_entry,
+            JournalEntry::new(9, 4, TransactionType::CloseAccount, 4.0)
+        );
+        assert_eq!(sweep.balance.available, 8.0);
+        assert_account!(accounts[&1], available = "0.0000", closed = true);
+        assert_account!(accounts[&9], available = "8.0000");
+    }
+
+    #[test]
+    fn test_process_transaction_applies_transfers_to_both_accounts_or_neither() {
+        let transfer = |transaction_id, counterparty, amount| {
+            testing::transfer(1, transaction_id, counterparty, amount)
+        };
+        let processing_options = ProcessingOptions {
+            balance_cap: Some(5.0),
+            ..Default::default()
+        };
+        let mut accounts = Accounts::default();
+        process_transaction(
+            &mut accounts,
+            &testing::deposit(1, 1, 5.0),
+            &processing_options,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+
+        for (transaction, error) in [
+            (
+                transfer(2, Some(2), None),
+                TransactionProcessingError::AmountNotPresentForTransfer { client: 1, tx: 2 },
+            ),
+            (
+                transfer(3, Some(2), Some(-1.0)),
+                TransactionProcessingError::InvalidAmount { client: 1, tx: 3 },
+            ),
+            (
+                transfer(4, None, Some(1.0)),
+                TransactionProcessingError::CounterpartyNotPresentForTransfer { client: 1, tx: 4 },
+            ),
+        ] {
+            assert_eq!(
+                process_transaction(
+                    &mut accounts,
+                    &transaction,
+                    &processing_options,
+                    &mut std::io::sink(),
+                ),
+                Err(error)
+            );
+        }
+
+        let applied_transaction = process_transaction(
+            &mut accounts,
+            &transfer(5, Some(2), Some(4.0)),
+            &processing_options,
+            &mut std::io::sink(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            applied_transaction.journal_entry,
+            JournalEntry::new(1, 5, TransactionType::Transfer, -4.0)
+        );
+        assert_eq!(
+            applied_transaction.counterparty.unwrap().journal_entry,
+            JournalEntry::new(2, 5, TransactionType::Transfer, 4.0)
+        );
+
+        // the counterparty's balance cap rejects the credit, so the debit is taken back
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &transfer(6, Some(2), Some(1.5)),
+                &processing_options,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::BalanceCapExceeded { client: 2, tx: 6 })
+        );
+        assert_account!(accounts[&1], available = "1.0000");
+        assert_eq!(accounts[&1].retained_transactions(), 2);
+        assert_account!(accounts[&2], available = "4.0000");
+        // and a counterparty account created for it is dropped
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &transfer(5, Some(3), Some(1.0)),
+                &processing_options,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::TransactionIDAlreadyExists { client: 1, tx: 5 })
+        );
+        assert!(!accounts.contains_key(&3));
+    }
+
+    #[test]
+    fn test_process_transaction_locates_disputes_of_another_clients_transaction() {
+        let mut accounts = testing::accounts_from(
+            &[testing::deposit(1, 1, 5.0), testing::deposit(2, 2, 3.0)],
+            &ProcessingOptions::default(),
+        );
+        let locate = ProcessingOptions {
+            cross_client_disputes: CrossClientDisputePolicy::Locate,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &testing::dispute(2, 1),
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::ReferencedTransactionNotFound { client: 2, tx: 1 })
+        );
+        let mut debug_logger = Vec::<u8>::new();
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &testing::dispute(2, 1),
+                &locate,
+                &mut debug_logger,
+            ),
+            Err(TransactionProcessingError::ClientMismatchOnDispute { client: 2, tx: 1 })
+        );
+        let debug_log_str = std::str::from_utf8(&debug_logger).unwrap();
+        assert!(debug_log_str.contains("transaction 1 belongs to client 1, not client 2"));
+        // an unknown client doesn't get an account for it
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &testing::chargeback(3, 2),
+                &locate,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::ClientMismatchOnDispute { client: 3, tx: 2 })
+        );
+        assert!(!accounts.contains_key(&3));
+        // a transaction no client has is still not found
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &testing::dispute(2, 7),
+                &locate,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::ReferencedTransactionNotFound { client: 2, tx: 7 })
+        );
+
+        process_transaction(
+            &mut accounts,
+            &testing::dispute(1, 1),
+            &locate,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        assert_account!(accounts[&1], available = "0.0000", held = "5.0000");
+        assert_account!(accounts[&2], available = "3.0000", held = "0.0000");
+    }
+
+    #[test]
+    fn test_process_transaction_applies_the_implicit_accounts_policy() {
+        let transaction = |transaction_type, client_id, amount| serializable_form::Transaction {
+            client_id,
+            transaction_id: 1,
+            transaction_type,
+            amount,
+            counterparty: None,
+        };
+        let deny_for_dispute_ops = ProcessingOptions {
+            implicit_accounts: ImplicitAccounts::DenyForDisputeOps,
+            ..Default::default()
+        };
+        let deny = ProcessingOptions {
+            implicit_accounts: ImplicitAccounts::Deny,
+            ..Default::default()
+        };
+        let mut accounts = Accounts::default();
+
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &transaction(TransactionType::Dispute, 1, None),
+                &deny_for_dispute_ops,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::ClientNotFound { client: 1, tx: 1 })
+        );
+        assert!(process_transaction(
+            &mut accounts,
+            &transaction(TransactionType::Deposit, 1, Some(10.0)),
+            &deny_for_dispute_ops,
+            &mut std::io::sink(),
+        )
+        .unwrap()
+        .is_some());
+
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &transaction(TransactionType::Deposit, 2, Some(10.0)),
+                &deny,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::ClientNotFound { client: 2, tx: 1 })
+        );
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &transaction(TransactionType::OpenAccount, 2, None),
+                &deny,
+                &mut std::io::sink(),
+            ),
+            Ok(None)
+        );
+        assert!(accounts[&2].is_untouched());
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &transaction(TransactionType::OpenAccount, 2, None),
+                &deny,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::AccountAlreadyOpen { client: 2, tx: 1 })
+        );
+        assert!(process_transaction(
+            &mut accounts,
+            &transaction(TransactionType::Deposit, 2, Some(10.0)),
+            &deny,
+            &mut std::io::sink(),
+        )
+        .unwrap()
+        .is_some());
+        assert_eq!(accounts.len(), 2);
+    }
+
+    #[test]
+    fn test_process_transaction_enforces_resource_limits() {
+        let deposit = |client_id, transaction_id| serializable_form::Transaction {
+            client_id,
+            transaction_id,
+            transaction_type: TransactionType::Deposit,
+            amount: Some(1.0),
+            counterparty: None,
+        };
+        let processing_options = ProcessingOptions {
+            max_transactions_per_client: Some(2),
+            max_clients: Some(1),
+            ..Default::default()
+        };
+        let mut accounts = Accounts::default();
+
+        for transaction_id in [1, 2] {
+            process_transaction(
+                &mut accounts,
+                &deposit(1, transaction_id),
+                &processing_options,
+                &mut std::io::sink(),
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &deposit(1, 3),
+                &processing_options,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::TransactionLimitExceeded { client: 1, tx: 3 })
+        );
+        // a repeated id is still reported as a duplicate
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &deposit(1, 2),
+                &processing_options,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::TransactionIDAlreadyExists { client: 1, tx: 2 })
+        );
+        let client_limit_error = process_transaction(
+            &mut accounts,
+            &deposit(2, 4),
+            &processing_options,
+            &mut std::io::sink(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            client_limit_error,
+            TransactionProcessingError::ClientLimitExceeded { client: 2, tx: 4 }
+        );
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[&1].balance.available, 2.0);
+
+        assert!(!processing_options.is_fatal(&client_limit_error));
+        let abort_options = ProcessingOptions {
+            limit_exceeded: LimitPolicy::Abort,
+            ..processing_options
+        };
+        assert!(abort_options.is_fatal(&client_limit_error));
+    }
+
+    #[test]
+    fn test_process_transaction_enforces_balance_caps() {
+        let deposit = |client_id, transaction_id, amount| serializable_form::Transaction {
+            client_id,
+            transaction_id,
+            transaction_type: TransactionType::Deposit,
+            amount: Some(amount),
+            counterparty: None,
+        };
+        let processing_options = ProcessingOptions {
+            balance_cap: Some(100.0),
+            client_balance_caps: HashMap::from([(2, 10.0)]),
+            ..Default::default()
+        };
+        let mut accounts = Accounts::default();
+
+        process_transaction(
+            &mut accounts,
+            &deposit(1, 1, 100.0),
+            &processing_options,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &deposit(1, 2, 0.01),
+                &processing_options,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::BalanceCapExceeded { client: 1, tx: 2 })
+        );
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &deposit(2, 3, 25.0),
+                &processing_options,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::BalanceCapExceeded { client: 2, tx: 3 })
+        );
+
+        let suspend_options = ProcessingOptions {
+            balance_cap_exceeded: BalanceCapPolicy::SuspendExcess,
+            ..processing_options
+        };
+        let applied_transaction = process_transaction(
+            &mut accounts,
+            &deposit(2, 4, 25.0),
+            &suspend_options,
+            &mut std::io::sink(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(applied_transaction.journal_entry.amount, 10.0);
+        assert_eq!(applied_transaction.suspended_excess, Some(15.0));
+        assert_eq!(accounts[&2].balance.available, 10.0);
+        // with no headroom left there is nothing to credit
+        assert_eq!(
+            process_transaction(
+                &mut accounts,
+                &deposit(2, 5, 1.0),
+                &suspend_options,
+                &mut std::io::sink(),
+            ),
+            Err(TransactionProcessingError::BalanceCapExceeded { client: 2, tx: 5 })
+        );
+    }
 
     #[test]
     fn test_process_transaction_creates_a_new_client_as_required() {
@@ -111,8 +2172,15 @@ mod tests {
             transaction_id: 1,
             transaction_type: TransactionType::Deposit,
             amount: Some(100.0),
+            counterparty: None,
         };
-        process_transaction(&mut accounts, &transaction_1, &mut std::io::sink()).unwrap();
+        process_transaction(
+            &mut accounts,
+            &transaction_1,
+            &ProcessingOptions::default(),
+            &mut std::io::sink(),
+        )
+        .unwrap();
         assert_eq!(accounts.len(), 1);
         assert_eq!(accounts[&1].balance.available, 100.0);
 
@@ -121,8 +2189,15 @@ mod tests {
             transaction_id: 1,
             transaction_type: TransactionType::Deposit,
             amount: Some(1000.0),
+            counterparty: None,
         };
-        process_transaction(&mut accounts, &transaction_2, &mut std::io::sink()).unwrap();
+        process_transaction(
+            &mut accounts,
+            &transaction_2,
+            &ProcessingOptions::default(),
+            &mut std::io::sink(),
+        )
+        .unwrap();
         assert_eq!(accounts.len(), 2);
         assert_eq!(accounts[&2].balance.available, 1000.0);
     }
@@ -147,12 +2222,14 @@ mod tests {
             transaction_id: 1,
             transaction_type: TransactionType::Deposit,
             amount: Some(100.0),
+            counterparty: None,
         };
         let t_client_2_tx_1 = serializable_form::Transaction {
             client_id: 2,
             transaction_id: 1,
             transaction_type: TransactionType::Deposit,
             amount: Some(1000.0),
+            counterparty: None,
         };
 
         // Client 1 dispute-resolve flow
@@ -161,18 +2238,21 @@ mod tests {
             transaction_id: 2,
             transaction_type: TransactionType::Deposit,
             amount: Some(10.0),
+            counterparty: None,
         };
         let t_client_1_dispute_tx_2 = serializable_form::Transaction {
             client_id: 1,
             transaction_id: 2,
             transaction_type: TransactionType::Dispute,
             amount: None,
+            counterparty: None,
         };
         let t_client_1_resolve_tx_2 = serializable_form::Transaction {
             client_id: 1,
             transaction_id: 2,
             transaction_type: TransactionType::Resolve,
             amount: None,
+            counterparty: None,
         };
 
         // Client 2 dispute-chargeback flow
@@ -181,18 +2261,21 @@ mod tests {
             transaction_id: 2,
             transaction_type: TransactionType::Deposit,
             amount: Some(100.0),
+            counterparty: None,
         };
         let t_client_2_dispute_tx_2 = serializable_form::Transaction {
             client_id: 2,
             transaction_id: 2,
             transaction_type: TransactionType::Dispute,
             amount: None,
+            counterparty: None,
         };
         let t_client_2_chargeback_tx_2 = serializable_form::Transaction {
             client_id: 2,
             transaction_id: 2,
             transaction_type: TransactionType::Chargeback,
             amount: None,
+            counterparty: None,
         };
 
         transactions.push(t_client_1_tx_1);
@@ -205,7 +2288,13 @@ mod tests {
         transactions.push(t_client_2_chargeback_tx_2);
 
         for transaction in transactions {
-            process_transaction(&mut accounts, &transaction, &mut std::io::sink()).unwrap();
+            process_transaction(
+                &mut accounts,
+                &transaction,
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
         }
 
         assert_eq!(accounts.len(), 2);
@@ -219,7 +2308,8 @@ mod tests {
         assert_eq!(accounts[&2].balance.total(), 1000.0);
         assert_eq!(accounts[&2].locked, true);
 
-        let output = create_serializable_output_from_accounts(&accounts).unwrap();
+        let output =
+            create_serializable_output_from_accounts(&accounts, &OutputOptions::default()).unwrap();
 
         assert_eq!(output.len(), 2);
         let client_1_output = output.iter().find(|output| output.client == 1).unwrap();
@@ -243,7 +2333,13 @@ mod tests {
 
         let input_file = Path::new("tests/fixtures/transactions.csv").to_owned();
 
-        cli(input_file, &mut output_writer, &mut debug_writer).unwrap();
+        cli(
+            input_file,
+            &CliOptions::default(),
+            &mut output_writer,
+            &mut debug_writer,
+        )
+        .unwrap();
 
         let output_string = String::from_utf8(output_writer).unwrap();
         let debug_string = String::from_utf8(debug_writer).unwrap();
@@ -261,4 +2357,352 @@ mod tests {
 
         assert!(output_string == expected_stdout_order1 || output_string == expected_stdout_order2);
     }
+
+    #[test]
+    fn test_create_serializable_output_from_accounts_with_output_options() {
+        let mut accounts = Accounts::default();
+
+        let transactions = vec![
+            serializable_form::Transaction {
+                client_id: 1,
+                transaction_id: 1,
+                transaction_type: TransactionType::Deposit,
+                amount: Some(1.0),
+                counterparty: None,
+            },
+            serializable_form::Transaction {
+                client_id: 2,
+                transaction_id: 2,
+                transaction_type: TransactionType::Deposit,
+                amount: Some(0.005),
+                counterparty: None,
+            },
+        ];
+        for transaction in transactions {
+            process_transaction(
+                &mut accounts,
+                &transaction,
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
+        }
+
+        // a dispute for an unknown transaction creates an untouched account if implicit accounts
+        // are allowed
+        let dispute = serializable_form::Transaction {
+            client_id: 3,
+            transaction_id: 3,
+            transaction_type: TransactionType::Dispute,
+            amount: None,
+            counterparty: None,
+        };
+        process_transaction(
+            &mut accounts,
+            &dispute,
+            &ProcessingOptions {
+                implicit_accounts: ImplicitAccounts::Allow,
+                ..Default::default()
+            },
+            &mut std::io::sink(),
+        )
+        .unwrap_err();
+
+        let output =
+            create_serializable_output_from_accounts(&accounts, &OutputOptions::default()).unwrap();
+        assert_eq!(output.len(), 3);
+
+        let skip_untouched = OutputOptions {
+            skip_untouched: true,
+            ..Default::default()
+        };
+        let mut clients: Vec<ClientId> =
+            create_serializable_output_from_accounts(&accounts, &skip_untouched)
+                .unwrap()
+                .iter()
+                .map(|output| output.client)
+                .collect();
+        clients.sort_unstable();
+        assert_eq!(clients, vec![1, 2]);
+
+        let min_abs_total = OutputOptions {
+            min_abs_total: Some(0.01),
+            ..Default::default()
+        };
+        let output = create_serializable_output_from_accounts(&accounts, &min_abs_total).unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].client, 1);
+    }
+
+    #[test]
+    fn test_create_serializable_output_from_accounts_in_chunks() {
+        let mut accounts = Accounts::default();
+        for client_id in 0..=(PARALLEL_OUTPUT_MIN_ACCOUNTS as ClientId) {
+            let transaction = serializable_form::Transaction {
+                client_id,
+                transaction_id: client_id as TransactionId,
+                transaction_type: TransactionType::Deposit,
+                amount: Some(client_id as f64 / 8.0),
+                counterparty: None,
+            };
+            process_transaction(
+                &mut accounts,
+                &transaction,
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
+        }
+
+        let output =
+            create_serializable_output_from_accounts(&accounts, &OutputOptions::default()).unwrap();
+        // in the accounts map's order, as formatted one at a time
+        assert_eq!(output.len(), accounts.len());
+        for (output, client_account) in output.iter().zip(accounts.values()) {
+            assert_eq!(output.client, client_account.client_id);
+            assert_eq!(
+                output.available,
+                format!("{:.4}", client_account.client_id as f64 / 8.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_output_from_accounts_matches_the_collected_output() {
+        let mut accounts = Accounts::default();
+        process_transactions_file(
+            &mut accounts,
+            Path::new("tests/fixtures/transactions-representment.csv").to_owned(),
+            &ProcessingOptions::default(),
+            &mut [],
+            &mut [],
+            &mut std::io::sink(),
+        )
+        .unwrap();
+
+        for output_options in [
+            OutputOptions::default(),
+            OutputOptions {
+                sort_by_client: true,
+                schema_version: SchemaVersion::V2,
+                lock_reasons: true,
+                chargeback_totals: true,
+                ..Default::default()
+            },
+        ] {
+            let mut streamed = Vec::new();
+            write_output_from_accounts(&accounts, &output_options, None, &mut streamed).unwrap();
+
+            let output =
+                create_serializable_output_from_accounts(&accounts, &output_options).unwrap();
+            let mut collected = Vec::new();
+            write_csv_output(&output, &output_options, None, &mut collected).unwrap();
+
+            assert_eq!(
+                String::from_utf8(streamed).unwrap(),
+                String::from_utf8(collected).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_process_transactions_file_records_journal_entries_for_applied_transactions() {
+        let mut accounts = Accounts::default();
+        let mut journal_entries = Vec::<JournalEntry>::new();
+
+        let input_file = Path::new("tests/fixtures/transactions-complex.csv").to_owned();
+        process_transactions_file(
+            &mut accounts,
+            input_file,
+            &ProcessingOptions::default(),
+            &mut [&mut journal_entries],
+            &mut [],
+            &mut std::io::sink(),
+        )
+        .unwrap();
+
+        let transaction_types: Vec<TransactionType> = journal_entries
+            .iter()
+            .map(|entry| entry.transaction_type)
+            .collect();
+        assert_eq!(
+            transaction_types,
+            vec![
+                TransactionType::Deposit,
+                TransactionType::Deposit,
+                TransactionType::Dispute,
+                TransactionType::Resolve,
+                TransactionType::Deposit,
+                TransactionType::Deposit,
+                TransactionType::Dispute,
+                TransactionType::Chargeback,
+            ]
+        );
+        assert_eq!(journal_entries[7].client_id, 2);
+        assert_eq!(journal_entries[7].amount, 100.0);
+    }
+
+    #[test]
+    fn test_two_pass_ordering_applies_disputes_after_their_transactions() {
+        let input_file = Path::new("tests/fixtures/transactions-out-of-order.csv").to_owned();
+
+        let mut accounts = Accounts::default();
+        process_transactions_file(
+            &mut accounts,
+            input_file.clone(),
+            &ProcessingOptions::default(),
+            &mut [],
+            &mut [],
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        assert_eq!(accounts[&1].balance.held, 0.0);
+        assert_eq!(accounts[&1].locked, false);
+
+        let mut accounts = Accounts::default();
+        process_transactions_file(
+            &mut accounts,
+            input_file,
+            &ProcessingOptions {
+                ordering: processing_options::Ordering::TwoPass,
+                ..Default::default()
+            },
+            &mut [],
+            &mut [],
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        assert_eq!(accounts[&1].balance.available, 0.0);
+        assert_eq!(accounts[&1].balance.held, 0.0);
+        assert_eq!(accounts[&1].locked, true);
+        assert_eq!(accounts[&2].balance.held, 2.0);
+    }
+
+    #[test]
+    fn test_process_transactions_reader_reads_the_input_again_for_two_pass_ordering() {
+        let mut accounts = Accounts::default();
+        process_transactions_reader(
+            &mut accounts,
+            std::fs::File::open("tests/fixtures/transactions-out-of-order.csv").unwrap(),
+            &ProcessingOptions {
+                ordering: processing_options::Ordering::TwoPass,
+                ..Default::default()
+            },
+            &mut [],
+            &mut [],
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        assert_eq!(accounts[&1].locked, true);
+        assert_eq!(accounts[&2].balance.held, 2.0);
+    }
+
+    #[test]
+    fn test_pending_dispute_window_retries_disputes_when_their_transaction_arrives() {
+        let mut accounts = Accounts::default();
+        let mut rejections = Vec::<u8>::new();
+        let mut retry_writer = retry::RetryWriter::new(&mut rejections).unwrap();
+        let pending_dispute_counts = process_transactions_file(
+            &mut accounts,
+            Path::new("tests/fixtures/transactions-out-of-order.csv").to_owned(),
+            &ProcessingOptions {
+                pending_dispute_window: 1,
+                ..Default::default()
+            },
+            &mut [],
+            &mut [&mut retry_writer],
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        drop(retry_writer);
+
+        // client 1's dispute expires a row before its deposit arrives, which leaves nothing for
+        // the chargeback to charge back; client 2's deposit arrives the row after its dispute
+        assert_eq!(accounts[&1].balance.available, 3.0);
+        assert_eq!(accounts[&1].locked, false);
+        assert_eq!(accounts[&2].balance.held, 2.0);
+        assert_eq!(
+            pending_dispute_counts.pending_disputes,
+            PendingDisputeCounts {
+                parked: 3,
+                unparked: 2,
+                expired: 1,
+            }
+        );
+        assert_eq!(
+            String::from_utf8(rejections).unwrap(),
+            "type,client,tx,amount,counterparty\ndispute,1,1,,\n"
+        );
+    }
+
+    #[test]
+    fn test_null_amounts_are_missing_or_rejected() {
+        let process = |null_amounts| {
+            let mut accounts = Accounts::default();
+            let mut rejections = Vec::<u8>::new();
+            let mut retry_writer = retry::RetryWriter::new(&mut rejections).unwrap();
+            let processing_counts = process_transactions_file(
+                &mut accounts,
+                Path::new("tests/fixtures/transactions-null-amounts.csv").to_owned(),
+                &ProcessingOptions {
+                    null_amounts,
+                    ..Default::default()
+                },
+                &mut [],
+                &mut [&mut retry_writer],
+                &mut std::io::sink(),
+            )
+            .unwrap();
+            drop(retry_writer);
+            (
+                accounts,
+                processing_counts.amount_normalizations,
+                rejections,
+            )
+        };
+
+        let (accounts, counts, _) = process(NullAmountPolicy::Missing);
+        assert_eq!(accounts[&1].balance.held, 2.0);
+        assert_eq!(
+            counts,
+            AmountNormalizationCounts {
+                null_as_missing: 4,
+                reformatted: 0,
+                rejected: 0,
+            }
+        );
+
+        // the dispute's NULL amount is rejected too, so nothing is held
+        let (accounts, counts, rejections) = process(NullAmountPolicy::Reject);
+        assert_eq!(accounts[&1].balance.available, 2.0);
+        assert_eq!(accounts[&1].balance.held, 0.0);
+        assert_eq!(
+            counts,
+            AmountNormalizationCounts {
+                null_as_missing: 0,
+                reformatted: 0,
+                rejected: 4,
+            }
+        );
+        assert_eq!(
+            String::from_utf8(rejections).unwrap(),
+            "type,client,tx,amount,counterparty\n"
+        );
+    }
+
+    #[test]
+    fn test_scan_size_hints() {
+        let size_hints = scan_size_hints(
+            Path::new("tests/fixtures/transactions-complex.csv"),
+            &ColumnMapping::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            size_hints,
+            SizeHints {
+                clients: 2,
+                transactions_per_client: 4,
+            }
+        );
+    }
 }
@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How many suppressed errors of a kind for a client are reported together.
+const SUPPRESSED_REPORT_INTERVAL: usize = 1000;
+
+const TRANSACTION_ERROR_PREFIX: &str = "error processing transaction - ";
+
+/// The lines buffered for an HTTP bulk endpoint before they are posted together.
+const HTTP_BATCH_LINES: usize = 500;
+
+/// The lines kept for an HTTP bulk endpoint while it is unreachable; the oldest are dropped
+/// beyond this.
+const HTTP_MAX_BUFFERED_LINES: usize = 100_000;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the debug log (which includes every rejection) is written.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LogTarget {
+    Stderr,
+    /// A file, rotated as configured
+    File(PathBuf),
+    /// A syslog server, as `host:port`, sent a UDP datagram per line
+    Syslog(String),
+    /// An HTTP endpoint which batches of lines are posted to
+    HttpBulk {
+        host: String,
+        path: String,
+    },
+}
+
+impl std::str::FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "stderr" {
+            Ok(LogTarget::Stderr)
+        } else if let Some(path) = s.strip_prefix("file:") {
+            Ok(LogTarget::File(PathBuf::from(path)))
+        } else if let Some(address) = s.strip_prefix("syslog:") {
+            Ok(LogTarget::Syslog(address.to_string()))
+        } else if let Some(url) = s.strip_prefix("http://") {
+            let (host, path) = match url.find('/') {
+                Some(index) => (&url[..index], &url[index..]),
+                None => (url, "/"),
+            };
+            Ok(LogTarget::HttpBulk {
+                host: host.to_string(),
+                path: path.to_string(),
+            })
+        } else {
+            Err(format!("unknown log target: {}", s))
+        }
+    }
+}
+
+/// When a log file is rotated: once writing a line would take it over `max_bytes`, or once it has
+/// been open for `max_age`.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct RotationOptions {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+}
+
+/// Opens the log target as a stream for the debug logger.
+pub fn open_log(
+    target: &LogTarget,
+    rotation: RotationOptions,
+) -> anyhow::Result<Box<dyn std::io::Write>> {
+    Ok(match target {
+        LogTarget::Stderr => Box::new(std::io::stderr()),
+        LogTarget::File(path) => Box::new(LineWriter::new(RotatingFile::open(path, rotation)?)),
+        LogTarget::Syslog(address) => Box::new(LineWriter::new(Syslog::connect(address)?)),
+        LogTarget::HttpBulk { host, path } => Box::new(LineWriter::new(HttpBulk::new(host, path))),
+    })
+}
+
+/// Wraps the debug log so that only the first `limit` errors of each kind for each client are
+/// written, followed by a line for every `SUPPRESSED_REPORT_INTERVAL` suppressed, and one for any
+/// left over at the end.
+pub fn deduplicate(log: Box<dyn std::io::Write>, limit: usize) -> Box<dyn std::io::Write> {
+    Box::new(LineWriter::new(Deduplicated::new(log, limit)))
+}
+
+/// Receives the log a line at a time.
+trait LineSink {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()>;
+
+    fn flush(&mut self) -> std::io::Result<()>;
+}
+
+/// Splits what is written into lines for a `LineSink`, holding back an incomplete last line.
+struct LineWriter<S: LineSink> {
+    sink: S,
+    partial_line: Vec<u8>,
+}
+
+impl<S: LineSink> LineWriter<S> {
+    fn new(sink: S) -> Self {
+        Self {
+            sink,
+            partial_line: Vec::new(),
+        }
+    }
+}
+
+impl<S: LineSink> std::io::Write for LineWriter<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.partial_line.extend_from_slice(buf);
+        while let Some(end) = self.partial_line.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.partial_line.drain(..=end).collect();
+            self.sink
+                .write_line(String::from_utf8_lossy(&line[..end]).as_ref())?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+impl<S: LineSink> Drop for LineWriter<S> {
+    fn drop(&mut self) {
+        if !self.partial_line.is_empty() {
+            let line = String::from_utf8_lossy(&self.partial_line).into_owned();
+            let _ = self.sink.write_line(&line);
+        }
+        let _ = self.sink.flush();
+    }
+}
+
+/// A log file which is renamed to `<path>.1`, `<path>.2`, ... when it is rotated.
+struct RotatingFile {
+    path: PathBuf,
+    rotation: RotationOptions,
+    file: std::io::BufWriter<std::fs::File>,
+    bytes: u64,
+    opened_at: SystemTime,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, rotation: RotationOptions) -> anyhow::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let bytes = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_owned(),
+            rotation,
+            file: std::io::BufWriter::new(file),
+            bytes,
+            opened_at: SystemTime::now(),
+        })
+    }
+
+    fn is_due(&self, line_bytes: u64) -> bool {
+        let too_big = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max_bytes| self.bytes > 0 && self.bytes + line_bytes > max_bytes);
+        let too_old = self.rotation.max_age.is_some_and(|max_age| {
+            self.opened_at
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed >= max_age)
+        });
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        let mut index = 1;
+        let rotated_path = loop {
+            let mut rotated_path = self.path.clone().into_os_string();
+            rotated_path.push(format!(".{}", index));
+            let rotated_path = PathBuf::from(rotated_path);
+            if !rotated_path.exists() {
+                break rotated_path;
+            }
+            index += 1;
+        };
+        std::fs::rename(&self.path, rotated_path)?;
+        self.file = std::io::BufWriter::new(std::fs::File::create(&self.path)?);
+        self.bytes = 0;
+        self.opened_at = SystemTime::now();
+        Ok(())
+    }
+}
+
+impl LineSink for RotatingFile {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let line_bytes = line.len() as u64 + 1;
+        if self.is_due(line_bytes) {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.bytes += line_bytes;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Sends each line as an RFC 5424 message from the user facility at debug severity.
+struct Syslog {
+    socket: UdpSocket,
+}
+
+impl Syslog {
+    fn connect(address: &str) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address)?;
+        Ok(Self { socket })
+    }
+}
+
+impl LineSink for Syslog {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        // like a line the HTTP endpoint could not take, a lost datagram must not stop processing
+        let _ = self
+            .socket
+            .send(format!("<15>1 - - rs_bpt - - - {}", line).as_bytes());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Posts the lines to an HTTP endpoint in batches, as plain text with a line each. Lines which
+/// could not be delivered are kept and sent with the next batch, so an endpoint which is briefly
+/// down does not lose them, nor does it stop processing.
+struct HttpBulk {
+    host: String,
+    path: String,
+    lines: VecDeque<String>,
+    lines_since_post: usize,
+}
+
+impl HttpBulk {
+    fn new(host: &str, path: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            path: path.to_string(),
+            lines: VecDeque::new(),
+            lines_since_post: 0,
+        }
+    }
+
+    fn post(&self) -> std::io::Result<()> {
+        let mut body = String::new();
+        for line in &self.lines {
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        let mut stream = TcpStream::connect(&self.host)?;
+        stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.host,
+            body.len(),
+            body
+        )?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let status = response.split(' ').nth(1).unwrap_or_default();
+        if status.starts_with('2') {
+            Ok(())
+        } else {
+            Err(std::io::Error::other(format!(
+                "log endpoint responded with status {}",
+                status
+            )))
+        }
+    }
+}
+
+impl LineSink for HttpBulk {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.lines.len() == HTTP_MAX_BUFFERED_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.to_string());
+        self.lines_since_post += 1;
+        if self.lines_since_post == HTTP_BATCH_LINES {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.lines_since_post = 0;
+        if !self.lines.is_empty() && self.post().is_ok() {
+            self.lines.clear();
+        }
+        Ok(())
+    }
+}
+
+/// The log with repeated errors suppressed. An error is an `error processing transaction - <kind>:
+/// ... (client <client>)` line; the line after it, with the transaction, is suppressed along with
+/// it.
+struct Deduplicated<W: std::io::Write> {
+    log: W,
+    limit: usize,
+    occurrences: HashMap<(String, String), usize>,
+    unreported: BTreeMap<(String, String), usize>,
+    suppressing_transaction: bool,
+}
+
+impl<W: std::io::Write> Deduplicated<W> {
+    fn new(log: W, limit: usize) -> Self {
+        Self {
+            log,
+            limit,
+            occurrences: HashMap::new(),
+            unreported: BTreeMap::new(),
+            suppressing_transaction: false,
+        }
+    }
+
+    fn report_suppressed(&mut self, key: &(String, String), count: usize) -> std::io::Result<()> {
+        writeln!(
+            self.log,
+            "suppressed {} similar {} errors (client {})",
+            count, key.0, key.1
+        )
+    }
+}
+
+// The kind of error and client of an error line
+fn error_key(line: &str) -> Option<(String, String)> {
+    let error = line.strip_prefix(TRANSACTION_ERROR_PREFIX)?;
+    let (kind, _) = error.split_once(':')?;
+    let client = error.strip_suffix(')')?.rsplit_once("(client ")?.1;
+    Some((kind.to_string(), client.to_string()))
+}
+
+impl<W: std::io::Write> LineSink for Deduplicated<W> {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if std::mem::take(&mut self.suppressing_transaction) {
+            return Ok(());
+        }
+        if let Some(key) = error_key(line) {
+            let occurrences = self.occurrences.entry(key.clone()).or_default();
+            *occurrences += 1;
+            if *occurrences > self.limit {
+                self.suppressing_transaction = true;
+                let unreported = self.unreported.entry(key.clone()).or_default();
+                *unreported += 1;
+                if *unreported == SUPPRESSED_REPORT_INTERVAL {
+                    self.unreported.remove(&key);
+                    self.report_suppressed(&key, SUPPRESSED_REPORT_INTERVAL)?;
+                }
+                return Ok(());
+            }
+        }
+        writeln!(self.log, "{}", line)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for (key, count) in std::mem::take(&mut self.unreported) {
+            self.report_suppressed(&key, count)?;
+        }
+        self.log.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_log_targets() {
+        assert_eq!("stderr".parse(), Ok(LogTarget::Stderr));
+        assert_eq!(
+            "file:debug.log".parse(),
+            Ok(LogTarget::File(PathBuf::from("debug.log")))
+        );
+        assert_eq!(
+            "syslog:localhost:514".parse(),
+            Ok(LogTarget::Syslog("localhost:514".to_string()))
+        );
+        assert_eq!(
+            "http://localhost:8080/logs/bulk".parse(),
+            Ok(LogTarget::HttpBulk {
+                host: "localhost:8080".to_string(),
+                path: "/logs/bulk".to_string()
+            })
+        );
+        assert_eq!(
+            "ftp://localhost".parse::<LogTarget>(),
+            Err("unknown log target: ftp://localhost".to_string())
+        );
+    }
+
+    #[test]
+    fn it_suppresses_repeated_errors_per_kind_and_client() {
+        let mut output = Vec::new();
+        {
+            let mut log = LineWriter::new(Deduplicated::new(&mut output, 2));
+            for (transaction_id, client) in [(1, 1), (2, 1), (3, 2), (4, 1), (5, 1)] {
+                writeln!(
+                    log,
+                    "error processing transaction - ReferencedTransactionNotFound: {} (client {})",
+                    transaction_id, client
+                )
+                .unwrap();
+                writeln!(log, "Transaction {{ tx: {} }}", transaction_id).unwrap();
+            }
+            writeln!(
+                log,
+                "error processing row - row 9 has unknown transaction type: fee"
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "error processing transaction - ReferencedTransactionNotFound: 1 (client 1)
+Transaction { tx: 1 }
+error processing transaction - ReferencedTransactionNotFound: 2 (client 1)
+Transaction { tx: 2 }
+error processing transaction - ReferencedTransactionNotFound: 3 (client 2)
+Transaction { tx: 3 }
+error processing row - row 9 has unknown transaction type: fee
+suppressed 2 similar ReferencedTransactionNotFound errors (client 1)
+"
+        );
+    }
+
+    #[test]
+    fn it_rotates_the_log_file_by_size() {
+        let directory = std::env::temp_dir().join("rs_bpt-logging-rotation");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("debug.log");
+
+        let rotation = RotationOptions {
+            max_bytes: Some(12),
+            max_age: None,
+        };
+        let mut log = open_log(&LogTarget::File(path.clone()), rotation).unwrap();
+        write!(log, "first line\nsecond").unwrap();
+        writeln!(log, " line").unwrap();
+        writeln!(log, "third line").unwrap();
+        drop(log);
+
+        let read = |name: &str| std::fs::read_to_string(directory.join(name)).unwrap();
+        assert_eq!(read("debug.log.1"), "first line\n");
+        assert_eq!(read("debug.log.2"), "second line\n");
+        assert_eq!(read("debug.log"), "third line\n");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn it_sends_each_line_to_syslog() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = LogTarget::Syslog(server.local_addr().unwrap().to_string());
+        let mut log = open_log(&target, RotationOptions::default()).unwrap();
+        writeln!(log, "error processing transaction 4").unwrap();
+
+        let mut datagram = [0; 512];
+        let length = server.recv(&mut datagram).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&datagram[..length]).unwrap(),
+            "<15>1 - - rs_bpt - - - error processing transaction 4"
+        );
+    }
+
+    #[test]
+    fn it_posts_the_buffered_lines_when_flushed() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = LogTarget::HttpBulk {
+            host: listener.local_addr().unwrap().to_string(),
+            path: "/bulk".to_string(),
+        };
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !String::from_utf8_lossy(&request).ends_with("line two\n") {
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let mut log = open_log(&target, RotationOptions::default()).unwrap();
+        writeln!(log, "line one").unwrap();
+        writeln!(log, "line two").unwrap();
+        log.flush().unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /bulk HTTP/1.1\r\n"));
+        assert!(request.ends_with("\r\n\r\nline one\nline two\n"));
+    }
+}
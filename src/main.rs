@@ -1,7 +1,46 @@
+use std::io::Write;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
-use rs_bpt::cli;
+#[cfg(feature = "plaintext-accounting")]
+use rs_bpt::plaintext_accounting::{PlaintextAccountingOptions, PlaintextFormat};
+use rs_bpt::{
+    amount_parsing::{AmountFormat, NullAmountPolicy},
+    balance_caps::read_balance_caps_file,
+    balance_history,
+    batch_store::{is_valid_batch_id, BatchStore},
+    bench, cli,
+    cli_options::CliOptions,
+    client_tiers::ClientTiers,
+    column_mapping::ColumnMapping,
+    compaction,
+    compression::Compression,
+    conformance,
+    dates::Date,
+    dispute_cases::{DisputeCasesFormat, DisputeCasesOptions},
+    error_report::{ErrorReportFormat, ErrorReportOptions},
+    groups::GroupOptions,
+    logging::{deduplicate, open_log, LogTarget, RotationOptions},
+    output_options::{BoolFormat, OutputOptions},
+    output_parts::OutputPartsOptions,
+    partial_progress::ProcessingAborted,
+    policy_matrix,
+    processing_options::{
+        BalanceCapPolicy, CrossClientDisputePolicy, HeldBalanceUnderflowPolicy, ImplicitAccounts,
+        LimitPolicy, LockedAccountPolicy, Ordering, ProcessingOptions, ResolveWithoutDisputePolicy,
+        UnknownTypePolicy,
+    },
+    schema::{write_schema, SchemaFormat, SchemaTarget, SchemaVersion},
+    settlement::{SettlementOptions, SettlementTemplate},
+    simulation::{self, SimulationOptions},
+    snapshot_diff,
+    statement::{StatementFormat, StatementOptions},
+    submission_order::SubmissionOrder,
+    suspense::{SuspenseMode, SuspenseOptions},
+    timestamps::{AmbiguousTimestampPolicy, TimestampFormat, UtcOffset},
+    type_aliases::TypeAliases,
+    ClientId,
+};
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "rs_bpt", about = "Batch process transactions")]
@@ -10,23 +49,820 @@ struct Opt {
     #[structopt(short, long)]
     debug: bool,
 
-    /// Input file
+    /// Write the debug log to this target instead of stderr (implies --debug): file:<path>, syslog:<host>:<port> or http://<host>:<port>/<path> (posted in batches)
+    #[structopt(long)]
+    debug_log: Option<LogTarget>,
+
+    /// Rotate a file debug log once it would exceed this many bytes
+    #[structopt(long)]
+    debug_log_max_bytes: Option<u64>,
+
+    /// Rotate a file debug log once it has been open for this many seconds
+    #[structopt(long)]
+    debug_log_max_age: Option<u64>,
+
+    /// Log only the first N errors of each kind for each client, then how many similar errors were suppressed
+    #[structopt(long)]
+    log_dedup: Option<usize>,
+
+    /// What to do when a resolve or chargeback would release more than is held: clamp, reject or abort
+    #[structopt(long, default_value = "reject")]
+    held_balance_underflow: HeldBalanceUnderflowPolicy,
+
+    /// Which transactions may create an unknown client's account: allow, deny-for-dispute-ops or deny
+    #[structopt(long, default_value = "deny-for-dispute-ops")]
+    implicit_accounts: ImplicitAccounts,
+
+    /// The order transactions were submitted in: file-order, per-client-timestamp (by a timestamp column) or external-sequence (by a sequence column)
+    #[structopt(long, default_value = "file-order")]
+    submission_order: SubmissionOrder,
+
+    /// Map upstream column names onto the input columns, e.g. 'txn_kind=type,cust=client,ref=tx,value=amount'
+    #[structopt(long = "map", default_value = "")]
+    column_mapping: ColumnMapping,
+
+    /// Map values of the type column onto transaction types, e.g. 'credit=deposit,debit=withdrawal'
+    #[structopt(long, default_value = "")]
+    type_aliases: TypeAliases,
+
+    /// What to do with a row whose type is not a transaction type: reject (skip it), dead-letter (skip it and write it to the --dead-letter file) or abort
+    #[structopt(long, default_value = "abort")]
+    unknown_type: UnknownTypePolicy,
+
+    /// Write the rows dead-lettered for unknown types to this file, as read
+    #[structopt(long, parse(from_os_str))]
+    dead_letter: Option<PathBuf>,
+
+    /// The order to apply rows in: file-order, or two-pass to apply disputes, resolves and chargebacks after all deposits and withdrawals
+    #[structopt(long, default_value = "file-order")]
+    ordering: Ordering,
+
+    /// What to do with an amount sent as an empty string, NULL or NaN: missing (reject deposits and withdrawals as having no amount) or reject (any transaction)
+    #[structopt(long, default_value = "missing")]
+    null_amounts: NullAmountPolicy,
+
+    /// How amounts are written: standard (1234.56 or 1.5e3), strict (1234.56 only), comma-decimal (1.234,56 or 1.5e3) or lenient ($1,234.56); amounts which don't match are rejected, except in standard
+    #[structopt(long, default_value = "standard")]
+    amount_format: AmountFormat,
+
+    /// How the timestamp column is written: epoch-seconds, epoch-millis, rfc3339 (e.g. 2022-03-01T09:30:00+01:00) or compact (YYYYMMDDHHMMSS)
+    #[structopt(long, default_value = "epoch-seconds")]
+    timestamp_format: TimestampFormat,
+
+    /// The UTC offset (e.g. +01:00) of timestamps written in local time, without an offset of their own
+    #[structopt(long)]
+    timestamp_timezone: Option<UtcOffset>,
+
+    /// What to do with a local time when there's no --timestamp-timezone, or an epoch value which looks like the other unit: reject (stop reading the input) or normalize (take it as UTC, or in the unit it looks like)
+    #[structopt(long, default_value = "reject")]
+    ambiguous_timestamps: AmbiguousTimestampPolicy,
+
+    /// Park a dispute op whose transaction has not been seen yet for up to this many rows, retrying it when the transaction arrives
+    #[structopt(long, default_value = "0")]
+    pending_dispute_window: usize,
+
+    /// Pre-allocate room for this many clients
+    #[structopt(long)]
+    expected_clients: Option<usize>,
+
+    /// Pre-allocate room for this many transactions per client
+    #[structopt(long)]
+    expected_transactions_per_client: Option<usize>,
+
+    /// Hard limit on the deposits and withdrawals retained per client
+    #[structopt(long)]
+    max_transactions_per_client: Option<usize>,
+
+    /// Hard limit on the number of clients
+    #[structopt(long)]
+    max_clients: Option<usize>,
+
+    /// Skip (and count in the summary) the rows whose transaction is already in the state of --snapshot-in, and the dispute ops of such a transaction, instead of applying them again
+    #[structopt(long)]
+    backfill: bool,
+
+    /// Forget settled transactions from --snapshot-in once they have been carried over into more than this many runs, so that disputes can no longer reach them
+    #[structopt(long)]
+    dispute_retention_runs: Option<u32>,
+
+    /// What to do when a limit is exceeded: reject or abort
+    #[structopt(long, default_value = "reject")]
+    limit_exceeded: LimitPolicy,
+
+    /// Close accounts with more chargebacks than this: a closed account rejects new deposits and
+    /// withdrawals
+    #[structopt(long)]
+    max_chargebacks: Option<usize>,
+
+    /// Credit the residual balance of an account closed by a close_account row to this client
+    #[structopt(long)]
+    sweep_client: Option<ClientId>,
+
+    /// What a locked account does with new deposits and withdrawals: allow or reject
+    #[structopt(long, default_value = "allow")]
+    locked_accounts: LockedAccountPolicy,
+
+    /// Cap on every client's total balance, unless --balance-caps gives the client its own cap
+    #[structopt(long)]
+    balance_cap: Option<f64>,
+
+    /// CSV file of per-client balance caps (client,cap columns)
+    #[structopt(long, parse(from_os_str))]
+    balance_caps: Option<PathBuf>,
+
+    /// What to do with a deposit which would exceed its client's balance cap: reject or suspend-excess (credit up to the cap and report the excess as suspense)
+    #[structopt(long, default_value = "reject")]
+    balance_cap_exceeded: BalanceCapPolicy,
+
+    /// JSON file of client tiers and the options they override (balance-cap, balance-cap-exceeded and max-transactions-per-client)
+    #[structopt(long, parse(from_os_str), requires = "client-tiers")]
+    tiers: Option<PathBuf>,
+
+    /// CSV file mapping clients to tiers (client,tier columns)
+    #[structopt(long, parse(from_os_str), requires = "tiers")]
+    client_tiers: Option<PathBuf>,
+
+    /// Scan the input file first to size the account maps (overrides the expected sizes)
+    #[structopt(long)]
+    presize: bool,
+
+    /// Process the input on this many threads, sharding the transactions by client id
+    #[structopt(long, default_value = "1")]
+    threads: usize,
+
+    /// Omit accounts with no applied transactions from the output
+    #[structopt(long)]
+    skip_untouched: bool,
+
+    /// How the locked column is written: true/false, TRUE/FALSE or 0/1
+    #[structopt(long, default_value = "true/false")]
+    bool_format: BoolFormat,
+
+    /// CSV file of operator flags and notes per client (client,flag,note columns), appended to the
+    /// output as flags and notes columns
+    #[structopt(long, parse(from_os_str))]
+    client_notes: Option<PathBuf>,
+
+    /// Append lock_tx and lock_case columns to the output saying which chargeback (transaction
+    /// and dispute case) locked each locked account
+    #[structopt(long)]
+    lock_reasons: bool,
+
+    /// Append chargebacks, charged_back and closed columns to the output with how many of each
+    /// account's transactions were charged back, their total amount, and whether the account was
+    /// closed by --max-chargebacks
+    #[structopt(long)]
+    chargeback_totals: bool,
+
+    /// Write the accounts in client id order
+    #[structopt(long)]
+    sort_by_client: bool,
+
+    /// Render the accounts and summary through this Tera template instead of writing CSV
+    #[cfg(feature = "output-template")]
+    #[structopt(long, parse(from_os_str))]
+    output_template: Option<PathBuf>,
+
+    /// Output schema version; version 1 is exactly client,available,held,total,locked, version 2
+    /// adds the columns of --client-notes, --lock-reasons and --chargeback-totals
+    #[structopt(long, default_value = "1")]
+    schema_version: SchemaVersion,
+
+    /// Omit accounts whose absolute total is below this amount from the output
+    #[structopt(long)]
+    min_abs_total: Option<f64>,
+
+    /// CSV file mapping clients to groups (client,group columns)
+    #[structopt(long, parse(from_os_str), requires = "group-output")]
+    groups: Option<PathBuf>,
+
+    /// Write balances and lock status aggregated per group to this CSV file
+    #[structopt(long, parse(from_os_str), requires = "groups")]
+    group_output: Option<PathBuf>,
+
+    /// Write a JSON summary of all accounts to this file
+    #[structopt(long, parse(from_os_str))]
+    summary: Option<PathBuf>,
+
+    /// Fail the run if the accounts' balances don't add up to the deposits, withdrawals and
+    /// chargebacks applied (the trial balance, which the summary includes)
+    #[structopt(long)]
+    verify: bool,
+
+    /// Write a double-entry journal of every applied transaction to this file
+    #[structopt(long, parse(from_os_str))]
+    journal: Option<PathBuf>,
+
+    /// Append each account's new balances to this file as transactions are applied
+    #[structopt(long, parse(from_os_str))]
+    change_log: Option<PathBuf>,
+
+    /// Compress the output, journal and change log: none or gz
+    #[cfg(feature = "gzip")]
+    #[structopt(long, default_value = "none")]
+    compress: Compression,
+
+    /// Write the applied transactions as plaintext accounting entries to this file
+    #[cfg(feature = "plaintext-accounting")]
+    #[structopt(long, parse(from_os_str))]
+    plaintext_accounting: Option<PathBuf>,
+
+    /// Plaintext accounting format: beancount or ledger
+    #[cfg(feature = "plaintext-accounting")]
+    #[structopt(long, default_value = "beancount")]
+    plaintext_format: PlaintextFormat,
+
+    /// Commodity used for plaintext accounting amounts
+    #[cfg(feature = "plaintext-accounting")]
+    #[structopt(long, default_value = "USD")]
+    plaintext_commodity: String,
+
+    /// Write the output as an Arrow IPC (Feather v2) file
+    #[cfg(feature = "arrow")]
+    #[structopt(long, parse(from_os_str))]
+    arrow_ipc: Option<PathBuf>,
+
+    /// Write a statement per client into this directory
+    #[structopt(long, parse(from_os_str))]
+    statements: Option<PathBuf>,
+
+    /// Statement format: qif or ofx
+    #[structopt(long, default_value = "qif")]
+    statement_format: StatementFormat,
+
+    /// Currency reported in OFX statements
+    #[structopt(long, default_value = "USD")]
+    statement_currency: String,
+
+    /// Write rejected deposits and withdrawals to this suspense report
+    #[structopt(long, parse(from_os_str))]
+    suspense: Option<PathBuf>,
+
+    /// Suspense bucket: per-client or global
+    #[structopt(long, default_value = "per-client")]
+    suspense_mode: SuspenseMode,
+
+    /// Write every rejected transaction, with its input line and error, to this report
+    #[structopt(long, parse(from_os_str))]
+    errors: Option<PathBuf>,
+
+    /// Format of the --errors report: csv or json
+    #[structopt(long, default_value = "csv")]
+    errors_format: ErrorReportFormat,
+
+    /// Write rejected transactions which may succeed in a later run (e.g. a dispute of a transaction not yet seen) to this file, in the input format
+    #[structopt(long, parse(from_os_str))]
+    retry: Option<PathBuf>,
+
+    /// What to do with a resolve of a transaction not under dispute in this run: reject, accept-if-case-known (in the --open-dispute-cases file) or park-for-review
+    #[structopt(long, default_value = "reject")]
+    resolve_without_dispute: ResolveWithoutDisputePolicy,
+
+    /// What to do with a dispute, resolve or chargeback of a transaction its client does not have: not-found, or locate (look for it in the other clients' accounts and reject it with a ClientMismatchOnDispute error)
+    #[structopt(long, default_value = "not-found")]
+    cross_client_disputes: CrossClientDisputePolicy,
+
+    /// Write the transactions parked for review to this file, in the input format
+    #[structopt(long, parse(from_os_str))]
+    review: Option<PathBuf>,
+
+    /// Write every dispute's case (amount and outcome: open, resolved or charged-back) to this file
+    #[structopt(long, parse(from_os_str))]
+    dispute_cases: Option<PathBuf>,
+
+    /// Import the open cases from a dispute cases file written by an earlier run
+    #[structopt(long, parse(from_os_str))]
+    open_dispute_cases: Option<PathBuf>,
+
+    /// CSV file of arbitration outcomes (case_id,outcome columns; resolved or charged-back) of the imported open cases, applied after the transactions
+    #[structopt(long, parse(from_os_str), requires = "open-dispute-cases")]
+    arbitration_outcomes: Option<PathBuf>,
+
+    /// Dispute cases file format: csv or json
+    #[structopt(long, default_value = "csv")]
+    dispute_cases_format: DisputeCasesFormat,
+
+    /// Write each client's gross deposits, withdrawals and net movement to this CSV file
+    #[structopt(long, parse(from_os_str))]
+    netting: Option<PathBuf>,
+
+    /// Write a settlement instruction for each client whose net movement exceeds the threshold
+    #[structopt(long, parse(from_os_str))]
+    settlement: Option<PathBuf>,
+
+    /// Only settle clients whose absolute net movement exceeds this amount
+    #[structopt(long, default_value = "0")]
+    settlement_threshold: f64,
+
+    /// Settlement line template with {client}, {direction}, {amount} and {value_date} placeholders
+    #[structopt(long, default_value = "{client},{direction},{amount},{value_date}")]
+    settlement_template: SettlementTemplate,
+
+    /// Settlement value date as YYYY-MM-DD (defaults to today)
+    #[structopt(long)]
+    settlement_value_date: Option<Date>,
+
+    /// Write the clients whose processing errored (and were left out of the output) to this CSV file
+    #[structopt(long, parse(from_os_str))]
+    errored_clients: Option<PathBuf>,
+
+    /// If processing is aborted (e.g. by an abort policy or a read error), write the accounts as they were then to this file
+    #[structopt(long, parse(from_os_str))]
+    partial_snapshot: Option<PathBuf>,
+
+    /// Start from the account state (balances, locks and open disputes) in this JSON snapshot, written by an earlier run's --snapshot-out
+    #[structopt(long, parse(from_os_str))]
+    snapshot_in: Option<PathBuf>,
+
+    /// Write the account state at the end of the run to this JSON snapshot, for a later run's --snapshot-in
+    #[structopt(long, parse(from_os_str))]
+    snapshot_out: Option<PathBuf>,
+
+    /// Instead of the accounts, write each client's balance changes against --snapshot-in and whether the run locked it, leaving the state as it was
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Write the output as numbered parts <prefix>-00001.csv, ... with a <prefix>-manifest.json
+    #[structopt(long, parse(from_os_str))]
+    output_parts: Option<PathBuf>,
+
+    /// Most rows in each output part
+    #[structopt(long, requires = "output-parts")]
+    max_output_rows: Option<usize>,
+
+    /// Most bytes in each output part (before compression)
+    #[structopt(long, requires = "output-parts")]
+    max_output_bytes: Option<u64>,
+
+    /// Keep each --threads shard's accounts in output parts of their own, <prefix>-shard-00-00001.csv, ..., instead of merging the shards
+    #[structopt(long, requires = "output-parts")]
+    shard_output_parts: bool,
+
+    /// Tag the run with this batch id (defaults to the next number in the --batch-store)
+    #[structopt(long, validator = validate_batch_id)]
+    batch_id: Option<String>,
+
+    /// Keep the run's output and summary in this directory, under its batch id
+    #[structopt(long, parse(from_os_str))]
+    batch_store: Option<PathBuf>,
+
+    /// Write the output's control totals and checksum to this JSON file
+    #[structopt(long, parse(from_os_str))]
+    control_totals: Option<PathBuf>,
+
+    /// Write a JSON manifest of the run (versions, config, timings, input and output digests)
+    #[structopt(long, parse(from_os_str))]
+    manifest: Option<PathBuf>,
+
+    /// Input file, or - to read the input from stdin
     #[structopt(parse(from_os_str))]
-    input: PathBuf,
+    input: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Print the schema of the input or of the output (for --schema-version)
+    Schema {
+        /// Which schema to print: input or output
+        #[structopt(default_value = "output")]
+        target: SchemaTarget,
+
+        /// Schema format: jsonschema or arrow
+        #[structopt(long, default_value = "jsonschema")]
+        format: SchemaFormat,
+    },
+
+    /// Inject anomalies into a clean input and report how each was handled
+    Simulate {
+        /// The clean transactions file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Probability of repeating a deposit or withdrawal with the same transaction id
+        #[structopt(long, default_value = "0")]
+        duplicate_rate: f64,
+
+        /// Probability of removing a deposit's or withdrawal's amount
+        #[structopt(long, default_value = "0")]
+        missing_amount_rate: f64,
+
+        /// Probability of adding a dispute of a transaction which does not exist
+        #[structopt(long, default_value = "0")]
+        orphan_dispute_rate: f64,
+
+        /// Probability of swapping a row with the next one
+        #[structopt(long, default_value = "0")]
+        out_of_order_rate: f64,
+
+        /// Seed for choosing the rows, to repeat a simulation exactly (recorded in the --manifest)
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+    },
+
+    /// Process an input under each processing options config in a directory and compare the results
+    PolicyMatrix {
+        /// The transactions file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Directory of JSON processing options configs
+        #[structopt(long, parse(from_os_str))]
+        configs: PathBuf,
+    },
+
+    /// Compare this batch's output with a reference implementation's, reporting the discrepancies (exits with status 1 if there are any)
+    Conformance {
+        /// The transactions file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// The reference implementation's output
+        #[structopt(long, parse(from_os_str))]
+        expected: PathBuf,
+
+        /// Largest difference between amounts which is not a discrepancy
+        #[structopt(long, default_value = "0.0001")]
+        tolerance: f64,
+    },
+
+    /// Time processing an input and write the results as JSON, for bench-compare
+    Bench {
+        /// The transactions file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// How many times to process the input
+        #[structopt(long, default_value = "10")]
+        iterations: usize,
+    },
+
+    /// Compare bench results with a baseline's, reporting the change of each benchmark (exits with status 1 if any regressed)
+    BenchCompare {
+        /// The baseline's bench results
+        #[structopt(parse(from_os_str))]
+        baseline: PathBuf,
+
+        /// The current bench results
+        #[structopt(parse(from_os_str))]
+        current: PathBuf,
+
+        /// Largest growth of a benchmark's mean time, as a percentage of the baseline's, which is not a regression
+        #[structopt(long, default_value = "5")]
+        max_regression: f64,
+    },
+
+    /// Print each client's end-of-day balances over the batch, from an input with a `timestamp` column (seconds since the Unix epoch)
+    BalanceHistory {
+        /// The transactions file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+
+    /// Print the balances as of a batch kept in a batch store
+    BalancesAsOf {
+        /// The batch id
+        batch_id: String,
+
+        /// The batch store directory
+        #[structopt(long, parse(from_os_str))]
+        batch_store: PathBuf,
+    },
+
+    /// Attribute each client's balance change between two output files to the journal entries between them
+    Explain {
+        /// The earlier output file
+        #[structopt(long, parse(from_os_str))]
+        before: PathBuf,
+
+        /// The later output file
+        #[structopt(long, parse(from_os_str))]
+        after: PathBuf,
+
+        /// The journal (written by --journal) of the transactions between them
+        #[structopt(long, parse(from_os_str))]
+        journal: PathBuf,
+    },
+
+    /// Forget the settled transactions retained in a state snapshot for longer than the retention window, archiving them as CSV
+    Compact {
+        /// The state snapshot, written by --snapshot-out
+        #[structopt(parse(from_os_str))]
+        snapshot: PathBuf,
+
+        /// Write the compacted snapshot to this file instead of replacing the snapshot
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// How many days a settled transaction is kept for disputes after it was first written to a snapshot
+        #[structopt(long, default_value = "180")]
+        retention_days: u64,
+
+        /// Write the forgotten transactions to this CSV file
+        #[structopt(long, parse(from_os_str))]
+        archive: PathBuf,
+    },
+}
+
+fn validate_batch_id(batch_id: String) -> Result<(), String> {
+    if is_valid_batch_id(&batch_id) {
+        Ok(())
+    } else {
+        Err(format!("invalid batch id: {}", batch_id))
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opt = Opt::from_args();
-    let transactions_file = opt.input;
-    let debug = opt.debug;
 
-    let mut debug_logger: Box<dyn std::io::Write> = if debug {
-        Box::new(std::io::stderr())
-    } else {
-        Box::new(std::io::sink())
+    let processing_options = ProcessingOptions {
+        held_balance_underflow: opt.held_balance_underflow,
+        implicit_accounts: opt.implicit_accounts,
+        submission_order: opt.submission_order,
+        column_mapping: opt.column_mapping,
+        type_aliases: opt.type_aliases,
+        unknown_type: opt.unknown_type,
+        ordering: opt.ordering,
+        null_amounts: opt.null_amounts,
+        amount_format: opt.amount_format,
+        timestamp_format: opt.timestamp_format,
+        timestamp_timezone: opt.timestamp_timezone,
+        ambiguous_timestamps: opt.ambiguous_timestamps,
+        pending_dispute_window: opt.pending_dispute_window,
+        expected_clients: opt.expected_clients,
+        expected_transactions_per_client: opt.expected_transactions_per_client,
+        max_transactions_per_client: opt.max_transactions_per_client,
+        max_clients: opt.max_clients,
+        backfill: opt.backfill,
+        dispute_retention_runs: opt.dispute_retention_runs,
+        limit_exceeded: opt.limit_exceeded,
+        max_chargebacks: opt.max_chargebacks,
+        sweep_client: opt.sweep_client,
+        locked_accounts: opt.locked_accounts,
+        resolve_without_dispute: opt.resolve_without_dispute,
+        cross_client_disputes: opt.cross_client_disputes,
+        balance_cap: opt.balance_cap,
+        client_balance_caps: match &opt.balance_caps {
+            Some(balance_caps_file) => read_balance_caps_file(balance_caps_file)?,
+            None => Default::default(),
+        },
+        balance_cap_exceeded: opt.balance_cap_exceeded,
+        client_tiers: match (&opt.tiers, &opt.client_tiers) {
+            (Some(tiers_file), Some(client_tiers_file)) => {
+                ClientTiers::from_paths(tiers_file, client_tiers_file)?
+            }
+            _ => Default::default(),
+        },
+        ..Default::default()
+    };
+
+    let mut debug_logger: Box<dyn std::io::Write> = match &opt.debug_log {
+        Some(debug_log) => open_log(
+            debug_log,
+            RotationOptions {
+                max_bytes: opt.debug_log_max_bytes,
+                max_age: opt.debug_log_max_age.map(std::time::Duration::from_secs),
+            },
+        )?,
+        None if opt.debug => Box::new(std::io::stderr()),
+        None => Box::new(std::io::sink()),
+    };
+    if let Some(limit) = opt.log_dedup {
+        debug_logger = deduplicate(debug_logger, limit);
+    }
+
+    match opt.command {
+        Some(Command::Schema { target, format }) => {
+            write_schema(target, opt.schema_version, format, &mut std::io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Simulate {
+            input,
+            duplicate_rate,
+            missing_amount_rate,
+            orphan_dispute_rate,
+            out_of_order_rate,
+            seed,
+        }) => {
+            let simulation_options = SimulationOptions {
+                duplicate_rate,
+                missing_amount_rate,
+                orphan_dispute_rate,
+                out_of_order_rate,
+                seed,
+            };
+            simulation::run(
+                &input,
+                &simulation_options,
+                &processing_options,
+                opt.manifest.as_deref(),
+                &mut std::io::stdout(),
+                &mut debug_logger,
+            )?;
+            return Ok(());
+        }
+        Some(Command::PolicyMatrix { input, configs }) => {
+            let configs = policy_matrix::read_configs(&configs)?;
+            let results = policy_matrix::run_policy_matrix(&input, &configs, &mut debug_logger)?;
+            policy_matrix::write_report(&results, &mut std::io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Conformance {
+            input,
+            expected,
+            tolerance,
+        }) => {
+            let discrepancies = rs_bpt::check_conformance(
+                input,
+                &expected,
+                tolerance,
+                &processing_options,
+                &mut debug_logger,
+            )?;
+            conformance::write_report(&discrepancies, &mut std::io::stdout())?;
+            if !discrepancies.is_empty() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Bench { input, iterations }) => {
+            let results = bench::run_bench(&input, iterations, &processing_options)?;
+            bench::write_results(&results, &mut std::io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::BenchCompare {
+            baseline,
+            current,
+            max_regression,
+        }) => {
+            let comparisons = bench::compare(
+                &bench::read_results(&baseline)?,
+                &bench::read_results(&current)?,
+                max_regression,
+            );
+            bench::write_report(&comparisons, &mut std::io::stdout())?;
+            if comparisons.iter().any(|comparison| comparison.regressed) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::BalanceHistory { input }) => {
+            let balances = balance_history::end_of_day_balances(
+                &input,
+                &processing_options,
+                &mut debug_logger,
+            )?;
+            balance_history::write_balance_history(&balances, &mut std::io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::BalancesAsOf {
+            batch_id,
+            batch_store,
+        }) => {
+            BatchStore::new(batch_store).write_balances(&batch_id, &mut std::io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Explain {
+            before,
+            after,
+            journal,
+        }) => {
+            snapshot_diff::explain(&before, &after, &journal, &mut std::io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Compact {
+            snapshot,
+            output,
+            retention_days,
+            archive,
+        }) => {
+            let output = output.unwrap_or_else(|| snapshot.clone());
+            let forgotten =
+                compaction::compact_snapshot(&snapshot, &output, &archive, retention_days)?;
+            writeln!(
+                debug_logger,
+                "forgot {} settled transactions retained for more than {} days",
+                forgotten, retention_days
+            )?;
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let transactions_file = match opt.input {
+        Some(input) => input,
+        None => structopt::clap::Error::with_description(
+            "The input file is required",
+            structopt::clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    };
+    let cli_options = CliOptions {
+        processing_options,
+        presize: opt.presize,
+        threads: opt.threads,
+        output_options: OutputOptions {
+            skip_untouched: opt.skip_untouched,
+            min_abs_total: opt.min_abs_total,
+            schema_version: opt.schema_version,
+            bool_format: opt.bool_format,
+            client_notes_file: opt.client_notes,
+            lock_reasons: opt.lock_reasons,
+            chargeback_totals: opt.chargeback_totals,
+            sort_by_client: opt.sort_by_client,
+            #[cfg(feature = "output-template")]
+            template_file: opt.output_template,
+        },
+        output_parts: opt.output_parts.map(|prefix| OutputPartsOptions {
+            prefix,
+            max_rows: opt.max_output_rows,
+            max_bytes: opt.max_output_bytes,
+            shards: opt.shard_output_parts.then_some(opt.threads),
+        }),
+        groups: opt
+            .groups
+            .zip(opt.group_output)
+            .map(|(mapping_file, output_file)| GroupOptions {
+                mapping_file,
+                output_file,
+            }),
+        summary_file: opt.summary,
+        verify: opt.verify,
+        journal_file: opt.journal,
+        change_log_file: opt.change_log,
+        #[cfg(feature = "gzip")]
+        compression: opt.compress,
+        #[cfg(not(feature = "gzip"))]
+        compression: Compression::None,
+        #[cfg(feature = "plaintext-accounting")]
+        plaintext_accounting: opt
+            .plaintext_accounting
+            .map(|file| PlaintextAccountingOptions {
+                file,
+                format: opt.plaintext_format,
+                commodity: opt.plaintext_commodity,
+            }),
+        #[cfg(feature = "arrow")]
+        arrow_file: opt.arrow_ipc,
+        statements: opt.statements.map(|directory| StatementOptions {
+            directory,
+            format: opt.statement_format,
+            currency: opt.statement_currency,
+        }),
+        suspense: opt.suspense.map(|file| SuspenseOptions {
+            file,
+            mode: opt.suspense_mode,
+        }),
+        errors: opt.errors.map(|file| ErrorReportOptions {
+            file,
+            format: opt.errors_format,
+        }),
+        retry_file: opt.retry,
+        review_file: opt.review,
+        dead_letter_file: opt.dead_letter,
+        netting_file: opt.netting,
+        settlement: opt.settlement.map(|file| SettlementOptions {
+            file,
+            threshold: opt.settlement_threshold,
+            template: opt.settlement_template,
+            value_date: opt.settlement_value_date,
+        }),
+        dispute_cases: (opt.dispute_cases.is_some() || opt.open_dispute_cases.is_some()).then_some(
+            DisputeCasesOptions {
+                file: opt.dispute_cases,
+                import_file: opt.open_dispute_cases,
+                arbitration_file: opt.arbitration_outcomes,
+                format: opt.dispute_cases_format,
+            },
+        ),
+        errored_clients_file: opt.errored_clients,
+        partial_snapshot_file: opt.partial_snapshot,
+        snapshot_in_file: opt.snapshot_in,
+        snapshot_out_file: opt.snapshot_out,
+        dry_run: opt.dry_run,
+        batch_id: opt.batch_id,
+        batch_store: opt.batch_store.map(BatchStore::new),
+        control_totals_file: opt.control_totals,
+        manifest_file: opt.manifest,
     };
 
     let mut stdout_stream = Box::new(std::io::stdout());
 
-    cli(transactions_file, &mut stdout_stream, &mut debug_logger)
+    let result = cli(
+        transactions_file,
+        &cli_options,
+        &mut stdout_stream,
+        &mut debug_logger,
+    );
+    if let Some(aborted) = result
+        .as_ref()
+        .err()
+        .and_then(|error| error.downcast_ref::<ProcessingAborted>())
+    {
+        eprintln!("Processing aborted. Partial progress:");
+        aborted.progress.write_json(&mut std::io::stderr())?;
+    }
+    result
 }
@@ -0,0 +1,114 @@
+use serde_derive::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::control_totals::Crc32Writer;
+use crate::summary::Summary;
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_secs()
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct FileDigest {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub crc32: String,
+}
+
+impl FileDigest {
+    pub fn of_file(path: &Path) -> anyhow::Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        let mut sink = std::io::sink();
+        let mut crc32_writer = Crc32Writer::new(&mut sink);
+        std::io::copy(&mut file, &mut crc32_writer)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            bytes: crc32_writer.bytes_written(),
+            crc32: format!("{:08x}", crc32_writer.crc32()),
+        })
+    }
+
+    /// Digests of a file, or of every file directly inside a directory (sorted by path)
+    pub fn of_path(path: &Path) -> anyhow::Result<Vec<Self>> {
+        if !path.is_dir() {
+            return Ok(vec![Self::of_file(path)?]);
+        }
+        let mut files = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        files.sort();
+        files
+            .iter()
+            .filter(|file| file.is_file())
+            .map(|file| Self::of_file(file))
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct StreamDigest {
+    pub bytes: u64,
+    pub crc32: String,
+}
+
+/// Everything needed to reproduce and audit a batch run.
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    pub crate_version: String,
+    pub config: String,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub inputs: Vec<FileDigest>,
+    pub outputs: Vec<FileDigest>,
+    pub stdout: StreamDigest,
+    /// The summary of the accounts, for a batch run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+    /// The seed of a simulation, which repeats it exactly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl RunManifest {
+    pub fn write_json(&self, output_stream: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut *output_stream, self)?;
+        writeln!(output_stream)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_digests_a_file_and_the_files_in_a_directory() {
+        let directory = std::env::temp_dir().join("rs_bpt-manifest-it_digests_a_file");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("b.txt"), "").unwrap();
+        std::fs::write(directory.join("a.txt"), "123456789").unwrap();
+
+        let digests = FileDigest::of_path(&directory).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            digests,
+            vec![
+                FileDigest {
+                    path: directory.join("a.txt"),
+                    bytes: 9,
+                    crc32: "cbf43926".to_string(),
+                },
+                FileDigest {
+                    path: directory.join("b.txt"),
+                    bytes: 0,
+                    crc32: "00000000".to_string(),
+                },
+            ]
+        );
+    }
+}
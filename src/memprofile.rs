@@ -0,0 +1,175 @@
+use serde_derive::Serialize;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use crate::hashing::Accounts;
+use crate::ClientAccount;
+
+/// The phases of a run which allocations are counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Reading options and side files, before the input is processed
+    Setup,
+    /// Reading and applying the input
+    Processing,
+    /// Writing the output and the reports
+    Reporting,
+}
+
+const SUBSYSTEMS: [Subsystem; 3] = [
+    Subsystem::Setup,
+    Subsystem::Processing,
+    Subsystem::Reporting,
+];
+
+impl Subsystem {
+    fn name(&self) -> &'static str {
+        match self {
+            Subsystem::Setup => "setup",
+            Subsystem::Processing => "processing",
+            Subsystem::Reporting => "reporting",
+        }
+    }
+}
+
+static CURRENT_SUBSYSTEM: AtomicU8 = AtomicU8::new(0);
+static ALLOCATIONS: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+static ALLOCATED_BYTES: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+/// Counts every allocation (and reallocation) against the current subsystem, then allocates with
+/// the system allocator.
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn record(size: usize) {
+        let subsystem = CURRENT_SUBSYSTEM.load(Ordering::Relaxed) as usize;
+        ALLOCATIONS[subsystem].fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES[subsystem].fetch_add(size as u64, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::record(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::record(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::record(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Counts the allocations from now on against the subsystem
+pub fn enter(subsystem: Subsystem) {
+    let index = SUBSYSTEMS
+        .iter()
+        .position(|known| *known == subsystem)
+        .unwrap_or(0);
+    CURRENT_SUBSYSTEM.store(index as u8, Ordering::Relaxed);
+}
+
+/// The process's peak resident set size, where the platform reports it (Linux)
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct AllocationCounts {
+    pub allocations: usize,
+    pub bytes: u64,
+}
+
+/// Memory use of the run so far, for sizing machines for the biggest inputs.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryReport {
+    pub peak_rss_bytes: Option<u64>,
+    /// The accounts in the accounts map, and how many it has room for
+    pub accounts: usize,
+    pub accounts_capacity: usize,
+    /// The deposits and withdrawals retained (for disputes) over all accounts
+    pub retained_transactions: usize,
+    /// By subsystem
+    pub allocations: BTreeMap<&'static str, AllocationCounts>,
+}
+
+impl MemoryReport {
+    pub fn new(accounts: &Accounts) -> Self {
+        Self {
+            peak_rss_bytes: peak_rss_bytes(),
+            accounts: accounts.len(),
+            accounts_capacity: accounts.capacity(),
+            retained_transactions: accounts
+                .values()
+                .map(ClientAccount::retained_transactions)
+                .sum(),
+            allocations: SUBSYSTEMS
+                .iter()
+                .enumerate()
+                .map(|(index, subsystem)| {
+                    (
+                        subsystem.name(),
+                        AllocationCounts {
+                            allocations: ALLOCATIONS[index].load(Ordering::Relaxed),
+                            bytes: ALLOCATED_BYTES[index].load(Ordering::Relaxed),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_allocations_and_reports_the_accounts() {
+        let mut accounts = Accounts::default();
+        accounts.insert(1, ClientAccount::new(1));
+        let vector: Vec<u64> = Vec::with_capacity(16);
+
+        let report = MemoryReport::new(&accounts);
+        drop(vector);
+
+        assert_eq!(report.accounts, 1);
+        assert!(report.accounts_capacity >= 1);
+        assert_eq!(report.retained_transactions, 0);
+        let total: usize = report
+            .allocations
+            .values()
+            .map(|counts| counts.allocations)
+            .sum();
+        assert!(total > 0);
+        if cfg!(target_os = "linux") {
+            assert!(report.peak_rss_bytes.unwrap() > 0);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::serializable_form::Output;
+use crate::ClientAccount;
+
+/// A hash function for building Merkle trees.
+pub trait MerkleHasher {
+    fn hash(&self, data: &[u8]) -> Vec<u8>;
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 (FIPS 180-4), written out as the `sha2` crate isn't available to the build. Library
+/// users can pass another implementation as a `MerkleHasher`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sha256;
+
+impl MerkleHasher for Sha256 {
+    fn hash(&self, data: &[u8]) -> Vec<u8> {
+        let mut state: [u32; 8] = [
+            0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+            0x5be0cd19,
+        ];
+
+        let mut message = data.to_vec();
+        message.push(0x80);
+        while message.len() % 64 != 56 {
+            message.push(0);
+        }
+        message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+        for block in message.chunks(64) {
+            let mut w = [0u32; 64];
+            for (i, word) in block.chunks(4).enumerate() {
+                w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+            }
+            for i in 16..64 {
+                let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+                let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+                w[i] = w[i - 16]
+                    .wrapping_add(s0)
+                    .wrapping_add(w[i - 7])
+                    .wrapping_add(s1);
+            }
+
+            let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+            for i in 0..64 {
+                let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+                let ch = (e & f) ^ (!e & g);
+                let temp1 = h
+                    .wrapping_add(s1)
+                    .wrapping_add(ch)
+                    .wrapping_add(SHA256_K[i])
+                    .wrapping_add(w[i]);
+                let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+                let maj = (a & b) ^ (a & c) ^ (b & c);
+                let temp2 = s0.wrapping_add(maj);
+
+                h = g;
+                g = f;
+                f = e;
+                e = d.wrapping_add(temp1);
+                d = c;
+                c = b;
+                b = a;
+                a = temp1.wrapping_add(temp2);
+            }
+
+            for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+                *word = word.wrapping_add(value);
+            }
+        }
+
+        state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+}
+
+/// The Merkle tree hash of the leaves as defined for Certificate Transparency (RFC 6962): leaves
+/// and inner nodes are hashed with distinct prefixes, and the tree is split at the largest power
+/// of two below the number of leaves. The root of no leaves is the hash of nothing.
+pub fn merkle_root(leaves: &[Vec<u8>], hasher: &dyn MerkleHasher) -> Vec<u8> {
+    match leaves.len() {
+        0 => hasher.hash(&[]),
+        1 => hasher.hash(&[&[0u8][..], &leaves[0]].concat()),
+        len => {
+            let split = 1 << (usize::BITS - (len - 1).leading_zeros() - 1);
+            let left = merkle_root(&leaves[..split], hasher);
+            let right = merkle_root(&leaves[split..], hasher);
+            hasher.hash(&[&[1u8][..], &left, &right].concat())
+        }
+    }
+}
+
+/// The Merkle root of the accounts' final states, as hex. Each leaf is an account's output row
+/// (`client,available,held,total,locked`, with `true`/`false`), in client id order, so runs
+/// which produce the same balances produce the same root whatever the order they were held in.
+pub fn accounts_merkle_root<'a>(
+    accounts: impl IntoIterator<Item = &'a ClientAccount>,
+    hasher: &dyn MerkleHasher,
+) -> anyhow::Result<String> {
+    let mut rows = accounts
+        .into_iter()
+        .map(Output::from_client_account)
+        .collect::<anyhow::Result<Vec<Output>>>()?;
+    rows.sort_by_key(|row| row.client);
+
+    let leaves: Vec<Vec<u8>> = rows
+        .iter()
+        .map(|row| {
+            format!(
+                "{},{},{},{},{}",
+                row.client, row.available, row.held, row.total, row.locked
+            )
+            .into_bytes()
+        })
+        .collect();
+    Ok(merkle_root(&leaves, hasher)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            hex(&Sha256.hash(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&Sha256.hash(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&Sha256.hash(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex(&Sha256.hash(
+                b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu"
+            )),
+            "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1"
+        );
+        assert_eq!(
+            hex(&Sha256.hash(&[b'a'; 1_000_000])),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn test_sha256_pads_across_block_boundaries() {
+        // 55 bytes is the longest message whose padding and length fit in its last block
+        for (length, expected) in [
+            (
+                55,
+                "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318",
+            ),
+            (
+                56,
+                "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a",
+            ),
+            (
+                63,
+                "7d3e74a05d7db15bce4ad9ec0658ea98e3f06eeecf16b4c6fff2da457ddc2f34",
+            ),
+            (
+                64,
+                "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb",
+            ),
+        ] {
+            assert_eq!(hex(&Sha256.hash(&vec![b'a'; length])), expected);
+        }
+    }
+
+    #[test]
+    fn test_merkle_root_splits_at_the_largest_power_of_two() {
+        let leaves: Vec<Vec<u8>> = (0u8..3).map(|leaf| vec![leaf]).collect();
+        let leaf = |data: &[u8]| Sha256.hash(&[&[0u8][..], data].concat());
+        let node = |left: &[u8], right: &[u8]| Sha256.hash(&[&[1u8][..], left, right].concat());
+
+        assert_eq!(
+            merkle_root(&leaves, &Sha256),
+            node(&node(&leaf(&[0]), &leaf(&[1])), &leaf(&[2]))
+        );
+    }
+
+    #[test]
+    fn test_accounts_merkle_root_does_not_depend_on_account_order() {
+        let mut first = ClientAccount::new(1);
+        first.balance.available = 1.5;
+        let second = ClientAccount::new(2);
+
+        let root = accounts_merkle_root([&first, &second], &Sha256).unwrap();
+        assert_eq!(root.len(), 64);
+        assert_eq!(
+            accounts_merkle_root([&second, &first], &Sha256).unwrap(),
+            root
+        );
+
+        first.balance.available = 1.4;
+        assert_ne!(
+            accounts_merkle_root([&first, &second], &Sha256).unwrap(),
+            root
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+
+use crate::journal::{JournalEntry, JournalSink, LedgerAccount};
+use crate::serializable_form::round_f64_4dp_string;
+use crate::{ClientId, TransactionType};
+
+/// A client's movements over the batch.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct ClientNetting {
+    pub gross_deposits: f64,
+    pub gross_withdrawals: f64,
+    pub chargebacks: f64,
+    // The change in the client's total balance
+    pub net_movement: f64,
+    pub held_delta: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct SerializableClientNetting {
+    client: ClientId,
+    gross_deposits: String,
+    gross_withdrawals: String,
+    chargebacks: String,
+    net_movement: String,
+    held_delta: String,
+}
+
+fn is_client_account(account: LedgerAccount) -> bool {
+    matches!(
+        account,
+        LedgerAccount::ClientAvailable(_) | LedgerAccount::ClientHeld(_)
+    )
+}
+
+/// Nets the applied transactions per client, for funding settlement accounts.
+#[derive(Debug, Default)]
+pub struct Netting {
+    clients: BTreeMap<ClientId, ClientNetting>,
+}
+
+impl Netting {
+    pub fn client(&self, client_id: ClientId) -> Option<&ClientNetting> {
+        self.clients.get(&client_id)
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = (&ClientId, &ClientNetting)> {
+        self.clients.iter()
+    }
+
+    pub fn write_report(&self, output_stream: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        let mut csv_writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(output_stream);
+        csv_writer.write_record([
+            "client",
+            "gross_deposits",
+            "gross_withdrawals",
+            "chargebacks",
+            "net_movement",
+            "held_delta",
+        ])?;
+        for (client_id, netting) in &self.clients {
+            csv_writer.serialize(SerializableClientNetting {
+                client: *client_id,
+                gross_deposits: round_f64_4dp_string(netting.gross_deposits)?,
+                gross_withdrawals: round_f64_4dp_string(netting.gross_withdrawals)?,
+                chargebacks: round_f64_4dp_string(netting.chargebacks)?,
+                net_movement: round_f64_4dp_string(netting.net_movement)?,
+                held_delta: round_f64_4dp_string(netting.held_delta)?,
+            })?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+impl JournalSink for Netting {
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        let netting = self.clients.entry(entry.client_id).or_default();
+        match entry.transaction_type {
+            TransactionType::Deposit => netting.gross_deposits += entry.amount,
+            TransactionType::Withdrawal => netting.gross_withdrawals += entry.amount,
+            TransactionType::Chargeback | TransactionType::FinalChargeback => {
+                netting.chargebacks += entry.amount
+            }
+            TransactionType::Representment => netting.chargebacks -= entry.amount,
+            _ => {}
+        }
+
+        match (
+            is_client_account(entry.debit),
+            is_client_account(entry.credit),
+        ) {
+            (false, true) => netting.net_movement += entry.amount,
+            (true, false) => netting.net_movement -= entry.amount,
+            _ => {}
+        }
+        if let LedgerAccount::ClientHeld(_) = entry.credit {
+            netting.held_delta += entry.amount;
+        }
+        if let LedgerAccount::ClientHeld(_) = entry.debit {
+            netting.held_delta -= entry.amount;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_nets_movements_per_client() {
+        let mut netting = Netting::default();
+        for entry in [
+            JournalEntry::new(1, 1, TransactionType::Deposit, 100.0),
+            JournalEntry::new(1, 2, TransactionType::Withdrawal, -30.0),
+            JournalEntry::new(1, 3, TransactionType::Deposit, 50.0),
+            JournalEntry::new(1, 3, TransactionType::Dispute, 50.0),
+            JournalEntry::new(1, 3, TransactionType::Chargeback, 50.0),
+            JournalEntry::new(1, 1, TransactionType::Dispute, 100.0),
+            JournalEntry::new(2, 4, TransactionType::Deposit, 5.0),
+        ] {
+            netting.record(&entry).unwrap();
+        }
+
+        assert_eq!(
+            netting.client(1),
+            Some(&ClientNetting {
+                gross_deposits: 150.0,
+                gross_withdrawals: 30.0,
+                chargebacks: 50.0,
+                net_movement: 70.0,
+                held_delta: 100.0,
+            })
+        );
+
+        let mut report = Vec::<u8>::new();
+        netting.write_report(&mut report).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&report).unwrap(),
+            "client,gross_deposits,gross_withdrawals,chargebacks,net_movement,held_delta\n\
+             1,150.0000,30.0000,50.0000,70.0000,100.0000\n\
+             2,5.0000,0.0000,0.0000,5.0000,0.0000\n"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::schema::{SchemaVersion, OPTIONAL_COLUMN_GROUPS};
+use crate::ClientAccount;
+
+/// How booleans (the locked column) are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoolFormat {
+    /// true/false
+    #[default]
+    Lowercase,
+    /// TRUE/FALSE
+    Uppercase,
+    /// 1/0
+    Numeric,
+}
+
+impl BoolFormat {
+    pub fn format(&self, value: bool) -> &'static str {
+        match (self, value) {
+            (BoolFormat::Lowercase, true) => "true",
+            (BoolFormat::Lowercase, false) => "false",
+            (BoolFormat::Uppercase, true) => "TRUE",
+            (BoolFormat::Uppercase, false) => "FALSE",
+            (BoolFormat::Numeric, true) => "1",
+            (BoolFormat::Numeric, false) => "0",
+        }
+    }
+
+    /// Reads a boolean written in any of the formats
+    pub fn parse_any(value: &str) -> Option<bool> {
+        match value.trim() {
+            "true" | "TRUE" | "1" => Some(true),
+            "false" | "FALSE" | "0" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for BoolFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "true/false" => Ok(BoolFormat::Lowercase),
+            "TRUE/FALSE" => Ok(BoolFormat::Uppercase),
+            "0/1" | "1/0" => Ok(BoolFormat::Numeric),
+            _ => Err(format!("unknown bool format: {}", s)),
+        }
+    }
+}
+
+/// Controls which accounts end up in the output, and with which columns.
+#[derive(Debug, Default, Clone)]
+pub struct OutputOptions {
+    /// Omit accounts which never had a transaction applied to them
+    pub skip_untouched: bool,
+
+    /// Omit accounts whose absolute total is below this amount
+    pub min_abs_total: Option<f64>,
+
+    /// The output schema version whose columns are written
+    pub schema_version: SchemaVersion,
+
+    /// How the locked column is written
+    pub bool_format: BoolFormat,
+
+    /// Append flags and notes columns from this client notes file (client,flag,note columns)
+    pub client_notes_file: Option<std::path::PathBuf>,
+
+    /// Append lock_tx and lock_case columns saying why each locked account was locked
+    pub lock_reasons: bool,
+
+    /// Append chargebacks, charged_back and closed columns with each account's chargeback exposure
+    pub chargeback_totals: bool,
+
+    /// Write the accounts in client id order instead of the accounts map's
+    pub sort_by_client: bool,
+
+    /// Render the output through this Tera template instead of writing CSV
+    #[cfg(feature = "output-template")]
+    pub template_file: Option<std::path::PathBuf>,
+}
+
+impl OutputOptions {
+    /// The columns the options (and client notes, if there are any) add after those of schema
+    /// version 1, in the order they are written
+    pub fn optional_columns(&self, with_client_notes: bool) -> Vec<&'static str> {
+        let [notes, lock_reasons, chargeback_totals] = OPTIONAL_COLUMN_GROUPS;
+        [
+            (with_client_notes, notes),
+            (self.lock_reasons, lock_reasons),
+            (self.chargeback_totals, chargeback_totals),
+        ]
+        .into_iter()
+        .filter(|(wanted, _)| *wanted)
+        .flat_map(|(_, columns)| columns.iter().copied())
+        .collect()
+    }
+
+    /// Checks that the schema version has the optional columns the options ask for
+    pub fn check_schema_version(&self, with_client_notes: bool) -> anyhow::Result<()> {
+        let optional_columns = self.optional_columns(with_client_notes);
+        if !optional_columns.is_empty() && !self.schema_version.has_optional_columns() {
+            anyhow::bail!(
+                "output schema version {} has no {} columns (they are in version 2)",
+                self.schema_version,
+                optional_columns.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    pub fn includes(&self, client_account: &ClientAccount) -> bool {
+        // an errored account's balances can't be trusted, it is reported separately
+        if client_account.errored().is_some() {
+            return false;
+        }
+
+        if self.skip_untouched && client_account.is_untouched() {
+            return false;
+        }
+
+        if let Some(min_abs_total) = self.min_abs_total {
+            if client_account.balance.total().abs() < min_abs_total {
+                return false;
+            }
+        }
+
+        true
+    }
+}
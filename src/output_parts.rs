@@ -0,0 +1,372 @@
+use serde_derive::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::compression::{CompressedWriter, Compression};
+use crate::control_totals::crc32_update;
+use crate::ClientId;
+
+#[derive(Debug, Clone)]
+pub struct OutputPartsOptions {
+    /// Parts are written to `<prefix>-00001.csv`, `<prefix>-00002.csv`, ... (with the
+    /// compression's extension, e.g. `<prefix>-00001.csv.gz`) and listed in `<prefix>-manifest.json`
+    pub prefix: PathBuf,
+    pub max_rows: Option<usize>,
+    /// Largest part size, before compression, including the header
+    pub max_bytes: Option<u64>,
+    /// Keep the rows of each processing shard (`client % shards`) in parts of their own,
+    /// `<prefix>-shard-00-00001.csv`, ..., rather than merging the shards
+    pub shards: Option<usize>,
+}
+
+impl OutputPartsOptions {
+    pub fn part_path(&self, number: usize, compression: Compression) -> PathBuf {
+        PathBuf::from(format!(
+            "{}-{:05}.csv{}",
+            self.prefix.display(),
+            number,
+            compression.extension()
+        ))
+    }
+
+    pub fn shard_part_path(
+        &self,
+        shard: usize,
+        number: usize,
+        compression: Compression,
+    ) -> PathBuf {
+        PathBuf::from(format!(
+            "{}-shard-{:02}-{:05}.csv{}",
+            self.prefix.display(),
+            shard,
+            number,
+            compression.extension()
+        ))
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        PathBuf::from(format!("{}-manifest.json", self.prefix.display()))
+    }
+}
+
+/// A part as listed in the parts manifest. `bytes` and `crc32` are of the part before compression.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct OutputPart {
+    pub path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard: Option<usize>,
+    pub rows: usize,
+    pub bytes: u64,
+    pub crc32: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OutputPartsManifest<'a> {
+    rows: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shards: Option<usize>,
+    parts: &'a [OutputPart],
+}
+
+struct OpenPart {
+    writer: CompressedWriter<File>,
+    path: PathBuf,
+    shard: Option<usize>,
+    rows: usize,
+    bytes: u64,
+    crc: u32,
+}
+
+/// Splits the output written to it into numbered parts by line, repeating the first line (the
+/// header) at the start of each part. A part always has at least one row, even if that row alone
+/// is over `max_bytes`. With `shards`, each row goes to a part of its shard, by the client id
+/// which starts it, and each shard's parts are numbered from 1. Output with no rows (or a shard
+/// with none) still gets a part, with just the header.
+pub struct OutputParts {
+    options: OutputPartsOptions,
+    compression: Compression,
+    header: Option<Vec<u8>>,
+    line: Vec<u8>,
+    /// The part being written for each shard (only the first, without shards)
+    current: Vec<Option<OpenPart>>,
+    /// How many parts each shard has had
+    part_counts: Vec<usize>,
+    parts: Vec<OutputPart>,
+}
+
+impl OutputParts {
+    pub fn new(options: OutputPartsOptions, compression: Compression) -> Self {
+        let shards = options.shards.unwrap_or(1).max(1);
+        Self {
+            options,
+            compression,
+            header: None,
+            line: Vec::new(),
+            current: (0..shards).map(|_| None).collect(),
+            part_counts: vec![0; shards],
+            parts: Vec::new(),
+        }
+    }
+
+    // The shard of a row, by the client id in its first column
+    fn shard_of(&self, line: &[u8]) -> std::io::Result<usize> {
+        let shards = self.current.len();
+        if shards == 1 {
+            return Ok(0);
+        }
+        let client = line.split(|byte| *byte == b',').next().unwrap_or_default();
+        std::str::from_utf8(client)
+            .ok()
+            .and_then(|client| client.trim().parse::<ClientId>().ok())
+            .map(|client_id| client_id as usize % shards)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "output row {:?} doesn't start with a client id to assign it a shard",
+                        String::from_utf8_lossy(line)
+                    ),
+                )
+            })
+    }
+
+    fn is_full(&self, part: &OpenPart, line_length: usize) -> bool {
+        self.options
+            .max_rows
+            .is_some_and(|max_rows| part.rows >= max_rows)
+            || self
+                .options
+                .max_bytes
+                .is_some_and(|max_bytes| part.bytes + line_length as u64 > max_bytes)
+    }
+
+    fn write_to_part(part: &mut OpenPart, bytes: &[u8]) -> std::io::Result<()> {
+        part.writer.write_all(bytes)?;
+        part.bytes += bytes.len() as u64;
+        part.crc = crc32_update(part.crc, bytes);
+        Ok(())
+    }
+
+    fn open_part(&mut self, shard: usize) -> std::io::Result<()> {
+        self.part_counts[shard] += 1;
+        let number = self.part_counts[shard];
+        let path = match self.options.shards {
+            Some(_) => self
+                .options
+                .shard_part_path(shard, number, self.compression),
+            None => self.options.part_path(number, self.compression),
+        };
+        let mut part = OpenPart {
+            writer: self.compression.writer(File::create(&path)?),
+            path,
+            shard: self.options.shards.map(|_| shard),
+            rows: 0,
+            bytes: 0,
+            crc: 0xFFFF_FFFF,
+        };
+        if let Some(header) = &self.header {
+            Self::write_to_part(&mut part, header)?;
+        }
+        self.current[shard] = Some(part);
+        Ok(())
+    }
+
+    fn close_part(&mut self, shard: usize) -> std::io::Result<()> {
+        if let Some(part) = self.current[shard].take() {
+            part.writer.finish()?;
+            self.parts.push(OutputPart {
+                path: part.path,
+                shard: part.shard,
+                rows: part.rows,
+                bytes: part.bytes,
+                crc32: format!("{:08x}", !part.crc),
+            });
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self) -> std::io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        if self.header.is_none() {
+            self.header = Some(line);
+            return Ok(());
+        }
+
+        let shard = self.shard_of(&line)?;
+        let is_full = match &self.current[shard] {
+            Some(part) => part.rows > 0 && self.is_full(part, line.len()),
+            None => true,
+        };
+        if is_full {
+            self.close_part(shard)?;
+            self.open_part(shard)?;
+        }
+        let part = self.current[shard].as_mut().expect("a part is open");
+        Self::write_to_part(part, &line)?;
+        part.rows += 1;
+        Ok(())
+    }
+
+    /// Writes any unterminated last line, closes the last part and writes the parts manifest
+    pub fn finish(mut self) -> anyhow::Result<Vec<OutputPart>> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        if self.header.is_some() {
+            for shard in 0..self.current.len() {
+                if self.part_counts[shard] == 0 {
+                    self.open_part(shard)?;
+                }
+            }
+        }
+        for shard in 0..self.current.len() {
+            self.close_part(shard)?;
+        }
+        // a shard's parts may have been closed between those of other shards
+        self.parts.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut manifest_writer = File::create(self.options.manifest_path())?;
+        serde_json::to_writer_pretty(
+            &mut manifest_writer,
+            &OutputPartsManifest {
+                rows: self.parts.iter().map(|part| part.rows).sum(),
+                shards: self.options.shards,
+                parts: &self.parts,
+            },
+        )?;
+        writeln!(manifest_writer)?;
+        Ok(self.parts)
+    }
+}
+
+impl Write for OutputParts {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for line in buf.split_inclusive(|byte| *byte == b'\n') {
+            self.line.extend_from_slice(line);
+            if line.ends_with(b"\n") {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for part in self.current.iter_mut().flatten() {
+            part.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_parts_repeat_the_header_and_roll_over() {
+        let prefix = std::env::temp_dir().join("rs_bpt-test_output_parts");
+        let options = OutputPartsOptions {
+            prefix,
+            max_rows: Some(3),
+            max_bytes: Some(40),
+            shards: None,
+        };
+        let mut output_parts = OutputParts::new(options.clone(), Compression::None);
+        output_parts
+            .write_all(b"client,total\n1,1.0000\n2,2.0000\n3,3.0000\n4,4.0000\n5,")
+            .unwrap();
+        output_parts.write_all(b"5.0000\n").unwrap();
+        let parts = output_parts.finish().unwrap();
+
+        // The header is 13 bytes and each row 9, so a part has at most 3 rows by bytes too
+        assert_eq!(
+            parts.iter().map(|part| part.rows).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        assert_eq!(
+            std::fs::read_to_string(options.part_path(2, Compression::None)).unwrap(),
+            "client,total\n4,4.0000\n5,5.0000\n"
+        );
+        assert_eq!(parts[1].bytes, 31);
+        let manifest = std::fs::read_to_string(options.manifest_path()).unwrap();
+        assert!(manifest.contains(r#""rows": 5"#));
+
+        for part in &parts {
+            std::fs::remove_file(&part.path).unwrap();
+        }
+        std::fs::remove_file(options.manifest_path()).unwrap();
+    }
+
+    #[test]
+    fn test_output_parts_keep_each_shard_apart() {
+        let prefix = std::env::temp_dir().join("rs_bpt-test_output_parts_keep_each_shard_apart");
+        let options = OutputPartsOptions {
+            prefix,
+            max_rows: Some(2),
+            max_bytes: None,
+            shards: Some(2),
+        };
+        let mut output_parts = OutputParts::new(options.clone(), Compression::None);
+        output_parts
+            .write_all(b"client,total\n1,1.0000\n2,2.0000\n3,3.0000\n5,5.0000\n4,4.0000\n")
+            .unwrap();
+        let parts = output_parts.finish().unwrap();
+
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| (part.shard, part.rows))
+                .collect::<Vec<_>>(),
+            vec![(Some(0), 2), (Some(1), 2), (Some(1), 1)]
+        );
+        assert_eq!(
+            std::fs::read_to_string(options.shard_part_path(0, 1, Compression::None)).unwrap(),
+            "client,total\n2,2.0000\n4,4.0000\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(options.shard_part_path(1, 2, Compression::None)).unwrap(),
+            "client,total\n5,5.0000\n"
+        );
+        let manifest = std::fs::read_to_string(options.manifest_path()).unwrap();
+        assert!(manifest.contains(r#""shards": 2"#));
+
+        for part in &parts {
+            std::fs::remove_file(&part.path).unwrap();
+        }
+        std::fs::remove_file(options.manifest_path()).unwrap();
+    }
+
+    #[test]
+    fn test_output_parts_write_a_header_only_part_for_an_empty_shard() {
+        let prefix = std::env::temp_dir().join("rs_bpt-test_output_parts_empty_shard");
+        let options = OutputPartsOptions {
+            prefix,
+            max_rows: None,
+            max_bytes: None,
+            shards: Some(3),
+        };
+        let mut output_parts = OutputParts::new(options.clone(), Compression::None);
+        output_parts
+            .write_all(b"client,total\n1,1.0000\n4,4.0000\n")
+            .unwrap();
+        let parts = output_parts.finish().unwrap();
+
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| (part.shard, part.rows))
+                .collect::<Vec<_>>(),
+            vec![(Some(0), 0), (Some(1), 2), (Some(2), 0)]
+        );
+        assert_eq!(
+            std::fs::read_to_string(options.shard_part_path(2, 1, Compression::None)).unwrap(),
+            "client,total\n"
+        );
+
+        for part in &parts {
+            std::fs::remove_file(&part.path).unwrap();
+        }
+        std::fs::remove_file(options.manifest_path()).unwrap();
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::client_notes::{ClientNote, ClientNotes};
+use crate::serializable_form;
+use crate::summary::Summary;
+
+/// Renders the output through a Tera template instead of writing CSV. The template gets
+/// `accounts` (the output rows in client id order, with the same fields as the CSV columns)
+/// and `summary` (as written by `--summary`). With `--client-notes`, `notes` maps each noted
+/// client's id to its `flags` and `notes` lists.
+pub fn render_output_template(
+    template_file: &Path,
+    output: &[serializable_form::Output],
+    summary: &Summary,
+    client_notes: Option<&ClientNotes>,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let template = std::fs::read_to_string(template_file)?;
+
+    let mut accounts: Vec<&serializable_form::Output> = output.iter().collect();
+    accounts.sort_by_key(|output| output.client);
+
+    let mut context = tera::Context::new();
+    context.insert("accounts", &accounts);
+    context.insert("summary", summary);
+    if let Some(client_notes) = client_notes {
+        let notes: BTreeMap<String, &ClientNote> = accounts
+            .iter()
+            .filter_map(|output| {
+                client_notes
+                    .get(output.client)
+                    .map(|client_note| (output.client.to_string(), client_note))
+            })
+            .collect();
+        context.insert("notes", &notes);
+    }
+    let rendered = tera::Tera::one_off(&template, &context, false)?;
+    output_stream.write_all(rendered.as_bytes())?;
+    Ok(())
+}
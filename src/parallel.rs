@@ -0,0 +1,456 @@
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc;
+
+use crate::client_account::applied_transaction::AppliedTransaction;
+use crate::client_account::error::TransactionProcessingError;
+use crate::hashing::Accounts;
+use crate::journal::{JournalEntry, JournalSink};
+use crate::partial_progress::{PartialProgress, ProcessingAborted};
+use crate::processing_options::ProcessingOptions;
+use crate::rejection::RejectionSink;
+use crate::submission_order::{self, InputRow, InputRowResults};
+use crate::unknown_types::UnknownTypeRow;
+use crate::{process_input, process_rows, serializable_form, ProcessingCounts, TransactionType};
+
+/// Rows read ahead for each shard before the reader waits for its worker
+const SHARD_CHANNEL_CAPACITY: usize = 4096;
+
+/// A row for a shard's worker, or `None` at the end of a pass over the input
+type ShardRow = Option<anyhow::Result<InputRow>>;
+
+/// What a worker records while applying its shard's rows, relayed to the caller's sinks and
+/// debug logger on the calling thread
+enum ShardEvent {
+    Recorded(JournalEntry),
+    Applied(AppliedTransaction),
+    Rejected(
+        Option<u64>,
+        serializable_form::Transaction,
+        TransactionProcessingError,
+    ),
+    DeadLettered(UnknownTypeRow),
+    Logged(Vec<u8>),
+}
+
+struct ShardSink {
+    events: mpsc::Sender<ShardEvent>,
+}
+
+impl ShardSink {
+    fn send(&self, event: ShardEvent) -> anyhow::Result<()> {
+        self.events
+            .send(event)
+            .map_err(|_| anyhow::anyhow!("parallel processing stopped"))
+    }
+}
+
+impl JournalSink for ShardSink {
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        self.send(ShardEvent::Recorded(entry.clone()))
+    }
+
+    fn record_applied(&mut self, applied_transaction: &AppliedTransaction) -> anyhow::Result<()> {
+        self.send(ShardEvent::Applied(applied_transaction.clone()))
+    }
+}
+
+impl RejectionSink for ShardSink {
+    fn record_rejection(
+        &mut self,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        self.record_rejection_at(None, transaction, error)
+    }
+
+    fn record_rejection_at(
+        &mut self,
+        line: Option<u64>,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        self.send(ShardEvent::Rejected(
+            line,
+            transaction.clone(),
+            error.clone(),
+        ))
+    }
+
+    fn record_dead_letter(&mut self, row: &UnknownTypeRow) -> anyhow::Result<()> {
+        self.send(ShardEvent::DeadLettered(row.clone()))
+    }
+}
+
+/// Relays a worker's debug log a line at a time, so that lines from different shards don't mix.
+/// Lines logged after processing stopped are dropped.
+struct ShardDebugLogger {
+    events: mpsc::Sender<ShardEvent>,
+    line: Vec<u8>,
+}
+
+impl std::io::Write for ShardDebugLogger {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.line.is_empty() {
+            let _ = self
+                .events
+                .send(ShardEvent::Logged(std::mem::take(&mut self.line)));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ShardDebugLogger {
+    fn drop(&mut self) {
+        let _ = std::io::Write::flush(self);
+    }
+}
+
+struct ShardOutcome {
+    accounts: Accounts,
+    progress: PartialProgress,
+    counts: Result<ProcessingCounts, String>,
+}
+
+fn relay(
+    event: ShardEvent,
+    journal_sinks: &mut [&mut dyn JournalSink],
+    rejection_sinks: &mut [&mut dyn RejectionSink],
+    debug_logger: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    match event {
+        ShardEvent::Recorded(entry) => {
+            for journal_sink in journal_sinks.iter_mut() {
+                journal_sink.record(&entry)?;
+            }
+        }
+        ShardEvent::Applied(applied_transaction) => {
+            for journal_sink in journal_sinks.iter_mut() {
+                journal_sink.record_applied(&applied_transaction)?;
+            }
+        }
+        ShardEvent::Rejected(line, transaction, error) => {
+            for rejection_sink in rejection_sinks.iter_mut() {
+                rejection_sink.record_rejection_at(line, &transaction, &error)?;
+            }
+        }
+        ShardEvent::DeadLettered(row) => {
+            for rejection_sink in rejection_sinks.iter_mut() {
+                rejection_sink.record_dead_letter(&row)?;
+            }
+        }
+        ShardEvent::Logged(line) => debug_logger.write_all(&line)?,
+    }
+    Ok(())
+}
+
+fn add_counts(total: &mut ProcessingCounts, counts: &ProcessingCounts) {
+    total.pending_disputes.parked += counts.pending_disputes.parked;
+    total.pending_disputes.unparked += counts.pending_disputes.unparked;
+    total.pending_disputes.expired += counts.pending_disputes.expired;
+    total.amount_normalizations.null_as_missing += counts.amount_normalizations.null_as_missing;
+    total.amount_normalizations.reformatted += counts.amount_normalizations.reformatted;
+    total.amount_normalizations.rejected += counts.amount_normalizations.rejected;
+    total.unknown_types.rejected += counts.unknown_types.rejected;
+    total.unknown_types.dead_lettered += counts.unknown_types.dead_lettered;
+    total.backfilled += counts.backfilled;
+}
+
+// The first transfer whose counterparty is in another shard than its client
+fn cross_shard_transfer(
+    input_rows: InputRowResults,
+    threads: usize,
+) -> Option<serializable_form::Transaction> {
+    input_rows
+        .flatten()
+        .map(|input_row| input_row.transaction)
+        .find(|transaction| {
+            transaction.transaction_type == TransactionType::Transfer
+                && transaction.counterparty.is_some_and(|counterparty| {
+                    counterparty as usize % threads != transaction.client_id as usize % threads
+                })
+        })
+}
+
+/// Processes the file like `process_transactions_file`, sharding the transactions by
+/// `client_id % threads` across worker threads, each applying its shard to its own accounts map.
+/// The shards are merged back into `accounts` at the end, including when processing is aborted.
+///
+/// Each client's transactions are applied in file order, but the journal entries and rejections
+/// of different clients reach the sinks in the order the workers apply them. Rows which can't be
+/// read as transactions go to the first shard. The pending dispute window counts the rows of the
+/// client's shard, and `max_clients` is not enforced across shards. As a worker only has its own
+/// shard's accounts, an input with a transfer between clients of different shards is refused
+/// before any row is applied, which costs an extra read of the input.
+pub fn process_transactions_parallel(
+    accounts: &mut Accounts,
+    input_transactions_file: PathBuf,
+    threads: usize,
+    processing_options: &ProcessingOptions,
+    journal_sinks: &mut [&mut dyn JournalSink],
+    rejection_sinks: &mut [&mut dyn RejectionSink],
+    debug_logger: &mut dyn std::io::Write,
+) -> Result<ProcessingCounts, Box<dyn std::error::Error>> {
+    let mut read_input =
+        || submission_order::read_rows(&input_transactions_file, processing_options);
+    if threads <= 1 {
+        return process_input(
+            accounts,
+            &mut read_input,
+            processing_options,
+            journal_sinks,
+            rejection_sinks,
+            debug_logger,
+        );
+    }
+
+    if let Some(transaction) = cross_shard_transfer(read_input()?, threads) {
+        return Err(format!(
+            "transfer {} from client {} to client {} crosses the shards of --threads",
+            transaction.transaction_id,
+            transaction.client_id,
+            transaction.counterparty.unwrap_or_default()
+        )
+        .into());
+    }
+
+    let mut shard_accounts: Vec<Accounts> = (0..threads)
+        .map(|_| {
+            Accounts::with_capacity_and_hasher(
+                processing_options.expected_clients.unwrap_or(0) / threads,
+                Default::default(),
+            )
+        })
+        .collect();
+    for (client_id, client_account) in accounts.drain() {
+        shard_accounts[client_id as usize % threads].insert(client_id, client_account);
+    }
+
+    let (event_sender, event_receiver) = mpsc::channel::<ShardEvent>();
+    let (outcomes, dispatched) = std::thread::scope(|scope| {
+        let mut row_senders = Vec::with_capacity(threads);
+        let mut handles = Vec::with_capacity(threads);
+        for mut accounts in shard_accounts.drain(..) {
+            let (row_sender, row_receiver) = mpsc::sync_channel::<ShardRow>(SHARD_CHANNEL_CAPACITY);
+            row_senders.push(row_sender);
+            let events = event_sender.clone();
+            handles.push(scope.spawn(move || {
+                let row_receiver = Rc::new(row_receiver);
+                let mut read_shard = || -> anyhow::Result<InputRowResults> {
+                    let row_receiver = row_receiver.clone();
+                    Ok(Box::new(std::iter::from_fn(move || {
+                        row_receiver.recv().ok().flatten()
+                    })))
+                };
+                let mut journal_sink = ShardSink {
+                    events: events.clone(),
+                };
+                let mut rejection_sink = ShardSink {
+                    events: events.clone(),
+                };
+                let mut debug_logger = ShardDebugLogger {
+                    events,
+                    line: Vec::new(),
+                };
+                let mut progress = PartialProgress::default();
+                let counts = process_rows(
+                    &mut accounts,
+                    &mut read_shard,
+                    processing_options,
+                    &mut [&mut journal_sink],
+                    &mut [&mut rejection_sink],
+                    &mut debug_logger,
+                    &mut progress,
+                )
+                .map_err(|error| error.to_string());
+                ShardOutcome {
+                    accounts,
+                    progress,
+                    counts,
+                }
+            }));
+        }
+        drop(event_sender);
+
+        // the reader dispatches the rows and relays the workers' events as they come
+        let dispatched = (|| -> anyhow::Result<()> {
+            'passes: for _ in 0..processing_options.ordering.passes() {
+                for input_row in read_input()? {
+                    let shard = match &input_row {
+                        Ok(input_row) => input_row.transaction.client_id as usize % threads,
+                        Err(_) => 0,
+                    };
+                    // a worker stops receiving when its shard is aborted
+                    if row_senders[shard].send(Some(input_row)).is_err() {
+                        break 'passes;
+                    }
+                    for event in event_receiver.try_iter() {
+                        relay(event, journal_sinks, rejection_sinks, debug_logger)?;
+                    }
+                }
+                for row_sender in &row_senders {
+                    let _ = row_sender.send(None);
+                }
+            }
+            drop(row_senders);
+            for event in event_receiver.iter() {
+                relay(event, journal_sinks, rejection_sinks, debug_logger)?;
+            }
+            Ok(())
+        })();
+        drop(event_receiver);
+
+        let outcomes: Vec<ShardOutcome> = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("shard worker panicked"))
+            .collect();
+        (outcomes, dispatched)
+    });
+
+    let mut counts = ProcessingCounts::default();
+    let mut progress = PartialProgress::default();
+    let mut aborted = None;
+    for outcome in outcomes {
+        accounts.extend(outcome.accounts);
+        progress.rows_processed += outcome.progress.rows_processed;
+        match outcome.counts {
+            Ok(shard_counts) => add_counts(&mut counts, &shard_counts),
+            Err(error) => {
+                if aborted.is_none() {
+                    progress.last_good_line = outcome.progress.last_good_line;
+                    progress.last_client = outcome.progress.last_client;
+                    aborted = Some(error);
+                }
+            }
+        }
+    }
+    if let Err(error) = dispatched {
+        aborted.get_or_insert_with(|| error.to_string());
+    }
+    match aborted {
+        Some(error) => Err(ProcessingAborted {
+            progress,
+            error: error.into(),
+        }
+        .into()),
+        None => Ok(counts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assert_account, process_transactions_file};
+
+    fn process(
+        threads: usize,
+        input_file: &str,
+        processing_options: &ProcessingOptions,
+    ) -> (
+        Accounts,
+        Result<ProcessingCounts, String>,
+        Vec<JournalEntry>,
+    ) {
+        let mut accounts = Accounts::default();
+        let mut journal_entries = Vec::<JournalEntry>::new();
+        let counts = if threads == 0 {
+            process_transactions_file(
+                &mut accounts,
+                PathBuf::from(input_file),
+                processing_options,
+                &mut [&mut journal_entries],
+                &mut [],
+                &mut std::io::sink(),
+            )
+        } else {
+            process_transactions_parallel(
+                &mut accounts,
+                PathBuf::from(input_file),
+                threads,
+                processing_options,
+                &mut [&mut journal_entries],
+                &mut [],
+                &mut std::io::sink(),
+            )
+        };
+        (
+            accounts,
+            counts.map_err(|error| error.to_string()),
+            journal_entries,
+        )
+    }
+
+    #[test]
+    fn it_processes_shards_like_a_single_thread() {
+        for input_file in [
+            "tests/fixtures/transactions-complex.csv",
+            "tests/fixtures/transactions-representment.csv",
+            "tests/fixtures/transactions-unknown-types.csv",
+        ] {
+            let processing_options = ProcessingOptions {
+                unknown_type: crate::processing_options::UnknownTypePolicy::Reject,
+                ..Default::default()
+            };
+            let (expected_accounts, expected_counts, expected_journal) =
+                process(0, input_file, &processing_options);
+            for threads in [1, 3] {
+                let (accounts, counts, journal) = process(threads, input_file, &processing_options);
+                assert_eq!(
+                    counts, expected_counts,
+                    "{} with {} threads",
+                    input_file, threads
+                );
+                assert_eq!(journal.len(), expected_journal.len());
+                assert_eq!(accounts.len(), expected_accounts.len());
+                for (client_id, client_account) in &accounts {
+                    let expected = &expected_accounts[client_id];
+                    assert_eq!(client_account.balance, expected.balance);
+                    assert_eq!(client_account.locked, expected.locked);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_refuses_transfers_across_shards_before_applying_any_row() {
+        let (accounts, counts, journal) = process(
+            2,
+            "tests/fixtures/transactions-transfers.csv",
+            &ProcessingOptions::default(),
+        );
+        assert_eq!(
+            counts,
+            Err("transfer 2 from client 1 to client 2 crosses the shards of --threads".to_string())
+        );
+        assert!(accounts.is_empty());
+        assert!(journal.is_empty());
+
+        // the same transfers stay within the one shard of a single thread
+        let (accounts, counts, _) = process(
+            1,
+            "tests/fixtures/transactions-transfers.csv",
+            &ProcessingOptions::default(),
+        );
+        assert!(counts.is_ok());
+        assert_account!(accounts[&2], available = "4.0000");
+    }
+
+    #[test]
+    fn it_keeps_the_accounts_processed_before_an_abort() {
+        let (accounts, counts, _) = process(
+            2,
+            "tests/fixtures/transactions-unknown-types.csv",
+            &ProcessingOptions::default(),
+        );
+        assert!(counts.is_err());
+        assert!(!accounts.is_empty());
+    }
+}
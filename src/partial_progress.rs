@@ -0,0 +1,53 @@
+use serde_derive::Serialize;
+
+use crate::ClientId;
+
+/// How far processing got, so that operators know where to resume or investigate after an abort.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+pub struct PartialProgress {
+    /// The rows applied (whether accepted or rejected) before processing stopped
+    pub rows_processed: usize,
+    /// The input line of the last row applied
+    pub last_good_line: Option<u64>,
+    /// The client of the last row applied
+    pub last_client: Option<ClientId>,
+}
+
+impl PartialProgress {
+    pub fn record(&mut self, line: u64, client_id: ClientId) {
+        self.rows_processed += 1;
+        self.last_good_line = Some(line);
+        self.last_client = Some(client_id);
+    }
+
+    pub fn write_json(&self, output_stream: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut *output_stream, self)?;
+        writeln!(output_stream)?;
+        Ok(())
+    }
+}
+
+/// The error which stopped processing a file, with the progress made until then. Displays as the
+/// error itself.
+pub struct ProcessingAborted {
+    pub progress: PartialProgress,
+    pub error: Box<dyn std::error::Error>,
+}
+
+impl std::fmt::Display for ProcessingAborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::fmt::Debug for ProcessingAborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for ProcessingAborted {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
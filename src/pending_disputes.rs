@@ -0,0 +1,148 @@
+use serde_derive::Serialize;
+use std::collections::VecDeque;
+
+use crate::serializable_form;
+
+/// How many parked dispute ops were later applied, and how many expired and were rejected.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+pub struct PendingDisputeCounts {
+    pub parked: usize,
+    pub unparked: usize,
+    pub expired: usize,
+}
+
+/// A bounded buffer of disputes, resolves and chargebacks whose referenced transaction has not
+/// been seen yet. Each is parked for up to `window` subsequent rows and retried once its
+/// transaction is applied.
+#[derive(Debug, Default)]
+pub struct PendingDisputes {
+    window: usize,
+    parked: VecDeque<(usize, serializable_form::Transaction)>,
+    counts: PendingDisputeCounts,
+}
+
+impl PendingDisputes {
+    /// A buffer which parks dispute ops for up to `window` rows. A zero window parks nothing.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            ..Default::default()
+        }
+    }
+
+    pub fn counts(&self) -> PendingDisputeCounts {
+        self.counts
+    }
+
+    /// Parks a dispute op read at `row` if the buffer is enabled. Returns whether it was parked.
+    pub fn park(&mut self, row: usize, transaction: &serializable_form::Transaction) -> bool {
+        if self.window == 0 || !transaction.transaction_type.is_dispute_op() {
+            return false;
+        }
+        self.parked.push_back((row, transaction.clone()));
+        self.counts.parked += 1;
+        true
+    }
+
+    /// Removes the dispute ops which reference the given transaction, in the order they were read.
+    pub fn unpark(
+        &mut self,
+        transaction: &serializable_form::Transaction,
+    ) -> Vec<serializable_form::Transaction> {
+        let mut unparked = Vec::new();
+        let mut still_parked = VecDeque::with_capacity(self.parked.len());
+        for (row, parked) in self.parked.drain(..) {
+            if parked.client_id == transaction.client_id
+                && parked.transaction_id == transaction.transaction_id
+            {
+                unparked.push(parked);
+            } else {
+                still_parked.push_back((row, parked));
+            }
+        }
+        self.parked = still_parked;
+        self.counts.unparked += unparked.len();
+        unparked
+    }
+
+    /// Removes the dispute ops which have waited more than `window` rows by `row`.
+    pub fn expire(&mut self, row: usize) -> Vec<serializable_form::Transaction> {
+        let mut expired = Vec::new();
+        while let Some((parked_row, _)) = self.parked.front() {
+            if parked_row + self.window >= row {
+                break;
+            }
+            if let Some((_, parked)) = self.parked.pop_front() {
+                expired.push(parked);
+            }
+        }
+        self.counts.expired += expired.len();
+        expired
+    }
+
+    /// Removes all remaining dispute ops, at the end of the file.
+    pub fn expire_all(&mut self) -> Vec<serializable_form::Transaction> {
+        let expired: Vec<_> = self.parked.drain(..).map(|(_, parked)| parked).collect();
+        self.counts.expired += expired.len();
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TransactionId, TransactionType};
+
+    fn transaction(
+        transaction_type: TransactionType,
+        transaction_id: TransactionId,
+    ) -> serializable_form::Transaction {
+        serializable_form::Transaction {
+            transaction_type,
+            client_id: 1,
+            transaction_id,
+            amount: None,
+            counterparty: None,
+        }
+    }
+
+    #[test]
+    fn test_parked_dispute_ops_are_unparked_or_expire_after_the_window() {
+        let mut pending_disputes = PendingDisputes::new(2);
+
+        assert!(!pending_disputes.park(0, &transaction(TransactionType::Deposit, 1)));
+        assert!(pending_disputes.park(0, &transaction(TransactionType::Dispute, 1)));
+        assert!(pending_disputes.park(1, &transaction(TransactionType::Dispute, 2)));
+        assert!(pending_disputes.park(2, &transaction(TransactionType::Resolve, 1)));
+
+        assert!(pending_disputes.expire(2).is_empty());
+        let unparked = pending_disputes.unpark(&transaction(TransactionType::Deposit, 1));
+        assert_eq!(
+            unparked
+                .iter()
+                .map(|parked| parked.transaction_type)
+                .collect::<Vec<_>>(),
+            vec![TransactionType::Dispute, TransactionType::Resolve]
+        );
+
+        let expired = pending_disputes.expire(4);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].transaction_id, 2);
+        assert!(pending_disputes.expire_all().is_empty());
+
+        assert_eq!(
+            pending_disputes.counts(),
+            PendingDisputeCounts {
+                parked: 3,
+                unparked: 2,
+                expired: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_a_zero_window_parks_nothing() {
+        let mut pending_disputes = PendingDisputes::new(0);
+        assert!(!pending_disputes.park(0, &transaction(TransactionType::Dispute, 1)));
+    }
+}
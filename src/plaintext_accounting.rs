@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::journal::{JournalEntry, LedgerAccount};
+use crate::serializable_form::round_f64_4dp_string;
+use crate::ClientId;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PlaintextFormat {
+    Beancount,
+    Ledger,
+}
+
+impl std::str::FromStr for PlaintextFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beancount" => Ok(PlaintextFormat::Beancount),
+            "ledger" => Ok(PlaintextFormat::Ledger),
+            _ => Err(format!("unknown plaintext accounting format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaintextAccountingOptions {
+    pub file: PathBuf,
+    pub format: PlaintextFormat,
+    pub commodity: String,
+}
+
+fn account_name(ledger_account: LedgerAccount) -> String {
+    match ledger_account {
+        LedgerAccount::ClientAvailable(client_id) => {
+            format!("Liabilities:Clients:C{}:Available", client_id)
+        }
+        LedgerAccount::ClientHeld(client_id) => format!("Liabilities:Clients:C{}:Held", client_id),
+        LedgerAccount::Suspense => "Assets:Suspense".to_string(),
+        LedgerAccount::ChargebackExpense => "Expenses:Chargebacks".to_string(),
+    }
+}
+
+/// Writes the journal entries as plaintext accounting transactions, grouped by client.
+/// Transactions have no timestamps so every entry is dated `date` (YYYY-MM-DD).
+pub fn write_plaintext_accounting(
+    journal_entries: &[JournalEntry],
+    format: PlaintextFormat,
+    date: &str,
+    commodity: &str,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut entries_by_client = BTreeMap::<ClientId, Vec<&JournalEntry>>::new();
+    for entry in journal_entries {
+        entries_by_client
+            .entry(entry.client_id)
+            .or_default()
+            .push(entry);
+    }
+
+    if format == PlaintextFormat::Beancount {
+        let mut account_names: Vec<String> = journal_entries
+            .iter()
+            .flat_map(|entry| [account_name(entry.debit), account_name(entry.credit)])
+            .collect();
+        account_names.sort();
+        account_names.dedup();
+        for name in account_names {
+            writeln!(output_stream, "{} open {} {}", date, name, commodity)?;
+        }
+        writeln!(output_stream)?;
+    }
+
+    let date = match format {
+        PlaintextFormat::Beancount => date.to_string(),
+        PlaintextFormat::Ledger => date.replace('-', "/"),
+    };
+
+    for (client_id, entries) in entries_by_client {
+        writeln!(output_stream, "; client {}", client_id)?;
+        for entry in entries {
+            match format {
+                PlaintextFormat::Beancount => writeln!(
+                    output_stream,
+                    "{} * \"{}\" \"client {} tx {}\"",
+                    date, entry.transaction_type, client_id, entry.transaction_id
+                )?,
+                PlaintextFormat::Ledger => writeln!(
+                    output_stream,
+                    "{} {} client {} tx {}",
+                    date, entry.transaction_type, client_id, entry.transaction_id
+                )?,
+            }
+            let amount = round_f64_4dp_string(entry.amount)?;
+            writeln!(
+                output_stream,
+                "    {}  {} {}",
+                account_name(entry.debit),
+                amount,
+                commodity
+            )?;
+            writeln!(
+                output_stream,
+                "    {}  -{} {}",
+                account_name(entry.credit),
+                amount,
+                commodity
+            )?;
+            writeln!(output_stream)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionType;
+
+    fn journal_entries() -> Vec<JournalEntry> {
+        vec![
+            JournalEntry::new(2, 1, TransactionType::Deposit, 10.0),
+            JournalEntry::new(1, 2, TransactionType::Deposit, 100.0),
+            JournalEntry::new(1, 3, TransactionType::Withdrawal, -25.0),
+        ]
+    }
+
+    #[test]
+    fn test_write_beancount() {
+        let mut output = Vec::<u8>::new();
+        write_plaintext_accounting(
+            &journal_entries(),
+            PlaintextFormat::Beancount,
+            "2022-03-01",
+            "USD",
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"2022-03-01 open Assets:Suspense USD
+2022-03-01 open Liabilities:Clients:C1:Available USD
+2022-03-01 open Liabilities:Clients:C2:Available USD
+
+; client 1
+2022-03-01 * "deposit" "client 1 tx 2"
+    Assets:Suspense  100.0000 USD
+    Liabilities:Clients:C1:Available  -100.0000 USD
+
+2022-03-01 * "withdrawal" "client 1 tx 3"
+    Liabilities:Clients:C1:Available  25.0000 USD
+    Assets:Suspense  -25.0000 USD
+
+; client 2
+2022-03-01 * "deposit" "client 2 tx 1"
+    Assets:Suspense  10.0000 USD
+    Liabilities:Clients:C2:Available  -10.0000 USD
+
+"#
+        );
+    }
+
+    #[test]
+    fn test_write_ledger() {
+        let mut output = Vec::<u8>::new();
+        write_plaintext_accounting(
+            &journal_entries()[..1],
+            PlaintextFormat::Ledger,
+            "2022-03-01",
+            "USD",
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"; client 2
+2022/03/01 deposit client 2 tx 1
+    Assets:Suspense  10.0000 USD
+    Liabilities:Clients:C2:Available  -10.0000 USD
+
+"#
+        );
+    }
+}
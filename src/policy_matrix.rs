@@ -0,0 +1,178 @@
+use serde_derive::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::client_account::error::TransactionProcessingError;
+use crate::hashing::Accounts;
+use crate::process_transactions_file;
+use crate::processing_options::ProcessingOptions;
+use crate::rejection::RejectionSink;
+use crate::serializable_form::{self, round_f64_4dp_string};
+
+/// Reads every `*.json` file in the directory as `ProcessingOptions`, in file name order, named
+/// by their file stem.
+pub fn read_configs(configs_directory: &Path) -> anyhow::Result<Vec<(String, ProcessingOptions)>> {
+    let mut config_files: Vec<PathBuf> = std::fs::read_dir(configs_directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    config_files.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "json")
+    });
+    config_files.sort();
+
+    let mut configs = Vec::new();
+    for config_file in config_files {
+        let name = config_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let processing_options = serde_json::from_reader(std::fs::File::open(&config_file)?)
+            .map_err(|error| {
+                anyhow::anyhow!("invalid config {}: {}", config_file.display(), error)
+            })?;
+        configs.push((name, processing_options));
+    }
+    Ok(configs)
+}
+
+#[derive(Debug, Default)]
+struct RejectionCount(usize);
+
+impl RejectionSink for RejectionCount {
+    fn record_rejection(
+        &mut self,
+        _transaction: &serializable_form::Transaction,
+        _error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        self.0 += 1;
+        Ok(())
+    }
+}
+
+/// The final balances and rejection count of a run under one config.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PolicyResult {
+    pub config: String,
+    pub clients: usize,
+    pub locked: usize,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub rejected: usize,
+    /// Set if the config stopped processing, in which case the balances are as they were then
+    pub aborted: Option<String>,
+}
+
+/// Processes the input once under each config.
+pub fn run_policy_matrix(
+    input_transactions_file: &Path,
+    configs: &[(String, ProcessingOptions)],
+    debug_logger: &mut dyn std::io::Write,
+) -> anyhow::Result<Vec<PolicyResult>> {
+    let mut results = Vec::new();
+    for (name, processing_options) in configs {
+        let mut accounts = Accounts::default();
+        let mut rejection_count = RejectionCount::default();
+        let aborted = process_transactions_file(
+            &mut accounts,
+            input_transactions_file.to_owned(),
+            processing_options,
+            &mut [],
+            &mut [&mut rejection_count],
+            debug_logger,
+        )
+        .err()
+        .map(|error| error.to_string());
+
+        let available: f64 = accounts
+            .values()
+            .map(|account| account.balance.available)
+            .sum();
+        let held: f64 = accounts.values().map(|account| account.balance.held).sum();
+        results.push(PolicyResult {
+            config: name.clone(),
+            clients: accounts.len(),
+            locked: accounts.values().filter(|account| account.locked).count(),
+            available: round_f64_4dp_string(available)?,
+            held: round_f64_4dp_string(held)?,
+            total: round_f64_4dp_string(available + held)?,
+            rejected: rejection_count.0,
+            aborted,
+        });
+    }
+    Ok(results)
+}
+
+pub fn write_report(
+    results: &[PolicyResult],
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(output_stream);
+    for result in results {
+        writer.serialize(result)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing_options::{HeldBalanceUnderflowPolicy, ImplicitAccounts};
+
+    #[test]
+    fn test_processing_options_are_read_from_json() {
+        let processing_options: ProcessingOptions = serde_json::from_str(
+            r#"{"held-balance-underflow": "clamp", "implicit-accounts": "deny-for-dispute-ops", "max-clients": 10}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            processing_options.held_balance_underflow,
+            HeldBalanceUnderflowPolicy::Clamp
+        );
+        assert_eq!(
+            processing_options.implicit_accounts,
+            ImplicitAccounts::DenyForDisputeOps
+        );
+        assert_eq!(processing_options.max_clients, Some(10));
+
+        assert!(serde_json::from_str::<ProcessingOptions>(r#"{"overdraft": "allow"}"#).is_err());
+    }
+
+    #[test]
+    fn test_run_policy_matrix() {
+        let configs = read_configs(Path::new("tests/fixtures/policies")).unwrap();
+        let results = run_policy_matrix(
+            Path::new("tests/fixtures/transactions-unknown-clients.csv"),
+            &configs,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                PolicyResult {
+                    config: "allow".to_string(),
+                    clients: 3,
+                    locked: 0,
+                    available: "6.0000".to_string(),
+                    held: "0.0000".to_string(),
+                    total: "6.0000".to_string(),
+                    rejected: 1,
+                    aborted: None,
+                },
+                PolicyResult {
+                    config: "deny-for-dispute-ops".to_string(),
+                    clients: 2,
+                    locked: 0,
+                    available: "6.0000".to_string(),
+                    held: "0.0000".to_string(),
+                    total: "6.0000".to_string(),
+                    rejected: 1,
+                    aborted: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,457 @@
+use serde_derive::Deserialize;
+
+use crate::amount_parsing::{AmountFormat, NullAmountPolicy};
+use crate::authorization::WithdrawalAuthorization;
+use crate::client_account::applied_transaction::AppliedTransaction;
+use crate::client_account::error::TransactionProcessingError;
+use crate::client_tiers::ClientTiers;
+use crate::column_mapping::ColumnMapping;
+use crate::serializable_form;
+use crate::submission_order::SubmissionOrder;
+use crate::timestamps::{AmbiguousTimestampPolicy, TimestampFormat, UtcOffset};
+use crate::type_aliases::TypeAliases;
+use crate::{ClientId, TransactionId, TransactionType};
+use std::collections::{HashMap, HashSet};
+
+/// What to do when a resolve or chargeback would release more than is currently held.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeldBalanceUnderflowPolicy {
+    /// Release only what is held, and log a warning
+    Clamp,
+    /// Reject the transaction with a `HeldBalanceUnderflow` error
+    #[default]
+    Reject,
+    /// Reject the transaction and stop processing
+    Abort,
+}
+
+impl std::str::FromStr for HeldBalanceUnderflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(HeldBalanceUnderflowPolicy::Clamp),
+            "reject" => Ok(HeldBalanceUnderflowPolicy::Reject),
+            "abort" => Ok(HeldBalanceUnderflowPolicy::Abort),
+            _ => Err(format!("unknown held balance underflow policy: {}", s)),
+        }
+    }
+}
+
+/// What to do when a resource limit is exceeded.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LimitPolicy {
+    /// Reject the transaction with a `TransactionLimitExceeded` or `ClientLimitExceeded` error
+    #[default]
+    Reject,
+    /// Reject the transaction and stop processing
+    Abort,
+}
+
+impl std::str::FromStr for LimitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(LimitPolicy::Reject),
+            "abort" => Ok(LimitPolicy::Abort),
+            _ => Err(format!("unknown limit policy: {}", s)),
+        }
+    }
+}
+
+/// What a locked account does with new deposits and withdrawals. Dispute ops are always applied.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockedAccountPolicy {
+    /// Apply them as if the account weren't locked
+    #[default]
+    Allow,
+    /// Reject them with an `AccountLocked` error
+    Reject,
+}
+
+impl std::str::FromStr for LockedAccountPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(LockedAccountPolicy::Allow),
+            "reject" => Ok(LockedAccountPolicy::Reject),
+            _ => Err(format!("unknown locked account policy: {}", s)),
+        }
+    }
+}
+
+/// Which transactions may create an account for a client id which has not been seen yet.
+/// An `open_account` row always may, and a `close_account` row never does.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImplicitAccounts {
+    /// Any transaction creates the account
+    Allow,
+    /// Deposits, withdrawals and transfers create the account, disputes, resolves and chargebacks
+    /// are rejected
+    #[default]
+    DenyForDisputeOps,
+    /// Only `open_account` creates the account
+    Deny,
+}
+
+impl ImplicitAccounts {
+    pub fn allows(&self, transaction_type: TransactionType) -> bool {
+        match (self, transaction_type) {
+            (_, TransactionType::CloseAccount) => false,
+            (_, TransactionType::OpenAccount) | (ImplicitAccounts::Allow, _) => true,
+            (ImplicitAccounts::DenyForDisputeOps, transaction_type) => matches!(
+                transaction_type,
+                TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer
+            ),
+            (ImplicitAccounts::Deny, _) => false,
+        }
+    }
+}
+
+impl std::str::FromStr for ImplicitAccounts {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(ImplicitAccounts::Allow),
+            "deny-for-dispute-ops" => Ok(ImplicitAccounts::DenyForDisputeOps),
+            "deny" => Ok(ImplicitAccounts::Deny),
+            _ => Err(format!("unknown implicit accounts policy: {}", s)),
+        }
+    }
+}
+
+/// The order in which the rows of the input file are applied.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Ordering {
+    /// Apply every row in file order
+    #[default]
+    FileOrder,
+    /// Apply the deposits, withdrawals and account openings first, then make a second pass over
+    /// the file for the disputes, resolves and chargebacks, so that a dispute may precede its
+    /// transaction in the file
+    TwoPass,
+}
+
+impl Ordering {
+    pub fn passes(&self) -> usize {
+        match self {
+            Ordering::FileOrder => 1,
+            Ordering::TwoPass => 2,
+        }
+    }
+
+    /// Whether a transaction of this type is applied in the given (zero-based) pass over the file.
+    /// A `close_account` is applied with the dispute ops before it, in the second pass.
+    pub fn applies_in_pass(&self, pass: usize, transaction_type: TransactionType) -> bool {
+        match self {
+            Ordering::FileOrder => true,
+            Ordering::TwoPass => {
+                (transaction_type.is_dispute_op()
+                    || transaction_type == TransactionType::CloseAccount)
+                    == (pass == 1)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Ordering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file-order" => Ok(Ordering::FileOrder),
+            "two-pass" => Ok(Ordering::TwoPass),
+            _ => Err(format!("unknown ordering: {}", s)),
+        }
+    }
+}
+
+/// What to do with a resolve of a transaction which is not under dispute in this run, e.g.
+/// because it was disputed in an earlier run.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolveWithoutDisputePolicy {
+    /// Reject the resolve with a `ReferencedTransactionNotFound` or
+    /// `TransactionDoesNotHavePendingDisupte` error
+    #[default]
+    Reject,
+    /// Accept the resolve without changing any balance if the transaction has a case in the
+    /// imported dispute cases, otherwise reject it
+    AcceptIfCaseKnown,
+    /// Reject the resolve with a `ParkedForReview` error, so it is written to the review file
+    ParkForReview,
+}
+
+impl std::str::FromStr for ResolveWithoutDisputePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(ResolveWithoutDisputePolicy::Reject),
+            "accept-if-case-known" => Ok(ResolveWithoutDisputePolicy::AcceptIfCaseKnown),
+            "park-for-review" => Ok(ResolveWithoutDisputePolicy::ParkForReview),
+            _ => Err(format!("unknown resolve without dispute policy: {}", s)),
+        }
+    }
+}
+
+/// What to do with a dispute, resolve or chargeback of a transaction which its client does not
+/// have, e.g. because the row was keyed with the wrong client.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrossClientDisputePolicy {
+    /// Reject it as if the transaction had never been seen, with a `ReferencedTransactionNotFound`
+    /// (or `ClientNotFound`) error
+    #[default]
+    NotFound,
+    /// Look the transaction up in the other clients' accounts, and if one of them has it reject
+    /// the row with a `ClientMismatchOnDispute` error naming that client in the debug log. Costs a
+    /// scan of the accounts for every such row.
+    Locate,
+}
+
+impl std::str::FromStr for CrossClientDisputePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "not-found" => Ok(CrossClientDisputePolicy::NotFound),
+            "locate" => Ok(CrossClientDisputePolicy::Locate),
+            _ => Err(format!("unknown cross client dispute policy: {}", s)),
+        }
+    }
+}
+
+/// What to do with a deposit which would take its client's total balance above the client's cap.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BalanceCapPolicy {
+    /// Reject the deposit with a `BalanceCapExceeded` error
+    #[default]
+    Reject,
+    /// Credit the deposit up to the cap and report the excess to the rejection sinks (e.g. the
+    /// suspense report) as a rejected deposit of the excess amount
+    SuspendExcess,
+}
+
+impl std::str::FromStr for BalanceCapPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(BalanceCapPolicy::Reject),
+            "suspend-excess" => Ok(BalanceCapPolicy::SuspendExcess),
+            _ => Err(format!("unknown balance cap policy: {}", s)),
+        }
+    }
+}
+
+/// What to do with an input row whose type is not a transaction type, e.g. a type added upstream.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownTypePolicy {
+    /// Skip the row, and log it and count it in the summary
+    Reject,
+    /// Skip the row like `Reject`, and pass it to the rejection sinks (e.g. the dead-letter file)
+    DeadLetter,
+    /// Stop processing
+    #[default]
+    Abort,
+}
+
+impl std::str::FromStr for UnknownTypePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(UnknownTypePolicy::Reject),
+            "dead-letter" => Ok(UnknownTypePolicy::DeadLetter),
+            "abort" => Ok(UnknownTypePolicy::Abort),
+            _ => Err(format!("unknown unknown type policy: {}", s)),
+        }
+    }
+}
+
+/// Policies applied while processing transactions. Can be read from JSON, with the fields named
+/// like the command line flags, e.g. `{"held-balance-underflow": "clamp"}`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProcessingOptions {
+    pub held_balance_underflow: HeldBalanceUnderflowPolicy,
+
+    pub implicit_accounts: ImplicitAccounts,
+
+    pub submission_order: SubmissionOrder,
+
+    pub column_mapping: ColumnMapping,
+
+    pub type_aliases: TypeAliases,
+
+    pub unknown_type: UnknownTypePolicy,
+
+    pub ordering: Ordering,
+
+    pub null_amounts: NullAmountPolicy,
+
+    pub amount_format: AmountFormat,
+
+    pub timestamp_format: TimestampFormat,
+
+    /// The UTC offset of timestamps written in local time, without one of their own
+    pub timestamp_timezone: Option<UtcOffset>,
+
+    pub ambiguous_timestamps: AmbiguousTimestampPolicy,
+
+    /// Park a dispute, resolve or chargeback whose transaction has not been seen yet for up to
+    /// this many rows, and retry it when the transaction arrives. Zero disables the buffer.
+    pub pending_dispute_window: usize,
+
+    /// How many recent events each `ClientAccount` keeps. Zero disables the event list.
+    pub account_event_capacity: usize,
+
+    /// Pre-allocate the accounts map for this many clients
+    pub expected_clients: Option<usize>,
+
+    /// Pre-allocate each new account's transaction map for this many transactions
+    pub expected_transactions_per_client: Option<usize>,
+
+    /// Hard limit on the deposits and withdrawals retained (for disputes) per client
+    pub max_transactions_per_client: Option<usize>,
+
+    /// Hard limit on the number of client accounts
+    pub max_clients: Option<usize>,
+
+    /// Skip the rows whose transaction is already in the state carried over from a snapshot
+    /// (including those forgotten past the dispute retention), and the disputes, resolves and
+    /// chargebacks of such a transaction, e.g. when reprocessing a corrected historical file,
+    /// rather than applying them again. A repeated id within the run is still a duplicate.
+    pub backfill: bool,
+
+    /// Forget the settled transactions carried over from a state snapshot once they have been
+    /// carried over into more than this many runs, so that disputes can only reach that far back
+    pub dispute_retention_runs: Option<u32>,
+
+    pub limit_exceeded: LimitPolicy,
+
+    /// Close accounts with more chargebacks than this
+    pub max_chargebacks: Option<usize>,
+
+    /// Credit the residual balance of an account closed by `close_account` to this client
+    pub sweep_client: Option<ClientId>,
+
+    pub locked_accounts: LockedAccountPolicy,
+
+    pub resolve_without_dispute: ResolveWithoutDisputePolicy,
+
+    pub cross_client_disputes: CrossClientDisputePolicy,
+
+    /// Cap on every client's total balance, unless the client has its own cap
+    pub balance_cap: Option<f64>,
+
+    /// Caps on the total balance of individual clients, read from a client,cap CSV file
+    #[serde(skip)]
+    pub client_balance_caps: HashMap<ClientId, f64>,
+
+    pub balance_cap_exceeded: BalanceCapPolicy,
+
+    /// Per-tier overrides of the balance cap and transaction limit options
+    #[serde(skip)]
+    pub client_tiers: ClientTiers,
+
+    /// The (client, transaction) of every case in the imported dispute cases, for
+    /// `ResolveWithoutDisputePolicy::AcceptIfCaseKnown`
+    #[serde(skip)]
+    pub known_dispute_cases: HashSet<(ClientId, TransactionId)>,
+
+    /// Consulted before applying large withdrawals. Only available to library users.
+    #[serde(skip)]
+    pub withdrawal_authorization: Option<WithdrawalAuthorization>,
+}
+
+impl ProcessingOptions {
+    /// Applies the resolve without dispute policy to the outcome of a transaction
+    pub fn resolve_without_dispute_outcome(
+        &self,
+        transaction: &serializable_form::Transaction,
+        outcome: Result<Option<AppliedTransaction>, TransactionProcessingError>,
+    ) -> Result<Option<AppliedTransaction>, TransactionProcessingError> {
+        let without_dispute = transaction.transaction_type == TransactionType::Resolve
+            && matches!(
+                outcome,
+                Err(
+                    TransactionProcessingError::ReferencedTransactionNotFound { .. }
+                        | TransactionProcessingError::TransactionDoesNotHavePendingDisupte { .. }
+                )
+            );
+        if !without_dispute {
+            return outcome;
+        }
+        match self.resolve_without_dispute {
+            ResolveWithoutDisputePolicy::Reject => outcome,
+            ResolveWithoutDisputePolicy::AcceptIfCaseKnown => {
+                let case = (transaction.client_id, transaction.transaction_id);
+                if self.known_dispute_cases.contains(&case) {
+                    Ok(None)
+                } else {
+                    outcome
+                }
+            }
+            ResolveWithoutDisputePolicy::ParkForReview => {
+                Err(TransactionProcessingError::ParkedForReview {
+                    client: transaction.client_id,
+                    tx: transaction.transaction_id,
+                })
+            }
+        }
+    }
+
+    /// The cap on the client's total balance, if it has one: its own cap, else its tier's cap,
+    /// else the cap on every client
+    pub fn balance_cap(&self, client_id: ClientId) -> Option<f64> {
+        self.client_balance_caps
+            .get(&client_id)
+            .copied()
+            .or_else(|| {
+                self.client_tiers
+                    .policy(client_id)
+                    .and_then(|policy| policy.balance_cap)
+            })
+            .or(self.balance_cap)
+    }
+
+    pub fn balance_cap_policy(&self, client_id: ClientId) -> BalanceCapPolicy {
+        self.client_tiers
+            .policy(client_id)
+            .and_then(|policy| policy.balance_cap_exceeded)
+            .unwrap_or(self.balance_cap_exceeded)
+    }
+
+    pub fn max_transactions_for_client(&self, client_id: ClientId) -> Option<usize> {
+        self.client_tiers
+            .policy(client_id)
+            .and_then(|policy| policy.max_transactions_per_client)
+            .or(self.max_transactions_per_client)
+    }
+
+    /// Whether the error should stop processing rather than just rejecting the transaction
+    pub fn is_fatal(&self, error: &TransactionProcessingError) -> bool {
+        match error {
+            TransactionProcessingError::HeldBalanceUnderflow { .. } => {
+                self.held_balance_underflow == HeldBalanceUnderflowPolicy::Abort
+            }
+            TransactionProcessingError::TransactionLimitExceeded { .. }
+            | TransactionProcessingError::ClientLimitExceeded { .. } => {
+                self.limit_exceeded == LimitPolicy::Abort
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use crate::client_account::error::TransactionProcessingError;
+use crate::serializable_form;
+use crate::unknown_types::UnknownTypeRow;
+
+/// Receives every transaction which was rejected, along with the reason.
+pub trait RejectionSink {
+    fn record_rejection(
+        &mut self,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()>;
+
+    /// Receives every rejected transaction with the input line it was read from, where known (not
+    /// for parked dispute ops, nor arbitration outcomes). Defaults to `record_rejection`.
+    fn record_rejection_at(
+        &mut self,
+        _line: Option<u64>,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        self.record_rejection(transaction, error)
+    }
+
+    /// Receives each row dead-lettered for an unknown type, which is not a transaction
+    fn record_dead_letter(&mut self, _row: &UnknownTypeRow) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use crate::client_account::error::TransactionProcessingError;
+use crate::rejection::RejectionSink;
+use crate::serializable_form;
+
+/// Whether a rejected transaction may succeed in a later batch, once the data it is missing
+/// (the referenced transaction or the client's account) has arrived.
+pub fn is_retryable(error: &TransactionProcessingError) -> bool {
+    matches!(
+        error,
+        TransactionProcessingError::ReferencedTransactionNotFound { .. }
+            | TransactionProcessingError::ClientNotFound { .. }
+    )
+}
+
+/// Writes retryable rejected transactions in the input format, so the file can be fed into the
+/// next batch run as is.
+pub struct RetryWriter<W: std::io::Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: std::io::Write> RetryWriter<W> {
+    pub fn new(output_stream: W) -> anyhow::Result<Self> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(output_stream);
+        writer.write_record(["type", "client", "tx", "amount", "counterparty"])?;
+        Ok(Self { writer })
+    }
+}
+
+impl<W: std::io::Write> RejectionSink for RetryWriter<W> {
+    fn record_rejection(
+        &mut self,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        if is_retryable(error) {
+            self.writer.serialize(transaction)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionType;
+
+    #[test]
+    fn test_only_retryable_rejections_are_written_in_the_input_format() {
+        let mut output = Vec::<u8>::new();
+        {
+            let mut retry_writer = RetryWriter::new(&mut output).unwrap();
+            retry_writer
+                .record_rejection(
+                    &serializable_form::Transaction {
+                        transaction_type: TransactionType::Dispute,
+                        client_id: 1,
+                        transaction_id: 7,
+                        amount: None,
+                        counterparty: None,
+                    },
+                    &TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 7 },
+                )
+                .unwrap();
+            retry_writer
+                .record_rejection(
+                    &serializable_form::Transaction {
+                        transaction_type: TransactionType::Deposit,
+                        client_id: 1,
+                        transaction_id: 1,
+                        amount: Some(1.5),
+                        counterparty: None,
+                    },
+                    &TransactionProcessingError::TransactionIDAlreadyExists { client: 1, tx: 1 },
+                )
+                .unwrap();
+            retry_writer
+                .record_rejection(
+                    &serializable_form::Transaction {
+                        transaction_type: TransactionType::Withdrawal,
+                        client_id: 9,
+                        transaction_id: 8,
+                        amount: Some(2.25),
+                        counterparty: None,
+                    },
+                    &TransactionProcessingError::ClientNotFound { client: 9, tx: 8 },
+                )
+                .unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "type,client,tx,amount,counterparty\ndispute,1,7,,\nwithdrawal,9,8,2.25,\n"
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use crate::client_account::error::TransactionProcessingError;
+use crate::rejection::RejectionSink;
+use crate::serializable_form;
+
+/// Writes the transactions parked for review in the input format, so that once reviewed they
+/// can be fed into a later batch run as is.
+pub struct ReviewWriter<W: std::io::Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: std::io::Write> ReviewWriter<W> {
+    pub fn new(output_stream: W) -> anyhow::Result<Self> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(output_stream);
+        writer.write_record(["type", "client", "tx", "amount", "counterparty"])?;
+        Ok(Self { writer })
+    }
+}
+
+impl<W: std::io::Write> RejectionSink for ReviewWriter<W> {
+    fn record_rejection(
+        &mut self,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        if let TransactionProcessingError::ParkedForReview { .. } = error {
+            self.writer.serialize(transaction)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,341 @@
+use serde_json::json;
+
+/// Version of the output columns. Version 1 is exactly `client,available,held,total,locked`
+/// and never changes; new columns only appear in later versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaVersion {
+    #[default]
+    V1,
+    /// Version 1's columns, followed by the optional column groups the output options ask for
+    V2,
+}
+
+pub const LATEST_SCHEMA_VERSION: SchemaVersion = SchemaVersion::V2;
+
+/// The optional column groups of version 2, in the order they are written
+pub const OPTIONAL_COLUMN_GROUPS: [&[&str]; 3] = [
+    &["flags", "notes"],
+    &["lock_tx", "lock_case"],
+    &["chargebacks", "charged_back", "closed"],
+];
+
+impl SchemaVersion {
+    pub fn number(&self) -> u32 {
+        match self {
+            SchemaVersion::V1 => 1,
+            SchemaVersion::V2 => 2,
+        }
+    }
+
+    /// Whether the version has the optional column groups
+    pub fn has_optional_columns(&self) -> bool {
+        match self {
+            SchemaVersion::V1 => false,
+            SchemaVersion::V2 => true,
+        }
+    }
+}
+
+impl std::fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.number())
+    }
+}
+
+impl std::str::FromStr for SchemaVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" => Ok(SchemaVersion::V1),
+            "2" => Ok(SchemaVersion::V2),
+            _ => Err(format!(
+                "unknown output schema version: {} (latest is {})",
+                s, LATEST_SCHEMA_VERSION
+            )),
+        }
+    }
+}
+
+/// Which contract a schema describes: the transactions read or the accounts written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaTarget {
+    Input,
+    Output,
+}
+
+impl std::str::FromStr for SchemaTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "input" => Ok(SchemaTarget::Input),
+            "output" => Ok(SchemaTarget::Output),
+            _ => Err(format!("unknown schema target: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaFormat {
+    JsonSchema,
+    // The JSON form of an Arrow schema, as used by Arrow's integration tests
+    Arrow,
+}
+
+impl std::str::FromStr for SchemaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonschema" => Ok(SchemaFormat::JsonSchema),
+            "arrow" => Ok(SchemaFormat::Arrow),
+            _ => Err(format!("unknown schema format: {}", s)),
+        }
+    }
+}
+
+/// JSON Schema for one row of the transaction input
+pub fn input_json_schema() -> serde_json::Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "rs_bpt input row",
+        "type": "object",
+        "properties": {
+            "type": {
+                "type": "string",
+                "enum": [
+                    "deposit",
+                    "withdrawal",
+                    "dispute",
+                    "resolve",
+                    "chargeback",
+                    "open_account",
+                    "close_account",
+                    "transfer",
+                    "representment",
+                    "final_chargeback",
+                    "representment_won",
+                ],
+            },
+            "client": { "type": "integer", "minimum": 0, "maximum": u16::MAX },
+            "tx": { "type": "integer", "minimum": 0, "maximum": u32::MAX },
+            "amount": { "type": ["number", "null"] },
+            "counterparty": { "type": ["integer", "null"], "minimum": 0, "maximum": u16::MAX },
+        },
+        "required": ["type", "client", "tx"],
+        "x-column-order": ["type", "client", "tx", "amount"],
+        "additionalProperties": false,
+    })
+}
+
+fn arrow_field(name: &str, data_type: serde_json::Value, nullable: bool) -> serde_json::Value {
+    json!({
+        "name": name,
+        "type": data_type,
+        "nullable": nullable,
+        "children": [],
+    })
+}
+
+/// Arrow schema for the transaction input
+pub fn input_arrow_schema() -> serde_json::Value {
+    json!({
+        "fields": [
+            arrow_field("type", json!({ "name": "utf8" }), false),
+            arrow_field("client", json!({ "name": "int", "bitWidth": 16, "isSigned": false }), false),
+            arrow_field("tx", json!({ "name": "int", "bitWidth": 32, "isSigned": false }), false),
+            arrow_field("amount", json!({ "name": "floatingpoint", "precision": "DOUBLE" }), true),
+            arrow_field("counterparty", json!({ "name": "int", "bitWidth": 16, "isSigned": false }), true),
+        ],
+    })
+}
+
+/// Arrow schema for the output in the given schema version. Amounts stay strings, as written.
+/// The optional columns of version 2 are nullable, as a group is only written when asked for.
+pub fn output_arrow_schema(version: SchemaVersion) -> serde_json::Value {
+    let mut fields = vec![
+        arrow_field(
+            "client",
+            json!({ "name": "int", "bitWidth": 16, "isSigned": false }),
+            false,
+        ),
+        arrow_field("available", json!({ "name": "utf8" }), false),
+        arrow_field("held", json!({ "name": "utf8" }), false),
+        arrow_field("total", json!({ "name": "utf8" }), false),
+        arrow_field("locked", json!({ "name": "bool" }), false),
+    ];
+    if version.has_optional_columns() {
+        fields.extend([
+            arrow_field("flags", json!({ "name": "utf8" }), true),
+            arrow_field("notes", json!({ "name": "utf8" }), true),
+            arrow_field(
+                "lock_tx",
+                json!({ "name": "int", "bitWidth": 32, "isSigned": false }),
+                true,
+            ),
+            arrow_field("lock_case", json!({ "name": "utf8" }), true),
+            arrow_field(
+                "chargebacks",
+                json!({ "name": "int", "bitWidth": 64, "isSigned": false }),
+                true,
+            ),
+            arrow_field("charged_back", json!({ "name": "utf8" }), true),
+            arrow_field("closed", json!({ "name": "bool" }), true),
+        ]);
+    }
+    json!({
+        "fields": fields,
+        "metadata": [{ "key": "rs_bpt.schema_version", "value": version.to_string() }],
+    })
+}
+
+/// JSON Schema for one row of the output, in the given schema version
+pub fn output_json_schema(version: SchemaVersion) -> serde_json::Value {
+    let amount = json!({ "type": "string", "pattern": "^-?[0-9]+\\.[0-9]{4}$" });
+    let mut schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "rs_bpt output row",
+        "x-schema-version": version.number(),
+        "type": "object",
+        "properties": {
+            "client": { "type": "integer", "minimum": 0, "maximum": u16::MAX },
+            "available": amount,
+            "held": amount,
+            "total": amount,
+            "locked": { "type": "boolean" },
+        },
+        "required": ["client", "available", "held", "total", "locked"],
+        "x-column-order": ["client", "available", "held", "total", "locked"],
+        "additionalProperties": false,
+    });
+    if version.has_optional_columns() {
+        let properties = schema["properties"].as_object_mut().expect("an object");
+        properties.extend([
+            ("flags".to_string(), json!({ "type": "string" })),
+            ("notes".to_string(), json!({ "type": "string" })),
+            (
+                "lock_tx".to_string(),
+                json!({ "type": ["integer", "null"], "minimum": 0, "maximum": u32::MAX }),
+            ),
+            (
+                "lock_case".to_string(),
+                json!({ "type": ["string", "null"] }),
+            ),
+            (
+                "chargebacks".to_string(),
+                json!({ "type": "integer", "minimum": 0 }),
+            ),
+            ("charged_back".to_string(), amount),
+            ("closed".to_string(), json!({ "type": "boolean" })),
+        ]);
+        let column_order = schema["x-column-order"].as_array_mut().expect("an array");
+        column_order.extend(
+            OPTIONAL_COLUMN_GROUPS
+                .iter()
+                .flat_map(|group| group.iter().map(|column| json!(column))),
+        );
+        schema["x-optional-column-groups"] = json!(OPTIONAL_COLUMN_GROUPS);
+    }
+    schema
+}
+
+pub fn write_schema(
+    target: SchemaTarget,
+    version: SchemaVersion,
+    format: SchemaFormat,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let schema = match (target, format) {
+        (SchemaTarget::Input, SchemaFormat::JsonSchema) => input_json_schema(),
+        (SchemaTarget::Input, SchemaFormat::Arrow) => input_arrow_schema(),
+        (SchemaTarget::Output, SchemaFormat::JsonSchema) => output_json_schema(version),
+        (SchemaTarget::Output, SchemaFormat::Arrow) => output_arrow_schema(version),
+    };
+    serde_json::to_writer_pretty(&mut *output_stream, &schema)?;
+    writeln!(output_stream)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_describes_the_v1_columns_in_order() {
+        let schema = output_json_schema(SchemaVersion::V1);
+        assert_eq!(schema["x-schema-version"], 1);
+        assert_eq!(
+            schema["x-column-order"],
+            json!(["client", "available", "held", "total", "locked"])
+        );
+        assert_eq!(schema["properties"]["locked"]["type"], "boolean");
+    }
+
+    #[test]
+    fn it_describes_the_input_columns() {
+        let schema = input_json_schema();
+        assert_eq!(
+            schema["x-column-order"],
+            json!(["type", "client", "tx", "amount"])
+        );
+        assert_eq!(schema["properties"]["type"]["enum"][4], "chargeback");
+
+        let arrow_schema = input_arrow_schema();
+        assert_eq!(arrow_schema["fields"][3]["name"], "amount");
+        assert_eq!(arrow_schema["fields"][3]["nullable"], true);
+    }
+
+    #[test]
+    fn it_describes_the_output_columns_as_arrow_fields() {
+        let arrow_schema = output_arrow_schema(SchemaVersion::V1);
+        let names: Vec<&str> = arrow_schema["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["client", "available", "held", "total", "locked"]);
+        assert_eq!(arrow_schema["metadata"][0]["value"], "1");
+    }
+
+    #[test]
+    fn it_describes_the_v2_optional_columns_after_the_v1_columns() {
+        let schema = output_json_schema(SchemaVersion::V2);
+        assert_eq!(schema["x-schema-version"], 2);
+        assert_eq!(
+            schema["x-column-order"],
+            json!([
+                "client",
+                "available",
+                "held",
+                "total",
+                "locked",
+                "flags",
+                "notes",
+                "lock_tx",
+                "lock_case",
+                "chargebacks",
+                "charged_back",
+                "closed"
+            ])
+        );
+        assert_eq!(schema["required"].as_array().unwrap().len(), 5);
+        assert_eq!(
+            schema["x-optional-column-groups"][1],
+            json!(["lock_tx", "lock_case"])
+        );
+
+        let arrow_schema = output_arrow_schema(SchemaVersion::V2);
+        assert_eq!(arrow_schema["fields"].as_array().unwrap().len(), 12);
+        assert_eq!(arrow_schema["fields"][7]["name"], "lock_tx");
+        assert_eq!(arrow_schema["fields"][7]["nullable"], true);
+    }
+
+    #[test]
+    fn it_rejects_unknown_schema_versions() {
+        assert_eq!("1".parse::<SchemaVersion>(), Ok(SchemaVersion::V1));
+        assert_eq!("2".parse::<SchemaVersion>(), Ok(SchemaVersion::V2));
+        assert!("3".parse::<SchemaVersion>().is_err());
+    }
+}
@@ -2,7 +2,10 @@ use anyhow::anyhow;
 use rust_decimal::prelude::*;
 use rust_decimal::Decimal;
 use serde_derive::{Deserialize, Serialize};
+use std::fmt::Write;
 
+use crate::client_account::lock_reason::LockReason;
+use crate::output_options::BoolFormat;
 use crate::{ClientAccount, ClientId, TransactionId, TransactionType};
 
 #[derive(Debug, Serialize)]
@@ -12,6 +15,10 @@ pub struct Output {
     pub held: String,
     pub total: String,
     pub locked: bool,
+    pub lock_reason: Option<LockReason>,
+    pub chargebacks: usize,
+    pub charged_back: String,
+    pub closed: bool,
 }
 
 impl Output {
@@ -22,11 +29,35 @@ impl Output {
             held: round_f64_4dp_string(client_account.balance.held)?,
             total: round_f64_4dp_string(client_account.balance.total())?,
             locked: client_account.locked,
+            lock_reason: client_account.lock_reason.clone(),
+            chargebacks: client_account.chargebacks,
+            charged_back: round_f64_4dp_string(client_account.charged_back)?,
+            closed: client_account.closed,
         })
     }
+
+    pub fn row(&self, bool_format: BoolFormat) -> OutputRow<'_> {
+        OutputRow {
+            client: self.client,
+            available: &self.available,
+            held: &self.held,
+            total: &self.total,
+            locked: bool_format.format(self.locked),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// An output line as written, with the booleans already formatted
+#[derive(Debug, Serialize)]
+pub struct OutputRow<'a> {
+    pub client: ClientId,
+    pub available: &'a str,
+    pub held: &'a str,
+    pub total: &'a str,
+    pub locked: &'static str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Transaction {
     #[serde(rename = "type")]
     pub transaction_type: TransactionType,
@@ -38,12 +69,18 @@ pub struct Transaction {
     pub transaction_id: TransactionId,
 
     pub amount: Option<f64>,
+
+    /// The client a transfer credits. Only read for transfers, and optional in the input.
+    pub counterparty: Option<ClientId>,
 }
 
 /// Round an f64 to a Decimal using "Banker's Rounding" with max 4 decimal places and represent it as a String
-fn round_f64_4dp_string(x: f64) -> anyhow::Result<String> {
+pub(crate) fn round_f64_4dp_string(x: f64) -> anyhow::Result<String> {
     let d =
         Decimal::from_f64(x).ok_or_else(|| anyhow!("Failed to represent f64 as Decimal: {}", x))?;
     let rounded_decimal = d.round_dp(4);
-    Ok(format!("{:.4}", rounded_decimal))
+    // room for the sign, 15 integer digits, the point and 4 decimals without reallocating
+    let mut formatted = String::with_capacity(21);
+    write!(formatted, "{:.4}", rounded_decimal)?;
+    Ok(formatted)
 }
@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+
+use crate::dates::Date;
+use crate::netting::Netting;
+use crate::serializable_form::round_f64_4dp_string;
+
+const PLACEHOLDERS: [&str; 4] = ["client", "direction", "amount", "value_date"];
+
+/// A line template with `{client}`, `{direction}`, `{amount}` and `{value_date}` placeholders.
+/// `{{` and `}}` are literal braces.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SettlementTemplate(String);
+
+impl Default for SettlementTemplate {
+    fn default() -> Self {
+        Self("{client},{direction},{amount},{value_date}".to_string())
+    }
+}
+
+impl std::str::FromStr for SettlementTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let template = Self(s.to_string());
+        // rendering fails on an unknown placeholder or an unbalanced brace
+        template.render(0, "credit", "0.0000", Date::from_days_since_epoch(0))?;
+        Ok(template)
+    }
+}
+
+impl SettlementTemplate {
+    pub fn render(
+        &self,
+        client: crate::ClientId,
+        direction: &str,
+        amount: &str,
+        value_date: Date,
+    ) -> Result<String, String> {
+        let mut line = String::new();
+        let mut chars = self.0.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    line.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    line.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("unbalanced '{' in settlement template".to_string()),
+                        }
+                    }
+                    match name.as_str() {
+                        "client" => line.push_str(&client.to_string()),
+                        "direction" => line.push_str(direction),
+                        "amount" => line.push_str(amount),
+                        "value_date" => line.push_str(&value_date.to_string()),
+                        _ => {
+                            return Err(format!(
+                            "unknown settlement template placeholder {{{}}} (expected one of {})",
+                            name,
+                            PLACEHOLDERS.join(", ")
+                        ))
+                        }
+                    }
+                }
+                '}' => return Err("unbalanced '}' in settlement template".to_string()),
+                c => line.push(c),
+            }
+        }
+        Ok(line)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SettlementOptions {
+    pub file: PathBuf,
+    /// Only clients whose absolute net movement exceeds this are settled
+    pub threshold: f64,
+    pub template: SettlementTemplate,
+    /// Defaults to today
+    pub value_date: Option<Date>,
+}
+
+/// Writes one settlement instruction per client whose net movement exceeds the threshold, in
+/// client id order. A positive net movement is paid out to the client (credit), a negative one
+/// is collected from the client (debit). The amount is always positive.
+pub fn write_settlement_file(
+    netting: &Netting,
+    threshold: f64,
+    template: &SettlementTemplate,
+    value_date: Date,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    for (client_id, client_netting) in netting.clients() {
+        let net_movement = client_netting.net_movement;
+        if net_movement.abs() <= threshold {
+            continue;
+        }
+        let direction = if net_movement < 0.0 {
+            "debit"
+        } else {
+            "credit"
+        };
+        let amount = round_f64_4dp_string(net_movement.abs())?;
+        let line = template
+            .render(*client_id, direction, &amount, value_date)
+            .map_err(anyhow::Error::msg)?;
+        writeln!(output_stream, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::{JournalEntry, JournalSink};
+    use crate::TransactionType;
+
+    #[test]
+    fn it_validates_templates() {
+        assert!("{client}|{{{direction}}}"
+            .parse::<SettlementTemplate>()
+            .is_ok());
+        assert!("{client}|{currency}".parse::<SettlementTemplate>().is_err());
+        assert!("{client".parse::<SettlementTemplate>().is_err());
+        assert!("client}".parse::<SettlementTemplate>().is_err());
+    }
+
+    #[test]
+    fn it_writes_instructions_above_the_threshold() {
+        let mut netting = Netting::default();
+        for entry in [
+            JournalEntry::new(1, 1, TransactionType::Deposit, 100.0),
+            JournalEntry::new(2, 2, TransactionType::Deposit, 1.0),
+            JournalEntry::new(3, 3, TransactionType::Withdrawal, -20.5),
+        ] {
+            netting.record(&entry).unwrap();
+        }
+        let template: SettlementTemplate = "{value_date} {{{client}}}".parse().unwrap();
+
+        let mut settlement = Vec::<u8>::new();
+        write_settlement_file(
+            &netting,
+            10.0,
+            &SettlementTemplate::default(),
+            "2022-03-01".parse().unwrap(),
+            &mut settlement,
+        )
+        .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&settlement).unwrap(),
+            "1,credit,100.0000,2022-03-01\n3,debit,20.5000,2022-03-01\n"
+        );
+
+        let mut settlement = Vec::<u8>::new();
+        write_settlement_file(
+            &netting,
+            0.0,
+            &template,
+            "2022-03-01".parse().unwrap(),
+            &mut settlement,
+        )
+        .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&settlement).unwrap(),
+            "2022-03-01 {1}\n2022-03-01 {2}\n2022-03-01 {3}\n"
+        );
+    }
+}
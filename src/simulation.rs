@@ -0,0 +1,321 @@
+use serde_derive::Serialize;
+use std::path::Path;
+
+use crate::control_totals::Crc32Writer;
+use crate::hashing::Accounts;
+use crate::manifest::{self, FileDigest, RunManifest, StreamDigest};
+use crate::processing_options::ProcessingOptions;
+use crate::serializable_form;
+use crate::{process_transaction_isolated, ClientId, TransactionId, TransactionType};
+
+/// An anomaly injected into a clean input.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anomaly {
+    /// A deposit or withdrawal repeated with the same transaction id
+    DuplicateTransactionId,
+    /// A deposit or withdrawal with its amount removed
+    MissingAmount,
+    /// A dispute of a transaction id which is not in the input
+    OrphanDispute,
+    /// A row swapped with the one after it
+    OutOfOrder,
+}
+
+/// The probability of injecting each anomaly at each row of the input.
+#[derive(Debug, Default, Clone)]
+pub struct SimulationOptions {
+    pub duplicate_rate: f64,
+    pub missing_amount_rate: f64,
+    pub orphan_dispute_rate: f64,
+    pub out_of_order_rate: f64,
+    /// Seeds the random choice of rows, so a simulation can be repeated exactly
+    pub seed: u64,
+}
+
+/// How the processor handled an injected anomaly.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SimulationOutcome {
+    pub row: usize,
+    pub anomaly: Anomaly,
+    #[serde(rename = "type")]
+    pub transaction_type: TransactionType,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub outcome: String,
+}
+
+// SplitMix64, which is plenty for picking rows and keeps simulations reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// True with probability `rate`
+    fn chance(&mut self, rate: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < rate
+    }
+}
+
+/// Injects anomalies into the transactions at the configured rates, returning each row with
+/// the anomaly injected at it, if any.
+pub fn inject_anomalies(
+    transactions: Vec<serializable_form::Transaction>,
+    options: &SimulationOptions,
+) -> Vec<(serializable_form::Transaction, Option<Anomaly>)> {
+    let mut rng = Rng(options.seed);
+    let mut orphan_transaction_id = transactions
+        .iter()
+        .map(|transaction| transaction.transaction_id)
+        .max()
+        .unwrap_or(0);
+
+    let mut rows = Vec::with_capacity(transactions.len());
+    for mut transaction in transactions {
+        let is_deposit_or_withdrawal = matches!(
+            transaction.transaction_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        );
+
+        if is_deposit_or_withdrawal && rng.chance(options.missing_amount_rate) {
+            transaction.amount = None;
+            rows.push((transaction, Some(Anomaly::MissingAmount)));
+            continue;
+        }
+
+        let client_id = transaction.client_id;
+        if is_deposit_or_withdrawal && rng.chance(options.duplicate_rate) {
+            rows.push((transaction.clone(), None));
+            rows.push((transaction, Some(Anomaly::DuplicateTransactionId)));
+        } else {
+            rows.push((transaction, None));
+        }
+
+        if rng.chance(options.orphan_dispute_rate) {
+            orphan_transaction_id = orphan_transaction_id.wrapping_add(1);
+            rows.push((
+                serializable_form::Transaction {
+                    transaction_type: TransactionType::Dispute,
+                    client_id,
+                    transaction_id: orphan_transaction_id,
+                    amount: None,
+                    counterparty: None,
+                },
+                Some(Anomaly::OrphanDispute),
+            ));
+        }
+    }
+
+    let mut row = 0;
+    while row + 1 < rows.len() {
+        if rng.chance(options.out_of_order_rate) {
+            rows.swap(row, row + 1);
+            rows[row + 1].1.get_or_insert(Anomaly::OutOfOrder);
+            row += 2;
+        } else {
+            row += 1;
+        }
+    }
+
+    rows
+}
+
+/// Processes the input with anomalies injected, in file order, and returns how each injected
+/// anomaly was handled.
+pub fn simulate(
+    input_transactions_file: &Path,
+    options: &SimulationOptions,
+    processing_options: &ProcessingOptions,
+    debug_logger: &mut dyn std::io::Write,
+) -> anyhow::Result<Vec<SimulationOutcome>> {
+    let mut reader = csv::Reader::from_path(input_transactions_file)?;
+    processing_options.column_mapping.apply_to(&mut reader)?;
+    let headers = reader.headers()?.clone();
+    let type_index = headers.iter().position(|header| header.trim() == "type");
+    let transactions = reader
+        .records()
+        .map(|record| {
+            processing_options
+                .type_aliases
+                .apply(record?, type_index)
+                .deserialize(Some(&headers))
+        })
+        .collect::<Result<Vec<serializable_form::Transaction>, _>>()?;
+
+    let mut accounts = Accounts::default();
+    let mut outcomes = Vec::new();
+    for (row, (transaction, anomaly)) in inject_anomalies(transactions, options)
+        .into_iter()
+        .enumerate()
+    {
+        let outcome = process_transaction_isolated(
+            &mut accounts,
+            &transaction,
+            processing_options,
+            debug_logger,
+        );
+        if let Some(anomaly) = anomaly {
+            outcomes.push(SimulationOutcome {
+                row,
+                anomaly,
+                transaction_type: transaction.transaction_type,
+                client: transaction.client_id,
+                tx: transaction.transaction_id,
+                outcome: match outcome {
+                    Ok(_) => "applied".to_string(),
+                    Err(error) => error.to_string(),
+                },
+            });
+        }
+    }
+    Ok(outcomes)
+}
+
+pub fn write_report(
+    outcomes: &[SimulationOutcome],
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(output_stream);
+    for outcome in outcomes {
+        writer.serialize(outcome)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Runs the simulation and writes its report, then the manifest of the run to `manifest_file` if
+/// given, with the seed so that the simulation can be repeated exactly.
+pub fn run(
+    input_transactions_file: &Path,
+    options: &SimulationOptions,
+    processing_options: &ProcessingOptions,
+    manifest_file: Option<&Path>,
+    output_stream: &mut dyn std::io::Write,
+    debug_logger: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let started_at = manifest::unix_timestamp();
+    let outcomes = simulate(
+        input_transactions_file,
+        options,
+        processing_options,
+        debug_logger,
+    )?;
+    let mut crc32_writer = Crc32Writer::new(output_stream);
+    write_report(&outcomes, &mut crc32_writer)?;
+
+    if let Some(manifest_file) = manifest_file {
+        let run_manifest = RunManifest {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            config: format!("{:?}, {:?}", options, processing_options),
+            started_at,
+            finished_at: manifest::unix_timestamp(),
+            inputs: vec![FileDigest::of_file(input_transactions_file)?],
+            outputs: Vec::new(),
+            stdout: StreamDigest {
+                bytes: crc32_writer.bytes_written(),
+                crc32: format!("{:08x}", crc32_writer.crc32()),
+            },
+            summary: None,
+            seed: Some(options.seed),
+        };
+        run_manifest.write_json(&mut std::fs::File::create(manifest_file)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transactions() -> Vec<serializable_form::Transaction> {
+        vec![
+            serializable_form::Transaction {
+                transaction_type: TransactionType::Deposit,
+                client_id: 1,
+                transaction_id: 1,
+                amount: Some(1.0),
+                counterparty: None,
+            },
+            serializable_form::Transaction {
+                transaction_type: TransactionType::Withdrawal,
+                client_id: 1,
+                transaction_id: 2,
+                amount: Some(0.5),
+                counterparty: None,
+            },
+        ]
+    }
+
+    fn anomalies(options: &SimulationOptions) -> Vec<(TransactionId, Option<Anomaly>)> {
+        inject_anomalies(transactions(), options)
+            .into_iter()
+            .map(|(transaction, anomaly)| (transaction.transaction_id, anomaly))
+            .collect()
+    }
+
+    #[test]
+    fn test_inject_anomalies() {
+        assert_eq!(
+            anomalies(&SimulationOptions::default()),
+            vec![(1, None), (2, None)]
+        );
+        assert_eq!(
+            anomalies(&SimulationOptions {
+                duplicate_rate: 1.0,
+                ..Default::default()
+            }),
+            vec![
+                (1, None),
+                (1, Some(Anomaly::DuplicateTransactionId)),
+                (2, None),
+                (2, Some(Anomaly::DuplicateTransactionId)),
+            ]
+        );
+        assert_eq!(
+            anomalies(&SimulationOptions {
+                missing_amount_rate: 1.0,
+                ..Default::default()
+            }),
+            vec![
+                (1, Some(Anomaly::MissingAmount)),
+                (2, Some(Anomaly::MissingAmount)),
+            ]
+        );
+        assert_eq!(
+            anomalies(&SimulationOptions {
+                orphan_dispute_rate: 1.0,
+                ..Default::default()
+            }),
+            vec![
+                (1, None),
+                (3, Some(Anomaly::OrphanDispute)),
+                (2, None),
+                (4, Some(Anomaly::OrphanDispute)),
+            ]
+        );
+        assert_eq!(
+            anomalies(&SimulationOptions {
+                out_of_order_rate: 1.0,
+                ..Default::default()
+            }),
+            vec![(2, None), (1, Some(Anomaly::OutOfOrder))]
+        );
+    }
+
+    #[test]
+    fn test_the_same_seed_injects_the_same_anomalies() {
+        let options = SimulationOptions {
+            duplicate_rate: 0.5,
+            orphan_dispute_rate: 0.5,
+            seed: 7,
+            ..Default::default()
+        };
+        assert_eq!(anomalies(&options), anomalies(&options));
+    }
+}
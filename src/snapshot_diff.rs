@@ -0,0 +1,199 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::client_account::account_balance::{AccountBalance, BalanceDelta};
+use crate::journal::{JournalEntry, LedgerAccount};
+use crate::serializable_form::round_f64_4dp_string;
+use crate::{ClientId, TransactionId, TransactionType};
+
+/// Balance changes smaller than this are taken to be rounding in the 4 decimal place snapshots.
+const TOLERANCE: f64 = 0.00005;
+
+#[derive(Debug, Deserialize)]
+struct SnapshotRow {
+    client: ClientId,
+    available: f64,
+    held: f64,
+}
+
+/// Reads the balances from an output file.
+pub fn read_snapshot(
+    input_stream: impl std::io::Read,
+) -> anyhow::Result<HashMap<ClientId, AccountBalance>> {
+    let mut reader = csv::Reader::from_reader(input_stream);
+    let mut balances = HashMap::new();
+    for row in reader.deserialize() {
+        let row: SnapshotRow = row?;
+        balances.insert(
+            row.client,
+            AccountBalance {
+                available: row.available,
+                held: row.held,
+            },
+        );
+    }
+    Ok(balances)
+}
+
+/// Part of a client's balance change between two snapshots, either due to a transaction or,
+/// for whatever the journal does not account for, unexplained.
+#[derive(Debug, PartialEq)]
+pub struct BalanceChange {
+    pub client_id: ClientId,
+    pub transaction: Option<(TransactionId, TransactionType)>,
+    pub delta: BalanceDelta,
+}
+
+// How a journal entry changes its client's balances
+fn entry_delta(entry: &JournalEntry) -> BalanceDelta {
+    let mut delta = BalanceDelta::default();
+    for (ledger_account, amount) in [(entry.credit, entry.amount), (entry.debit, -entry.amount)] {
+        match ledger_account {
+            LedgerAccount::ClientAvailable(_) => delta.available += amount,
+            LedgerAccount::ClientHeld(_) => delta.held += amount,
+            LedgerAccount::Suspense | LedgerAccount::ChargebackExpense => {}
+        }
+    }
+    delta
+}
+
+/// Attributes each client's balance change from `before` to `after` to the journal entries
+/// between them, in client id order and journal order within a client.
+pub fn attribute_balance_changes(
+    before: &HashMap<ClientId, AccountBalance>,
+    after: &HashMap<ClientId, AccountBalance>,
+    journal_entries: &[JournalEntry],
+) -> Vec<BalanceChange> {
+    let client_ids: BTreeSet<ClientId> = before
+        .keys()
+        .chain(after.keys())
+        .copied()
+        .chain(journal_entries.iter().map(|entry| entry.client_id))
+        .collect();
+
+    let mut balance_changes = Vec::new();
+    for client_id in client_ids {
+        let mut explained = BalanceDelta::default();
+        for entry in journal_entries
+            .iter()
+            .filter(|entry| entry.client_id == client_id)
+        {
+            let delta = entry_delta(entry);
+            explained.available += delta.available;
+            explained.held += delta.held;
+            balance_changes.push(BalanceChange {
+                client_id,
+                transaction: Some((entry.transaction_id, entry.transaction_type)),
+                delta,
+            });
+        }
+
+        let balance_before = before.get(&client_id).copied().unwrap_or_default();
+        let balance_after = after.get(&client_id).copied().unwrap_or_default();
+        let actual = balance_before.diff(&balance_after);
+        let unexplained = BalanceDelta {
+            available: actual.available - explained.available,
+            held: actual.held - explained.held,
+        };
+        if !unexplained.within(TOLERANCE) {
+            balance_changes.push(BalanceChange {
+                client_id,
+                transaction: None,
+                delta: unexplained,
+            });
+        }
+    }
+    balance_changes
+}
+
+#[derive(Debug, Serialize)]
+struct SerializableBalanceChange {
+    client: ClientId,
+    tx: Option<TransactionId>,
+    reason: String,
+    available: String,
+    held: String,
+    total: String,
+}
+
+pub fn write_report(
+    balance_changes: &[BalanceChange],
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(output_stream);
+    for balance_change in balance_changes {
+        writer.serialize(SerializableBalanceChange {
+            client: balance_change.client_id,
+            tx: balance_change
+                .transaction
+                .map(|(transaction_id, _)| transaction_id),
+            reason: match balance_change.transaction {
+                Some((_, transaction_type)) => transaction_type.to_string(),
+                None => "unexplained".to_string(),
+            },
+            available: round_f64_4dp_string(balance_change.delta.available)?,
+            held: round_f64_4dp_string(balance_change.delta.held)?,
+            total: round_f64_4dp_string(balance_change.delta.total())?,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the snapshots and journal and writes the attribution report.
+pub fn explain(
+    before_file: &Path,
+    after_file: &Path,
+    journal_file: &Path,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let before = read_snapshot(std::fs::File::open(before_file)?)?;
+    let after = read_snapshot(std::fs::File::open(after_file)?)?;
+    let journal_entries = crate::journal::read_journal(std::fs::File::open(journal_file)?)?;
+    write_report(
+        &attribute_balance_changes(&before, &after, &journal_entries),
+        output_stream,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balance_changes_are_attributed_to_transactions() {
+        let before = read_snapshot(
+            "client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n2,5.0000,0.0000,5.0000,false\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let after = read_snapshot(
+            "client,available,held,total,locked\n1,7.0000,4.0000,11.0000,false\n2,4.0000,0.0000,4.0000,false\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let journal_entries = vec![
+            JournalEntry::new(1, 3, TransactionType::Deposit, 4.0),
+            JournalEntry::new(1, 4, TransactionType::Withdrawal, -3.0),
+            JournalEntry::new(1, 3, TransactionType::Dispute, 4.0),
+        ];
+
+        let mut output = Vec::<u8>::new();
+        write_report(
+            &attribute_balance_changes(&before, &after, &journal_entries),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"client,tx,reason,available,held,total
+1,3,deposit,4.0000,0.0000,4.0000
+1,4,withdrawal,-3.0000,0.0000,-3.0000
+1,3,dispute,-4.0000,4.0000,0.0000
+2,,unexplained,-1.0000,0.0000,-1.0000
+"#
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use serde_derive::Serialize;
+use std::collections::HashMap;
+
+use crate::client_account::account_balance::{AccountBalance, BalanceDelta};
+use crate::hashing::Accounts;
+use crate::serializable_form::round_f64_4dp_string;
+use crate::ClientId;
+
+/// Balance changes smaller than this are rounding in the 4 decimal place output.
+const TOLERANCE: f64 = 0.00005;
+
+/// Each client's balance and lock as loaded, before the run changes them
+#[derive(Debug, Default)]
+pub struct OpeningState {
+    accounts: HashMap<ClientId, (AccountBalance, bool)>,
+}
+
+impl OpeningState {
+    pub fn of(accounts: &Accounts) -> Self {
+        Self {
+            accounts: accounts
+                .values()
+                .map(|client_account| {
+                    (
+                        client_account.client_id,
+                        (client_account.balance, client_account.locked),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How a client's account changed in the run. A client new in the run changes from nothing.
+#[derive(Debug, PartialEq)]
+pub struct AccountDelta {
+    pub client_id: ClientId,
+    pub delta: BalanceDelta,
+    pub newly_locked: bool,
+}
+
+/// The accounts whose balance changed or which were locked in the run, in client id order
+pub fn account_deltas(opening_state: &OpeningState, accounts: &Accounts) -> Vec<AccountDelta> {
+    let mut account_deltas: Vec<AccountDelta> = accounts
+        .values()
+        .filter_map(|client_account| {
+            let (balance, locked) = opening_state
+                .accounts
+                .get(&client_account.client_id)
+                .copied()
+                .unwrap_or_default();
+            let account_delta = AccountDelta {
+                client_id: client_account.client_id,
+                delta: balance.diff(&client_account.balance),
+                newly_locked: client_account.locked && !locked,
+            };
+            (account_delta.newly_locked || !account_delta.delta.within(TOLERANCE))
+                .then_some(account_delta)
+        })
+        .collect();
+    account_deltas.sort_unstable_by_key(|account_delta| account_delta.client_id);
+    account_deltas
+}
+
+#[derive(Debug, Serialize)]
+struct SerializableAccountDelta {
+    client: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    newly_locked: bool,
+}
+
+pub fn write_report(
+    account_deltas: &[AccountDelta],
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    // the header is written explicitly so that a run which changed nothing still has one
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(output_stream);
+    csv_writer.write_record(["client", "available", "held", "total", "newly_locked"])?;
+    for account_delta in account_deltas {
+        csv_writer.serialize(SerializableAccountDelta {
+            client: account_delta.client_id,
+            available: round_f64_4dp_string(account_delta.delta.available)?,
+            held: round_f64_4dp_string(account_delta.delta.held)?,
+            total: round_f64_4dp_string(account_delta.delta.total())?,
+            newly_locked: account_delta.newly_locked,
+        })?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing_options::ProcessingOptions;
+    use crate::testing::{accounts_from, chargeback, deposit, dispute, withdrawal};
+
+    #[test]
+    fn it_reports_the_accounts_changed_since_the_opening_state() {
+        let options = ProcessingOptions::default();
+        let mut accounts = accounts_from(
+            &[deposit(1, 1, 5.0), deposit(2, 2, 1.0), deposit(3, 3, 2.0)],
+            &options,
+        );
+        let opening_state = OpeningState::of(&accounts);
+        for transaction in [
+            dispute(1, 1),
+            chargeback(1, 1),
+            withdrawal(2, 4, 0.5),
+            deposit(4, 5, 3.0),
+        ] {
+            crate::process_transaction(&mut accounts, &transaction, &options, &mut std::io::sink())
+                .unwrap();
+        }
+
+        let mut report = Vec::new();
+        write_report(&account_deltas(&opening_state, &accounts), &mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "client,available,held,total,newly_locked
+1,-5.0000,0.0000,-5.0000,true
+2,-0.5000,0.0000,-0.5000,false
+4,3.0000,0.0000,3.0000,false
+"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::path::Path;
+
+use crate::hashing::Accounts;
+use crate::{ClientAccount, ClientId};
+
+/// The version of the snapshot format written, which is the only version read
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    version: u32,
+    accounts: Vec<&'a ClientAccount>,
+    saved_at: u64,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    version: u32,
+    accounts: Vec<ClientAccount>,
+    /// When the snapshot was written, in seconds since the Unix epoch. Older snapshots have none.
+    #[serde(default)]
+    saved_at: Option<u64>,
+}
+
+/// Writes the state of every account, with its balances, lock and the transactions retained for
+/// disputes, as JSON in client id order, so that the next run can carry on from it. Account
+/// events and extensions are not included. The time it is written is recorded, to date the
+/// transactions retained since for `compaction`.
+pub fn write_snapshot<S: BuildHasher>(
+    accounts: &HashMap<ClientId, ClientAccount, S>,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let mut snapshot = SnapshotRef {
+        version: SNAPSHOT_VERSION,
+        accounts: accounts.values().collect(),
+        saved_at: crate::manifest::unix_timestamp(),
+    };
+    snapshot
+        .accounts
+        .sort_unstable_by_key(|client_account| client_account.client_id);
+    serde_json::to_writer(&mut *output_stream, &snapshot)?;
+    writeln!(output_stream)?;
+    Ok(())
+}
+
+/// Reads the accounts from a snapshot written by `write_snapshot`. The transactions it is the first
+/// to retain are stamped with the time it was written.
+pub fn read_snapshot(input_stream: impl std::io::Read) -> anyhow::Result<Accounts> {
+    let snapshot: Snapshot = serde_json::from_reader(input_stream)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        anyhow::bail!("unsupported snapshot version: {}", snapshot.version);
+    }
+    Ok(snapshot
+        .accounts
+        .into_iter()
+        .map(|mut client_account| {
+            if let Some(saved_at) = snapshot.saved_at {
+                client_account.stamp_retained_since(saved_at);
+            }
+            (client_account.client_id, client_account)
+        })
+        .collect())
+}
+
+/// Carries the accounts of a snapshot over into a new run, forgetting the settled transactions
+/// past the dispute retention (see `ClientAccount::carry_over`). Returns how many were forgotten.
+pub fn carry_over<S: BuildHasher>(
+    accounts: &mut HashMap<ClientId, ClientAccount, S>,
+    dispute_retention_runs: Option<u32>,
+) -> usize {
+    accounts
+        .values_mut()
+        .map(|client_account| client_account.carry_over(dispute_retention_runs))
+        .sum()
+}
+
+pub fn save_snapshot<S: BuildHasher>(
+    accounts: &HashMap<ClientId, ClientAccount, S>,
+    file: &Path,
+) -> anyhow::Result<()> {
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(file)?);
+    write_snapshot(accounts, &mut writer)?;
+    std::io::Write::flush(&mut writer)?;
+    Ok(())
+}
+
+pub fn load_snapshot(file: &Path) -> anyhow::Result<Accounts> {
+    read_snapshot(std::io::BufReader::new(std::fs::File::open(file)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_account;
+    use crate::client_account::client_account_transaction::ClientAccountTransaction;
+    use crate::processing_options::ProcessingOptions;
+    use crate::testing::{accounts_from, chargeback, deposit, dispute, resolve};
+    use crate::TransactionType;
+
+    fn apply(
+        client_account: &mut ClientAccount,
+        transaction_type: TransactionType,
+        transaction_id: crate::TransactionId,
+        amount: Option<f64>,
+    ) {
+        client_account
+            .process_client_transaction(
+                ClientAccountTransaction {
+                    transaction_type,
+                    transaction_id,
+                    amount,
+                },
+                &ProcessingOptions::default(),
+                &mut std::io::sink(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn it_restores_the_accounts_it_snapshots() {
+        let mut accounts = Accounts::default();
+        let mut client_account = ClientAccount::new(1);
+        apply(&mut client_account, TransactionType::Deposit, 1, Some(0.1));
+        apply(&mut client_account, TransactionType::Deposit, 2, Some(0.2));
+        apply(&mut client_account, TransactionType::Dispute, 2, None);
+        accounts.insert(1, client_account);
+        let mut client_account = ClientAccount::new(2);
+        apply(&mut client_account, TransactionType::Deposit, 3, Some(5.0));
+        apply(&mut client_account, TransactionType::Dispute, 3, None);
+        apply(&mut client_account, TransactionType::Chargeback, 3, None);
+        accounts.insert(2, client_account);
+
+        let mut snapshot = Vec::new();
+        write_snapshot(&accounts, &mut snapshot).unwrap();
+        let mut restored = read_snapshot(snapshot.as_slice()).unwrap();
+
+        let client_account = &restored[&1];
+        assert_eq!(client_account.balance, accounts[&1].balance);
+        assert_eq!(client_account.retained_transactions(), 2);
+        let client_account = &restored[&2];
+        assert!(client_account.locked);
+        assert_eq!(client_account.lock_reason, accounts[&2].lock_reason);
+        assert_eq!(client_account.chargebacks, 1);
+
+        // the restored dispute can be resolved
+        let client_account = restored.get_mut(&1).unwrap();
+        apply(client_account, TransactionType::Resolve, 2, None);
+        assert_eq!(client_account.balance.held, 0.0);
+        assert_eq!(client_account.balance.available, 0.1 + 0.2);
+    }
+
+    #[test]
+    fn it_forgets_settled_transactions_past_the_dispute_retention() {
+        let mut accounts = accounts_from(
+            &[
+                deposit(1, 1, 5.0),
+                deposit(1, 2, 3.0),
+                dispute(1, 2),
+                deposit(2, 3, 1.0),
+                dispute(2, 3),
+                chargeback(2, 3),
+            ],
+            &ProcessingOptions::default(),
+        );
+
+        assert_eq!(carry_over(&mut accounts, Some(1)), 0);
+        assert_eq!(accounts[&1].retained_transactions(), 2);
+        // the open dispute and the chargeback which may still be represented are kept
+        assert_eq!(carry_over(&mut accounts, Some(1)), 1);
+        assert!(!accounts[&1].has_transaction(1));
+        assert!(accounts[&1].has_transaction(2));
+        assert!(accounts[&2].has_transaction(3));
+        assert!(!accounts[&1].is_untouched());
+        assert_eq!(carry_over(&mut accounts, None), 0);
+
+        let options = ProcessingOptions::default();
+        let error = crate::process_transaction(
+            &mut accounts,
+            &dispute(1, 1),
+            &options,
+            &mut std::io::sink(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            crate::TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 1 }
+        );
+        crate::process_transaction(
+            &mut accounts,
+            &resolve(1, 2),
+            &options,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        assert_account!(accounts[&1], available = "8.0000", held = "0.0000");
+    }
+
+    #[test]
+    fn it_rejects_other_snapshot_versions() {
+        let error = read_snapshot(r#"{"version": 2, "accounts": []}"#.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "unsupported snapshot version: 2");
+    }
+}
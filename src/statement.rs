@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::dates::Date;
+use crate::hashing::Accounts;
+use crate::journal::{JournalEntry, LedgerAccount};
+use crate::serializable_form::round_f64_4dp_string;
+use crate::{ClientAccount, ClientId, TransactionId, TransactionType};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StatementFormat {
+    Qif,
+    Ofx,
+}
+
+impl StatementFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            StatementFormat::Qif => "qif",
+            StatementFormat::Ofx => "ofx",
+        }
+    }
+}
+
+impl std::str::FromStr for StatementFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "qif" => Ok(StatementFormat::Qif),
+            "ofx" => Ok(StatementFormat::Ofx),
+            _ => Err(format!("unknown statement format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatementOptions {
+    pub directory: PathBuf,
+    pub format: StatementFormat,
+    pub currency: String,
+}
+
+/// A movement of a client's available funds, positive when the client is credited.
+#[derive(Debug, PartialEq)]
+pub struct StatementLine {
+    pub transaction_id: TransactionId,
+    pub transaction_type: TransactionType,
+    pub amount: f64,
+}
+
+/// Groups the journal entries which move a client's available funds into statement lines per client.
+pub fn statement_lines_by_client(
+    journal_entries: &[JournalEntry],
+) -> BTreeMap<ClientId, Vec<StatementLine>> {
+    let mut lines_by_client = BTreeMap::<ClientId, Vec<StatementLine>>::new();
+    for entry in journal_entries {
+        let available = LedgerAccount::ClientAvailable(entry.client_id);
+        let amount = if entry.credit == available {
+            entry.amount
+        } else if entry.debit == available {
+            -entry.amount
+        } else {
+            continue;
+        };
+
+        lines_by_client
+            .entry(entry.client_id)
+            .or_default()
+            .push(StatementLine {
+                transaction_id: entry.transaction_id,
+                transaction_type: entry.transaction_type,
+                amount,
+            });
+    }
+    lines_by_client
+}
+
+pub fn write_qif_statement(
+    client_id: ClientId,
+    lines: &[StatementLine],
+    date: Date,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    writeln!(output_stream, "!Type:Bank")?;
+    for line in lines {
+        writeln!(output_stream, "D{}", date.to_us_string())?;
+        writeln!(output_stream, "T{}", round_f64_4dp_string(line.amount)?)?;
+        writeln!(output_stream, "N{}", line.transaction_id)?;
+        writeln!(output_stream, "P{}", line.transaction_type)?;
+        writeln!(
+            output_stream,
+            "Mclient {} tx {}",
+            client_id, line.transaction_id
+        )?;
+        writeln!(output_stream, "^")?;
+    }
+    Ok(())
+}
+
+pub fn write_ofx_statement(
+    client_account: &ClientAccount,
+    lines: &[StatementLine],
+    date: Date,
+    currency: &str,
+    output_stream: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let date = date.to_compact_string();
+
+    writeln!(
+        output_stream,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<?OFX OFXHEADER="200" VERSION="220" SECURITY="NONE" OLDFILEUID="NONE" NEWFILEUID="NONE"?>
+<OFX>
+<BANKMSGSRSV1>
+<STMTTRNRS>
+<TRNUID>{client}</TRNUID>
+<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>
+<STMTRS>
+<CURDEF>{currency}</CURDEF>
+<BANKACCTFROM><BANKID>rs_bpt</BANKID><ACCTID>{client}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>
+<BANKTRANLIST>
+<DTSTART>{date}</DTSTART><DTEND>{date}</DTEND>"#,
+        client = client_account.client_id,
+        currency = currency,
+        date = date,
+    )?;
+
+    for line in lines {
+        writeln!(
+            output_stream,
+            "<STMTTRN><TRNTYPE>{}</TRNTYPE><DTPOSTED>{}</DTPOSTED><TRNAMT>{}</TRNAMT><FITID>{}-{}</FITID><NAME>{}</NAME></STMTTRN>",
+            if line.amount < 0.0 { "DEBIT" } else { "CREDIT" },
+            date,
+            round_f64_4dp_string(line.amount)?,
+            line.transaction_id,
+            line.transaction_type,
+            line.transaction_type,
+        )?;
+    }
+
+    writeln!(
+        output_stream,
+        r#"</BANKTRANLIST>
+<LEDGERBAL><BALAMT>{total}</BALAMT><DTASOF>{date}</DTASOF></LEDGERBAL>
+<AVAILBAL><BALAMT>{available}</BALAMT><DTASOF>{date}</DTASOF></AVAILBAL>
+</STMTRS>
+</STMTTRNRS>
+</BANKMSGSRSV1>
+</OFX>"#,
+        total = round_f64_4dp_string(client_account.balance.total())?,
+        available = round_f64_4dp_string(client_account.balance.available)?,
+        date = date,
+    )?;
+
+    Ok(())
+}
+
+/// Writes one statement file per client with activity, named `client-<id>.<qif|ofx>`.
+pub fn write_statements(
+    accounts: &Accounts,
+    journal_entries: &[JournalEntry],
+    options: &StatementOptions,
+    date: Date,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(&options.directory)?;
+
+    for (client_id, lines) in statement_lines_by_client(journal_entries) {
+        let file_path = statement_file_path(&options.directory, client_id, options.format);
+        let mut statement_writer = std::fs::File::create(file_path)?;
+        match options.format {
+            StatementFormat::Qif => {
+                write_qif_statement(client_id, &lines, date, &mut statement_writer)?
+            }
+            StatementFormat::Ofx => write_ofx_statement(
+                &accounts[&client_id],
+                &lines,
+                date,
+                &options.currency,
+                &mut statement_writer,
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+fn statement_file_path(directory: &Path, client_id: ClientId, format: StatementFormat) -> PathBuf {
+    directory.join(format!("client-{}.{}", client_id, format.extension()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATE: Date = Date {
+        year: 2022,
+        month: 3,
+        day: 1,
+    };
+
+    fn journal_entries() -> Vec<JournalEntry> {
+        vec![
+            JournalEntry::new(1, 1, TransactionType::Deposit, 100.0),
+            JournalEntry::new(2, 2, TransactionType::Deposit, 5.0),
+            JournalEntry::new(1, 1, TransactionType::Dispute, 100.0),
+            JournalEntry::new(1, 1, TransactionType::Chargeback, 100.0),
+            JournalEntry::new(1, 3, TransactionType::Withdrawal, -25.0),
+        ]
+    }
+
+    #[test]
+    fn test_statement_lines_only_include_available_balance_movements() {
+        let lines_by_client = statement_lines_by_client(&journal_entries());
+
+        assert_eq!(lines_by_client.len(), 2);
+        let amounts: Vec<(TransactionType, f64)> = lines_by_client[&1]
+            .iter()
+            .map(|line| (line.transaction_type, line.amount))
+            .collect();
+        assert_eq!(
+            amounts,
+            vec![
+                (TransactionType::Deposit, 100.0),
+                (TransactionType::Dispute, -100.0),
+                (TransactionType::Withdrawal, -25.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_qif_statement() {
+        let lines_by_client = statement_lines_by_client(&journal_entries());
+        let mut output = Vec::<u8>::new();
+        write_qif_statement(2, &lines_by_client[&2], DATE, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"!Type:Bank
+D03/01/2022
+T5.0000
+N2
+Pdeposit
+Mclient 2 tx 2
+^
+"#
+        );
+    }
+
+    #[test]
+    fn test_write_ofx_statement() {
+        let mut client_account = ClientAccount::new(1);
+        client_account.balance.available = -25.0;
+
+        let lines_by_client = statement_lines_by_client(&journal_entries());
+        let mut output = Vec::<u8>::new();
+        write_ofx_statement(
+            &client_account,
+            &lines_by_client[&1],
+            DATE,
+            "USD",
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("<CURDEF>USD</CURDEF>"));
+        assert!(output.contains("<ACCTID>1</ACCTID>"));
+        assert!(output.contains("<STMTTRN><TRNTYPE>CREDIT</TRNTYPE><DTPOSTED>20220301</DTPOSTED><TRNAMT>100.0000</TRNAMT><FITID>1-deposit</FITID><NAME>deposit</NAME></STMTTRN>"));
+        assert!(output.contains("<TRNTYPE>DEBIT</TRNTYPE><DTPOSTED>20220301</DTPOSTED><TRNAMT>-25.0000</TRNAMT><FITID>3-withdrawal</FITID>"));
+        assert!(output.contains("<AVAILBAL><BALAMT>-25.0000</BALAMT>"));
+        assert!(output.ends_with("</OFX>\n"));
+    }
+}
@@ -0,0 +1,211 @@
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::journal::{JournalEntry, JournalSink};
+use crate::serializable_form::round_f64_4dp_string;
+use crate::{ClientId, TransactionType};
+
+/// Values are recorded to this many significant digits.
+const SIGNIFICANT_DIGITS: u32 = 3;
+
+/// Amounts are recorded in units of 0.0001.
+const AMOUNT_SCALE: f64 = 10_000.0;
+
+/// A histogram in the style of HdrHistogram: each value is recorded to 3 significant digits, so
+/// percentiles are within 0.1% of the true value, and the number of buckets grows with the
+/// range of the values rather than with how many there are.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    counts: BTreeMap<u64, u64>,
+    count: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        *self.counts.entry(bucket(value)).or_default() += 1;
+        self.count += 1;
+        self.max = self.max.max(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// The lowest value which at least `percentile` percent of the recorded values are at or
+    /// below, rounded down to 3 significant digits. Zero if nothing was recorded.
+    pub fn value_at_percentile(&self, percentile: f64) -> u64 {
+        let rank = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut cumulative = 0;
+        for (value, count) in &self.counts {
+            cumulative += count;
+            if cumulative >= rank {
+                return *value;
+            }
+        }
+        0
+    }
+}
+
+// Rounds the value down to SIGNIFICANT_DIGITS significant digits
+fn bucket(value: u64) -> u64 {
+    let digits = value.checked_ilog10().map_or(1, |log| log + 1);
+    if digits <= SIGNIFICANT_DIGITS {
+        return value;
+    }
+    let scale = 10u64.pow(digits - SIGNIFICANT_DIGITS);
+    value / scale * scale
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct AmountPercentiles {
+    pub count: u64,
+    pub p50: String,
+    pub p90: String,
+    pub p99: String,
+    pub max: String,
+}
+
+impl AmountPercentiles {
+    fn from_histogram(histogram: &Histogram) -> anyhow::Result<Self> {
+        let amount = |units: u64| round_f64_4dp_string(units as f64 / AMOUNT_SCALE);
+        Ok(Self {
+            count: histogram.count(),
+            p50: amount(histogram.value_at_percentile(50.0))?,
+            p90: amount(histogram.value_at_percentile(90.0))?,
+            p99: amount(histogram.value_at_percentile(99.0))?,
+            max: amount(histogram.max())?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct CountPercentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl CountPercentiles {
+    fn from_histogram(histogram: &Histogram) -> Self {
+        Self {
+            p50: histogram.value_at_percentile(50.0),
+            p90: histogram.value_at_percentile(90.0),
+            p99: histogram.value_at_percentile(99.0),
+            max: histogram.max(),
+        }
+    }
+}
+
+/// The percentiles written to the summary.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct MetricsSummary {
+    pub deposit_amounts: AmountPercentiles,
+    pub withdrawal_amounts: AmountPercentiles,
+    pub transactions_per_client: CountPercentiles,
+}
+
+/// Tracks the distribution of deposit and withdrawal amounts and of the number of transactions
+/// applied per client as transactions are applied, without keeping the transactions.
+#[derive(Debug, Default)]
+pub struct StreamingMetrics {
+    deposit_amounts: Histogram,
+    withdrawal_amounts: Histogram,
+    // client ids are 16 bit, so exact counts take no more room than a sketch would
+    transactions_per_client: HashMap<ClientId, u64>,
+}
+
+impl StreamingMetrics {
+    pub fn summary(&self) -> anyhow::Result<MetricsSummary> {
+        let mut transactions_per_client = Histogram::default();
+        for count in self.transactions_per_client.values() {
+            transactions_per_client.record(*count);
+        }
+        Ok(MetricsSummary {
+            deposit_amounts: AmountPercentiles::from_histogram(&self.deposit_amounts)?,
+            withdrawal_amounts: AmountPercentiles::from_histogram(&self.withdrawal_amounts)?,
+            transactions_per_client: CountPercentiles::from_histogram(&transactions_per_client),
+        })
+    }
+}
+
+impl JournalSink for StreamingMetrics {
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        let amount = (entry.amount * AMOUNT_SCALE).round() as u64;
+        match entry.transaction_type {
+            TransactionType::Deposit => self.deposit_amounts.record(amount),
+            TransactionType::Withdrawal => self.withdrawal_amounts.record(amount),
+            _ => {}
+        }
+        *self
+            .transactions_per_client
+            .entry(entry.client_id)
+            .or_default() += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_percentiles_are_within_three_significant_digits() {
+        let mut histogram = Histogram::default();
+        for value in 1..=100_000 {
+            histogram.record(value);
+        }
+
+        assert_eq!(histogram.count(), 100_000);
+        assert_eq!(histogram.value_at_percentile(50.0), 50_000);
+        assert_eq!(histogram.value_at_percentile(99.0), 99_000);
+        assert_eq!(histogram.max(), 100_000);
+        assert_eq!(bucket(123_456), 123_000);
+        assert_eq!(bucket(999), 999);
+        assert_eq!(Histogram::default().value_at_percentile(50.0), 0);
+    }
+
+    #[test]
+    fn test_streaming_metrics_summary() {
+        let mut metrics = StreamingMetrics::default();
+        for entry in [
+            JournalEntry::new(1, 1, TransactionType::Deposit, 10.0),
+            JournalEntry::new(1, 2, TransactionType::Deposit, 2.5),
+            JournalEntry::new(1, 1, TransactionType::Dispute, 10.0),
+            JournalEntry::new(2, 3, TransactionType::Withdrawal, -1.5),
+        ] {
+            metrics.record(&entry).unwrap();
+        }
+
+        assert_eq!(
+            metrics.summary().unwrap(),
+            MetricsSummary {
+                deposit_amounts: AmountPercentiles {
+                    count: 2,
+                    p50: "2.5000".to_string(),
+                    p90: "10.0000".to_string(),
+                    p99: "10.0000".to_string(),
+                    max: "10.0000".to_string(),
+                },
+                withdrawal_amounts: AmountPercentiles {
+                    count: 1,
+                    p50: "1.5000".to_string(),
+                    p90: "1.5000".to_string(),
+                    p99: "1.5000".to_string(),
+                    max: "1.5000".to_string(),
+                },
+                transactions_per_client: CountPercentiles {
+                    p50: 1,
+                    p90: 3,
+                    p99: 3,
+                    max: 3,
+                },
+            }
+        );
+    }
+}
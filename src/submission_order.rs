@@ -0,0 +1,443 @@
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::amount_parsing::{
+    normalize_amount, AmountFormat, AmountNormalization, NullAmountPolicy,
+};
+use crate::processing_options::ProcessingOptions;
+use crate::serializable_form;
+use crate::timestamps::TimestampParser;
+use crate::unknown_types::UnknownTypeRow;
+use crate::{ClientId, TransactionType};
+
+/// The column giving each row's time for `SubmissionOrder::PerClientTimestamp`.
+pub const TIMESTAMP_COLUMN: &str = "timestamp";
+
+/// The column giving each row's position for `SubmissionOrder::ExternalSequence`.
+pub const SEQUENCE_COLUMN: &str = "sequence";
+
+/// The client and tx of a row as written, e.g. `client 1, tx 7`, to say which transaction a row
+/// which can't be read is. A missing field is written as `?`.
+pub fn row_ids(headers: &csv::StringRecord, record: &csv::StringRecord) -> String {
+    let field = |column: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim() == column)
+            .and_then(|index| record.get(index))
+            .map_or("?", str::trim)
+            .to_string()
+    };
+    format!("client {}, tx {}", field("client"), field("tx"))
+}
+
+// A row whose fields can't be read as a transaction
+fn unreadable_row(
+    record: &csv::StringRecord,
+    headers: &csv::StringRecord,
+    line: u64,
+    error: csv::Error,
+) -> anyhow::Error {
+    anyhow::anyhow!(
+        "row {} ({}) can't be read: {}",
+        line,
+        row_ids(headers, record),
+        error
+    )
+}
+
+/// The order in which transactions are taken to have been submitted, which decides how
+/// conflicting transactions (e.g. a withdrawal and the deposit it depends on) are resolved.
+/// Rows with equal keys keep their file order.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SubmissionOrder {
+    /// Rows are applied in the order they appear in the file
+    #[default]
+    FileOrder,
+    /// Each client's rows are applied in ascending order of the `timestamp` column, read in the
+    /// configured timestamp format.
+    /// Clients still take turns in file order, since one client's rows never affect another's.
+    PerClientTimestamp,
+    /// All rows are applied in ascending order of the integer `sequence` column
+    ExternalSequence,
+}
+
+impl SubmissionOrder {
+    fn key_column(&self) -> Option<&'static str> {
+        match self {
+            SubmissionOrder::FileOrder => None,
+            SubmissionOrder::PerClientTimestamp => Some(TIMESTAMP_COLUMN),
+            SubmissionOrder::ExternalSequence => Some(SEQUENCE_COLUMN),
+        }
+    }
+}
+
+impl std::str::FromStr for SubmissionOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file-order" => Ok(SubmissionOrder::FileOrder),
+            "per-client-timestamp" => Ok(SubmissionOrder::PerClientTimestamp),
+            "external-sequence" => Ok(SubmissionOrder::ExternalSequence),
+            _ => Err(format!("unknown submission order: {}", s)),
+        }
+    }
+}
+
+type TransactionResults = Box<dyn Iterator<Item = anyhow::Result<serializable_form::Transaction>>>;
+
+pub type InputRowResults = Box<dyn Iterator<Item = anyhow::Result<InputRow>>>;
+
+/// A transaction as read from the input, with how its amount was normalized.
+#[derive(Debug, Clone)]
+pub struct InputRow {
+    pub transaction: serializable_form::Transaction,
+    pub amount_normalization: AmountNormalization,
+    /// The line of the input the row is on
+    pub line: u64,
+}
+
+/// Reads the transactions in the file in submission order. File order is streamed, the other
+/// orders read the whole file to sort it.
+pub fn read_transactions(
+    input_transactions_file: &Path,
+    submission_order: SubmissionOrder,
+) -> anyhow::Result<TransactionResults> {
+    let processing_options = ProcessingOptions {
+        submission_order,
+        ..Default::default()
+    };
+    let rows = read_rows(input_transactions_file, &processing_options)?;
+    Ok(Box::new(rows.map(|row| row.map(|row| row.transaction))))
+}
+
+/// Reads the rows in the file in the processing options' submission order, with its columns
+/// mapped, types aliased and amounts normalized as configured. A row with an unknown type is
+/// read as an `UnknownTypeRow` error.
+pub fn read_rows(
+    input_transactions_file: &Path,
+    processing_options: &ProcessingOptions,
+) -> anyhow::Result<InputRowResults> {
+    read_rows_from_reader(
+        std::fs::File::open(input_transactions_file)?,
+        processing_options,
+    )
+}
+
+/// Reads the rows from CSV like `read_rows`, e.g. from stdin.
+pub fn read_rows_from_reader(
+    input: impl std::io::Read + 'static,
+    processing_options: &ProcessingOptions,
+) -> anyhow::Result<InputRowResults> {
+    let mut reader = csv::Reader::from_reader(input);
+    processing_options.column_mapping.apply_to(&mut reader)?;
+    let headers = reader.headers()?.clone();
+    let columns = Columns::of(&headers);
+    let null_amounts = processing_options.null_amounts;
+    let amount_format = processing_options.amount_format;
+    let submission_order = processing_options.submission_order;
+    let type_aliases = processing_options.type_aliases.clone();
+
+    let key_column = match submission_order.key_column() {
+        None => {
+            return Ok(Box::new(reader.into_records().map(move |record| {
+                let record = type_aliases.apply(record?, columns.transaction_type);
+                parse_row(&record, &headers, &columns, null_amounts, amount_format)
+            })))
+        }
+        Some(key_column) => key_column,
+    };
+
+    let key_index = headers
+        .iter()
+        .position(|header| header.trim() == key_column)
+        .ok_or_else(|| anyhow::anyhow!("input has no {} column", key_column))?;
+
+    let timestamp_parser = TimestampParser::of(processing_options);
+    let parse_key = |key: &str| -> Result<u64, String> {
+        match submission_order {
+            SubmissionOrder::PerClientTimestamp => {
+                let timestamp = timestamp_parser.parse(key)?;
+                u64::try_from(timestamp)
+                    .map_err(|_| format!("timestamp before the Unix epoch: {}", key))
+            }
+            _ => key
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid {}: {}", key_column, key)),
+        }
+    };
+
+    let mut rows = Vec::<(u64, InputRow)>::new();
+    let mut unknown_type_rows = Vec::new();
+    for record in reader.records() {
+        let record = type_aliases.apply(record?, columns.transaction_type);
+        let key = parse_key(record.get(key_index).unwrap_or_default()).map_err(|error| {
+            anyhow::anyhow!(
+                "row {} ({}) has no valid {} ({})",
+                record.position().map_or(0, |position| position.line()),
+                row_ids(&headers, &record),
+                key_column,
+                error
+            )
+        })?;
+        match parse_row(&record, &headers, &columns, null_amounts, amount_format) {
+            Ok(row) => rows.push((key, row)),
+            Err(error) if error.is::<UnknownTypeRow>() => unknown_type_rows.push(Err(error)),
+            Err(error) => return Err(error),
+        }
+    }
+
+    let rows = match submission_order {
+        SubmissionOrder::PerClientTimestamp => sort_per_client(rows),
+        _ => {
+            rows.sort_by_key(|(key, _)| *key);
+            rows.into_iter().map(|(_, row)| row).collect()
+        }
+    };
+    // rows with unknown types are never applied, so they are reported ahead of the sorted rows
+    Ok(Box::new(
+        unknown_type_rows
+            .into_iter()
+            .chain(rows.into_iter().map(Ok)),
+    ))
+}
+
+/// The positions of the columns needed before a row is parsed
+struct Columns {
+    transaction_type: Option<usize>,
+    amount: Option<usize>,
+}
+
+impl Columns {
+    fn of(headers: &csv::StringRecord) -> Self {
+        let position = |name: &str| headers.iter().position(|header| header.trim() == name);
+        Self {
+            transaction_type: position("type"),
+            amount: position("amount"),
+        }
+    }
+}
+
+fn parse_row(
+    record: &csv::StringRecord,
+    headers: &csv::StringRecord,
+    columns: &Columns,
+    null_amounts: NullAmountPolicy,
+    amount_format: AmountFormat,
+) -> anyhow::Result<InputRow> {
+    let line = record.position().map_or(0, |position| position.line());
+    if let Some(transaction_type) = columns.transaction_type.and_then(|index| record.get(index)) {
+        if transaction_type.trim().parse::<TransactionType>().is_err() {
+            return Err(UnknownTypeRow {
+                line,
+                value: transaction_type.to_string(),
+                headers: headers.clone(),
+                record: record.clone(),
+            }
+            .into());
+        }
+    }
+
+    let amount = match columns.amount.and_then(|index| record.get(index)) {
+        Some(amount) => amount,
+        None => {
+            return Ok(InputRow {
+                transaction: record
+                    .deserialize(Some(headers))
+                    .map_err(|error| unreadable_row(record, headers, line, error))?,
+                amount_normalization: AmountNormalization::Unchanged,
+                line,
+            })
+        }
+    };
+
+    let transaction_type = columns
+        .transaction_type
+        .and_then(|index| record.get(index))
+        .and_then(|transaction_type| transaction_type.trim().parse::<TransactionType>().ok());
+    let (normalized_amount, amount_normalization) =
+        normalize_amount(amount, transaction_type, null_amounts, amount_format);
+    let transaction = if amount_normalization == AmountNormalization::Unchanged {
+        record
+            .deserialize(Some(headers))
+            .map_err(|error| unreadable_row(record, headers, line, error))?
+    } else {
+        let normalized_record: csv::StringRecord = record
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                if Some(index) == columns.amount {
+                    normalized_amount.as_str()
+                } else {
+                    field
+                }
+            })
+            .collect();
+        normalized_record
+            .deserialize(Some(headers))
+            .map_err(|error| unreadable_row(record, headers, line, error))?
+    };
+    Ok(InputRow {
+        transaction,
+        amount_normalization,
+        line,
+    })
+}
+
+// Sorts each client's rows by key, keeping the positions in the file which each client's rows take.
+fn sort_per_client(rows: Vec<(u64, InputRow)>) -> Vec<InputRow> {
+    let client_ids: Vec<ClientId> = rows
+        .iter()
+        .map(|(_, row)| row.transaction.client_id)
+        .collect();
+
+    let mut rows_by_client = BTreeMap::<ClientId, Vec<(u64, InputRow)>>::new();
+    for row in rows {
+        rows_by_client
+            .entry(row.1.transaction.client_id)
+            .or_default()
+            .push(row);
+    }
+    let mut sorted_by_client = BTreeMap::new();
+    for (client_id, mut client_rows) in rows_by_client {
+        client_rows.sort_by_key(|(key, _)| *key);
+        sorted_by_client.insert(
+            client_id,
+            client_rows
+                .into_iter()
+                .map(|(_, row)| row)
+                .collect::<std::collections::VecDeque<_>>(),
+        );
+    }
+
+    client_ids
+        .into_iter()
+        .filter_map(|client_id| {
+            sorted_by_client
+                .get_mut(&client_id)
+                .and_then(|client_rows| client_rows.pop_front())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_input(name: &str, contents: &str) -> std::path::PathBuf {
+        let input_file = std::env::temp_dir().join(format!("rs_bpt-submission_order-{}.csv", name));
+        std::fs::write(&input_file, contents).unwrap();
+        input_file
+    }
+
+    fn read(
+        input_file: &Path,
+        submission_order: SubmissionOrder,
+    ) -> Vec<(ClientId, TransactionType)> {
+        read_transactions(input_file, submission_order)
+            .unwrap()
+            .map(|transaction| {
+                let transaction = transaction.unwrap();
+                (transaction.client_id, transaction.transaction_type)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_transactions_in_submission_order() {
+        let input_file = write_input(
+            "orders",
+            "type,client,tx,amount,timestamp,sequence\n\
+             withdrawal,1,2,1.0,20,4\n\
+             deposit,2,3,1.0,5,1\n\
+             deposit,1,1,2.0,10,3\n\
+             dispute,2,3,,6,2\n",
+        );
+
+        assert_eq!(
+            read(&input_file, SubmissionOrder::FileOrder),
+            vec![
+                (1, TransactionType::Withdrawal),
+                (2, TransactionType::Deposit),
+                (1, TransactionType::Deposit),
+                (2, TransactionType::Dispute),
+            ]
+        );
+        assert_eq!(
+            read(&input_file, SubmissionOrder::PerClientTimestamp),
+            vec![
+                (1, TransactionType::Deposit),
+                (2, TransactionType::Deposit),
+                (1, TransactionType::Withdrawal),
+                (2, TransactionType::Dispute),
+            ]
+        );
+        assert_eq!(
+            read(&input_file, SubmissionOrder::ExternalSequence),
+            vec![
+                (2, TransactionType::Deposit),
+                (2, TransactionType::Dispute),
+                (1, TransactionType::Deposit),
+                (1, TransactionType::Withdrawal),
+            ]
+        );
+        std::fs::remove_file(&input_file).unwrap();
+    }
+
+    #[test]
+    fn test_read_transactions_requires_the_key_column() {
+        let input_file = write_input("missing-column", "type,client,tx,amount\ndeposit,1,1,1.0\n");
+        assert_eq!(
+            read_transactions(&input_file, SubmissionOrder::ExternalSequence)
+                .err()
+                .unwrap()
+                .to_string(),
+            "input has no sequence column"
+        );
+        std::fs::remove_file(&input_file).unwrap();
+    }
+
+    #[test]
+    fn test_read_transactions_names_the_client_and_tx_of_unreadable_rows() {
+        let input_file = write_input(
+            "unreadable",
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,one,2,1.0\n\
+             withdrawal,1,3,two\n",
+        );
+        let errors: Vec<String> = read_transactions(&input_file, SubmissionOrder::FileOrder)
+            .unwrap()
+            .filter_map(|transaction| transaction.err().map(|error| error.to_string()))
+            .collect();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("row 3 (client one, tx 2) can't be read: "));
+        assert!(errors[1].starts_with("row 4 (client 1, tx 3) can't be read: "));
+        std::fs::remove_file(&input_file).unwrap();
+    }
+
+    #[test]
+    fn test_read_transactions_reports_unknown_types_ahead_of_sorted_rows() {
+        let input_file = write_input(
+            "unknown-types",
+            "type,client,tx,amount,sequence\n\
+             deposit,1,1,1.0,2\n\
+             refund,1,2,1.0,1\n",
+        );
+        let mut transactions =
+            read_transactions(&input_file, SubmissionOrder::ExternalSequence).unwrap();
+        let error = transactions.next().unwrap().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<UnknownTypeRow>().map(|row| row.line),
+            Some(3)
+        );
+        assert_eq!(
+            transactions.next().unwrap().unwrap().transaction_type,
+            TransactionType::Deposit
+        );
+        std::fs::remove_file(&input_file).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+use serde_derive::Serialize;
+
+use crate::amount_parsing::AmountNormalizationCounts;
+use crate::hashing::Accounts;
+use crate::merkle::{accounts_merkle_root, Sha256};
+use crate::pending_disputes::PendingDisputeCounts;
+use crate::serializable_form::round_f64_4dp_string;
+use crate::streaming_metrics::MetricsSummary;
+use crate::trial_balance::TrialBalanceReport;
+use crate::unknown_types::UnknownTypeCounts;
+use crate::{ClientAccount, ClientId};
+
+const TOP_HELD_COUNT: usize = 10;
+
+/// Upper bounds (exclusive) of the positive balance histogram buckets.
+const BALANCE_BUCKET_BOUNDS: [f64; 4] = [100.0, 1_000.0, 10_000.0, 100_000.0];
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct HeldBalance {
+    pub client: ClientId,
+    pub held: String,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct BalanceBucket {
+    pub bucket: String,
+    pub count: usize,
+}
+
+/// Aggregates over all accounts at the end of a run, emitted as JSON for dashboards.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<String>,
+    pub client_count: usize,
+    pub locked_count: usize,
+    /// The accounts closed for too many chargebacks
+    pub closed_count: usize,
+    pub chargeback_count: usize,
+    pub total_charged_back: String,
+    pub negative_count: usize,
+    pub total_available: String,
+    pub total_held: String,
+    pub total: String,
+    pub top_held: Vec<HeldBalance>,
+    pub balance_distribution: Vec<BalanceBucket>,
+    /// SHA-256 Merkle root of the final account states, to verify two runs agree
+    pub accounts_merkle_root: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_disputes: Option<PendingDisputeCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_normalizations: Option<AmountNormalizationCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unknown_types: Option<UnknownTypeCounts>,
+    /// The transactions skipped by `--backfill` as already in the loaded state
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backfilled: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trial_balance: Option<TrialBalanceReport>,
+    /// Memory use, with the `memprofile` feature
+    #[cfg(feature = "memprofile")]
+    pub memory: crate::memprofile::MemoryReport,
+}
+
+impl Summary {
+    pub fn from_accounts(accounts: &Accounts) -> anyhow::Result<Self> {
+        let mut total_available = 0.0;
+        let mut total_held = 0.0;
+        let mut locked_count = 0;
+        let mut closed_count = 0;
+        let mut chargeback_count = 0;
+        let mut total_charged_back = 0.0;
+        let mut negative_count = 0;
+        let mut bucket_counts = vec![0; BALANCE_BUCKET_BOUNDS.len() + 3];
+
+        for client_account in accounts.values() {
+            total_available += client_account.balance.available;
+            total_held += client_account.balance.held;
+            if client_account.locked {
+                locked_count += 1;
+            }
+            if client_account.closed {
+                closed_count += 1;
+            }
+            chargeback_count += client_account.chargebacks;
+            total_charged_back += client_account.charged_back;
+
+            let total = client_account.balance.total();
+            if total < 0.0 {
+                negative_count += 1;
+            }
+            bucket_counts[balance_bucket_index(total)] += 1;
+        }
+
+        let mut by_held: Vec<&ClientAccount> = accounts.values().collect();
+        by_held.sort_by(|a, b| {
+            b.balance
+                .held
+                .total_cmp(&a.balance.held)
+                .then(a.client_id.cmp(&b.client_id))
+        });
+        let top_held = by_held
+            .iter()
+            .take(TOP_HELD_COUNT)
+            .map(|client_account| {
+                Ok(HeldBalance {
+                    client: client_account.client_id,
+                    held: round_f64_4dp_string(client_account.balance.held)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let balance_distribution = bucket_counts
+            .into_iter()
+            .enumerate()
+            .map(|(index, count)| BalanceBucket {
+                bucket: balance_bucket_label(index),
+                count,
+            })
+            .collect();
+
+        Ok(Self {
+            batch_id: None,
+            client_count: accounts.len(),
+            locked_count,
+            closed_count,
+            chargeback_count,
+            total_charged_back: round_f64_4dp_string(total_charged_back)?,
+            negative_count,
+            total_available: round_f64_4dp_string(total_available)?,
+            total_held: round_f64_4dp_string(total_held)?,
+            total: round_f64_4dp_string(total_available + total_held)?,
+            top_held,
+            balance_distribution,
+            accounts_merkle_root: accounts_merkle_root(accounts.values(), &Sha256)?,
+            pending_disputes: None,
+            metrics: None,
+            amount_normalizations: None,
+            unknown_types: None,
+            backfilled: None,
+            trial_balance: None,
+            #[cfg(feature = "memprofile")]
+            memory: crate::memprofile::MemoryReport::new(accounts),
+        })
+    }
+
+    /// Tags the summary with the run's batch id, if it has one
+    pub fn with_batch_id(mut self, batch_id: Option<String>) -> Self {
+        self.batch_id = batch_id;
+        self
+    }
+
+    /// Adds the pending dispute buffer's counts, if it was enabled
+    pub fn with_pending_disputes(mut self, pending_disputes: Option<PendingDisputeCounts>) -> Self {
+        self.pending_disputes = pending_disputes;
+        self
+    }
+
+    /// Adds the counts of the amounts normalized, if there were any
+    pub fn with_amount_normalizations(
+        mut self,
+        amount_normalizations: Option<AmountNormalizationCounts>,
+    ) -> Self {
+        self.amount_normalizations = amount_normalizations;
+        self
+    }
+
+    /// Adds the counts of the rows skipped for unknown types, if there were any
+    pub fn with_unknown_types(mut self, unknown_types: Option<UnknownTypeCounts>) -> Self {
+        self.unknown_types = unknown_types;
+        self
+    }
+
+    /// Adds the count of the transactions skipped as already in the loaded state, with `--backfill`
+    pub fn with_backfilled(mut self, backfilled: Option<usize>) -> Self {
+        self.backfilled = backfilled;
+        self
+    }
+
+    /// Adds the totals of the funds moved during the run, checked against the accounts
+    pub fn with_trial_balance(mut self, trial_balance: TrialBalanceReport) -> Self {
+        self.trial_balance = Some(trial_balance);
+        self
+    }
+
+    /// Adds the percentiles tracked while processing
+    pub fn with_metrics(mut self, metrics: MetricsSummary) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn write_json(&self, output_stream: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut *output_stream, self)?;
+        writeln!(output_stream)?;
+        Ok(())
+    }
+}
+
+// Buckets are: negative, zero, one per positive upper bound, and everything above the last bound.
+fn balance_bucket_index(total: f64) -> usize {
+    if total < 0.0 {
+        0
+    } else if total == 0.0 {
+        1
+    } else {
+        2 + BALANCE_BUCKET_BOUNDS
+            .iter()
+            .take_while(|bound| total >= **bound)
+            .count()
+    }
+}
+
+fn balance_bucket_label(index: usize) -> String {
+    match index {
+        0 => "< 0".to_string(),
+        1 => "0".to_string(),
+        i if i - 2 < BALANCE_BUCKET_BOUNDS.len() => {
+            let upper = BALANCE_BUCKET_BOUNDS[i - 2];
+            let lower = if i == 2 {
+                0.0
+            } else {
+                BALANCE_BUCKET_BOUNDS[i - 3]
+            };
+            format!("{} - {}", lower, upper)
+        }
+        _ => format!(
+            ">= {}",
+            BALANCE_BUCKET_BOUNDS[BALANCE_BUCKET_BOUNDS.len() - 1]
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(client_id: ClientId, available: f64, held: f64, locked: bool) -> ClientAccount {
+        let mut client_account = ClientAccount::new(client_id);
+        client_account.balance.available = available;
+        client_account.balance.held = held;
+        client_account.locked = locked;
+        client_account
+    }
+
+    #[test]
+    fn test_summary_from_accounts() {
+        let mut accounts = Accounts::default();
+        accounts.insert(1, account(1, 100.0, 10.0, false));
+        accounts.insert(2, account(2, -5.0, 0.0, true));
+        accounts.insert(3, account(3, 0.0, 0.0, false));
+        accounts.insert(4, account(4, 1500.0, 20.0, false));
+
+        let summary = Summary::from_accounts(&accounts).unwrap();
+
+        assert_eq!(summary.client_count, 4);
+        assert_eq!(summary.locked_count, 1);
+        assert_eq!(summary.negative_count, 1);
+        assert_eq!(summary.total_available, "1595.0000");
+        assert_eq!(summary.total_held, "30.0000");
+        assert_eq!(summary.total, "1625.0000");
+
+        assert_eq!(summary.top_held.len(), 4);
+        assert_eq!(summary.top_held[0].client, 4);
+        assert_eq!(summary.top_held[0].held, "20.0000");
+        assert_eq!(summary.top_held[1].client, 1);
+
+        let counts: Vec<(&str, usize)> = summary
+            .balance_distribution
+            .iter()
+            .map(|bucket| (bucket.bucket.as_str(), bucket.count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("< 0", 1),
+                ("0", 1),
+                ("0 - 100", 0),
+                ("100 - 1000", 1),
+                ("1000 - 10000", 1),
+                ("10000 - 100000", 0),
+                (">= 100000", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_top_held_is_limited_to_ten_accounts() {
+        let mut accounts = Accounts::default();
+        for client_id in 1..=15 {
+            accounts.insert(client_id, account(client_id, 0.0, client_id as f64, false));
+        }
+
+        let summary = Summary::from_accounts(&accounts).unwrap();
+
+        assert_eq!(summary.top_held.len(), 10);
+        assert_eq!(summary.top_held[0].client, 15);
+        assert_eq!(summary.top_held[9].client, 6);
+    }
+}
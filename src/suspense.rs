@@ -0,0 +1,219 @@
+use serde_derive::Serialize;
+use std::path::PathBuf;
+
+use crate::client_account::error::TransactionProcessingError;
+use crate::rejection::RejectionSink;
+use crate::serializable_form::{self, round_f64_4dp_string};
+use crate::{ClientId, TransactionId, TransactionType};
+
+/// Whether rejected transactions are held in one suspense bucket per client or in a single global one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SuspenseMode {
+    PerClient,
+    Global,
+}
+
+impl std::str::FromStr for SuspenseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-client" => Ok(SuspenseMode::PerClient),
+            "global" => Ok(SuspenseMode::Global),
+            _ => Err(format!("unknown suspense mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SuspenseOptions {
+    pub file: PathBuf,
+    pub mode: SuspenseMode,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SuspenseItem {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub transaction_type: TransactionType,
+    pub amount: Option<f64>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SerializableSuspenseItem {
+    bucket: String,
+    client: ClientId,
+    tx: TransactionId,
+    #[serde(rename = "type")]
+    transaction_type: TransactionType,
+    amount: Option<String>,
+    reason: String,
+}
+
+/// Collects rejected deposits and withdrawals so operations can review and re-key them.
+#[derive(Debug)]
+pub struct Suspense {
+    mode: SuspenseMode,
+    items: Vec<SuspenseItem>,
+}
+
+impl Suspense {
+    pub fn new(mode: SuspenseMode) -> Self {
+        Self {
+            mode,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn items(&self) -> &[SuspenseItem] {
+        &self.items
+    }
+
+    fn bucket(&self, client_id: ClientId) -> String {
+        match self.mode {
+            SuspenseMode::PerClient => format!("client:{}", client_id),
+            SuspenseMode::Global => "global".to_string(),
+        }
+    }
+
+    /// Writes the suspended transactions as CSV, ordered by bucket.
+    pub fn write_report(&self, output_stream: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        let mut items: Vec<&SuspenseItem> = self.items.iter().collect();
+        if self.mode == SuspenseMode::PerClient {
+            items.sort_by_key(|item| item.client_id);
+        }
+
+        let mut writer = csv::Writer::from_writer(output_stream);
+        for item in items {
+            writer.serialize(SerializableSuspenseItem {
+                bucket: self.bucket(item.client_id),
+                client: item.client_id,
+                tx: item.transaction_id,
+                transaction_type: item.transaction_type,
+                amount: item.amount.map(round_f64_4dp_string).transpose()?,
+                reason: item.reason.clone(),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl RejectionSink for Suspense {
+    fn record_rejection(
+        &mut self,
+        transaction: &serializable_form::Transaction,
+        error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        if matches!(
+            transaction.transaction_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        ) {
+            self.items.push(SuspenseItem {
+                client_id: transaction.client_id,
+                transaction_id: transaction.transaction_id,
+                transaction_type: transaction.transaction_type,
+                amount: transaction.amount,
+                reason: error.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(
+        client_id: ClientId,
+        transaction_id: TransactionId,
+        transaction_type: TransactionType,
+        amount: Option<f64>,
+    ) -> serializable_form::Transaction {
+        serializable_form::Transaction {
+            client_id,
+            transaction_id,
+            transaction_type,
+            amount,
+            counterparty: None,
+        }
+    }
+
+    #[test]
+    fn it_only_suspends_deposits_and_withdrawals() {
+        let mut suspense = Suspense::new(SuspenseMode::Global);
+
+        suspense
+            .record_rejection(
+                &transaction(1, 1, TransactionType::Deposit, Some(2.0)),
+                &TransactionProcessingError::TransactionIDAlreadyExists { client: 1, tx: 1 },
+            )
+            .unwrap();
+        suspense
+            .record_rejection(
+                &transaction(1, 7, TransactionType::Dispute, None),
+                &TransactionProcessingError::ReferencedTransactionNotFound { client: 1, tx: 7 },
+            )
+            .unwrap();
+        suspense
+            .record_rejection(
+                &transaction(2, 3, TransactionType::Withdrawal, None),
+                &TransactionProcessingError::AmountNotPresentForWithdrawal { client: 2, tx: 3 },
+            )
+            .unwrap();
+
+        assert_eq!(suspense.items().len(), 2);
+        assert_eq!(
+            suspense.items()[0],
+            SuspenseItem {
+                client_id: 1,
+                transaction_id: 1,
+                transaction_type: TransactionType::Deposit,
+                amount: Some(2.0),
+                reason: "TransactionIDAlreadyExists: 1".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        let mut per_client = Suspense::new(SuspenseMode::PerClient);
+        let mut global = Suspense::new(SuspenseMode::Global);
+        for suspense in [&mut per_client, &mut global] {
+            suspense
+                .record_rejection(
+                    &transaction(2, 3, TransactionType::Withdrawal, None),
+                    &TransactionProcessingError::AmountNotPresentForWithdrawal { client: 2, tx: 3 },
+                )
+                .unwrap();
+            suspense
+                .record_rejection(
+                    &transaction(1, 1, TransactionType::Deposit, Some(2.0)),
+                    &TransactionProcessingError::TransactionIDAlreadyExists { client: 1, tx: 1 },
+                )
+                .unwrap();
+        }
+
+        let mut output = Vec::<u8>::new();
+        per_client.write_report(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"bucket,client,tx,type,amount,reason
+client:1,1,1,deposit,2.0000,TransactionIDAlreadyExists: 1
+client:2,2,3,withdrawal,,AmountNotPresentForWithdrawal: 3
+"#
+        );
+
+        let mut output = Vec::<u8>::new();
+        global.write_report(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"bucket,client,tx,type,amount,reason
+global,2,3,withdrawal,,AmountNotPresentForWithdrawal: 3
+global,1,1,deposit,2.0000,TransactionIDAlreadyExists: 1
+"#
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Fixtures and assertions for tests of the processor, including those of crates built on it.
+//! Balances are compared as the output formats them, rounded to four decimal places, rather than
+//! as raw f64s.
+
+use crate::client_account::ClientAccount;
+use crate::hashing::Accounts;
+use crate::processing_options::ProcessingOptions;
+use crate::serializable_form::Transaction;
+use crate::{process_transaction, ClientId, TransactionId, TransactionType};
+
+/// Asserts the formatted fields of a client's account, e.g.
+/// `assert_account!(account, available = "110.0000", held = "0.0000", locked = false)`.
+/// Any of the fields of `serializable_form::Output` can be given, in any order.
+#[macro_export]
+macro_rules! assert_account {
+    ($account:expr, $($field:ident = $expected:expr),+ $(,)?) => {{
+        let account: &$crate::client_account::ClientAccount = &$account;
+        let formatted = $crate::serializable_form::Output::from_client_account(account)
+            .expect("the account's balances can be formatted");
+        $(
+            assert_eq!(
+                formatted.$field,
+                $expected,
+                "{} of client {}",
+                stringify!($field),
+                account.client_id
+            );
+        )+
+    }};
+}
+
+pub fn transaction(
+    transaction_type: TransactionType,
+    client_id: ClientId,
+    transaction_id: TransactionId,
+    amount: Option<f64>,
+) -> Transaction {
+    Transaction {
+        transaction_type,
+        client_id,
+        transaction_id,
+        amount,
+        counterparty: None,
+    }
+}
+
+pub fn deposit(client_id: ClientId, transaction_id: TransactionId, amount: f64) -> Transaction {
+    transaction(
+        TransactionType::Deposit,
+        client_id,
+        transaction_id,
+        Some(amount),
+    )
+}
+
+pub fn withdrawal(client_id: ClientId, transaction_id: TransactionId, amount: f64) -> Transaction {
+    transaction(
+        TransactionType::Withdrawal,
+        client_id,
+        transaction_id,
+        Some(amount),
+    )
+}
+
+pub fn dispute(client_id: ClientId, transaction_id: TransactionId) -> Transaction {
+    transaction(TransactionType::Dispute, client_id, transaction_id, None)
+}
+
+pub fn resolve(client_id: ClientId, transaction_id: TransactionId) -> Transaction {
+    transaction(TransactionType::Resolve, client_id, transaction_id, None)
+}
+
+pub fn chargeback(client_id: ClientId, transaction_id: TransactionId) -> Transaction {
+    transaction(TransactionType::Chargeback, client_id, transaction_id, None)
+}
+
+pub fn transfer(
+    client_id: ClientId,
+    transaction_id: TransactionId,
+    counterparty: Option<ClientId>,
+    amount: Option<f64>,
+) -> Transaction {
+    Transaction {
+        counterparty,
+        ..transaction(TransactionType::Transfer, client_id, transaction_id, amount)
+    }
+}
+
+/// Processes the transactions in order with the given options, panicking if any is rejected
+pub fn accounts_from(transactions: &[Transaction], options: &ProcessingOptions) -> Accounts {
+    let mut accounts = Accounts::default();
+    for transaction in transactions {
+        if let Err(e) =
+            process_transaction(&mut accounts, transaction, options, &mut std::io::sink())
+        {
+            panic!(
+                "transaction {} of client {} was rejected: {}",
+                transaction.transaction_id, transaction.client_id, e
+            );
+        }
+    }
+    accounts
+}
+
+/// A client's account after depositing `amount` as transaction 1
+pub fn funded_account(client_id: ClientId, amount: f64) -> ClientAccount {
+    accounts_from(
+        &[deposit(client_id, 1, amount)],
+        &ProcessingOptions::default(),
+    )
+    .remove(&client_id)
+    .expect("the deposit opens the account")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_asserts_the_formatted_fields() {
+        let accounts = accounts_from(
+            &[
+                deposit(1, 1, 100.0),
+                deposit(1, 2, 10.00004),
+                dispute(1, 1),
+                chargeback(1, 1),
+            ],
+            &ProcessingOptions::default(),
+        );
+        assert_account!(
+            accounts[&1],
+            available = "10.0000",
+            held = "0.0000",
+            total = "10.0000",
+            locked = true,
+            chargebacks = 1,
+        );
+        assert_account!(funded_account(2, 5.5), available = "5.5000", locked = false);
+    }
+
+    #[test]
+    #[should_panic(expected = "held of client 1")]
+    fn it_names_the_mismatched_field() {
+        assert_account!(
+            funded_account(1, 5.0),
+            available = "5.0000",
+            held = "1.0000"
+        );
+    }
+}
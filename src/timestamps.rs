@@ -0,0 +1,350 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use serde_derive::Deserialize;
+
+use crate::processing_options::ProcessingOptions;
+
+const MILLIS_PER_SECOND: i64 = 1_000;
+
+/// Epoch values at least this large (in magnitude) are past the year 5000 in seconds, but before
+/// 1974 in milliseconds, so are taken to be milliseconds.
+const EPOCH_MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// How the `timestamp` column is written in the input.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimestampFormat {
+    /// Seconds since the Unix epoch, e.g. `1646092800`
+    #[default]
+    EpochSeconds,
+    /// Milliseconds since the Unix epoch, e.g. `1646092800000`
+    EpochMillis,
+    /// RFC 3339, e.g. `2022-03-01T00:00:00Z` or `2022-03-01 09:30:00.250+01:00`
+    Rfc3339,
+    /// `YYYYMMDDHHMMSS`, e.g. `20220301000000`
+    Compact,
+}
+
+impl TimestampFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimestampFormat::EpochSeconds => "epoch-seconds",
+            TimestampFormat::EpochMillis => "epoch-millis",
+            TimestampFormat::Rfc3339 => "rfc3339",
+            TimestampFormat::Compact => "compact",
+        }
+    }
+}
+
+impl std::str::FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "epoch-seconds" => Ok(TimestampFormat::EpochSeconds),
+            "epoch-millis" => Ok(TimestampFormat::EpochMillis),
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            "compact" => Ok(TimestampFormat::Compact),
+            _ => Err(format!("unknown timestamp format: {}", s)),
+        }
+    }
+}
+
+/// What to do with a timestamp which could mean more than one time: a local time with no UTC
+/// offset when no timezone is configured, or an epoch value in the other unit than the format's.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AmbiguousTimestampPolicy {
+    /// Stop reading the input at the row
+    #[default]
+    Reject,
+    /// Take a local time as UTC, and an epoch value in the unit its size suggests
+    Normalize,
+}
+
+impl std::str::FromStr for AmbiguousTimestampPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(AmbiguousTimestampPolicy::Reject),
+            "normalize" => Ok(AmbiguousTimestampPolicy::Normalize),
+            _ => Err(format!("unknown ambiguous timestamp policy: {}", s)),
+        }
+    }
+}
+
+/// A fixed offset from UTC, written `Z`, `UTC` or `±HH:MM` (or `±HHMM`).
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct UtcOffset {
+    pub minutes: i32,
+}
+
+impl std::str::FromStr for UtcOffset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "Z" || s == "z" || s == "UTC" {
+            return Ok(Self { minutes: 0 });
+        }
+        let invalid = || format!("invalid UTC offset (expected Z or ±HH:MM): {}", s);
+        // chrono also takes seconds, e.g. `+05:30:15`, so the shape is checked first
+        let is_shaped = match s.len() {
+            5 => true,
+            6 => s.as_bytes()[3] == b':',
+            _ => false,
+        };
+        if !is_shaped {
+            return Err(invalid());
+        }
+        let offset: FixedOffset = s.parse().map_err(|_| invalid())?;
+        Ok(Self {
+            minutes: offset.local_minus_utc() / 60,
+        })
+    }
+}
+
+impl TryFrom<String> for UtcOffset {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Parses the `timestamp` column as configured by the processing options (which may differ per
+/// input source).
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct TimestampParser {
+    pub format: TimestampFormat,
+    /// The offset of local times, which have none of their own
+    pub timezone: Option<UtcOffset>,
+    pub ambiguous: AmbiguousTimestampPolicy,
+}
+
+impl TimestampParser {
+    pub fn of(processing_options: &ProcessingOptions) -> Self {
+        Self {
+            format: processing_options.timestamp_format,
+            timezone: processing_options.timestamp_timezone,
+            ambiguous: processing_options.ambiguous_timestamps,
+        }
+    }
+
+    /// The timestamp in milliseconds since the Unix epoch
+    pub fn parse(&self, timestamp: &str) -> Result<i64, String> {
+        let timestamp = timestamp.trim();
+        let invalid = || format!("invalid {} timestamp: {}", self.format.name(), timestamp);
+        match self.format {
+            TimestampFormat::EpochSeconds | TimestampFormat::EpochMillis => {
+                let value: i64 = timestamp.parse().map_err(|_| invalid())?;
+                let in_millis = self.format == TimestampFormat::EpochMillis;
+                let looks_like_millis = value.abs() >= EPOCH_MILLIS_THRESHOLD;
+                if looks_like_millis != in_millis {
+                    self.normalize(timestamp, "an epoch value in the other unit")?;
+                }
+                let millis = if looks_like_millis {
+                    value
+                } else {
+                    value.checked_mul(MILLIS_PER_SECOND).ok_or_else(invalid)?
+                };
+                // past the dates chrono can represent
+                DateTime::from_timestamp_millis(millis).ok_or_else(invalid)?;
+                Ok(millis)
+            }
+            TimestampFormat::Rfc3339 => {
+                let (local_millis, offset) = parse_rfc3339(timestamp).ok_or_else(invalid)?;
+                let offset = match offset {
+                    Some(offset) => offset,
+                    None => self.local_offset(timestamp)?,
+                };
+                Ok(local_millis - i64::from(offset.minutes) * 60 * MILLIS_PER_SECOND)
+            }
+            TimestampFormat::Compact => {
+                let local_millis = parse_compact(timestamp).ok_or_else(invalid)?;
+                let offset = self.local_offset(timestamp)?;
+                Ok(local_millis - i64::from(offset.minutes) * 60 * MILLIS_PER_SECOND)
+            }
+        }
+    }
+
+    /// The timestamp's time in seconds since the Unix epoch, rounded down
+    pub fn parse_seconds(&self, timestamp: &str) -> Result<i64, String> {
+        Ok(self.parse(timestamp)?.div_euclid(MILLIS_PER_SECOND))
+    }
+
+    fn local_offset(&self, timestamp: &str) -> Result<UtcOffset, String> {
+        match self.timezone {
+            Some(timezone) => Ok(timezone),
+            None => {
+                self.normalize(timestamp, "a local time with no timezone")?;
+                Ok(UtcOffset::default())
+            }
+        }
+    }
+
+    fn normalize(&self, timestamp: &str, reason: &str) -> Result<(), String> {
+        match self.ambiguous {
+            AmbiguousTimestampPolicy::Reject => {
+                Err(format!("ambiguous timestamp ({}): {}", reason, timestamp))
+            }
+            AmbiguousTimestampPolicy::Normalize => Ok(()),
+        }
+    }
+}
+
+// YYYYMMDDHHMMSS, in milliseconds since the epoch as if it were UTC
+fn parse_compact(timestamp: &str) -> Option<i64> {
+    // chrono takes fields of any width, so the length is checked first
+    if timestamp.len() != 14 || !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    NaiveDateTime::parse_from_str(timestamp, "%Y%m%d%H%M%S")
+        .ok()
+        .map(|date_time| date_time.and_utc().timestamp_millis())
+}
+
+// YYYY-MM-DD(T| )HH:MM:SS[.fraction][Z|±HH:MM], in milliseconds since the epoch as if it were
+// UTC, with the offset if it has one. The fraction is truncated to milliseconds.
+fn parse_rfc3339(timestamp: &str) -> Option<(i64, Option<UtcOffset>)> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(timestamp) {
+        let offset = UtcOffset {
+            minutes: date_time.offset().local_minus_utc() / 60,
+        };
+        return Some((
+            date_time.naive_local().and_utc().timestamp_millis(),
+            Some(offset),
+        ));
+    }
+    // a local time, which chrono parses only with an offset
+    DateTime::parse_from_rfc3339(&format!("{}Z", timestamp))
+        .ok()
+        .map(|date_time| (date_time.timestamp_millis(), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(
+        format: TimestampFormat,
+        timezone: Option<&str>,
+        ambiguous: AmbiguousTimestampPolicy,
+    ) -> TimestampParser {
+        TimestampParser {
+            format,
+            timezone: timezone.map(|timezone| timezone.parse().unwrap()),
+            ambiguous,
+        }
+    }
+
+    #[test]
+    fn test_parse_timestamp_formats() {
+        use AmbiguousTimestampPolicy::Reject;
+        let march_first = 1_646_092_800_000;
+
+        let epoch_seconds = parser(TimestampFormat::EpochSeconds, None, Reject);
+        assert_eq!(epoch_seconds.parse("1646092800"), Ok(march_first));
+        assert_eq!(epoch_seconds.parse(" -1 "), Ok(-1_000));
+        assert!(epoch_seconds.parse("1646092800.5").is_err());
+
+        let epoch_millis = parser(TimestampFormat::EpochMillis, None, Reject);
+        assert_eq!(epoch_millis.parse("1646092800250"), Ok(march_first + 250));
+
+        let rfc3339 = parser(TimestampFormat::Rfc3339, None, Reject);
+        assert_eq!(rfc3339.parse("2022-03-01T00:00:00Z"), Ok(march_first));
+        assert_eq!(
+            rfc3339.parse("2022-03-01 02:30:00.25+02:30"),
+            Ok(march_first + 250)
+        );
+        assert_eq!(rfc3339.parse("2022-02-28T19:00:00-05:00"), Ok(march_first));
+        assert!(rfc3339.parse("2022-02-29T00:00:00Z").is_err());
+        assert!(rfc3339.parse("2022-03-01T00:00:00+2").is_err());
+
+        let compact = parser(TimestampFormat::Compact, Some("+01:00"), Reject);
+        assert_eq!(compact.parse("20220301010000"), Ok(march_first));
+        assert!(compact.parse("2022030101000").is_err());
+        assert_eq!(
+            compact.parse_seconds("20220301010000"),
+            Ok(march_first / 1_000)
+        );
+    }
+
+    #[test]
+    fn test_parse_ambiguous_timestamps() {
+        use AmbiguousTimestampPolicy::{Normalize, Reject};
+        let march_first = 1_646_092_800_000;
+
+        let local = parser(TimestampFormat::Rfc3339, None, Reject);
+        assert_eq!(
+            local.parse("2022-03-01T00:00:00"),
+            Err(
+                "ambiguous timestamp (a local time with no timezone): 2022-03-01T00:00:00"
+                    .to_string()
+            )
+        );
+        let local = parser(TimestampFormat::Rfc3339, None, Normalize);
+        assert_eq!(local.parse("2022-03-01T00:00:00"), Ok(march_first));
+        let local = parser(TimestampFormat::Rfc3339, Some("-01:00"), Reject);
+        assert_eq!(
+            local.parse("2022-02-29T23:00:00"),
+            Err("invalid rfc3339 timestamp: 2022-02-29T23:00:00".to_string())
+        );
+        assert_eq!(local.parse("2022-02-28T23:00:00"), Ok(march_first));
+
+        assert!(parser(TimestampFormat::EpochSeconds, None, Reject)
+            .parse("1646092800000")
+            .is_err());
+        assert_eq!(
+            parser(TimestampFormat::EpochSeconds, None, Normalize).parse("1646092800000"),
+            Ok(march_first)
+        );
+        assert!(parser(TimestampFormat::EpochMillis, None, Reject)
+            .parse("1646092800")
+            .is_err());
+        assert_eq!(
+            parser(TimestampFormat::EpochMillis, None, Normalize).parse("1646092800"),
+            Ok(march_first)
+        );
+    }
+
+    #[test]
+    fn test_parse_utc_offsets() {
+        assert_eq!("Z".parse(), Ok(UtcOffset { minutes: 0 }));
+        assert_eq!("+05:30".parse(), Ok(UtcOffset { minutes: 330 }));
+        assert_eq!("-0800".parse(), Ok(UtcOffset { minutes: -480 }));
+        assert!("05:30".parse::<UtcOffset>().is_err());
+        assert!("+24:00".parse::<UtcOffset>().is_err());
+        assert_eq!("+14:00".parse(), Ok(UtcOffset { minutes: 840 }));
+        assert_eq!("-12:00".parse(), Ok(UtcOffset { minutes: -720 }));
+        assert!("+05:30:15".parse::<UtcOffset>().is_err());
+        assert!("+05:60".parse::<UtcOffset>().is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp_edge_cases() {
+        use AmbiguousTimestampPolicy::Reject;
+        let leap_day = 1_709_164_800_000;
+
+        let rfc3339 = parser(TimestampFormat::Rfc3339, None, Reject);
+        assert_eq!(rfc3339.parse("2024-02-29T00:00:00Z"), Ok(leap_day));
+        assert_eq!(rfc3339.parse("2000-02-29T00:00:00Z"), Ok(951_782_400_000));
+        assert!(rfc3339.parse("2100-02-29T00:00:00Z").is_err());
+        // the furthest offsets in use either side of UTC
+        assert_eq!(rfc3339.parse("2024-02-29T14:00:00+14:00"), Ok(leap_day));
+        assert_eq!(rfc3339.parse("2024-02-28T12:00:00-12:00"), Ok(leap_day));
+        assert!(rfc3339.parse("2024-02-28T24:00:00Z").is_err());
+        assert!(rfc3339.parse("2024-02-28T23:60:00Z").is_err());
+        assert!(rfc3339.parse("2024-2-29T00:00:00Z").is_err());
+
+        let compact = parser(TimestampFormat::Compact, Some("-12:00"), Reject);
+        assert_eq!(compact.parse("20240228120000"), Ok(leap_day));
+        assert!(compact.parse("20230229000000").is_err());
+        assert!(compact.parse("20240228240000").is_err());
+
+        // past the dates that can be represented
+        assert!(parser(TimestampFormat::EpochMillis, None, Reject)
+            .parse("9223372036854775807")
+            .is_err());
+    }
+}
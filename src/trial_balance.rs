@@ -0,0 +1,183 @@
+use serde_derive::Serialize;
+
+use crate::client_account::applied_transaction::AppliedTransaction;
+use crate::hashing::Accounts;
+use crate::journal::{JournalEntry, JournalSink, LedgerAccount};
+use crate::serializable_form::round_f64_4dp_string;
+use crate::TransactionType;
+
+// Differences smaller than half of the smallest output unit are rounding noise
+const TRIAL_BALANCE_TOLERANCE: f64 = 0.00005;
+
+/// The trial balance as reported in the summary
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TrialBalanceReport {
+    pub opening_total: String,
+    pub deposits: String,
+    pub withdrawals: String,
+    pub charged_back: String,
+    /// The residual balances swept out of closed accounts, net of those credited to the sweep client
+    pub swept: String,
+    /// The net of both sides of the transfers, which is zero
+    pub transferred: String,
+    pub expected_total: String,
+    pub total: String,
+    pub expected_held: String,
+    pub held: String,
+    pub balanced: bool,
+}
+
+/// Running totals of the funds which the applied transactions moved into and out of the clients'
+/// accounts, and into held. At the end of a run the accounts' balances must add up to them.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TrialBalance {
+    opening_total: f64,
+    opening_held: f64,
+    deposits: f64,
+    withdrawals: f64,
+    charged_back: f64,
+    swept: f64,
+    transferred: f64,
+    held: f64,
+}
+
+fn is_client_account(account: LedgerAccount) -> bool {
+    matches!(
+        account,
+        LedgerAccount::ClientAvailable(_) | LedgerAccount::ClientHeld(_)
+    )
+}
+
+// How much the entry changed the clients' total balance by
+fn client_total_change(entry: &JournalEntry) -> f64 {
+    match (
+        is_client_account(entry.debit),
+        is_client_account(entry.credit),
+    ) {
+        (false, true) => entry.amount,
+        (true, false) => -entry.amount,
+        _ => 0.0,
+    }
+}
+
+impl TrialBalance {
+    /// Starts from the balances the accounts already have, e.g. from a state snapshot or imported
+    /// dispute cases
+    pub fn opening(accounts: &Accounts) -> Self {
+        Self {
+            opening_total: accounts
+                .values()
+                .map(|client_account| client_account.balance.total())
+                .sum(),
+            opening_held: accounts
+                .values()
+                .map(|client_account| client_account.balance.held)
+                .sum(),
+            ..Default::default()
+        }
+    }
+
+    fn expected_total(&self) -> f64 {
+        self.opening_total + self.deposits - self.withdrawals - self.charged_back - self.swept
+            + self.transferred
+    }
+
+    /// Checks the accounts' balances against the totals
+    pub fn report(&self, accounts: &Accounts) -> anyhow::Result<TrialBalanceReport> {
+        let total: f64 = accounts
+            .values()
+            .map(|client_account| client_account.balance.total())
+            .sum();
+        let held: f64 = accounts
+            .values()
+            .map(|client_account| client_account.balance.held)
+            .sum();
+        let expected_total = self.expected_total();
+        let expected_held = self.opening_held + self.held;
+        Ok(TrialBalanceReport {
+            opening_total: round_f64_4dp_string(self.opening_total)?,
+            deposits: round_f64_4dp_string(self.deposits)?,
+            withdrawals: round_f64_4dp_string(self.withdrawals)?,
+            charged_back: round_f64_4dp_string(self.charged_back)?,
+            swept: round_f64_4dp_string(self.swept)?,
+            transferred: round_f64_4dp_string(self.transferred)?,
+            expected_total: round_f64_4dp_string(expected_total)?,
+            total: round_f64_4dp_string(total)?,
+            expected_held: round_f64_4dp_string(expected_held)?,
+            held: round_f64_4dp_string(held)?,
+            balanced: (total - expected_total).abs() <= TRIAL_BALANCE_TOLERANCE
+                && (held - expected_held).abs() <= TRIAL_BALANCE_TOLERANCE,
+        })
+    }
+}
+
+impl JournalSink for TrialBalance {
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        let change = client_total_change(entry);
+        match entry.transaction_type {
+            TransactionType::Deposit => self.deposits += change,
+            TransactionType::Withdrawal => self.withdrawals -= change,
+            // a representment returns the charged back funds until the second decision
+            TransactionType::Chargeback
+            | TransactionType::Representment
+            | TransactionType::FinalChargeback => self.charged_back -= change,
+            TransactionType::CloseAccount => self.swept -= change,
+            TransactionType::Transfer => self.transferred += change,
+            // these only move funds between a client's available and held balances
+            TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::RepresentmentWon
+            | TransactionType::OpenAccount => {}
+        }
+        Ok(())
+    }
+
+    fn record_applied(&mut self, applied_transaction: &AppliedTransaction) -> anyhow::Result<()> {
+        self.held += applied_transaction.held_change;
+        self.record(&applied_transaction.journal_entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClientAccount;
+
+    #[test]
+    fn it_balances_against_the_accounts() {
+        let mut accounts = Accounts::default();
+        let mut opening_account = ClientAccount::new(1);
+        opening_account.balance.available = 10.0;
+        accounts.insert(1, opening_account);
+        let mut trial_balance = TrialBalance::opening(&accounts);
+
+        for entry in [
+            JournalEntry::new(1, 1, TransactionType::Deposit, 5.0),
+            JournalEntry::new(1, 2, TransactionType::Withdrawal, -2.0),
+            JournalEntry::new(1, 1, TransactionType::Dispute, 5.0),
+            JournalEntry::new(1, 1, TransactionType::Chargeback, 5.0),
+            JournalEntry::new(1, 3, TransactionType::Transfer, -1.0),
+            JournalEntry::new(2, 3, TransactionType::Transfer, 1.0),
+        ] {
+            trial_balance.record(&entry).unwrap();
+        }
+        accounts.get_mut(&1).unwrap().balance.available = 7.0;
+        let mut counterparty_account = ClientAccount::new(2);
+        counterparty_account.balance.available = 1.0;
+        accounts.insert(2, counterparty_account);
+
+        let report = trial_balance.report(&accounts).unwrap();
+        assert_eq!(report.deposits, "5.0000");
+        assert_eq!(report.withdrawals, "2.0000");
+        assert_eq!(report.charged_back, "5.0000");
+        assert_eq!(report.transferred, "0.0000");
+        assert_eq!(report.expected_total, "8.0000");
+        assert!(report.balanced);
+
+        // a balance changed without a journal entry
+        accounts.get_mut(&2).unwrap().balance.available = 2.0;
+        let report = trial_balance.report(&accounts).unwrap();
+        assert_eq!(report.total, "9.0000");
+        assert!(!report.balanced);
+    }
+}
@@ -0,0 +1,102 @@
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::TransactionType;
+
+/// Maps the values some feeds use in the `type` column, e.g. `credit=deposit,debit=withdrawal` or
+/// numeric codes, onto transaction types. Values which are neither an alias nor a transaction
+/// type are rejected like any other unknown type. Read from JSON as an object of value to
+/// transaction type, e.g. `{"credit": "deposit"}`.
+#[derive(Debug, Default, PartialEq, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct TypeAliases {
+    aliases: BTreeMap<String, TransactionType>,
+}
+
+impl TypeAliases {
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    pub fn get(&self, value: &str) -> Option<TransactionType> {
+        self.aliases.get(value.trim()).copied()
+    }
+
+    /// The record with an aliased value in the type column replaced by its transaction type
+    pub fn apply(&self, record: csv::StringRecord, type_index: Option<usize>) -> csv::StringRecord {
+        let transaction_type = match type_index
+            .and_then(|index| record.get(index))
+            .and_then(|value| self.get(value))
+        {
+            Some(transaction_type) => transaction_type.to_string(),
+            None => return record,
+        };
+        let mut aliased: csv::StringRecord = record
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                if Some(index) == type_index {
+                    transaction_type.as_str()
+                } else {
+                    field
+                }
+            })
+            .collect();
+        aliased.set_position(record.position().cloned());
+        aliased
+    }
+}
+
+impl std::str::FromStr for TypeAliases {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut aliases = BTreeMap::new();
+        for alias in s.split(',').filter(|alias| !alias.trim().is_empty()) {
+            let (value, transaction_type) = alias
+                .split_once('=')
+                .ok_or_else(|| format!("invalid type alias: {}", alias))?;
+            aliases.insert(value.trim().to_string(), transaction_type.trim().parse()?);
+        }
+        Ok(Self { aliases })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_replaces_aliased_types() {
+        let type_aliases: TypeAliases =
+            "credit=deposit,debit=withdrawal,1=deposit".parse().unwrap();
+        let from_json: TypeAliases =
+            serde_json::from_str(r#"{"credit": "deposit", "debit": "withdrawal", "1": "deposit"}"#)
+                .unwrap();
+        assert_eq!(from_json, type_aliases);
+
+        for (record, expected) in [
+            (vec!["1", "credit", "1.0"], vec!["1", "deposit", "1.0"]),
+            (vec!["1", " debit", "1.0"], vec!["1", "withdrawal", "1.0"]),
+            (vec!["1", "dispute", ""], vec!["1", "dispute", ""]),
+            (vec!["1", "refund", ""], vec!["1", "refund", ""]),
+        ] {
+            assert_eq!(
+                type_aliases.apply(csv::StringRecord::from(record), Some(1)),
+                csv::StringRecord::from(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn it_rejects_aliases_of_unknown_types() {
+        assert_eq!(
+            "credit=top-up".parse::<TypeAliases>(),
+            Err("unknown transaction type: top-up".to_string())
+        );
+        assert_eq!(
+            "credit".parse::<TypeAliases>(),
+            Err("invalid type alias: credit".to_string())
+        );
+    }
+}
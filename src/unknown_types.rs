@@ -0,0 +1,121 @@
+use serde_derive::Serialize;
+
+use crate::client_account::error::TransactionProcessingError;
+use crate::processing_options::UnknownTypePolicy;
+use crate::rejection::RejectionSink;
+use crate::serializable_form;
+use crate::submission_order::row_ids;
+
+/// An input row whose `type` is not a transaction type (after any type aliases), as read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownTypeRow {
+    pub line: u64,
+    pub value: String,
+    pub headers: csv::StringRecord,
+    pub record: csv::StringRecord,
+}
+
+impl std::fmt::Display for UnknownTypeRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "row {} has unknown transaction type: {} ({})",
+            self.line,
+            self.value,
+            row_ids(&self.headers, &self.record)
+        )
+    }
+}
+
+impl std::error::Error for UnknownTypeRow {}
+
+/// Counts of the rows with unknown types which were skipped, for the summary.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+pub struct UnknownTypeCounts {
+    pub rejected: usize,
+    pub dead_lettered: usize,
+}
+
+impl UnknownTypeCounts {
+    pub fn record(&mut self, policy: UnknownTypePolicy) {
+        match policy {
+            UnknownTypePolicy::Reject => self.rejected += 1,
+            UnknownTypePolicy::DeadLetter => self.dead_lettered += 1,
+            UnknownTypePolicy::Abort => {}
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Writes the rows dead-lettered for their unknown types as read, with the input's header, so
+/// they can be fed into a later run once the new types are supported.
+pub struct DeadLetterWriter<W: std::io::Write> {
+    writer: csv::Writer<W>,
+    wrote_headers: bool,
+}
+
+impl<W: std::io::Write> DeadLetterWriter<W> {
+    pub fn new(output_stream: W) -> Self {
+        Self {
+            writer: csv::WriterBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_writer(output_stream),
+            wrote_headers: false,
+        }
+    }
+}
+
+impl<W: std::io::Write> RejectionSink for DeadLetterWriter<W> {
+    fn record_rejection(
+        &mut self,
+        _transaction: &serializable_form::Transaction,
+        _error: &TransactionProcessingError,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn record_dead_letter(&mut self, row: &UnknownTypeRow) -> anyhow::Result<()> {
+        if !self.wrote_headers {
+            self.writer.write_record(&row.headers)?;
+            self.wrote_headers = true;
+        }
+        self.writer.write_record(&row.record)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_writes_dead_letters_as_read() {
+        let headers = csv::StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        let mut output = Vec::new();
+        let mut writer = DeadLetterWriter::new(&mut output);
+        for (line, record) in [
+            (3, vec!["refund", "1", "7", "2.5"]),
+            (5, vec!["fee", "2", "9", "0.1"]),
+        ] {
+            let record = csv::StringRecord::from(record);
+            writer
+                .record_dead_letter(&UnknownTypeRow {
+                    line,
+                    value: record[0].to_string(),
+                    headers: headers.clone(),
+                    record,
+                })
+                .unwrap();
+        }
+        drop(writer);
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "type,client,tx,amount\nrefund,1,7,2.5\nfee,2,9,0.1\n"
+        );
+    }
+}
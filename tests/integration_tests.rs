@@ -1,5 +1,7 @@
 use assert_cmd::prelude::*;
 use predicates::prelude::*;
+use rs_bpt::control_totals::Crc32Writer;
+use std::io::Write;
 use std::process::Command;
 
 #[test]
@@ -87,3 +89,1875 @@ fn test_complex() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn it_omits_untouched_and_small_accounts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-untouched.csv");
+    cmd.arg("--skip-untouched");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,1.0000,0.0000,1.0000,false"));
+    cmd.assert()
+        .stdout(predicate::str::contains("4,0.0000,0.0000,0.0000,false"));
+    cmd.assert().stdout(predicate::str::contains("\n3,").not());
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-untouched.csv");
+    cmd.arg("--min-abs-total");
+    cmd.arg("0.01");
+
+    cmd.assert().success();
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_formats_the_locked_column() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--bool-format");
+    cmd.arg("0/1");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,110.0000,0.0000,110.0000,0\n"));
+    cmd.assert()
+        .stdout(predicate::str::contains("2,1000.0000,0.0000,1000.0000,1\n"));
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_a_json_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let summary_file = std::env::temp_dir().join("rs_bpt-it_writes_a_json_summary.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--summary");
+    cmd.arg(&summary_file);
+
+    cmd.assert().success();
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&summary_file)?)?;
+    std::fs::remove_file(&summary_file)?;
+
+    assert_eq!(summary["client_count"], 2);
+    assert_eq!(summary["locked_count"], 1);
+    assert_eq!(summary["chargeback_count"], 1);
+    assert_eq!(summary["total_charged_back"], "100.0000");
+    assert_eq!(summary["negative_count"], 0);
+    assert_eq!(summary["total"], "1110.0000");
+    assert_eq!(summary["top_held"].as_array().unwrap().len(), 2);
+    assert_eq!(summary["metrics"]["deposit_amounts"]["max"], "1000.0000");
+    assert_eq!(summary["accounts_merkle_root"].as_str().unwrap().len(), 64);
+
+    Ok(())
+}
+
+#[test]
+fn it_verifies_the_trial_balance() -> Result<(), Box<dyn std::error::Error>> {
+    let summary_file = std::env::temp_dir().join("rs_bpt-it_verifies_the_trial_balance.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--verify");
+    cmd.arg("--summary");
+    cmd.arg(&summary_file);
+
+    cmd.assert().success();
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&summary_file)?)?;
+    std::fs::remove_file(&summary_file)?;
+
+    let trial_balance = &summary["trial_balance"];
+    assert_eq!(trial_balance["charged_back"], "100.0000");
+    assert_eq!(trial_balance["expected_total"], "1110.0000");
+    assert_eq!(trial_balance["total"], "1110.0000");
+    assert_eq!(trial_balance["balanced"], true);
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_a_journal() -> Result<(), Box<dyn std::error::Error>> {
+    let journal_file = std::env::temp_dir().join("rs_bpt-it_writes_a_journal.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--journal");
+    cmd.arg(&journal_file);
+
+    cmd.assert().success();
+
+    let journal = std::fs::read_to_string(&journal_file)?;
+    std::fs::remove_file(&journal_file)?;
+
+    assert!(journal.starts_with("tx,client,type,debit,credit,amount\n"));
+    assert!(journal.contains("1,1,deposit,suspense,client:1:available,1.0000\n"));
+    assert!(journal.contains("4,1,withdrawal,client:1:available,suspense,1.5000\n"));
+    assert_eq!(journal.lines().count(), 6);
+
+    Ok(())
+}
+
+#[cfg(feature = "plaintext-accounting")]
+#[test]
+fn it_writes_beancount_entries() -> Result<(), Box<dyn std::error::Error>> {
+    let beancount_file = std::env::temp_dir().join("rs_bpt-it_writes_beancount_entries.beancount");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--plaintext-accounting");
+    cmd.arg(&beancount_file);
+    cmd.arg("--plaintext-commodity");
+    cmd.arg("EUR");
+
+    cmd.assert().success();
+
+    let beancount = std::fs::read_to_string(&beancount_file)?;
+    std::fs::remove_file(&beancount_file)?;
+
+    assert!(beancount.contains(" open Liabilities:Clients:C1:Available EUR\n"));
+    assert!(beancount.contains("; client 2\n"));
+    assert!(beancount.contains("    Liabilities:Clients:C1:Available  1.5000 EUR\n"));
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_a_statement_per_client() -> Result<(), Box<dyn std::error::Error>> {
+    let statements_dir = std::env::temp_dir().join("rs_bpt-it_writes_a_statement_per_client");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--statements");
+    cmd.arg(&statements_dir);
+    cmd.arg("--statement-format");
+    cmd.arg("ofx");
+
+    cmd.assert().success();
+
+    let client_1_statement = std::fs::read_to_string(statements_dir.join("client-1.ofx"))?;
+    let client_2_statement = std::fs::read_to_string(statements_dir.join("client-2.ofx"))?;
+    std::fs::remove_dir_all(&statements_dir)?;
+
+    assert_eq!(client_1_statement.matches("<STMTTRN>").count(), 3);
+    assert!(client_1_statement.contains("<AVAILBAL><BALAMT>1.5000</BALAMT>"));
+    assert_eq!(client_2_statement.matches("<STMTTRN>").count(), 2);
+    assert!(client_2_statement.contains("<LEDGERBAL><BALAMT>-1.0000</BALAMT>"));
+
+    Ok(())
+}
+
+#[test]
+fn it_reports_rejected_deposits_in_suspense() -> Result<(), Box<dyn std::error::Error>> {
+    let suspense_file = std::env::temp_dir().join("rs_bpt-it_reports_rejected_deposits.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-with-dupes.csv");
+    cmd.arg("--suspense");
+    cmd.arg(&suspense_file);
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,1.0000,0.0000,1.0000,false"));
+
+    let suspense = std::fs::read_to_string(&suspense_file)?;
+    std::fs::remove_file(&suspense_file)?;
+
+    assert_eq!(
+        suspense,
+        "bucket,client,tx,type,amount,reason\nclient:1,1,1,deposit,2.0000,TransactionIDAlreadyExists: 1\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_control_totals() -> Result<(), Box<dyn std::error::Error>> {
+    let control_totals_file = std::env::temp_dir().join("rs_bpt-it_writes_control_totals.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--control-totals");
+    cmd.arg(&control_totals_file);
+
+    // the output order varies between runs so check the checksum against this run's output
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let mut sink = std::io::sink();
+    let mut crc32_writer = Crc32Writer::new(&mut sink);
+    crc32_writer.write_all(&output.stdout)?;
+
+    let control_totals: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&control_totals_file)?)?;
+    std::fs::remove_file(&control_totals_file)?;
+
+    assert_eq!(control_totals["client_count"], 2);
+    assert_eq!(control_totals["sum_available"], "0.5000");
+    assert_eq!(control_totals["sum_held"], "0.0000");
+    assert_eq!(control_totals["sum_total"], "0.5000");
+    assert_eq!(control_totals["checksum_algorithm"], "crc32");
+    assert_eq!(
+        control_totals["checksum"],
+        format!("{:08x}", crc32_writer.crc32())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_a_run_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest_file = std::env::temp_dir().join("rs_bpt-it_writes_a_run_manifest.json");
+    let journal_file = std::env::temp_dir().join("rs_bpt-it_writes_a_run_manifest-journal.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--journal");
+    cmd.arg(&journal_file);
+    cmd.arg("--manifest");
+    cmd.arg(&manifest_file);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&manifest_file)?)?;
+    let journal = std::fs::read(&journal_file)?;
+    std::fs::remove_file(&manifest_file)?;
+    std::fs::remove_file(&journal_file)?;
+
+    assert_eq!(manifest["crate_version"], env!("CARGO_PKG_VERSION"));
+    assert!(manifest["started_at"].as_u64() <= manifest["finished_at"].as_u64());
+    assert_eq!(manifest["summary"]["client_count"], 2);
+    assert_eq!(
+        manifest["inputs"][0]["bytes"],
+        std::fs::metadata("tests/fixtures/transactions.csv")?.len()
+    );
+    assert_eq!(manifest["outputs"].as_array().unwrap().len(), 1);
+    assert_eq!(manifest["outputs"][0]["bytes"], journal.len());
+    assert_eq!(manifest["stdout"]["bytes"], output.stdout.len());
+
+    Ok(())
+}
+
+#[test]
+fn it_prints_the_output_schema() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--schema-version");
+    cmd.arg("1");
+    cmd.arg("schema");
+    cmd.arg("--format");
+    cmd.arg("jsonschema");
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(schema["x-schema-version"], 1);
+    assert_eq!(schema["required"].as_array().unwrap().len(), 5);
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--schema-version");
+    cmd.arg("2");
+    cmd.arg("schema");
+    cmd.arg("--format");
+    cmd.arg("jsonschema");
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(schema["x-schema-version"], 2);
+    assert_eq!(schema["x-column-order"].as_array().unwrap().len(), 12);
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--schema-version");
+    cmd.arg("3");
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown output schema version: 3"));
+
+    Ok(())
+}
+
+#[test]
+fn it_prints_the_input_schema_as_arrow() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("schema");
+    cmd.arg("input");
+    cmd.arg("--format");
+    cmd.arg("arrow");
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(schema["fields"].as_array().unwrap().len(), 5);
+    assert_eq!(schema["fields"][1]["name"], "client");
+    assert_eq!(schema["fields"][1]["type"]["bitWidth"], 16);
+
+    Ok(())
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn it_writes_an_arrow_ipc_file() -> Result<(), Box<dyn std::error::Error>> {
+    let arrow_file = std::env::temp_dir().join("rs_bpt-it_writes_an_arrow_ipc_file.arrow");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--arrow-ipc");
+    cmd.arg(&arrow_file);
+
+    cmd.assert().success();
+
+    let reader = arrow::ipc::reader::FileReader::try_new(std::fs::File::open(&arrow_file)?, None)?;
+    let num_rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+    std::fs::remove_file(&arrow_file)?;
+
+    assert_eq!(num_rows, 2);
+
+    Ok(())
+}
+
+#[test]
+fn it_rejects_dispute_ops_for_unknown_clients() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-unknown-clients.csv");
+    cmd.arg("--implicit-accounts");
+    cmd.arg("deny-for-dispute-ops");
+    cmd.arg("--debug");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,6.0000,0.0000,6.0000,false"));
+    cmd.assert()
+        .stdout(predicate::str::contains("3,0.0000,0.0000,0.0000,false"));
+    cmd.assert().stdout(predicate::str::contains("\n2,").not());
+    cmd.assert()
+        .stderr(predicate::str::contains("ClientNotFound: 2"));
+
+    Ok(())
+}
+
+#[test]
+fn it_transfers_between_clients() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-transfers.csv");
+    cmd.arg("--debug");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("\n1,6.0000,0.0000,6.0000,false"));
+    cmd.assert()
+        .stdout(predicate::str::contains("\n2,4.0000,0.0000,4.0000,false"));
+    cmd.assert()
+        .stderr(predicate::str::contains(
+            "CounterpartyNotPresentForTransfer: 3",
+        ))
+        .stderr(predicate::str::contains("AccountClosed: 3"));
+
+    Ok(())
+}
+
+#[test]
+fn it_locates_disputes_of_another_clients_transaction() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-cross-client-disputes.csv");
+    cmd.arg("--debug");
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "ReferencedTransactionNotFound: 1 (client 2)",
+        ))
+        .stderr(predicate::str::contains("ClientMismatchOnDispute").not());
+
+    cmd.arg("--cross-client-disputes");
+    cmd.arg("locate");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("\n1,0.0000,5.0000,5.0000,false"));
+    cmd.assert()
+        .stdout(predicate::str::contains("\n2,3.0000,0.0000,3.0000,false"));
+    cmd.assert()
+        .stderr(predicate::str::contains(
+            "ClientMismatchOnDispute: 1 (client 2)",
+        ))
+        .stderr(predicate::str::contains(
+            "transaction 2 belongs to client 2, not client 3",
+        ));
+
+    // the other shards' accounts can't be searched
+    cmd.arg("--threads");
+    cmd.arg("2");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--cross-client-disputes locate can't search across the shards of --threads",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_sweeps_closed_accounts_to_the_sweep_client() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-close-account.csv");
+    cmd.arg("--sweep-client");
+    cmd.arg("9");
+    cmd.arg("--debug");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("\n1,0.0000,0.0000,0.0000,false"));
+    cmd.assert()
+        .stdout(predicate::str::contains("\n9,7.5000,0.0000,7.5000,false"));
+    cmd.assert()
+        .stderr(predicate::str::contains("AccountHasPendingDisputes: 1"))
+        .stderr(predicate::str::contains("AccountClosed: 1"));
+
+    Ok(())
+}
+
+#[test]
+fn it_rejects_dispute_ops_for_unknown_clients_by_default() -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-unknown-clients.csv");
+    cmd.arg("--debug");
+
+    cmd.assert().success();
+    cmd.assert().stdout(predicate::str::contains("\n2,").not());
+    cmd.assert()
+        .stderr(predicate::str::contains("ClientNotFound: 2"));
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-unknown-clients.csv");
+    cmd.arg("--implicit-accounts");
+    cmd.arg("allow");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("2,0.0000,0.0000,0.0000,false"));
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_retryable_rejections_in_the_input_format() -> Result<(), Box<dyn std::error::Error>> {
+    let retry_file = std::env::temp_dir().join("rs_bpt-it_writes_retryable_rejections.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-unknown-clients.csv");
+    cmd.arg("--implicit-accounts");
+    cmd.arg("deny-for-dispute-ops");
+    cmd.arg("--retry");
+    cmd.arg(&retry_file);
+
+    cmd.assert().success();
+
+    let retry = std::fs::read_to_string(&retry_file)?;
+    std::fs::remove_file(&retry_file)?;
+    assert_eq!(retry, "type,client,tx,amount,counterparty\ndispute,2,1,,\n");
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_an_error_report() -> Result<(), Box<dyn std::error::Error>> {
+    let errors_file = std::env::temp_dir().join("rs_bpt-it_writes_an_error_report.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-with-dupes.csv");
+    cmd.arg("--errors");
+    cmd.arg(&errors_file);
+    cmd.arg("--errors-format");
+    cmd.arg("json");
+
+    cmd.assert().success();
+
+    let errors: serde_json::Value = serde_json::from_reader(std::fs::File::open(&errors_file)?)?;
+    std::fs::remove_file(&errors_file)?;
+    assert_eq!(
+        errors,
+        serde_json::json!([{
+            "line": 3,
+            "client": 1,
+            "tx": 1,
+            "type": "deposit",
+            "error": "TransactionIDAlreadyExists",
+            "message": "TransactionIDAlreadyExists: 1"
+        }])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_applies_out_of_order_disputes_with_two_pass_ordering(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-out-of-order.csv");
+    cmd.arg("--ordering");
+    cmd.arg("two-pass");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,0.0000,0.0000,0.0000,true"));
+    cmd.assert()
+        .stdout(predicate::str::contains("2,0.0000,2.0000,2.0000,false"));
+
+    Ok(())
+}
+
+#[test]
+fn it_applies_transactions_in_external_sequence_order() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-sequenced.csv");
+    cmd.arg("--submission-order");
+    cmd.arg("external-sequence");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,1.0000,4.0000,5.0000,false"));
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-sequenced.csv");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,5.0000,0.0000,5.0000,false"));
+
+    Ok(())
+}
+
+#[test]
+fn it_orders_by_timestamps_in_the_configured_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-timestamped-rfc3339.csv");
+    cmd.arg("--submission-order");
+    cmd.arg("per-client-timestamp");
+    cmd.arg("--timestamp-format");
+    cmd.arg("rfc3339");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "row 4 (client 2, tx 4) has no valid timestamp (ambiguous timestamp (a local time with no timezone): 2022-03-01 12:00:00)",
+    ));
+
+    // the local time is 10:00 UTC, after client 2's deposit
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-timestamped-rfc3339.csv");
+    cmd.arg("--submission-order");
+    cmd.arg("per-client-timestamp");
+    cmd.arg("--timestamp-format");
+    cmd.arg("rfc3339");
+    cmd.arg("--timestamp-timezone");
+    cmd.arg("+02:00");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,6.0000,0.0000,6.0000,false"))
+        .stdout(predicate::str::contains("2,4.0000,0.0000,4.0000,false"));
+
+    Ok(())
+}
+
+#[test]
+fn it_simulates_injected_anomalies() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("simulate");
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--duplicate-rate");
+    cmd.arg("1");
+
+    cmd.assert().success();
+    cmd.assert().stdout(predicate::str::starts_with(
+        "row,anomaly,type,client,tx,outcome\n1,duplicate-transaction-id,deposit,1,1,TransactionIDAlreadyExists: 1\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_records_the_simulation_seed_in_the_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest_file = std::env::temp_dir().join("rs_bpt-it_records_the_simulation_seed.json");
+
+    let mut reports = Vec::new();
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("rs_bpt")?;
+        cmd.arg("--manifest");
+        cmd.arg(&manifest_file);
+        cmd.arg("simulate");
+        cmd.arg("tests/fixtures/transactions-complex.csv");
+        cmd.arg("--duplicate-rate");
+        cmd.arg("0.5");
+        cmd.arg("--seed");
+        cmd.arg("42");
+        reports.push(cmd.assert().success().get_output().stdout.clone());
+    }
+    assert_eq!(reports[0], reports[1]);
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&manifest_file)?)?;
+    std::fs::remove_file(&manifest_file)?;
+
+    assert_eq!(manifest["seed"], 42);
+    assert_eq!(manifest["stdout"]["bytes"], reports[0].len());
+    assert!(manifest.get("summary").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn it_fails_on_benchmark_regressions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("bench-compare");
+    cmd.arg("tests/fixtures/bench-baseline.json");
+    cmd.arg("tests/fixtures/bench-current.json");
+
+    cmd.assert().failure().code(1).stdout(
+        "benchmark,baseline_ns,current_ns,change_percent,regressed\n\
+         process_transactions_file,2000000.0,2300000.0,15.00,true\n\
+         scan_size_hints,400000.0,404000.0,1.00,false\n",
+    );
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("bench-compare");
+    cmd.arg("tests/fixtures/bench-baseline.json");
+    cmd.arg("tests/fixtures/bench-current.json");
+    cmd.arg("--max-regression");
+    cmd.arg("20");
+
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_bench_results() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("bench");
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--iterations");
+    cmd.arg("2");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let results: serde_json::Value = serde_json::from_slice(&output)?;
+    assert!(
+        results["process_transactions_file"]["mean_ns"]
+            .as_f64()
+            .unwrap()
+            > 0.0
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_explains_balance_changes_between_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("explain");
+    cmd.arg("--before");
+    cmd.arg("tests/fixtures/snapshot-before.csv");
+    cmd.arg("--after");
+    cmd.arg("tests/fixtures/snapshot-after.csv");
+    cmd.arg("--journal");
+    cmd.arg("tests/fixtures/journal.csv");
+
+    cmd.assert().success();
+    cmd.assert().stdout(
+        r#"client,tx,reason,available,held,total
+9,7,withdrawal,-42.1000,0.0000,-42.1000
+9,8,deposit,5.0000,0.0000,5.0000
+9,8,dispute,-5.0000,5.0000,0.0000
+9,,unexplained,-5.0000,0.0000,-5.0000
+"#,
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_carries_open_dispute_cases_into_a_later_run() -> Result<(), Box<dyn std::error::Error>> {
+    let cases_file = std::env::temp_dir().join("rs_bpt-it_carries_open_dispute_cases.csv");
+    let next_cases_file =
+        std::env::temp_dir().join("rs_bpt-it_carries_open_dispute_cases-next.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-open-dispute.csv");
+    cmd.arg("--dispute-cases");
+    cmd.arg(&cases_file);
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-chargeback-open-case.csv");
+    cmd.arg("--open-dispute-cases");
+    cmd.arg(&cases_file);
+    cmd.arg("--dispute-cases");
+    cmd.arg(&next_cases_file);
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,0.0000,0.0000,0.0000,true"));
+
+    let cases = std::fs::read_to_string(&cases_file)?;
+    let next_cases = std::fs::read_to_string(&next_cases_file)?;
+    std::fs::remove_file(&cases_file)?;
+    std::fs::remove_file(&next_cases_file)?;
+    assert_eq!(
+        cases,
+        "case_id,client,tx,amount,outcome\n1-1,1,1,5.0000,open\n"
+    );
+    assert_eq!(
+        next_cases,
+        "case_id,client,tx,amount,outcome\n1-1,1,1,5.0000,charged-back\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_checks_conformance_with_an_expected_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("conformance");
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--expected");
+    cmd.arg("tests/fixtures/transactions-expected.csv");
+
+    cmd.assert().success();
+    cmd.assert()
+        .stdout("client,discrepancy,column,expected,actual\n");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("conformance");
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--expected");
+    cmd.arg("tests/fixtures/transactions-expected.csv");
+
+    cmd.assert().failure().code(1);
+    cmd.assert()
+        .stdout(predicate::str::contains("amount-delta"));
+
+    Ok(())
+}
+
+#[test]
+fn it_prints_end_of_day_balance_history() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("balance-history");
+    cmd.arg("tests/fixtures/transactions-timestamped.csv");
+
+    cmd.assert().success();
+    cmd.assert().stdout(
+        r#"client,date,available,held,locked
+1,2022-03-01,11.0000,0.0000,false
+1,2022-03-02,11.0000,0.0000,false
+1,2022-03-03,7.0000,0.0000,false
+2,2022-03-01,5.0000,0.0000,false
+2,2022-03-02,0.0000,5.0000,false
+2,2022-03-03,0.0000,5.0000,false
+"#,
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn it_compresses_the_output_and_journal() -> Result<(), Box<dyn std::error::Error>> {
+    let journal_file = std::env::temp_dir().join("rs_bpt-it_compresses_the_journal.csv.gz");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--compress");
+    cmd.arg("gz");
+    cmd.arg("--journal");
+    cmd.arg(&journal_file);
+    cmd.arg("tests/fixtures/transactions.csv");
+
+    cmd.assert().success();
+    cmd.assert().stdout(predicate::function(|output: &[u8]| {
+        output.starts_with(&[0x1f, 0x8b, 8])
+    }));
+    let journal = std::fs::read(&journal_file)?;
+    assert!(journal.starts_with(&[0x1f, 0x8b, 8]));
+
+    std::fs::remove_file(&journal_file)?;
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn it_names_compressed_output_parts_gz() -> Result<(), Box<dyn std::error::Error>> {
+    let prefix = std::env::temp_dir().join("rs_bpt-it_names_compressed_output_parts_gz");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--output-parts");
+    cmd.arg(&prefix);
+    cmd.arg("--compress");
+    cmd.arg("gz");
+    cmd.arg("tests/fixtures/transactions.csv");
+
+    cmd.assert().success();
+
+    let part_file = format!("{}-00001.csv.gz", prefix.display());
+    assert!(std::fs::read(&part_file)?.starts_with(&[0x1f, 0x8b, 8]));
+    std::fs::remove_file(&part_file)?;
+    let manifest_file = format!("{}-manifest.json", prefix.display());
+    let manifest = std::fs::read_to_string(&manifest_file)?;
+    assert!(manifest.contains(&format!("{:?}", part_file)));
+    std::fs::remove_file(&manifest_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_splits_the_output_into_parts() -> Result<(), Box<dyn std::error::Error>> {
+    let prefix = std::env::temp_dir().join("rs_bpt-it_splits_the_output");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--output-parts");
+    cmd.arg(&prefix);
+    cmd.arg("--max-output-rows");
+    cmd.arg("1");
+    cmd.arg("tests/fixtures/transactions.csv");
+
+    cmd.assert().success();
+    cmd.assert().stdout("");
+
+    let mut rows = Vec::new();
+    for part in 1..=2 {
+        let part_file = format!("{}-{:05}.csv", prefix.display(), part);
+        let contents = std::fs::read_to_string(&part_file)?;
+        let mut lines = contents.lines();
+        assert_eq!(lines.next(), Some("client,available,held,total,locked"));
+        rows.extend(lines.map(str::to_string));
+        std::fs::remove_file(&part_file)?;
+    }
+    rows.sort();
+    assert_eq!(
+        rows,
+        vec![
+            "1,1.5000,0.0000,1.5000,false",
+            "2,-1.0000,0.0000,-1.0000,false"
+        ]
+    );
+
+    let manifest_file = format!("{}-manifest.json", prefix.display());
+    let manifest = std::fs::read_to_string(&manifest_file)?;
+    assert!(manifest.contains(r#""rows": 2"#));
+    std::fs::remove_file(&manifest_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_keeps_the_output_parts_of_each_shard_apart() -> Result<(), Box<dyn std::error::Error>> {
+    let prefix = std::env::temp_dir().join("rs_bpt-it_keeps_the_output_parts_of_each_shard_apart");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--output-parts");
+    cmd.arg(&prefix);
+    cmd.arg("--shard-output-parts");
+    cmd.arg("--threads");
+    cmd.arg("2");
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.assert().success().stdout("");
+
+    for (shard, row) in [
+        (0, "2,-1.0000,0.0000,-1.0000,false"),
+        (1, "1,1.5000,0.0000,1.5000,false"),
+    ] {
+        let part_file = format!("{}-shard-{:02}-00001.csv", prefix.display(), shard);
+        assert_eq!(
+            std::fs::read_to_string(&part_file)?,
+            format!("client,available,held,total,locked\n{}\n", row)
+        );
+        std::fs::remove_file(&part_file)?;
+    }
+    let manifest_file = format!("{}-manifest.json", prefix.display());
+    let manifest = std::fs::read_to_string(&manifest_file)?;
+    assert!(manifest.contains(r#""shards": 2"#));
+    assert!(manifest.contains(r#""shard": 1"#));
+    std::fs::remove_file(&manifest_file)?;
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--output-parts");
+    cmd.arg(&prefix);
+    cmd.arg("--shard-output-parts");
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--shard-output-parts needs the shards of --threads",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_reads_comma_decimal_amounts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--amount-format");
+    cmd.arg("comma-decimal");
+    cmd.arg("tests/fixtures/transactions-comma-decimal.csv");
+
+    // client 2's only deposit is rejected before it can create the account
+    cmd.assert().success();
+    cmd.assert().stdout(
+        r#"client,available,held,total,locked
+1,1334.0600,0.0000,1334.0600,false
+"#,
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_reads_spreadsheet_amounts_leniently_and_logs_them() -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--debug");
+    cmd.arg("--amount-format");
+    cmd.arg("lenient");
+    cmd.arg("tests/fixtures/transactions-spreadsheet.csv");
+
+    cmd.assert().success();
+    cmd.assert().stdout(
+        r#"client,available,held,total,locked
+1,1212.5000,0.0000,1212.5000,false
+"#,
+    );
+    cmd.assert().stderr(
+        predicate::str::contains(
+            r#"amount "$1,234.50" of transaction 1 (client 1) read as 1234.5"#,
+        )
+        .and(predicate::str::contains(
+            r#"amount "€34.50" of transaction 2 (client 1) read as 34.5"#,
+        )),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_keeps_batches_in_a_batch_store() -> Result<(), Box<dyn std::error::Error>> {
+    let batch_store = std::env::temp_dir().join("rs_bpt-it_keeps_batches");
+    let _ = std::fs::remove_dir_all(&batch_store);
+
+    for input_file in [
+        "tests/fixtures/transactions.csv",
+        "tests/fixtures/transactions-complex.csv",
+    ] {
+        let mut cmd = Command::cargo_bin("rs_bpt")?;
+        cmd.arg("--batch-store");
+        cmd.arg(&batch_store);
+        cmd.arg(input_file);
+        cmd.assert().success();
+    }
+    let summary = std::fs::read_to_string(batch_store.join("2").join("summary.json"))?;
+    assert!(summary.contains(r#""batch_id": "2""#));
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("balances-as-of");
+    cmd.arg("1");
+    cmd.arg("--batch-store");
+    cmd.arg(&batch_store);
+    cmd.assert().success();
+    cmd.assert().stdout(
+        r#"client,available,held,total,locked
+1,1.5000,0.0000,1.5000,false
+2,-1.0000,0.0000,-1.0000,false
+"#,
+    );
+
+    std::fs::remove_dir_all(&batch_store)?;
+    Ok(())
+}
+
+#[test]
+fn it_applies_the_resolve_without_dispute_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let retry_file = std::env::temp_dir().join("rs_bpt-it_applies_the_resolve_policy-retry.csv");
+    let review_file = std::env::temp_dir().join("rs_bpt-it_applies_the_resolve_policy-review.csv");
+
+    // the case of transaction 1 is known from the earlier run, transaction 9 is not
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--resolve-without-dispute");
+    cmd.arg("accept-if-case-known");
+    cmd.arg("--open-dispute-cases");
+    cmd.arg("tests/fixtures/dispute-cases-resolved.csv");
+    cmd.arg("--retry");
+    cmd.arg(&retry_file);
+    cmd.arg("tests/fixtures/transactions-late-resolves.csv");
+    cmd.assert().success();
+    assert_eq!(
+        std::fs::read_to_string(&retry_file)?,
+        "type,client,tx,amount,counterparty\nresolve,1,9,,\n"
+    );
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("--resolve-without-dispute");
+    cmd.arg("park-for-review");
+    cmd.arg("--retry");
+    cmd.arg(&retry_file);
+    cmd.arg("--review");
+    cmd.arg(&review_file);
+    cmd.arg("tests/fixtures/transactions-late-resolves.csv");
+    cmd.assert().success();
+    cmd.assert()
+        .stdout(predicate::str::contains("1,3.0000,0.0000,3.0000,false"));
+    assert_eq!(
+        std::fs::read_to_string(&retry_file)?,
+        "type,client,tx,amount,counterparty\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(&review_file)?,
+        "type,client,tx,amount,counterparty\nresolve,1,1,,\nresolve,1,9,,\n"
+    );
+
+    std::fs::remove_file(&retry_file)?;
+    std::fs::remove_file(&review_file)?;
+    Ok(())
+}
+
+#[test]
+fn it_carries_client_notes_into_the_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--client-notes");
+    cmd.arg("tests/fixtures/client-notes.csv");
+
+    // version 1 of the output schema is frozen
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "output schema version 1 has no flags, notes columns (they are in version 2)",
+    ));
+
+    cmd.arg("--schema-version");
+    cmd.arg("2");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "client,available,held,total,locked,flags,notes\n",
+        ))
+        .stdout(predicate::str::contains(
+            "1,1.5000,0.0000,1.5000,false,under-investigation,\"ticket 4411; chargeback pattern, see ticket 4411\"\n",
+        ))
+        .stdout(predicate::str::contains("2,-1.0000,0.0000,-1.0000,false,,\n"));
+
+    Ok(())
+}
+
+#[test]
+fn it_suspends_the_excess_over_a_balance_cap() -> Result<(), Box<dyn std::error::Error>> {
+    let suspense_file =
+        std::env::temp_dir().join("rs_bpt-it_suspends_the_excess_over_a_balance_cap.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--balance-caps");
+    cmd.arg("tests/fixtures/balance-caps.csv");
+    cmd.arg("--balance-cap-exceeded");
+    cmd.arg("suspend-excess");
+    cmd.arg("--suspense");
+    cmd.arg(&suspense_file);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,1.5000,0.0000,1.5000,false"))
+        .stdout(predicate::str::contains("2,-1.5000,0.0000,-1.5000,false"));
+
+    let suspense = std::fs::read_to_string(&suspense_file)?;
+    std::fs::remove_file(&suspense_file)?;
+
+    assert_eq!(
+        suspense,
+        "bucket,client,tx,type,amount,reason\n\
+         client:2,2,2,deposit,0.5000,BalanceCapExceeded: 2\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_applies_the_options_of_each_clients_tier() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--tiers");
+    cmd.arg("tests/fixtures/tiers.json");
+    cmd.arg("--client-tiers");
+    cmd.arg("tests/fixtures/client-tiers.csv");
+
+    // the retail tier's balance cap rejects client 2's deposit
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,1.5000,0.0000,1.5000,false"))
+        .stdout(predicate::str::contains("2,-3.0000,0.0000,-3.0000,false"));
+
+    Ok(())
+}
+
+#[test]
+fn it_follows_chargebacks_through_representment() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-representment.csv");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,110.0000,0.0000,110.0000,true"))
+        .stdout(predicate::str::contains("2,0.0000,0.0000,0.0000,true"));
+
+    Ok(())
+}
+
+#[test]
+fn it_applies_arbitration_outcomes_after_the_transactions() -> Result<(), Box<dyn std::error::Error>>
+{
+    let cases_file = std::env::temp_dir().join("rs_bpt-it_applies_arbitration_outcomes.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--open-dispute-cases");
+    cmd.arg("tests/fixtures/dispute-cases-open.csv");
+    cmd.arg("--arbitration-outcomes");
+    cmd.arg("tests/fixtures/arbitration-outcomes.csv");
+    cmd.arg("--dispute-cases");
+    cmd.arg(&cases_file);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("3,0.0000,0.0000,0.0000,true"))
+        .stdout(predicate::str::contains("4,3.0000,0.0000,3.0000,false"));
+
+    let cases = std::fs::read_to_string(&cases_file)?;
+    std::fs::remove_file(&cases_file)?;
+    assert_eq!(
+        cases,
+        "case_id,client,tx,amount,outcome\n\
+         3-10,3,10,5.0000,charged-back\n\
+         4-11,4,11,3.0000,resolved\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_maps_upstream_column_names() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-upstream-headers.csv");
+    cmd.arg("--map");
+    cmd.arg("txn_kind=type,cust=client,ref=tx,value=amount");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,1.5000,0.0000,1.5000,false"))
+        .stdout(predicate::str::contains("2,-1.0000,0.0000,-1.0000,false"));
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--map");
+    cmd.arg("kind=category");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "cannot map column kind onto unknown column category",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_maps_type_aliases_onto_transaction_types() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-type-codes.csv");
+    cmd.arg("--type-aliases");
+    cmd.arg("credit=deposit,debit=withdrawal,1=deposit,2=withdrawal");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,1.5000,0.0000,1.5000,false"))
+        .stdout(predicate::str::contains("2,-1.0000,0.0000,-1.0000,false"));
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-type-codes.csv");
+    cmd.arg("--type-aliases");
+    cmd.arg("credit=deposit,debit=withdrawal");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "row 3 has unknown transaction type: 1",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_skips_rows_with_unknown_types_per_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-unknown-types.csv");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "row 4 has unknown transaction type: refund",
+    ));
+
+    let dead_letter_file = std::env::temp_dir().join("rs_bpt-it_skips_rows_with_unknown_types.csv");
+    let summary_file =
+        std::env::temp_dir().join("rs_bpt-it_skips_rows_with_unknown_types-summary.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-unknown-types.csv");
+    cmd.arg("--unknown-type");
+    cmd.arg("dead-letter");
+    cmd.arg("--dead-letter");
+    cmd.arg(&dead_letter_file);
+    cmd.arg("--summary");
+    cmd.arg(&summary_file);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,-0.5000,0.0000,-0.5000,false"))
+        .stdout(predicate::str::contains("2,2.0000,0.0000,2.0000,false"));
+
+    let dead_letters = std::fs::read_to_string(&dead_letter_file)?;
+    std::fs::remove_file(&dead_letter_file)?;
+    assert_eq!(
+        dead_letters,
+        "type,client,tx,amount\nrefund,1,3,2.0\nfee,2,5,0.5\n"
+    );
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&summary_file)?)?;
+    std::fs::remove_file(&summary_file)?;
+    assert_eq!(
+        summary["unknown_types"],
+        serde_json::json!({"rejected": 0, "dead_lettered": 2})
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_reports_partial_progress_when_aborted() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_file = std::env::temp_dir().join("rs_bpt-it_reports_partial_progress.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-unknown-types.csv");
+    cmd.arg("--partial-snapshot");
+    cmd.arg(&snapshot_file);
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("\"rows_processed\": 2"))
+        .stderr(predicate::str::contains("\"last_good_line\": 3"))
+        .stderr(predicate::str::contains("\"last_client\": 2"));
+
+    let snapshot = std::fs::read_to_string(&snapshot_file)?;
+    std::fs::remove_file(&snapshot_file)?;
+    assert!(snapshot.starts_with("client,available,held,total,locked\n"));
+    assert!(snapshot.contains("1,1.0000,0.0000,1.0000,false\n"));
+    assert!(snapshot.contains("2,2.0000,0.0000,2.0000,false\n"));
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_the_debug_log_to_a_file() -> Result<(), Box<dyn std::error::Error>> {
+    let log_file = std::env::temp_dir().join("rs_bpt-it_writes_the_debug_log_to_a_file.log");
+    let _ = std::fs::remove_file(&log_file);
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-unknown-types.csv");
+    cmd.arg("--unknown-type");
+    cmd.arg("reject");
+    cmd.arg("--debug-log");
+    cmd.arg(format!("file:{}", log_file.display()));
+
+    cmd.assert().success().stderr(predicate::str::is_empty());
+
+    let log = std::fs::read_to_string(&log_file)?;
+    std::fs::remove_file(&log_file)?;
+    assert!(log.contains(
+        "error processing row - row 4 has unknown transaction type: refund (client 1, tx 3)\n"
+    ));
+    assert!(log.contains(
+        "error processing row - row 6 has unknown transaction type: fee (client 2, tx 5)\n"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_reads_the_input_from_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("-");
+    cmd.stdin(std::fs::File::open("tests/fixtures/transactions.csv")?);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,1.5000,0.0000,1.5000,false"))
+        .stdout(predicate::str::contains("2,-1.0000,0.0000,-1.0000,false"));
+
+    Ok(())
+}
+
+#[test]
+fn it_suppresses_repeated_errors_in_the_debug_log() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-orphan-disputes.csv");
+    cmd.arg("--debug");
+    cmd.arg("--log-dedup");
+    cmd.arg("1");
+
+    cmd.assert().success().stderr(
+        "error processing transaction - ReferencedTransactionNotFound: 11 (client 1)\n\
+         ClientAccountTransaction { transaction_type: Dispute, transaction_id: 11, amount: None }\n\
+         error processing transaction - ClientNotFound: 2 (client 2)\n\
+         Transaction { transaction_type: Dispute, client_id: 2, transaction_id: 13, amount: None, counterparty: None }\n\
+         suppressed 3 similar ReferencedTransactionNotFound errors (client 1)\n",
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_reports_why_accounts_were_locked() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-representment.csv");
+    cmd.arg("--lock-reasons");
+    cmd.arg("--schema-version");
+    cmd.arg("2");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "client,available,held,total,locked,lock_tx,lock_case\n",
+        ))
+        .stdout(predicate::str::is_match("\n1,[^\n]*,true,1,1-1\n")?)
+        .stdout(predicate::str::is_match("\n2,[^\n]*,true,3,2-3\n")?);
+
+    Ok(())
+}
+
+#[test]
+fn it_reports_chargeback_exposure_and_closes_accounts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-representment.csv");
+    cmd.arg("--chargeback-totals");
+    cmd.arg("--max-chargebacks");
+    cmd.arg("0");
+    cmd.arg("--schema-version");
+    cmd.arg("2");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "client,available,held,total,locked,chargebacks,charged_back,closed\n",
+        ))
+        .stdout(predicate::str::contains(
+            "1,110.0000,0.0000,110.0000,true,1,100.0000,true\n",
+        ))
+        .stdout(predicate::str::contains(
+            "2,0.0000,0.0000,0.0000,true,1,50.0000,true\n",
+        ));
+
+    Ok(())
+}
+
+#[cfg(feature = "memprofile")]
+#[test]
+fn it_reports_memory_use_in_the_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let summary_file = std::env::temp_dir().join("rs_bpt-it_reports_memory_use.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--summary");
+    cmd.arg(&summary_file);
+
+    cmd.assert().success();
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&summary_file)?)?;
+    std::fs::remove_file(&summary_file)?;
+
+    assert_eq!(summary["memory"]["accounts"], 2);
+    assert_eq!(summary["memory"]["retained_transactions"], 4);
+    assert!(summary["memory"]["allocations"]["processing"]["allocations"].as_u64() > Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn it_rejects_deposits_and_withdrawals_on_locked_accounts() -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-locked.csv");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,65.0000,0.0000,65.0000,true\n"));
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-locked.csv");
+    cmd.arg("--locked-accounts");
+    cmd.arg("reject");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,20.0000,0.0000,20.0000,true\n"));
+
+    Ok(())
+}
+
+#[test]
+fn it_continues_from_a_state_snapshot() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_file = std::env::temp_dir().join("rs_bpt-it_continues_from_a_state_snapshot.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-open-dispute.csv");
+    cmd.arg("--snapshot-out");
+    cmd.arg(&snapshot_file);
+    cmd.assert().success();
+
+    let snapshot = std::fs::read_to_string(&snapshot_file)?;
+    assert!(snapshot.starts_with("{\"version\":1,\"accounts\":["));
+
+    // the dispute left open by the first run is charged back in the second
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-chargeback-open-case.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    let assert = cmd.assert();
+    std::fs::remove_file(&snapshot_file)?;
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("1,0.0000,0.0000,0.0000,true\n"))
+        .stdout(predicate::str::contains("2,1.0000,0.0000,1.0000,false\n"));
+
+    Ok(())
+}
+
+#[test]
+fn it_diffs_a_dry_run_against_the_loaded_state() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_file =
+        std::env::temp_dir().join("rs_bpt-it_diffs_a_dry_run_against_the_loaded_state.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-open-dispute.csv");
+    cmd.arg("--snapshot-out");
+    cmd.arg(&snapshot_file);
+    cmd.assert().success();
+    let snapshot = std::fs::read_to_string(&snapshot_file)?;
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-chargeback-open-case.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--dry-run");
+    cmd.assert()
+        .success()
+        .stdout("client,available,held,total,newly_locked\n1,0.0000,-5.0000,-5.0000,true\n");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-chargeback-open-case.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--snapshot-out");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--dry-run");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--dry-run leaves the state as it was, so can't write --snapshot-out",
+    ));
+
+    // the state is left as it was
+    assert_eq!(std::fs::read_to_string(&snapshot_file)?, snapshot);
+    std::fs::remove_file(&snapshot_file)?;
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-chargeback-open-case.csv");
+    cmd.arg("--dry-run");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--dry-run needs the state of --snapshot-in to diff against",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_diffs_a_dry_run_against_the_imported_open_cases() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_file =
+        std::env::temp_dir().join("rs_bpt-it_diffs_a_dry_run_against_the_imported_open_cases.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--snapshot-out");
+    cmd.arg(&snapshot_file);
+    cmd.assert().success();
+
+    // the open cases' held funds are part of the state the run starts from
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-backfill-next-day.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--open-dispute-cases");
+    cmd.arg("tests/fixtures/dispute-cases-open.csv");
+    cmd.arg("--dry-run");
+    let assert = cmd.assert();
+    std::fs::remove_file(&snapshot_file)?;
+
+    assert
+        .success()
+        .stdout("client,available,held,total,newly_locked\n2,1.0000,0.0000,1.0000,false\n");
+
+    Ok(())
+}
+
+#[test]
+fn it_backfills_only_the_transactions_not_in_the_loaded_state(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_file = std::env::temp_dir()
+        .join("rs_bpt-it_backfills_only_the_transactions_not_in_the_loaded_state.json");
+    let summary_file = std::env::temp_dir()
+        .join("rs_bpt-it_backfills_only_the_transactions_not_in_the_loaded_state-summary.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-open-dispute.csv");
+    cmd.arg("--snapshot-out");
+    cmd.arg(&snapshot_file);
+    cmd.assert().success();
+
+    // the deposits of the first run are skipped, but the repeated id of this run is still a
+    // duplicate
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-backfill.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--backfill");
+    cmd.arg("--summary");
+    cmd.arg(&summary_file);
+    cmd.arg("--debug");
+    let assert = cmd.assert();
+    std::fs::remove_file(&snapshot_file)?;
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&summary_file)?)?;
+    std::fs::remove_file(&summary_file)?;
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("1,0.0000,5.0000,5.0000,false\n"))
+        .stdout(predicate::str::contains("2,3.0000,0.0000,3.0000,false\n"))
+        .stderr(
+            "error processing transaction - TransactionIDAlreadyExists: 3 (client 2)\n\
+             ClientAccountTransaction { transaction_type: Deposit, transaction_id: 3, amount: Some(2.0) }\n",
+        );
+    assert_eq!(summary["backfilled"], 2);
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-backfill.csv");
+    cmd.arg("--backfill");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--backfill needs the state of --snapshot-in to skip the known ids of",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_backfills_the_dispute_ops_and_forgotten_transactions_of_the_loaded_state(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_file = std::env::temp_dir()
+        .join("rs_bpt-it_backfills_the_dispute_ops_and_forgotten_transactions.json");
+    let retained_snapshot_file = std::env::temp_dir()
+        .join("rs_bpt-it_backfills_the_dispute_ops_and_forgotten_transactions-retained.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-backfill-history.csv");
+    cmd.arg("--snapshot-out");
+    cmd.arg(&snapshot_file);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,10.0000,0.0000,10.0000,true\n"));
+
+    // the chargeback of tx 2 isn't applied again, only the new deposit
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-backfill-corrected.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--backfill");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,11.0000,0.0000,11.0000,true\n"));
+
+    // nor is the deposit of tx 1 once it has been forgotten past the dispute retention
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-backfill-next-day.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--dispute-retention-runs");
+    cmd.arg("0");
+    cmd.arg("--snapshot-out");
+    cmd.arg(&retained_snapshot_file);
+    cmd.assert().success();
+    std::fs::remove_file(&snapshot_file)?;
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-backfill-corrected.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&retained_snapshot_file);
+    cmd.arg("--backfill");
+    let assert = cmd.assert();
+    std::fs::remove_file(&retained_snapshot_file)?;
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("1,11.0000,0.0000,11.0000,true\n"));
+
+    Ok(())
+}
+
+#[test]
+fn it_forgets_settled_transactions_past_the_dispute_retention(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_file = std::env::temp_dir()
+        .join("rs_bpt-it_forgets_settled_transactions_past_the_dispute_retention.json");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-open-dispute.csv");
+    cmd.arg("--snapshot-out");
+    cmd.arg(&snapshot_file);
+    cmd.assert().success();
+
+    // client 2's deposit can be disputed in the next run
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-dispute-carried-over.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--dispute-retention-runs");
+    cmd.arg("1");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1,0.0000,0.0000,0.0000,true\n"))
+        .stdout(predicate::str::contains("2,0.0000,1.0000,1.0000,false\n"));
+
+    // but not past a retention of no runs, while client 1's open dispute is kept
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-dispute-carried-over.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--dispute-retention-runs");
+    cmd.arg("0");
+    cmd.arg("--debug");
+    let assert = cmd.assert();
+    std::fs::remove_file(&snapshot_file)?;
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("1,0.0000,0.0000,0.0000,true\n"))
+        .stdout(predicate::str::contains("2,1.0000,0.0000,1.0000,false\n"))
+        .stderr(predicate::str::contains(
+            "forgot 1 settled transactions carried over into more than 0 runs",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn it_shards_processing_across_threads() -> Result<(), Box<dyn std::error::Error>> {
+    let sorted_lines = |stdout: Vec<u8>| {
+        let mut lines: Vec<String> = String::from_utf8(stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        lines
+    };
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    let expected = sorted_lines(cmd.assert().success().get_output().stdout.clone());
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--threads");
+    cmd.arg("4");
+    let sharded = sorted_lines(cmd.assert().success().get_output().stdout.clone());
+
+    assert_eq!(sharded, expected);
+
+    Ok(())
+}
+
+#[test]
+fn it_sorts_the_output_by_client() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--sort-by-client");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let clients: Vec<u16> = String::from_utf8(output)?
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap().parse().unwrap())
+        .collect();
+    let mut sorted = clients.clone();
+    sorted.sort_unstable();
+    assert!(clients.len() > 1);
+    assert_eq!(clients, sorted);
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_a_netting_report() -> Result<(), Box<dyn std::error::Error>> {
+    let netting_file = std::env::temp_dir().join("rs_bpt-it_writes_a_netting_report.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--netting");
+    cmd.arg(&netting_file);
+
+    cmd.assert().success();
+
+    let netting = std::fs::read_to_string(&netting_file)?;
+    std::fs::remove_file(&netting_file)?;
+
+    assert_eq!(
+        netting,
+        "client,gross_deposits,gross_withdrawals,chargebacks,net_movement,held_delta\n\
+         1,3.0000,1.5000,0.0000,1.5000,0.0000\n\
+         2,2.0000,3.0000,0.0000,-1.0000,0.0000\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_a_settlement_file() -> Result<(), Box<dyn std::error::Error>> {
+    let settlement_file = std::env::temp_dir().join("rs_bpt-it_writes_a_settlement_file.txt");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--settlement");
+    cmd.arg(&settlement_file);
+    cmd.arg("--settlement-threshold");
+    cmd.arg("1.2");
+    cmd.arg("--settlement-template");
+    cmd.arg("PAY|{client}|{direction}|{amount}|{value_date}");
+    cmd.arg("--settlement-value-date");
+    cmd.arg("2022-03-01");
+
+    cmd.assert().success();
+
+    let settlement = std::fs::read_to_string(&settlement_file)?;
+    std::fs::remove_file(&settlement_file)?;
+
+    assert_eq!(settlement, "PAY|1|credit|1.5000|2022-03-01\n");
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_group_balances() -> Result<(), Box<dyn std::error::Error>> {
+    let group_output_file = std::env::temp_dir().join("rs_bpt-it_writes_group_balances.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions.csv");
+    cmd.arg("--groups");
+    cmd.arg("tests/fixtures/client-groups.csv");
+    cmd.arg("--group-output");
+    cmd.arg(&group_output_file);
+
+    cmd.assert().success();
+
+    let group_output = std::fs::read_to_string(&group_output_file)?;
+    std::fs::remove_file(&group_output_file)?;
+
+    assert_eq!(
+        group_output,
+        "group,clients,locked_clients,available,held,total,locked\nacme,2,0,0.5000,0.0000,0.5000,false\n"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "output-template")]
+#[test]
+fn it_renders_an_output_template() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--output-template");
+    cmd.arg("tests/fixtures/accounts.tera");
+
+    cmd.assert().success();
+    let output = cmd.output().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "ACCT 1 110.0000\nACCT 2 1000.0000 LOCKED\nTOTAL 2 1110.0000\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_a_change_log() -> Result<(), Box<dyn std::error::Error>> {
+    let change_log_file = std::env::temp_dir().join("rs_bpt-it_writes_a_change_log.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-complex.csv");
+    cmd.arg("--change-log");
+    cmd.arg(&change_log_file);
+
+    cmd.assert().success();
+
+    let change_log = std::fs::read_to_string(&change_log_file)?;
+    std::fs::remove_file(&change_log_file)?;
+
+    assert!(change_log.starts_with("client,available,held,total,locked,tx,reason\n"));
+    assert!(change_log.ends_with("\n2,1000.0000,0.0000,1000.0000,true,2,chargeback\n"));
+    assert_eq!(change_log.lines().count(), 9);
+
+    Ok(())
+}
+
+#[test]
+fn it_compacts_a_state_snapshot() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_file = std::env::temp_dir().join("rs_bpt-it_compacts_a_state_snapshot.json");
+    let archive_file = std::env::temp_dir().join("rs_bpt-it_compacts_a_state_snapshot.csv");
+
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("compact");
+    cmd.arg("tests/fixtures/snapshot-compact.json");
+    cmd.arg("--output");
+    cmd.arg(&snapshot_file);
+    cmd.arg("--retention-days");
+    cmd.arg("30");
+    cmd.arg("--archive");
+    cmd.arg(&archive_file);
+    cmd.assert().success();
+
+    let archive = std::fs::read_to_string(&archive_file)?;
+    std::fs::remove_file(&archive_file)?;
+    assert_eq!(
+        archive,
+        "client,tx,type,amount,charged_back,retained_since\n2,2,deposit,1.0000,false,86400\n"
+    );
+
+    // the open dispute is kept, and a dispute of the forgotten deposit is rejected
+    let mut cmd = Command::cargo_bin("rs_bpt")?;
+    cmd.arg("tests/fixtures/transactions-dispute-compacted.csv");
+    cmd.arg("--snapshot-in");
+    cmd.arg(&snapshot_file);
+    let assert = cmd.assert();
+    std::fs::remove_file(&snapshot_file)?;
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("1,5.0000,0.0000,5.0000,false\n"))
+        .stdout(predicate::str::contains("2,1.0000,0.0000,1.0000,false\n"));
+
+    Ok(())
+}